@@ -0,0 +1,69 @@
+// Compares the per-bit distance circuit of BitVeilChip with the popcount circuit, which looks up
+// the distance of each nibble pair. Run with `cargo bench --bench popcount`.
+use circuits::circuits::{
+    BitVeilProver, CircuitKind, generate_salt, generate_setup_params, k_for_length, k_for_popcount,
+};
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+
+const LENGTHS: [usize; 3] = [256, 1024, 2048];
+
+fn vectors(length: usize) -> (Vec<u64>, Vec<u64>) {
+    let a = (0..length).map(|i| (i % 3 == 0) as u64).collect();
+    let b = (0..length).map(|i| (i % 5 == 0) as u64).collect();
+    (a, b)
+}
+
+fn bench_prove(c: &mut Criterion) {
+    let mut group = c.benchmark_group("prove");
+    group.sample_size(10);
+    for length in LENGTHS {
+        let (a, b) = vectors(length);
+        let (salt_a, salt_b) = (generate_salt(), generate_salt());
+        for (kind, k) in [
+            (CircuitKind::Distance, k_for_length(length)),
+            (CircuitKind::Popcount, k_for_popcount(length)),
+        ] {
+            let prover = BitVeilProver::new(generate_setup_params(k), kind, length)
+                .expect("Failed to generate keys");
+            group.bench_with_input(
+                BenchmarkId::new(format!("{} (k = {})", kind, k), length),
+                &length,
+                |bencher, _| {
+                    bencher.iter(|| {
+                        prover
+                            .prove(a.clone(), b.clone(), salt_a, salt_b)
+                            .expect("Failed to generate proof")
+                    })
+                },
+            );
+        }
+    }
+    group.finish();
+}
+
+fn bench_keygen(c: &mut Criterion) {
+    let mut group = c.benchmark_group("keygen");
+    group.sample_size(10);
+    for length in LENGTHS {
+        for (kind, k) in [
+            (CircuitKind::Distance, k_for_length(length)),
+            (CircuitKind::Popcount, k_for_popcount(length)),
+        ] {
+            let params = generate_setup_params(k);
+            group.bench_with_input(
+                BenchmarkId::new(format!("{} (k = {})", kind, k), length),
+                &length,
+                |bencher, &length| {
+                    bencher.iter(|| {
+                        BitVeilProver::new(params.clone(), kind, length)
+                            .expect("Failed to generate keys")
+                    })
+                },
+            );
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_prove, bench_keygen);
+criterion_main!(benches);
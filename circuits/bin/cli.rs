@@ -1,16 +1,30 @@
 use anyhow::{Context, Result};
 use circuits::circuits::{
-    calculate_hamming_distance, create_circuit, draw_circuit, empty_circuit, generate_keys,
-    generate_proof, generate_setup_params, run_mock_prover, verify,
+    BitVeilProver, BitVeilVerifier, CircuitKind, DEFAULT_LENGTH, DistanceMetric, EmbeddingMetric,
+    Hamming, InnerProduct, KeyInfo, MAX_CANDIDATES, MAX_ELEMENT, MAX_LENGTH, PopcountHamming,
+    ProofBundle, RotatedHamming, SimilarityMetric, WeightedHamming, calculate_hamming_distance,
+    calculate_similarity, check_rotation, circuit_length, commit_integer_vector,
+    commit_masked_vector, commit_session_vector, commit_vector, context_id, draw_circuit,
+    empty_batch_circuit, empty_circuit, empty_embedding_circuit, empty_masked_circuit,
+    empty_membership_circuit, empty_rotation_threshold_circuit, empty_session_circuit,
+    empty_similarity_circuit, empty_threshold_circuit, fp_from_hex, fp_to_hex, generate_nonce,
+    generate_nullifier_secret, generate_salt, generate_setup_params, k_for_batch, k_for_embedding,
+    k_for_length, k_for_masked, k_for_membership, k_for_rotation, k_for_session, k_for_similarity,
+    read_params, run_metric_mock_prover, unpack_words,
 };
+use circuits::error::BitVeilError;
+use circuits::merkle::Registry;
 use clap::{Parser, Subcommand};
 use colored::Colorize;
 use halo2_proofs::{
-    pasta::{EqAffine, Fp},
+    dev::CircuitCost,
+    pasta::{Eq, EqAffine, Fp},
+    plonk::Circuit,
     poly::commitment::Params,
 };
 use std::fs;
-use std::path::PathBuf;
+use std::io::Write;
+use std::path::{Path, PathBuf};
 
 // Helper function to convert Fp to u64
 fn fp_to_u64(fp: &Fp) -> u64 {
@@ -34,7 +48,7 @@ fn fp_to_u64(fp: &Fp) -> u64 {
 fn load_params(path: &PathBuf) -> Result<Params<EqAffine>> {
     let params_data =
         fs::read(path).context(format!("Failed to read params file: {}", path.display()))?;
-    Params::<EqAffine>::read(&mut &params_data[..]).context("Failed to deserialize params")
+    read_params(&mut &params_data[..]).context("Failed to deserialize params")
 }
 
 // Helper function to save setup parameters
@@ -46,6 +60,157 @@ fn save_params(params: &Params<EqAffine>, path: &PathBuf) -> Result<()> {
     fs::write(path, buf).context(format!("Failed to write params to {}", path.display()))
 }
 
+// Helper function to save a proof bundle, as JSON if the path ends in .json
+fn save_bundle(bundle: &ProofBundle, path: &PathBuf) -> Result<()> {
+    let buf = if path.extension().is_some_and(|ext| ext == "json") {
+        bundle.to_json().into_bytes()
+    } else {
+        let mut buf = Vec::new();
+        bundle
+            .write(&mut buf)
+            .context("Failed to serialize proof")?;
+        buf
+    };
+    fs::write(path, buf).context(format!("Failed to write proof to {}", path.display()))
+}
+
+// Helper function to load a proof bundle in either format
+fn load_bundle(path: &PathBuf) -> Result<ProofBundle> {
+    let proof_data =
+        fs::read(path).context(format!("Failed to read proof file: {}", path.display()))?;
+    ProofBundle::decode(&proof_data).context("Failed to deserialize proof")
+}
+
+// Lists the proof bundles to verify in a batch, either the .bin and .json files of a directory in
+// name order or the paths listed in a manifest, which are relative to the manifest's directory.
+// Blank lines and lines starting with # are skipped in manifests.
+fn list_bundles(path: &PathBuf) -> Result<Vec<PathBuf>> {
+    if path.is_dir() {
+        let mut paths = fs::read_dir(path)
+            .context(format!("Failed to read directory: {}", path.display()))?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        paths.retain(|path| {
+            path.is_file()
+                && path
+                    .extension()
+                    .is_some_and(|ext| ext == "bin" || ext == "json")
+        });
+        paths.sort();
+        return Ok(paths);
+    }
+
+    let manifest =
+        fs::read_to_string(path).context(format!("Failed to read manifest: {}", path.display()))?;
+    let base = path.parent().unwrap_or(Path::new("."));
+    Ok(manifest
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| base.join(line))
+        .collect())
+}
+
+// Helper function to load a registry, or to start an empty one if the file does not exist yet
+fn load_registry(path: &PathBuf, create: bool) -> Result<Registry> {
+    if create && !path.exists() {
+        return Ok(Registry::new());
+    }
+    let registry_data =
+        fs::read(path).context(format!("Failed to read registry file: {}", path.display()))?;
+    Registry::read(&mut &registry_data[..]).context("Failed to deserialize registry")
+}
+
+// Helper function to save a registry
+fn save_registry(registry: &Registry, path: &PathBuf) -> Result<()> {
+    let mut buf = Vec::new();
+    registry
+        .write(&mut buf)
+        .context("Failed to serialize registry")?;
+    fs::write(path, buf).context(format!("Failed to write registry to {}", path.display()))
+}
+
+// Helper function to load the nullifiers a verifier has already seen, one hex value per line,
+// which are none if the file does not exist yet
+fn load_nullifiers(path: &PathBuf) -> Result<Vec<Fp>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let nullifiers = fs::read_to_string(path)
+        .context(format!("Failed to read nullifier file: {}", path.display()))?;
+    nullifiers
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| parse_field(line, "nullifier"))
+        .collect()
+}
+
+// Helper function to record a nullifier as seen
+fn save_nullifier(nullifier: &Fp, path: &PathBuf) -> Result<()> {
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .context(format!("Failed to open nullifier file: {}", path.display()))?;
+    writeln!(file, "{}", fp_to_hex(nullifier))
+        .context(format!("Failed to write nullifier to {}", path.display()))
+}
+
+// Helper function to save key info
+fn save_key_info(info: &KeyInfo, path: &PathBuf) -> Result<()> {
+    let mut buf = Vec::new();
+    info.write(&mut buf).context("Failed to serialize key")?;
+    fs::write(path, buf).context(format!("Failed to write key to {}", path.display()))
+}
+
+// Helper function to load key info and check it belongs to the expected circuit
+fn load_key_info(path: &PathBuf, kind: CircuitKind, length: Option<usize>) -> Result<KeyInfo> {
+    let key_data =
+        fs::read(path).context(format!("Failed to read key file: {}", path.display()))?;
+    let info = KeyInfo::read(&mut &key_data[..]).context("Failed to deserialize key")?;
+    if info.kind != kind {
+        anyhow::bail!(
+            "Key {} was generated for {} proofs, run setup{} to generate {} keys",
+            path.display(),
+            info.kind,
+            match kind {
+                CircuitKind::Distance => {
+                    " without --threshold, --candidates, --masked, --shifts, --weights, \
+                     --similarity, --inner-product, --embedding, --popcount, --membership or \
+                     --session"
+                }
+                CircuitKind::Threshold => " with --threshold and without --shifts",
+                CircuitKind::Batch => " with --candidates",
+                CircuitKind::Masked => " with --masked",
+                CircuitKind::Rotation => " with --shifts and without --threshold",
+                CircuitKind::RotationThreshold => " with --shifts and --threshold",
+                CircuitKind::Weighted => " with --weights",
+                CircuitKind::Jaccard => " with --similarity jaccard",
+                CircuitKind::Dice => " with --similarity dice",
+                CircuitKind::InnerProduct => " with --inner-product",
+                CircuitKind::L1 => " with --embedding l1",
+                CircuitKind::L2 => " with --embedding l2",
+                CircuitKind::Popcount => " with --popcount",
+                CircuitKind::Membership => " with --membership",
+                CircuitKind::Session => " with --session",
+            },
+            kind
+        );
+    }
+    if let Some(length) = length
+        && circuit_length(length) != info.length
+    {
+        anyhow::bail!(
+            "Key {} was generated for vectors of length {}, run setup with --length {}",
+            path.display(),
+            info.length,
+            length
+        );
+    }
+    Ok(info)
+}
+
 #[derive(Parser)]
 #[command(name = "bitveil")]
 #[command(author = "Kunal Singh Dadhwal <kunalsinghdadhwal@gmail.com>")]
@@ -61,13 +226,69 @@ struct Cli {
 enum Commands {
     /// Initialize setup parameters and generate keys
     Setup {
-        /// Power of 2 for circuit size (k value, e.g., 8 means 2^8 = 256 rows)
-        #[arg(short, long, default_value_t = 8)]
-        k: u32,
+        /// Length of the binary vectors the keys are generated for
+        #[arg(short, long, default_value_t = DEFAULT_LENGTH)]
+        length: usize,
+
+        /// Power of 2 for circuit size (k value, e.g., 9 means 2^9 = 512 rows), derived from the length if omitted
+        #[arg(short, long)]
+        k: Option<u32>,
 
         /// Output directory for setup files
         #[arg(short, long, default_value = "./keys")]
         output: PathBuf,
+
+        /// Generate keys for threshold proofs instead of proofs which reveal the distance
+        #[arg(short, long)]
+        threshold: bool,
+
+        /// Generate keys for batch proofs comparing one probe with this many candidates
+        #[arg(short, long, conflicts_with = "threshold")]
+        candidates: Option<usize>,
+
+        /// Generate keys for masked proofs, which only compare the bits valid in both masks
+        #[arg(short, long, conflicts_with_all = ["threshold", "candidates"])]
+        masked: bool,
+
+        /// Generate keys for rotation proofs, which compare the vectors at each cyclic shift from
+        /// -SHIFTS to SHIFTS bits and prove the smallest distance
+        #[arg(short = 'r', long, conflicts_with_all = ["candidates", "masked"])]
+        shifts: Option<usize>,
+
+        /// Generate keys for weighted proofs, which add up the weight of each differing bit
+        /// (comma-separated, one weight per bit, e.g., "1,4,1,2"). The vector length is the number
+        /// of weights.
+        #[arg(short, long, conflicts_with_all = ["length", "threshold", "candidates", "masked", "shifts"])]
+        weights: Option<String>,
+
+        /// Generate keys for similarity proofs, which prove that the Jaccard (or Tanimoto) or Dice
+        /// similarity is at least a public threshold
+        #[arg(short, long, conflicts_with_all = ["threshold", "candidates", "masked", "shifts", "weights"])]
+        similarity: Option<SimilarityMetric>,
+
+        /// Generate keys for inner-product proofs over vectors of integers from 0 to 255
+        #[arg(short, long, conflicts_with_all = ["threshold", "candidates", "masked", "shifts", "weights", "similarity"])]
+        inner_product: bool,
+
+        /// Generate keys for embedding proofs, which prove that the L1 or the squared L2 distance
+        /// between two vectors of integers from 0 to 255 is at most a public threshold
+        #[arg(short, long, conflicts_with_all = ["threshold", "candidates", "masked", "shifts", "weights", "similarity", "inner_product"])]
+        embedding: Option<EmbeddingMetric>,
+
+        /// Generate keys for the popcount circuit, which proves the same distance as the default
+        /// circuit with a lookup table per nibble instead of a row per bit
+        #[arg(long, conflicts_with_all = ["threshold", "candidates", "masked", "shifts", "weights", "similarity", "inner_product", "embedding"])]
+        popcount: bool,
+
+        /// Generate keys for membership proofs, which prove that the distance between a probe and
+        /// some template enrolled in a registry is at most a public threshold
+        #[arg(long, conflicts_with_all = ["threshold", "candidates", "masked", "shifts", "weights", "similarity", "inner_product", "embedding", "popcount"])]
+        membership: bool,
+
+        /// Generate keys for session proofs, which bind the distance to a session nonce and
+        /// reveal a nullifier of vector B that a relying party can detect reuse with
+        #[arg(long, conflicts_with_all = ["threshold", "candidates", "masked", "shifts", "weights", "similarity", "inner_product", "embedding", "popcount", "membership"])]
+        session: bool,
     },
 
     /// Generate a zero-knowledge proof
@@ -88,27 +309,325 @@ enum Commands {
         #[arg(short = 'k', long, default_value = "./keys/proving_key.bin")]
         proving_key: PathBuf,
 
-        /// Output file for proof
+        /// Output file for proof bundle, written as JSON if it ends in .json
         #[arg(short, long, default_value = "./proof.bin")]
         output: PathBuf,
 
+        /// Salt for the commitment to vector A (hex, random if omitted)
+        #[arg(long)]
+        salt_a: Option<String>,
+
+        /// Salt for the commitment to vector B (hex, random if omitted)
+        #[arg(long)]
+        salt_b: Option<String>,
+
+        /// Prove that the distance is at most this threshold instead of revealing it
+        #[arg(short, long)]
+        threshold: Option<u64>,
+
+        /// Prove the smallest distance over each cyclic shift of vector B from -SHIFTS to SHIFTS
+        /// bits, with keys from `setup --shifts`
+        #[arg(short = 'r', long)]
+        shifts: Option<usize>,
+
+        /// Validity mask of vector A, which only compares the bits where both masks are 1
+        #[arg(long, requires = "mask_b", conflicts_with_all = ["threshold", "shifts"])]
+        mask_a: Option<String>,
+
+        /// Validity mask of vector B
+        #[arg(long, requires = "mask_a")]
+        mask_b: Option<String>,
+
+        /// Prove the weighted distance with the weights of keys from `setup --weights`
+        #[arg(short, long, conflicts_with_all = ["threshold", "shifts", "mask_a"])]
+        weighted: bool,
+
+        /// Prove that this similarity is at least --ratio, with keys from `setup --similarity`
+        #[arg(short, long, requires = "ratio", conflicts_with_all = ["threshold", "shifts", "mask_a", "weighted"])]
+        similarity: Option<SimilarityMetric>,
+
+        /// Similarity threshold as a fraction of at most 1 (e.g., "3/4")
+        #[arg(long, requires = "similarity")]
+        ratio: Option<String>,
+
+        /// Prove the distance with keys from `setup --popcount`
+        #[arg(long, conflicts_with_all = ["threshold", "shifts", "mask_a", "weighted", "similarity"])]
+        popcount: bool,
+
+        /// Prove the distance in a session with keys from `setup --session`, revealing the
+        /// nullifier of vector B in this context (e.g., the name of the relying party)
+        #[arg(long, conflicts_with_all = ["threshold", "shifts", "mask_a", "weighted", "similarity", "popcount"])]
+        context: Option<String>,
+
+        /// Session nonce or challenge from the verifier to bind the proof to (hex, random if
+        /// omitted)
+        #[arg(long, requires = "context")]
+        nonce: Option<String>,
+
+        /// Nullifier secret of vector B, which is committed to with it and which the nullifier is
+        /// derived from (hex, random if omitted). Keep it to prove about vector B again.
+        #[arg(long, requires = "context")]
+        secret: Option<String>,
+
+        /// Read the vectors as hex-encoded packed bytes, most significant bit first
+        #[arg(long)]
+        packed: bool,
+
+        /// Domain tag to bind the proof to, such as an application id and protocol version,
+        /// which the verifier must be given too
+        #[arg(long)]
+        domain: Option<String>,
+
         /// Print hamming distance
         #[arg(long, default_value_t = true)]
         show_distance: bool,
     },
 
+    /// Generate one zero-knowledge proof of the distances between a probe and several candidates
+    ProveBatch {
+        /// Probe binary vector (comma-separated, e.g., "1,0,1,0")
+        #[arg(long)]
+        probe: String,
+
+        /// Candidate binary vector, repeated once per candidate
+        #[arg(short, long = "candidate", required = true)]
+        candidates: Vec<String>,
+
+        /// Path to setup parameters
+        #[arg(short, long, default_value = "./keys/params.bin")]
+        params: PathBuf,
+
+        /// Path to proving key
+        #[arg(short = 'k', long, default_value = "./keys/proving_key.bin")]
+        proving_key: PathBuf,
+
+        /// Output file for proof bundle, written as JSON if it ends in .json
+        #[arg(short, long, default_value = "./proof.bin")]
+        output: PathBuf,
+
+        /// Salt for the commitment to the probe (hex, random if omitted)
+        #[arg(long)]
+        salt_probe: Option<String>,
+
+        /// Salt for the commitment to each candidate in order (hex, random if omitted)
+        #[arg(long = "salt")]
+        salts: Vec<String>,
+
+        /// Read the vectors as hex-encoded packed bytes, most significant bit first
+        #[arg(long)]
+        packed: bool,
+
+        /// Domain tag to bind the proof to, such as an application id and protocol version,
+        /// which the verifier must be given too
+        #[arg(long)]
+        domain: Option<String>,
+    },
+
+    /// Generate a zero-knowledge proof of the inner product of two integer vectors
+    ProveInnerProduct {
+        /// First integer vector with elements from 0 to 255 (comma-separated, e.g., "12,0,255,3")
+        #[arg(short, long)]
+        vector_a: String,
+
+        /// Second integer vector with elements from 0 to 255 (comma-separated)
+        #[arg(short = 'b', long)]
+        vector_b: String,
+
+        /// Path to setup parameters
+        #[arg(short, long, default_value = "./keys/params.bin")]
+        params: PathBuf,
+
+        /// Path to proving key
+        #[arg(short = 'k', long, default_value = "./keys/proving_key.bin")]
+        proving_key: PathBuf,
+
+        /// Output file for proof bundle, written as JSON if it ends in .json
+        #[arg(short, long, default_value = "./proof.bin")]
+        output: PathBuf,
+
+        /// Salt for the commitment to vector A (hex, random if omitted)
+        #[arg(long)]
+        salt_a: Option<String>,
+
+        /// Salt for the commitment to vector B (hex, random if omitted)
+        #[arg(long)]
+        salt_b: Option<String>,
+
+        /// Read the vectors as hex-encoded bytes, one element per byte
+        #[arg(long)]
+        packed: bool,
+
+        /// Domain tag to bind the proof to, such as an application id and protocol version,
+        /// which the verifier must be given too
+        #[arg(long)]
+        domain: Option<String>,
+    },
+
+    /// Generate a zero-knowledge proof that the distance between two integer vectors is at most a
+    /// threshold
+    ProveEmbedding {
+        /// First integer vector with elements from 0 to 255 (comma-separated, e.g., "12,0,255,3")
+        #[arg(short, long, allow_hyphen_values = true)]
+        vector_a: String,
+
+        /// Second integer vector with elements from 0 to 255 (comma-separated)
+        #[arg(short = 'b', long, allow_hyphen_values = true)]
+        vector_b: String,
+
+        /// Distance the keys were generated for with `setup --embedding`
+        #[arg(short, long)]
+        embedding: EmbeddingMetric,
+
+        /// Prove that the distance is at most this threshold
+        #[arg(short, long)]
+        threshold: u64,
+
+        /// Path to setup parameters
+        #[arg(short, long, default_value = "./keys/params.bin")]
+        params: PathBuf,
+
+        /// Path to proving key
+        #[arg(short = 'k', long, default_value = "./keys/proving_key.bin")]
+        proving_key: PathBuf,
+
+        /// Output file for proof bundle, written as JSON if it ends in .json
+        #[arg(short, long, default_value = "./proof.bin")]
+        output: PathBuf,
+
+        /// Salt for the commitment to vector A (hex, random if omitted)
+        #[arg(long)]
+        salt_a: Option<String>,
+
+        /// Salt for the commitment to vector B (hex, random if omitted)
+        #[arg(long)]
+        salt_b: Option<String>,
+
+        /// Read the vectors as hex-encoded bytes, one element per byte
+        #[arg(long)]
+        packed: bool,
+
+        /// Domain tag to bind the proof to, such as an application id and protocol version,
+        /// which the verifier must be given too
+        #[arg(long)]
+        domain: Option<String>,
+
+        /// Read the elements as signed integers from -128 to 127, such as int8 embeddings, and
+        /// shift them by 128. The distances are unchanged.
+        #[arg(long)]
+        signed: bool,
+    },
+
+    /// Generate a zero-knowledge proof that a probe is within a threshold of some template enrolled
+    /// in a registry, without revealing which
+    ProveMembership {
+        /// Probe binary vector (comma-separated, e.g., "1,0,1,0")
+        #[arg(long)]
+        probe: String,
+
+        /// Enrolled template binary vector (comma-separated)
+        #[arg(long)]
+        template: String,
+
+        /// Salt the template was committed to with when it was enrolled (hex)
+        #[arg(long)]
+        salt_template: String,
+
+        /// Salt for the commitment to the probe (hex, random if omitted)
+        #[arg(long)]
+        salt_probe: Option<String>,
+
+        /// Prove that the distance is at most this threshold
+        #[arg(short, long)]
+        threshold: u64,
+
+        /// Path to the registry the template is enrolled in
+        #[arg(short, long, default_value = "./registry.bin")]
+        registry: PathBuf,
+
+        /// Path to setup parameters
+        #[arg(short, long, default_value = "./keys/params.bin")]
+        params: PathBuf,
+
+        /// Path to proving key
+        #[arg(short = 'k', long, default_value = "./keys/proving_key.bin")]
+        proving_key: PathBuf,
+
+        /// Output file for proof bundle, written as JSON if it ends in .json
+        #[arg(short, long, default_value = "./proof.bin")]
+        output: PathBuf,
+
+        /// Read the vectors as hex-encoded packed bytes, most significant bit first
+        #[arg(long)]
+        packed: bool,
+
+        /// Domain tag to bind the proof to, such as an application id and protocol version,
+        /// which the verifier must be given too
+        #[arg(long)]
+        domain: Option<String>,
+    },
+
     /// Verify a zero-knowledge proof
     Verify {
-        /// Hamming distance to verify
+        /// Hamming distance the proof must claim
         #[arg(short, long)]
-        distance: u64,
+        distance: Option<u64>,
+
+        /// Threshold the proof must claim, for proofs generated with --threshold or embedding proofs
+        #[arg(short, long, conflicts_with = "distance")]
+        threshold: Option<u64>,
+
+        /// Number of bits valid in both masks a masked proof must claim
+        #[arg(long)]
+        valid_bits: Option<u64>,
+
+        /// Hamming distances a batch proof must claim, one per candidate (comma-separated)
+        #[arg(long, conflicts_with_all = ["distance", "threshold"])]
+        distances: Option<String>,
+
+        /// Similarity threshold a similarity proof must claim (e.g., "3/4")
+        #[arg(long, conflicts_with_all = ["distance", "threshold", "distances"])]
+        ratio: Option<String>,
+
+        /// Inner product an inner-product proof must claim
+        #[arg(long, conflicts_with_all = ["distance", "threshold", "distances", "ratio"])]
+        inner_product: Option<u64>,
+
+        /// Commitment to vector A the proof must be for (hex)
+        #[arg(long)]
+        commitment_a: Option<String>,
+
+        /// Commitment to vector B the proof must be for (hex)
+        #[arg(long)]
+        commitment_b: Option<String>,
+
+        /// Registry whose current root a membership proof must be for
+        #[arg(long)]
+        registry: Option<PathBuf>,
+
+        /// Nonce a session proof must be bound to, such as the challenge sent to the prover (hex)
+        #[arg(long)]
+        nonce: Option<String>,
+
+        /// Context the nullifier of a session proof must be derived in
+        #[arg(long)]
+        context: Option<String>,
+
+        /// File of the nullifiers already seen, one per line. A session proof whose nullifier is
+        /// listed is rejected, and the nullifier of a verified proof is added.
+        #[arg(long)]
+        nullifiers: Option<PathBuf>,
 
-        /// Path to proof file
+        /// Domain tag the proof must be bound to. Proofs bound to a domain only verify when it is
+        /// given.
+        #[arg(long)]
+        domain: Option<String>,
+
+        /// Path to proof bundle (binary or JSON)
         #[arg(short, long)]
         proof: PathBuf,
 
         /// Path to setup parameters
-        #[arg(short = 'p', long, default_value = "./keys/params.bin")]
+        #[arg(long, default_value = "./keys/params.bin")]
         params: PathBuf,
 
         /// Path to verifying key
@@ -116,6 +635,63 @@ enum Commands {
         verifying_key: PathBuf,
     },
 
+    /// Verify many proof bundles generated with the same verifying key in one batch
+    VerifyBatch {
+        /// Directory of proof bundles (.bin or .json), or a manifest listing one bundle path per line
+        #[arg(short, long)]
+        proofs: PathBuf,
+
+        /// Domain tag the proofs must be bound to. Proofs bound to another domain are reported as
+        /// failed.
+        #[arg(long)]
+        domain: Option<String>,
+
+        /// Path to setup parameters
+        #[arg(long, default_value = "./keys/params.bin")]
+        params: PathBuf,
+
+        /// Path to verifying key
+        #[arg(short = 'k', long, default_value = "./keys/verifying_key.bin")]
+        verifying_key: PathBuf,
+    },
+
+    /// Enroll the commitments to templates in a registry, creating it if it does not exist
+    RegistryAdd {
+        /// Commitment to a template (hex), repeated once per template
+        #[arg(short, long = "commitment", required = true)]
+        commitments: Vec<String>,
+
+        /// Path to the registry
+        #[arg(short, long, default_value = "./registry.bin")]
+        registry: PathBuf,
+    },
+
+    /// Replace or remove the template at an index of a registry
+    RegistryUpdate {
+        /// Index of the template
+        #[arg(short, long)]
+        index: usize,
+
+        /// Commitment to the new template (hex)
+        #[arg(short, long, required_unless_present = "remove")]
+        commitment: Option<String>,
+
+        /// Remove the template instead, leaving its index empty
+        #[arg(long, conflicts_with = "commitment")]
+        remove: bool,
+
+        /// Path to the registry
+        #[arg(short, long, default_value = "./registry.bin")]
+        registry: PathBuf,
+    },
+
+    /// Print the root and the enrolled commitments of a registry
+    RegistryShow {
+        /// Path to the registry
+        #[arg(short, long, default_value = "./registry.bin")]
+        registry: PathBuf,
+    },
+
     /// Calculate Hamming distance between two binary vectors (no ZK proof)
     Distance {
         /// First binary vector (comma-separated, e.g., "1,0,1,0")
@@ -125,6 +701,39 @@ enum Commands {
         /// Second binary vector (comma-separated, e.g., "0,1,0,1")
         #[arg(short = 'b', long)]
         vector_b: String,
+
+        /// Also calculate the weighted distance with one weight per bit (comma-separated)
+        #[arg(short, long)]
+        weights: Option<String>,
+    },
+
+    /// Compute the salted commitment to a binary vector
+    Commit {
+        /// Binary vector (comma-separated, e.g., "1,0,1,0")
+        #[arg(short, long, allow_hyphen_values = true)]
+        vector: String,
+
+        /// Salt for the commitment (hex, random if omitted)
+        #[arg(short, long)]
+        salt: Option<String>,
+
+        /// Validity mask to commit to along with the vector, for masked proofs
+        #[arg(short, long)]
+        mask: Option<String>,
+
+        /// Read the vector as integers from 0 to 255 and commit to it for inner-product and
+        /// embedding proofs
+        #[arg(short, long, conflicts_with = "mask")]
+        integer: bool,
+
+        /// Read the integers as signed integers from -128 to 127, shifted by 128 like
+        /// `prove-embedding --signed`
+        #[arg(long, requires = "integer")]
+        signed: bool,
+
+        /// Read the vector as hex-encoded packed bytes, most significant bit first
+        #[arg(long)]
+        packed: bool,
     },
 
     /// Run mock prover for testing (no actual proof generation)
@@ -137,27 +746,95 @@ enum Commands {
         #[arg(short = 'b', long)]
         vector_b: String,
 
-        /// Circuit size parameter (k)
-        #[arg(short, long, default_value_t = 8)]
-        k: u32,
+        /// Circuit size parameter (k), derived from the vector length if omitted
+        #[arg(short, long)]
+        k: Option<u32>,
     },
 
     /// Draw circuit layout (generates layout.png)
     Draw {
-        /// Circuit size parameter (k)
-        #[arg(short, long, default_value_t = 8)]
-        k: u32,
+        /// Length of the binary vectors to draw the circuit for
+        #[arg(short, long, default_value_t = DEFAULT_LENGTH)]
+        length: usize,
+
+        /// Circuit size parameter (k), derived from the length if omitted
+        #[arg(short, long)]
+        k: Option<u32>,
 
         /// Output file path
         #[arg(short, long, default_value = "./layout.png")]
         output: PathBuf,
     },
 
+    /// Report the rows, columns and proof size of the circuit for a vector length
+    Cost {
+        /// Length of the binary vectors to measure the circuit for
+        #[arg(short, long, default_value_t = DEFAULT_LENGTH)]
+        length: usize,
+
+        /// Circuit size parameter (k), derived from the length if omitted
+        #[arg(short, long)]
+        k: Option<u32>,
+
+        /// Measure the threshold circuit instead of the one which reveals the distance
+        #[arg(short, long)]
+        threshold: bool,
+
+        /// Measure the batch circuit comparing one probe with this many candidates
+        #[arg(short, long, conflicts_with = "threshold")]
+        candidates: Option<usize>,
+
+        /// Measure the masked circuit
+        #[arg(short, long, conflicts_with_all = ["threshold", "candidates"])]
+        masked: bool,
+
+        /// Measure the rotation circuit comparing the vectors at this many shifts in each
+        /// direction
+        #[arg(short = 'r', long, conflicts_with_all = ["candidates", "masked"])]
+        shifts: Option<usize>,
+
+        /// Measure the weighted circuit with these weights, one per bit (comma-separated)
+        #[arg(short, long, conflicts_with_all = ["length", "threshold", "candidates", "masked", "shifts"])]
+        weights: Option<String>,
+
+        /// Measure the similarity circuit for this similarity
+        #[arg(short, long, conflicts_with_all = ["threshold", "candidates", "masked", "shifts", "weights"])]
+        similarity: Option<SimilarityMetric>,
+
+        /// Measure the inner-product circuit
+        #[arg(short, long, conflicts_with_all = ["threshold", "candidates", "masked", "shifts", "weights", "similarity"])]
+        inner_product: bool,
+
+        /// Measure the embedding circuit for this distance
+        #[arg(short, long, conflicts_with_all = ["threshold", "candidates", "masked", "shifts", "weights", "similarity", "inner_product"])]
+        embedding: Option<EmbeddingMetric>,
+
+        /// Measure the popcount circuit
+        #[arg(long, conflicts_with_all = ["threshold", "candidates", "masked", "shifts", "weights", "similarity", "inner_product", "embedding"])]
+        popcount: bool,
+
+        /// Measure the membership circuit
+        #[arg(long, conflicts_with_all = ["threshold", "candidates", "masked", "shifts", "weights", "similarity", "inner_product", "embedding", "popcount"])]
+        membership: bool,
+
+        /// Measure the session circuit
+        #[arg(long, conflicts_with_all = ["threshold", "candidates", "masked", "shifts", "weights", "similarity", "inner_product", "embedding", "popcount", "membership"])]
+        session: bool,
+
+        /// Print the full report of halo2's cost model
+        #[arg(long)]
+        verbose: bool,
+    },
+
     /// Generate example vectors for testing
     Example {
-        /// Hamming distance to generate (0-32)
+        /// Hamming distance to generate (0 to the vector length)
         #[arg(short, long, default_value_t = 8)]
         distance: usize,
+
+        /// Length of the example vectors
+        #[arg(short, long, default_value_t = DEFAULT_LENGTH)]
+        length: usize,
     },
 }
 
@@ -165,8 +842,67 @@ fn main() -> Result<()> {
     let cli = Cli::parse();
 
     match &cli.command {
-        Some(Commands::Setup { k, output }) => {
-            println!("Initializing BitVeil setup (k={})", k);
+        Some(Commands::Setup {
+            length,
+            k,
+            output,
+            threshold,
+            candidates,
+            masked,
+            shifts,
+            weights,
+            similarity,
+            inner_product,
+            embedding,
+            popcount,
+            membership,
+            session,
+        }) => {
+            // Weighted keys are generated for one bit per weight
+            let weights = weights.as_deref().map(parse_weights).transpose()?;
+            let length = &weights.as_ref().map_or(*length, Vec::len);
+            if *length == 0 || *length > MAX_LENGTH {
+                anyhow::bail!("Vector length must be between 1 and {}", MAX_LENGTH);
+            }
+            if let Some(candidates) = candidates
+                && (*candidates == 0 || *candidates > MAX_CANDIDATES)
+            {
+                anyhow::bail!(
+                    "Number of candidates must be between 1 and {}",
+                    MAX_CANDIDATES
+                );
+            }
+            if let Some(shifts) = shifts {
+                check_rotation(*length, *shifts)?;
+            }
+            // The circuit the keys are generated for below
+            let kind = match (candidates, shifts, &weights, similarity, embedding) {
+                (Some(_), _, _, _, _) => CircuitKind::Batch,
+                (None, Some(_), _, _, _) if *threshold => CircuitKind::RotationThreshold,
+                (None, Some(_), _, _, _) => CircuitKind::Rotation,
+                (None, None, Some(_), _, _) => CircuitKind::Weighted,
+                (None, None, None, Some(metric), _) => metric.kind(),
+                (None, None, None, None, Some(metric)) => metric.kind(),
+                (None, None, None, None, None) if *threshold => CircuitKind::Threshold,
+                (None, None, None, None, None) if *masked => CircuitKind::Masked,
+                (None, None, None, None, None) if *inner_product => CircuitKind::InnerProduct,
+                (None, None, None, None, None) if *popcount => CircuitKind::Popcount,
+                (None, None, None, None, None) if *membership => CircuitKind::Membership,
+                (None, None, None, None, None) if *session => CircuitKind::Session,
+                (None, None, None, None, None) => CircuitKind::Distance,
+            };
+            let min_k = kind.k(*length, candidates.unwrap_or(1), shifts.unwrap_or(0));
+            let k = k.unwrap_or(min_k);
+            if k < min_k {
+                anyhow::bail!(
+                    "k={} is too small for vectors of length {}, at least k={} is required",
+                    k,
+                    length,
+                    min_k
+                );
+            }
+
+            println!("Initializing BitVeil setup (length={}, k={})", length, k);
             println!("Output directory: {}", output.display());
 
             // Create output directory
@@ -174,104 +910,763 @@ fn main() -> Result<()> {
 
             // Generate setup parameters
             println!("Generating setup parameters...");
-            let params = generate_setup_params(*k);
+            let params = generate_setup_params(k);
 
             // Generate keys
             println!("Generating proving and verifying keys...");
-            let circuit = empty_circuit();
-            let (_pk, _vk) = generate_keys(&params, &circuit);
+            let prover = match (candidates, shifts, weights, similarity, embedding) {
+                (Some(candidates), _, _, _, _) => {
+                    BitVeilProver::new_batch(params, *length, *candidates)?
+                }
+                (None, Some(shifts), _, _, _) => {
+                    BitVeilProver::new_rotation(params, *length, *shifts, *threshold)?
+                }
+                (None, None, Some(weights), _, _) => BitVeilProver::new_weighted(params, weights)?,
+                (None, None, None, Some(metric), _) => {
+                    BitVeilProver::new_similarity(params, *length, *metric)?
+                }
+                (None, None, None, None, Some(metric)) => {
+                    BitVeilProver::new_embedding(params, *length, *metric)?
+                }
+                (None, None, None, None, None) if *threshold => {
+                    BitVeilProver::new(params, CircuitKind::Threshold, *length)?
+                }
+                (None, None, None, None, None) if *masked => {
+                    BitVeilProver::new(params, CircuitKind::Masked, *length)?
+                }
+                (None, None, None, None, None) if *inner_product => {
+                    BitVeilProver::new(params, CircuitKind::InnerProduct, *length)?
+                }
+                (None, None, None, None, None) if *popcount => {
+                    BitVeilProver::new(params, CircuitKind::Popcount, *length)?
+                }
+                (None, None, None, None, None) if *membership => {
+                    BitVeilProver::new(params, CircuitKind::Membership, *length)?
+                }
+                (None, None, None, None, None) if *session => {
+                    BitVeilProver::new(params, CircuitKind::Session, *length)?
+                }
+                (None, None, None, None, None) => {
+                    BitVeilProver::new(params, CircuitKind::Distance, *length)?
+                }
+            };
+            let (params, info) = (prover.params(), prover.key_info());
 
-            // Note: Keys cannot be serialized in halo2_proofs 0.3.1
-            // They will be regenerated from params when needed
-            println!("Saving setup parameters...");
+            // Note: Keys cannot be serialized in halo2_proofs 0.3.1, so the key files record
+            // the circuit and fingerprints, and the keys are regenerated and checked when loaded
+            println!("Saving setup parameters and keys...");
 
             // Save params
             let params_path = output.join("params.bin");
-            save_params(&params, &params_path)?;
+            save_params(params, &params_path)?;
+
+            // Save keys
+            let pk_path = output.join("proving_key.bin");
+            save_key_info(info, &pk_path)?;
+            let vk_path = output.join("verifying_key.bin");
+            save_key_info(info, &vk_path)?;
 
             println!("{}", "Setup complete!".green().bold());
             println!("  Parameters: {}", params_path.display());
-            println!("  Note: Keys will be regenerated from params when needed");
+            println!("  Proving key: {}", pk_path.display());
+            println!("  Verifying key: {}", vk_path.display());
+            println!("  Circuit: {} (length {})", info.kind, info.length);
+            if info.kind == CircuitKind::Batch {
+                println!("  Candidates: {}", info.candidates);
+            }
+            if info.kind.is_rotation() {
+                println!("  Shifts: -{} to {}", info.shifts, info.shifts);
+            }
+            if info.kind == CircuitKind::Weighted {
+                println!(
+                    "  Weights: {} bits, total weight {}",
+                    info.weights.len(),
+                    info.weights.iter().sum::<u64>()
+                );
+            }
+            println!("  Verifying key fingerprint: {}", hex::encode(info.vk_hash));
         }
 
         Some(Commands::Prove {
             vector_a,
             vector_b,
             params: params_path,
-            proving_key: _,
+            proving_key,
             output,
             show_distance,
+            salt_a,
+            salt_b,
+            threshold,
+            shifts,
+            mask_a,
+            mask_b,
+            weighted,
+            similarity,
+            ratio,
+            popcount,
+            context,
+            nonce,
+            secret,
+            packed,
+            domain,
         }) => {
             println!("Generating zero-knowledge proof...");
+            let ratio = ratio.as_deref().map(parse_ratio).transpose()?;
 
             // Parse vectors
-            let a = parse_binary_vector(vector_a)?;
-            let b = parse_binary_vector(vector_b)?;
-
-            if a.len() != b.len() {
-                anyhow::bail!("Vectors must be the same length");
-            }
+            let a = parse_vector(vector_a, *packed, "A")?;
+            let b = parse_vector(vector_b, *packed, "B")?;
+            let masks = match (mask_a, mask_b) {
+                (Some(mask_a), Some(mask_b)) => Some((
+                    parse_vector(mask_a, *packed, "mask A")?,
+                    parse_vector(mask_b, *packed, "mask B")?,
+                )),
+                _ => None,
+            };
 
             println!("Vector A: {:?}", a);
             println!("Vector B: {:?}", b);
 
             // Calculate hamming distance
-            let hamming_distance = calculate_hamming_distance(a.clone(), b.clone());
+            let hamming_distance = calculate_hamming_distance(a.clone(), b.clone())?;
             if *show_distance {
                 let distance_u64 = fp_to_u64(&hamming_distance[0]);
                 println!("Hamming Distance: {}", distance_u64);
             }
 
-            // Create circuit
-            let circuit = create_circuit(a, b);
+            // Commit to both vectors
+            let salt_a = parse_salt(salt_a.as_deref())?;
+            let salt_b = parse_salt(salt_b.as_deref())?;
+            let session = match context {
+                Some(label) => Some((
+                    match nonce {
+                        Some(nonce) => parse_field(nonce, "nonce")?,
+                        None => generate_nonce(),
+                    },
+                    match secret {
+                        Some(secret) => parse_field(secret, "nullifier secret")?,
+                        None => generate_nullifier_secret(),
+                    },
+                    label,
+                )),
+                None => None,
+            };
+            let (commitment_a, commitment_b) = match (&masks, &session) {
+                (Some((mask_a, mask_b)), _) => (
+                    commit_masked_vector(&a, mask_a, salt_a)?,
+                    commit_masked_vector(&b, mask_b, salt_b)?,
+                ),
+                (None, Some((_, secret, _))) => (
+                    commit_vector(&a, salt_a)?,
+                    commit_session_vector(&b, salt_b, *secret)?,
+                ),
+                (None, None) => (commit_vector(&a, salt_a)?, commit_vector(&b, salt_b)?),
+            };
+
+            let distance_u64 = fp_to_u64(&hamming_distance[0]);
 
-            // Load params and generate keys
+            // Load params
             println!("Loading setup parameters...");
             let params = load_params(params_path)?;
-            println!("Generating keys from params...");
-            let (pk, _vk) = generate_keys(&params, &empty_circuit());
+            let length = a.len();
+
+            // Load the proving key of the circuit, which only reveals the threshold in
+            // threshold mode
+            println!("Loading proving key...");
+            let kind = match (threshold, shifts, &masks, similarity) {
+                (Some(_), Some(_), _, _) => CircuitKind::RotationThreshold,
+                (Some(_), None, _, _) => CircuitKind::Threshold,
+                (None, Some(_), _, _) => CircuitKind::Rotation,
+                (None, None, Some(_), _) => CircuitKind::Masked,
+                (None, None, None, Some(metric)) => metric.kind(),
+                (None, None, None, None) if *weighted => CircuitKind::Weighted,
+                (None, None, None, None) if *popcount => CircuitKind::Popcount,
+                (None, None, None, None) if session.is_some() => CircuitKind::Session,
+                (None, None, None, None) => CircuitKind::Distance,
+            };
+            let info = load_key_info(proving_key, kind, Some(length))?;
+            if let Some(shifts) = shifts
+                && *shifts != info.shifts
+            {
+                anyhow::bail!(
+                    "Key {} was generated for {} shifts, run setup with --shifts {}",
+                    proving_key.display(),
+                    info.shifts,
+                    shifts
+                );
+            }
+            let prover = BitVeilProver::from_key_info(params, info)
+                .context("Invalid proving key")?
+                .with_domain(domain.as_deref().unwrap_or_default())?;
+
+            println!("Generating proof...");
+            // Save the proof together with its public inputs and the keys it was made with
+            let bundle = match (threshold, shifts, masks, ratio) {
+                (Some(threshold), Some(_), _, _) => {
+                    prover.prove_rotation_threshold(a, b, salt_a, salt_b, *threshold)?
+                }
+                (Some(threshold), None, _, _) => {
+                    prover.prove_threshold(a, b, salt_a, salt_b, *threshold)?
+                }
+                (None, Some(_), _, _) => prover.prove_rotation(a, b, salt_a, salt_b)?,
+                (None, None, Some((mask_a, mask_b)), _) => {
+                    prover.prove_masked(a, b, mask_a, mask_b, salt_a, salt_b)?
+                }
+                (None, None, None, Some((numerator, denominator))) => {
+                    prover.prove_similarity(a, b, salt_a, salt_b, numerator, denominator)?
+                }
+                (None, None, None, None) if *weighted => {
+                    prover.prove_weighted(a, b, salt_a, salt_b)?
+                }
+                (None, None, None, None) => match session {
+                    Some((nonce, secret, label)) => prover.prove_session(
+                        a,
+                        b,
+                        salt_a,
+                        salt_b,
+                        secret,
+                        nonce,
+                        context_id(label),
+                    )?,
+                    None => prover.prove(a, b, salt_a, salt_b)?,
+                },
+            };
+            save_bundle(&bundle, output)?;
+
+            println!("{}", "Proof generated successfully!".green().bold());
+            println!("  Proof saved to: {}", output.display());
+            match (threshold, kind) {
+                (Some(threshold), CircuitKind::RotationThreshold) => println!(
+                    "  Proves Hamming Distance <= {} at some shift within {} bits",
+                    threshold,
+                    shifts.unwrap_or_default()
+                ),
+                (Some(threshold), _) => println!("  Proves Hamming Distance <= {}", threshold),
+                (None, CircuitKind::Rotation) => println!(
+                    "  Smallest Hamming Distance over shifts within {} bits: {}",
+                    shifts.unwrap_or_default(),
+                    fp_to_u64(&bundle.distance())
+                ),
+                (None, CircuitKind::Weighted) => println!(
+                    "  Weighted Hamming Distance: {}",
+                    fp_to_u64(&bundle.distance())
+                ),
+                (None, CircuitKind::Masked) => println!(
+                    "  Masked Hamming Distance: {} over {} valid bits",
+                    fp_to_u64(&bundle.distance()),
+                    fp_to_u64(&bundle.valid_bits())
+                ),
+                (None, CircuitKind::Jaccard | CircuitKind::Dice) => println!(
+                    "  Proves {} similarity >= {}/{}",
+                    kind,
+                    fp_to_u64(&bundle.distance()),
+                    fp_to_u64(&bundle.denominator())
+                ),
+                (None, _) => println!("  Hamming Distance: {}", distance_u64),
+            }
+            println!("  Commitment A: {}", fp_to_hex(&commitment_a));
+            println!("  Salt A: {}", fp_to_hex(&salt_a));
+            println!("  Commitment B: {}", fp_to_hex(&commitment_b));
+            println!("  Salt B: {}", fp_to_hex(&salt_b));
+            if let Some((_, secret, label)) = session {
+                println!("  Nullifier secret: {}", fp_to_hex(&secret));
+                println!("  Nonce: {}", fp_to_hex(&bundle.nonce()));
+                println!("  Context: {} ({})", label, fp_to_hex(&bundle.context()));
+                println!("  Nullifier: {}", fp_to_hex(&bundle.nullifier()));
+            }
+            println!("  Vector length: {}", length);
+            println!("  Proof size: {} bytes", bundle.proof.len());
+        }
 
-            // Generate proof
-            let proof = generate_proof(&params, &pk, circuit, &hamming_distance);
+        Some(Commands::ProveBatch {
+            probe,
+            candidates,
+            params: params_path,
+            proving_key,
+            output,
+            salt_probe,
+            salts,
+            packed,
+            domain,
+        }) => {
+            println!("Generating zero-knowledge batch proof...");
 
-            // Save proof
-            fs::write(output, &proof).context("Failed to write proof file")?;
+            // Parse vectors
+            let probe = parse_vector(probe, *packed, "probe")?;
+            let candidates = candidates
+                .iter()
+                .map(|candidate| parse_vector(candidate, *packed, "candidate"))
+                .collect::<Result<Vec<_>>>()?;
+            if !salts.is_empty() && salts.len() != candidates.len() {
+                anyhow::bail!(
+                    "Got {} salts for {} candidates, give one salt per candidate or none",
+                    salts.len(),
+                    candidates.len()
+                );
+            }
 
-            let distance_u64 = fp_to_u64(&hamming_distance[0]);
+            println!("Probe: {:?}", probe);
+            println!("Candidates: {}", candidates.len());
+
+            // Commit to the probe and to each candidate
+            let salt_probe = parse_salt(salt_probe.as_deref())?;
+            let salts = (0..candidates.len())
+                .map(|i| parse_salt(salts.get(i).map(String::as_str)))
+                .collect::<Result<Vec<_>>>()?;
+
+            // Load params
+            println!("Loading setup parameters...");
+            let params = load_params(params_path)?;
+            let length = probe.len();
+
+            println!("Loading proving key...");
+            let info = load_key_info(proving_key, CircuitKind::Batch, Some(length))?;
+            let prover = BitVeilProver::from_key_info(params, info)
+                .context("Invalid proving key")?
+                .with_domain(domain.as_deref().unwrap_or_default())?;
+
+            println!("Generating proof...");
+
+            let bundle = prover.prove_batch(probe, candidates, salt_probe, salts.clone())?;
+            save_bundle(&bundle, output)?;
 
             println!("{}", "Proof generated successfully!".green().bold());
             println!("  Proof saved to: {}", output.display());
-            println!("  Hamming Distance: {}", distance_u64);
-            println!("  Proof size: {} bytes", proof.len());
+            println!(
+                "  Probe commitment: {}",
+                fp_to_hex(&bundle.probe_commitment())
+            );
+            println!("  Probe salt: {}", fp_to_hex(&salt_probe));
+            for (i, ((distance, commitment), salt)) in bundle
+                .batch_distances()
+                .iter()
+                .zip(bundle.candidate_commitments())
+                .zip(&salts)
+                .enumerate()
+            {
+                println!(
+                    "  Candidate {}: Hamming Distance {}, commitment {}, salt {}",
+                    i,
+                    fp_to_u64(distance),
+                    fp_to_hex(&commitment),
+                    fp_to_hex(salt)
+                );
+            }
+            println!("  Vector length: {}", length);
+            println!("  Proof size: {} bytes", bundle.proof.len());
+        }
+
+        Some(Commands::ProveInnerProduct {
+            vector_a,
+            vector_b,
+            params: params_path,
+            proving_key,
+            output,
+            salt_a,
+            salt_b,
+            packed,
+            domain,
+        }) => {
+            println!("Generating zero-knowledge inner-product proof...");
+
+            // Parse vectors
+            let a = parse_integer_vector(vector_a, *packed, false)?;
+            let b = parse_integer_vector(vector_b, *packed, false)?;
+
+            println!("Vector A: {:?}", a);
+            println!("Vector B: {:?}", b);
+
+            // Commit to both vectors
+            let salt_a = parse_salt(salt_a.as_deref())?;
+            let salt_b = parse_salt(salt_b.as_deref())?;
+
+            // Load params
+            println!("Loading setup parameters...");
+            let params = load_params(params_path)?;
+            let length = a.len();
+
+            println!("Loading proving key...");
+            let info = load_key_info(proving_key, CircuitKind::InnerProduct, Some(length))?;
+            let prover = BitVeilProver::from_key_info(params, info)
+                .context("Invalid proving key")?
+                .with_domain(domain.as_deref().unwrap_or_default())?;
+
+            println!("Generating proof...");
+
+            let bundle = prover.prove_inner_product(a, b, salt_a, salt_b)?;
+            save_bundle(&bundle, output)?;
+
+            println!("{}", "Proof generated successfully!".green().bold());
+            println!("  Proof saved to: {}", output.display());
+            println!("  Inner product: {}", fp_to_u64(&bundle.distance()));
+            println!("  Commitment A: {}", fp_to_hex(&bundle.commitment_a()));
+            println!("  Commitment B: {}", fp_to_hex(&bundle.commitment_b()));
+            println!("  Salt A: {}", fp_to_hex(&salt_a));
+            println!("  Salt B: {}", fp_to_hex(&salt_b));
+            println!("  Vector length: {}", length);
+            println!("  Proof size: {} bytes", bundle.proof.len());
+        }
+
+        Some(Commands::ProveEmbedding {
+            vector_a,
+            vector_b,
+            embedding,
+            threshold,
+            params: params_path,
+            proving_key,
+            output,
+            salt_a,
+            salt_b,
+            packed,
+            signed,
+            domain,
+        }) => {
+            println!("Generating zero-knowledge {} distance proof...", embedding);
+
+            // Parse vectors
+            let a = parse_integer_vector(vector_a, *packed, *signed)?;
+            let b = parse_integer_vector(vector_b, *packed, *signed)?;
+
+            println!("Vector A: {:?}", a);
+            println!("Vector B: {:?}", b);
+
+            // Commit to both vectors
+            let salt_a = parse_salt(salt_a.as_deref())?;
+            let salt_b = parse_salt(salt_b.as_deref())?;
+
+            // Load params
+            println!("Loading setup parameters...");
+            let params = load_params(params_path)?;
+            let length = a.len();
+
+            println!("Loading proving key...");
+            let info = load_key_info(proving_key, embedding.kind(), Some(length))?;
+            let prover = BitVeilProver::from_key_info(params, info)
+                .context("Invalid proving key")?
+                .with_domain(domain.as_deref().unwrap_or_default())?;
+
+            println!("Generating proof...");
+
+            let bundle = prover.prove_embedding(a, b, salt_a, salt_b, *threshold)?;
+            save_bundle(&bundle, output)?;
+
+            println!("{}", "Proof generated successfully!".green().bold());
+            println!("  Proof saved to: {}", output.display());
+            println!("  Proves {} distance <= {}", embedding, threshold);
+            println!("  Commitment A: {}", fp_to_hex(&bundle.commitment_a()));
+            println!("  Commitment B: {}", fp_to_hex(&bundle.commitment_b()));
+            println!("  Salt A: {}", fp_to_hex(&salt_a));
+            println!("  Salt B: {}", fp_to_hex(&salt_b));
+            println!("  Vector length: {}", length);
+            println!("  Proof size: {} bytes", bundle.proof.len());
+        }
+
+        Some(Commands::ProveMembership {
+            probe,
+            template,
+            salt_template,
+            salt_probe,
+            threshold,
+            registry,
+            params: params_path,
+            proving_key,
+            output,
+            packed,
+            domain,
+        }) => {
+            println!("Generating zero-knowledge membership proof...");
+
+            // Parse vectors
+            let probe = parse_vector(probe, *packed, "probe")?;
+            let template = parse_vector(template, *packed, "template")?;
+
+            println!("Probe: {:?}", probe);
+
+            // The template is found in the registry by its commitment
+            let salt_template = parse_field(salt_template, "salt")?;
+            let salt_probe = parse_salt(salt_probe.as_deref())?;
+            let registry = load_registry(registry, false)?;
+            println!("Registry: {} entries", registry.len());
+
+            // Load params
+            println!("Loading setup parameters...");
+            let params = load_params(params_path)?;
+            let length = probe.len();
+
+            println!("Loading proving key...");
+            let info = load_key_info(proving_key, CircuitKind::Membership, Some(length))?;
+            let prover = BitVeilProver::from_key_info(params, info)
+                .context("Invalid proving key")?
+                .with_domain(domain.as_deref().unwrap_or_default())?;
+
+            println!("Generating proof...");
+
+            let bundle = prover.prove_membership(
+                probe,
+                template,
+                salt_probe,
+                salt_template,
+                *threshold,
+                &registry,
+            )?;
+            save_bundle(&bundle, output)?;
+
+            println!("{}", "Proof generated successfully!".green().bold());
+            println!("  Proof saved to: {}", output.display());
+            println!(
+                "  Proves Hamming Distance <= {} to a template in the registry",
+                threshold
+            );
+            println!("  Probe commitment: {}", fp_to_hex(&bundle.commitment_a()));
+            println!("  Probe salt: {}", fp_to_hex(&salt_probe));
+            println!("  Registry root: {}", fp_to_hex(&bundle.registry_root()));
+            println!("  Vector length: {}", length);
+            println!("  Proof size: {} bytes", bundle.proof.len());
         }
 
         Some(Commands::Verify {
             distance,
+            threshold,
+            valid_bits,
+            distances,
+            ratio,
+            inner_product,
+            commitment_a,
+            commitment_b,
+            registry,
+            nonce,
+            context,
+            nullifiers,
             proof,
             params: params_path,
-            verifying_key: _,
+            verifying_key,
+            domain,
         }) => {
             println!("Verifying zero-knowledge proof...");
 
             // Read proof
-            let proof_bytes = fs::read(proof).context("Failed to read proof file")?;
-
-            println!("Claimed Hamming Distance: {}", distance);
-            println!("Proof size: {} bytes", proof_bytes.len());
-
-            // Create public input
-            let pub_input = vec![Fp::from(*distance)];
+            let bundle = load_bundle(proof)?;
+
+            match bundle.circuit {
+                CircuitKind::Threshold => println!(
+                    "Claimed Hamming Distance <= {}",
+                    fp_to_u64(&bundle.distance())
+                ),
+                CircuitKind::Distance | CircuitKind::Popcount | CircuitKind::Session => println!(
+                    "Claimed Hamming Distance: {}",
+                    fp_to_u64(&bundle.distance())
+                ),
+                CircuitKind::Batch => {
+                    println!(
+                        "Probe commitment: {}",
+                        fp_to_hex(&bundle.probe_commitment())
+                    )
+                }
+                CircuitKind::Masked => println!(
+                    "Claimed masked Hamming Distance: {} over {} valid bits",
+                    fp_to_u64(&bundle.distance()),
+                    fp_to_u64(&bundle.valid_bits())
+                ),
+                CircuitKind::Rotation => println!(
+                    "Claimed smallest Hamming Distance over shifts within {} bits: {}",
+                    bundle.shifts,
+                    fp_to_u64(&bundle.distance())
+                ),
+                CircuitKind::RotationThreshold => println!(
+                    "Claimed Hamming Distance <= {} at some shift within {} bits",
+                    fp_to_u64(&bundle.distance()),
+                    bundle.shifts
+                ),
+                CircuitKind::Weighted => println!(
+                    "Claimed weighted Hamming Distance: {}",
+                    fp_to_u64(&bundle.distance())
+                ),
+                CircuitKind::Jaccard | CircuitKind::Dice => println!(
+                    "Claimed {} similarity >= {}/{}",
+                    bundle.circuit,
+                    fp_to_u64(&bundle.distance()),
+                    fp_to_u64(&bundle.denominator())
+                ),
+                CircuitKind::InnerProduct => {
+                    println!("Claimed inner product: {}", fp_to_u64(&bundle.distance()))
+                }
+                CircuitKind::L1 | CircuitKind::L2 => println!(
+                    "Claimed {} distance <= {}",
+                    bundle.circuit,
+                    fp_to_u64(&bundle.distance())
+                ),
+                CircuitKind::Membership => println!(
+                    "Claimed Hamming Distance <= {} to a template in the registry",
+                    fp_to_u64(&bundle.distance())
+                ),
+            }
+            if bundle.circuit == CircuitKind::Batch {
+                for (i, (distance, commitment)) in bundle
+                    .batch_distances()
+                    .iter()
+                    .zip(bundle.candidate_commitments())
+                    .enumerate()
+                {
+                    println!(
+                        "Candidate {}: claimed Hamming Distance {}, commitment {}",
+                        i,
+                        fp_to_u64(distance),
+                        fp_to_hex(&commitment)
+                    );
+                }
+            } else if bundle.circuit == CircuitKind::Membership {
+                println!("Probe commitment: {}", fp_to_hex(&bundle.commitment_a()));
+                println!("Registry root: {}", fp_to_hex(&bundle.registry_root()));
+            } else {
+                println!("Commitment A: {}", fp_to_hex(&bundle.commitment_a()));
+                println!("Commitment B: {}", fp_to_hex(&bundle.commitment_b()));
+            }
+            if bundle.circuit == CircuitKind::Session {
+                println!("Nonce: {}", fp_to_hex(&bundle.nonce()));
+                println!("Context: {}", fp_to_hex(&bundle.context()));
+                println!("Nullifier: {}", fp_to_hex(&bundle.nullifier()));
+            }
+            if !bundle.domain.is_empty() {
+                println!("Domain: {}", bundle.domain);
+            }
+            println!("Vector length: {}", bundle.length);
+            println!("Proof size: {} bytes", bundle.proof.len());
+
+            // The proof must be bound to the given domain, or to none if no domain is given
+            bundle.check_domain(domain.as_deref().unwrap_or_default())?;
+
+            // Check the public inputs against the expected ones, if given
+            match (distance, threshold) {
+                (Some(distance), _) => {
+                    if !matches!(
+                        bundle.circuit,
+                        CircuitKind::Distance
+                            | CircuitKind::Popcount
+                            | CircuitKind::Session
+                            | CircuitKind::Masked
+                            | CircuitKind::Rotation
+                            | CircuitKind::Weighted
+                    ) || bundle.distance() != Fp::from(*distance)
+                    {
+                        anyhow::bail!("Proof does not claim a Hamming distance of {}", distance);
+                    }
+                }
+                (_, Some(threshold)) => {
+                    if !matches!(
+                        bundle.circuit,
+                        CircuitKind::Threshold
+                            | CircuitKind::RotationThreshold
+                            | CircuitKind::L1
+                            | CircuitKind::L2
+                            | CircuitKind::Membership
+                    ) || bundle.distance() != Fp::from(*threshold)
+                    {
+                        anyhow::bail!("Proof does not claim a threshold of {}", threshold);
+                    }
+                }
+                (None, None) => {}
+            }
+            if let Some(valid_bits) = valid_bits
+                && (bundle.circuit != CircuitKind::Masked
+                    || bundle.valid_bits() != Fp::from(*valid_bits))
+            {
+                anyhow::bail!("Proof does not claim {} valid bits", valid_bits);
+            }
+            if let Some(ratio) = ratio {
+                let (numerator, denominator) = parse_ratio(ratio)?;
+                if bundle.circuit.similarity().is_none()
+                    || bundle.distance() != Fp::from(numerator)
+                    || bundle.denominator() != Fp::from(denominator)
+                {
+                    anyhow::bail!("Proof does not claim a similarity threshold of {}", ratio);
+                }
+            }
+            if let Some(inner_product) = inner_product
+                && (bundle.circuit != CircuitKind::InnerProduct
+                    || bundle.distance() != Fp::from(*inner_product))
+            {
+                anyhow::bail!("Proof does not claim an inner product of {}", inner_product);
+            }
+            if let Some(distances) = distances {
+                let expected = distances
+                    .split(',')
+                    .map(|d| d.trim().parse::<u64>().map(Fp::from))
+                    .collect::<Result<Vec<_>, _>>()
+                    .context("Invalid distances")?;
+                if bundle.circuit != CircuitKind::Batch || bundle.batch_distances() != expected {
+                    anyhow::bail!("Proof does not claim the distances {}", distances);
+                }
+            }
+            if bundle.circuit == CircuitKind::Batch
+                && (commitment_a.is_some() || commitment_b.is_some())
+            {
+                anyhow::bail!("Batch proofs have no commitments A and B");
+            }
+            if bundle.circuit == CircuitKind::Membership && commitment_b.is_some() {
+                anyhow::bail!("Membership proofs have no commitment B, check --registry instead");
+            }
+            if let Some(registry) = registry {
+                let root = load_registry(registry, false)?.root();
+                if bundle.circuit != CircuitKind::Membership || bundle.registry_root() != root {
+                    anyhow::bail!("Proof is not for the current root of the given registry");
+                }
+            }
+            if let Some(nonce) = nonce
+                && (bundle.circuit != CircuitKind::Session
+                    || parse_field(nonce, "nonce")? != bundle.nonce())
+            {
+                anyhow::bail!("Proof is not bound to the given nonce");
+            }
+            if let Some(context) = context
+                && (bundle.circuit != CircuitKind::Session
+                    || context_id(context) != bundle.context())
+            {
+                anyhow::bail!("Proof is not for the context {}", context);
+            }
+            if let Some(path) = nullifiers {
+                if bundle.circuit != CircuitKind::Session {
+                    anyhow::bail!("Only session proofs have a nullifier");
+                }
+                if load_nullifiers(path)?.contains(&bundle.nullifier()) {
+                    anyhow::bail!(
+                        "Nullifier {} has already been used",
+                        fp_to_hex(&bundle.nullifier())
+                    );
+                }
+            }
+            if let Some(commitment_a) = commitment_a
+                && parse_field(commitment_a, "commitment A")? != bundle.commitment_a()
+            {
+                anyhow::bail!("Proof is not for the given commitment A");
+            }
+            if let Some(commitment_b) = commitment_b
+                && parse_field(commitment_b, "commitment B")? != bundle.commitment_b()
+            {
+                anyhow::bail!("Proof is not for the given commitment B");
+            }
 
-            // Load params and generate keys
+            // Load params
             println!("Loading setup parameters...");
             let params = load_params(params_path)?;
-            println!("Generating keys from params...");
-            let (_pk, vk) = generate_keys(&params, &empty_circuit());
 
-            // Verify proof
-            match verify(&params, &vk, &pub_input, proof_bytes) {
+            // Load the key of the circuit the proof was made with and verify it
+            println!("Loading verifying key...");
+            let info = load_key_info(verifying_key, bundle.circuit, Some(bundle.length))?;
+            let verifier = BitVeilVerifier::from_key_info(params, info)
+                .context("Invalid verifying key")?
+                .with_domain(domain.as_deref().unwrap_or_default())?;
+
+            println!("Verifying proof...");
+            let result = verifier.verify(&bundle);
+
+            match result {
                 Ok(_) => {
                     println!("{}", "Proof verified successfully!".green().bold());
+                    // Only record the nullifier once the proof shows it is genuine
+                    if let Some(path) = nullifiers {
+                        save_nullifier(&bundle.nullifier(), path)?;
+                        println!("Nullifier recorded in {}", path.display());
+                    }
                 }
                 Err(_) => {
                     println!("{}", "Proof verification failed!".red().bold());
@@ -280,20 +1675,138 @@ fn main() -> Result<()> {
             }
         }
 
-        Some(Commands::Distance { vector_a, vector_b }) => {
+        Some(Commands::VerifyBatch {
+            proofs,
+            params: params_path,
+            verifying_key,
+            domain,
+        }) => {
+            let paths = list_bundles(proofs)?;
+            if paths.is_empty() {
+                anyhow::bail!("No proof bundles found in {}", proofs.display());
+            }
+            println!("Verifying {} proofs in one batch...", paths.len());
+
+            let bundles = paths.iter().map(load_bundle).collect::<Result<Vec<_>>>()?;
+
+            // Load params
+            println!("Loading setup parameters...");
+            let params = load_params(params_path)?;
+
+            // Proofs for other circuits are reported as failed instead of stopping the batch
+            println!("Loading verifying key...");
+            let key_data = fs::read(verifying_key).context(format!(
+                "Failed to read key file: {}",
+                verifying_key.display()
+            ))?;
+            let info = KeyInfo::read(&mut &key_data[..]).context("Failed to deserialize key")?;
+            let verifier = BitVeilVerifier::from_key_info(params, info)
+                .context("Invalid verifying key")?
+                .with_domain(domain.as_deref().unwrap_or_default())?;
+
+            match verifier.verify_batch(&bundles) {
+                Ok(()) => {
+                    println!(
+                        "{}",
+                        format!("All {} proofs verified successfully!", bundles.len())
+                            .green()
+                            .bold()
+                    );
+                }
+                Err(BitVeilError::BatchVerificationFailed(failed)) => {
+                    println!(
+                        "{}",
+                        format!(
+                            "{} of {} proofs failed verification:",
+                            failed.len(),
+                            bundles.len()
+                        )
+                        .red()
+                        .bold()
+                    );
+                    for i in failed {
+                        println!("  [{}] {}", i, paths[i].display());
+                    }
+                    std::process::exit(1);
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        Some(Commands::RegistryAdd {
+            commitments,
+            registry: registry_path,
+        }) => {
+            let mut registry = load_registry(registry_path, true)?;
+            for commitment in commitments {
+                let commitment = parse_field(commitment, "commitment")?;
+                let index = registry.insert(commitment)?;
+                println!("Enrolled {} at index {}", fp_to_hex(&commitment), index);
+            }
+            save_registry(&registry, registry_path)?;
+
+            println!("{}", "Registry updated!".green().bold());
+            println!("  Registry: {}", registry_path.display());
+            println!("  Entries: {}", registry.len());
+            println!("  Root: {}", fp_to_hex(&registry.root()));
+        }
+
+        Some(Commands::RegistryUpdate {
+            index,
+            commitment,
+            remove,
+            registry: registry_path,
+        }) => {
+            let mut registry = load_registry(registry_path, false)?;
+            match commitment {
+                Some(commitment) if !*remove => {
+                    let commitment = parse_field(commitment, "commitment")?;
+                    registry.update(*index, commitment)?;
+                    println!("Replaced index {} with {}", index, fp_to_hex(&commitment));
+                }
+                _ => {
+                    registry.remove(*index)?;
+                    println!("Removed index {}", index);
+                }
+            }
+            save_registry(&registry, registry_path)?;
+
+            println!("{}", "Registry updated!".green().bold());
+            println!("  Registry: {}", registry_path.display());
+            println!("  Root: {}", fp_to_hex(&registry.root()));
+        }
+
+        Some(Commands::RegistryShow {
+            registry: registry_path,
+        }) => {
+            let registry = load_registry(registry_path, false)?;
+
+            println!("Registry: {}", registry_path.display());
+            println!("  Entries: {}", registry.len());
+            println!("  Root: {}", fp_to_hex(&registry.root()));
+            for (i, leaf) in registry.leaves().iter().enumerate() {
+                if *leaf == Fp::zero() {
+                    println!("  [{}] (removed)", i);
+                } else {
+                    println!("  [{}] {}", i, fp_to_hex(leaf));
+                }
+            }
+        }
+
+        Some(Commands::Distance {
+            vector_a,
+            vector_b,
+            weights,
+        }) => {
             println!("Calculating Hamming Distance...");
 
             let a = parse_binary_vector(vector_a)?;
             let b = parse_binary_vector(vector_b)?;
 
-            if a.len() != b.len() {
-                anyhow::bail!("Vectors must be the same length");
-            }
-
             println!("Vector A: {:?}", a);
             println!("Vector B: {:?}", b);
 
-            let hamming_distance = calculate_hamming_distance(a.clone(), b.clone());
+            let hamming_distance = Hamming.calculate(&a, &b)?;
             let diff_positions: Vec<usize> = a
                 .iter()
                 .zip(&b)
@@ -302,7 +1815,7 @@ fn main() -> Result<()> {
                 .map(|(i, _)| i)
                 .collect();
 
-            let distance_u64 = fp_to_u64(&hamming_distance[0]);
+            let distance_u64 = fp_to_u64(&hamming_distance);
 
             println!("\nResults:");
             println!("  Hamming Distance: {}", distance_u64);
@@ -311,6 +1824,44 @@ fn main() -> Result<()> {
                 (1.0 - (distance_u64 as f64 / a.len() as f64)) * 100.0
             );
             println!("  Differing positions: {:?}", diff_positions);
+            if let Some(weights) = weights {
+                let weights = parse_weights(weights)?;
+                let weighted = WeightedHamming { weights }.calculate(&a, &b)?;
+                println!("  Weighted Hamming Distance: {}", fp_to_u64(&weighted));
+            }
+            for metric in [SimilarityMetric::Jaccard, SimilarityMetric::Dice] {
+                let (numerator, denominator) = calculate_similarity(&a, &b, metric)?;
+                println!("  {} similarity: {}/{}", metric, numerator, denominator);
+            }
+        }
+
+        Some(Commands::Commit {
+            vector,
+            salt,
+            mask,
+            integer,
+            signed,
+            packed,
+        }) => {
+            let bits = if *integer {
+                parse_integer_vector(vector, *packed, *signed)?
+            } else {
+                parse_vector(vector, *packed, "being committed")?
+            };
+            let salt = parse_salt(salt.as_deref())?;
+            let commitment = match mask {
+                Some(mask) => {
+                    let mask = parse_vector(mask, *packed, "mask being committed")?;
+                    println!("Mask: {:?}", mask);
+                    commit_masked_vector(&bits, &mask, salt)?
+                }
+                None if *integer => commit_integer_vector(&bits, salt)?,
+                None => commit_vector(&bits, salt)?,
+            };
+
+            println!("Vector: {:?}", bits);
+            println!("  Salt: {}", fp_to_hex(&salt));
+            println!("  Commitment: {}", fp_to_hex(&commitment));
         }
 
         Some(Commands::MockProve {
@@ -323,26 +1874,29 @@ fn main() -> Result<()> {
             let a = parse_binary_vector(vector_a)?;
             let b = parse_binary_vector(vector_b)?;
 
-            if a.len() != b.len() {
-                anyhow::bail!("Vectors must be the same length");
-            }
-
             println!("Vector A: {:?}", a);
             println!("Vector B: {:?}", b);
 
-            let hamming_distance = calculate_hamming_distance(a.clone(), b.clone());
-            let distance_u64 = fp_to_u64(&hamming_distance[0]);
+            let distance_u64 = fp_to_u64(&Hamming.calculate(&a, &b)?);
             println!("Hamming Distance: {}", distance_u64);
 
-            let circuit = create_circuit(a, b);
-            run_mock_prover(*k, &circuit, &hamming_distance);
+            let salt_a = generate_salt();
+            let salt_b = generate_salt();
+            match run_metric_mock_prover(&Hamming, *k, a, b, salt_a, salt_b) {
+                Ok(()) => println!("{}", "MockProver OK".green().bold()),
+                Err(e) => {
+                    println!("{}", e.to_string().red());
+                    std::process::exit(1);
+                }
+            }
         }
 
-        Some(Commands::Draw { k, output }) => {
+        Some(Commands::Draw { length, k, output }) => {
             println!("Drawing circuit layout...");
 
-            let circuit = empty_circuit();
-            draw_circuit(*k, &circuit);
+            let k = k.unwrap_or_else(|| k_for_length(*length));
+            let circuit = empty_circuit(*length);
+            draw_circuit(k, &circuit)?;
 
             // Move the file if a different output is specified
             if output.file_name().unwrap() != "layout.png" {
@@ -355,9 +1909,102 @@ fn main() -> Result<()> {
             }
         }
 
-        Some(Commands::Example { distance }) => {
-            if *distance > 32 {
-                anyhow::bail!("Distance must be between 0 and 32");
+        Some(Commands::Cost {
+            length,
+            k,
+            threshold,
+            candidates,
+            masked,
+            shifts,
+            weights,
+            similarity,
+            inner_product,
+            embedding,
+            popcount,
+            membership,
+            session,
+            verbose,
+        }) => {
+            let weights = weights.as_deref().map(parse_weights).transpose()?;
+            let length = &weights.as_ref().map_or(*length, Vec::len);
+            let (k, (rows, proof_size, report)) =
+                match (candidates, shifts, &weights, similarity, embedding) {
+                    (Some(candidates), _, _, _, _) => {
+                        let k = k.unwrap_or_else(|| k_for_batch(*length, *candidates));
+                        (
+                            k,
+                            measure_cost(k, &empty_batch_circuit(*length, *candidates)),
+                        )
+                    }
+                    (None, Some(shifts), _, _, _) if *threshold => {
+                        let k = k.unwrap_or_else(|| k_for_rotation(*length, *shifts));
+                        let circuit = empty_rotation_threshold_circuit(*length, *shifts);
+                        (k, measure_cost(k, &circuit))
+                    }
+                    (None, Some(shifts), _, _, _) => {
+                        metric_cost(&RotatedHamming { shifts: *shifts }, *k, *length)
+                    }
+                    (None, None, Some(weights), _, _) => {
+                        let metric = WeightedHamming {
+                            weights: weights.clone(),
+                        };
+                        metric_cost(&metric, *k, *length)
+                    }
+                    (None, None, None, Some(metric), _) => {
+                        let k = k.unwrap_or_else(|| k_for_similarity(*length));
+                        (
+                            k,
+                            measure_cost(k, &empty_similarity_circuit(*length, *metric)),
+                        )
+                    }
+                    (None, None, None, None, Some(metric)) => {
+                        let k = k.unwrap_or_else(|| k_for_embedding(*length));
+                        (
+                            k,
+                            measure_cost(k, &empty_embedding_circuit(*length, *metric)),
+                        )
+                    }
+                    (None, None, None, None, None) if *masked => {
+                        let k = k.unwrap_or_else(|| k_for_masked(*length));
+                        (k, measure_cost(k, &empty_masked_circuit(*length)))
+                    }
+                    (None, None, None, None, None) if *inner_product => {
+                        metric_cost(&InnerProduct, *k, *length)
+                    }
+                    (None, None, None, None, None) if *popcount => {
+                        metric_cost(&PopcountHamming, *k, *length)
+                    }
+                    (None, None, None, None, None) if *membership => {
+                        let k = k.unwrap_or_else(|| k_for_membership(*length));
+                        (k, measure_cost(k, &empty_membership_circuit(*length)))
+                    }
+                    (None, None, None, None, None) if *session => {
+                        let k = k.unwrap_or_else(|| k_for_session(*length));
+                        (k, measure_cost(k, &empty_session_circuit(*length)))
+                    }
+                    (None, None, None, None, None) if *threshold => {
+                        let k = k.unwrap_or_else(|| k_for_length(*length));
+                        (k, measure_cost(k, &empty_threshold_circuit(*length)))
+                    }
+                    (None, None, None, None, None) => metric_cost(&Hamming, *k, *length),
+                };
+
+            println!(
+                "Circuit for vectors of length {} (padded to {})",
+                length,
+                circuit_length(*length)
+            );
+            println!("  k: {} ({} rows available)", k, 1u64 << k);
+            println!("  Rows used: {}", rows);
+            println!("  Proof size: {} bytes", proof_size);
+            if *verbose {
+                println!("{}", report);
+            }
+        }
+
+        Some(Commands::Example { distance, length }) => {
+            if *distance > *length {
+                anyhow::bail!("Distance must be between 0 and {}", length);
             }
 
             println!(
@@ -366,12 +2013,12 @@ fn main() -> Result<()> {
             );
 
             // Generate vector A (all zeros for simplicity)
-            let vector_a: Vec<u64> = vec![0; 32];
+            let vector_a: Vec<u64> = vec![0; *length];
 
             // Generate vector B with exactly 'distance' bits set to 1
-            let mut vector_b: Vec<u64> = vec![0; 32];
-            for i in 0..*distance {
-                vector_b[i] = 1;
+            let mut vector_b: Vec<u64> = vec![0; *length];
+            for bit in vector_b.iter_mut().take(*distance) {
+                *bit = 1;
             }
 
             println!("\nExample vectors:");
@@ -436,3 +2083,134 @@ fn parse_binary_vector(input: &str) -> Result<Vec<u64>> {
         })
         .collect()
 }
+
+// Parses an integer vector for the inner-product and embedding circuits, given either as
+// comma-separated numbers or as hex-encoded bytes with one element per byte. Signed elements from
+// -128 to 127 are shifted by 128, which for bytes flips the sign bit of the two's complement.
+fn parse_integer_vector(input: &str, packed: bool, signed: bool) -> Result<Vec<u64>> {
+    let offset = if signed { 128 } else { 0 };
+    if packed {
+        let bytes = hex::decode(input.trim_start_matches("0x")).with_context(|| {
+            format!(
+                "{}",
+                format!("Invalid input: '{}' is not valid hex", input).red()
+            )
+        })?;
+        return Ok(bytes
+            .into_iter()
+            .map(|byte| u64::from(byte ^ offset as u8))
+            .collect());
+    }
+    input
+        .split(',')
+        .map(|s| {
+            let trimmed = s.trim();
+            let num = trimmed.parse::<i64>().with_context(|| {
+                format!(
+                    "{}",
+                    format!("Invalid input: '{}' is not a valid number", trimmed).red()
+                )
+            })?;
+            if num < -offset || num > MAX_ELEMENT as i64 - offset {
+                anyhow::bail!(
+                    "{}",
+                    format!(
+                        "Invalid input: '{}' must be {} to {}",
+                        num,
+                        -offset,
+                        MAX_ELEMENT as i64 - offset
+                    )
+                    .red()
+                );
+            }
+            Ok((num + offset) as u64)
+        })
+        .collect()
+}
+
+// Parses the weights of a weighted circuit, one per bit
+fn parse_weights(input: &str) -> Result<Vec<u64>> {
+    input
+        .split(',')
+        .map(|s| {
+            let trimmed = s.trim();
+            trimmed.parse::<u64>().with_context(|| {
+                format!(
+                    "{}",
+                    format!("Invalid weight: '{}' is not a valid number", trimmed).red()
+                )
+            })
+        })
+        .collect()
+}
+
+// Parses a similarity threshold given as a fraction, e.g., "3/4"
+fn parse_ratio(input: &str) -> Result<(u64, u64)> {
+    let invalid = || {
+        format!(
+            "{}",
+            format!("Invalid ratio: '{}' is not a fraction like 3/4", input).red()
+        )
+    };
+    let (numerator, denominator) = input.split_once('/').with_context(invalid)?;
+    let numerator = numerator.trim().parse::<u64>().with_context(invalid)?;
+    let denominator = denominator.trim().parse::<u64>().with_context(invalid)?;
+    Ok((numerator, denominator))
+}
+
+// Measures a circuit with halo2's cost model, returning the rows it uses, the size of its proofs
+// and the full report. The cost model only reports the rows through its debug output.
+fn measure_cost<C: Circuit<Fp> + std::fmt::Debug>(k: u32, circuit: &C) -> (usize, usize, String) {
+    let cost = CircuitCost::<Eq, C>::measure(k, circuit);
+    let report = format!("{:#?}", cost);
+    let rows = report
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("max_rows: "))
+        .and_then(|rows| rows.trim_end_matches(',').parse().ok())
+        .unwrap_or(0);
+    (rows, cost.proof_size(1).into(), report)
+}
+
+// Measures the circuit of a metric at the given k, or at the smallest k that fits it
+fn metric_cost<M: DistanceMetric>(
+    metric: &M,
+    k: Option<u32>,
+    length: usize,
+) -> (u32, (usize, usize, String))
+where
+    M::Circuit: std::fmt::Debug,
+{
+    let k = k.unwrap_or_else(|| metric.k(length));
+    (k, measure_cost(k, &metric.empty_circuit(length)))
+}
+
+// Parses a vector given either as comma-separated bits or as hex-encoded packed bytes
+fn parse_vector(input: &str, packed: bool, name: &'static str) -> Result<Vec<u64>> {
+    if !packed {
+        return parse_binary_vector(input);
+    }
+    let bytes = hex::decode(input.trim_start_matches("0x")).with_context(|| {
+        format!(
+            "{}",
+            format!("Invalid input: '{}' is not valid hex", input).red()
+        )
+    })?;
+    Ok(unpack_words(&bytes, bytes.len() * 8, name)?)
+}
+
+fn parse_field(input: &str, name: &str) -> Result<Fp> {
+    fp_from_hex(input).with_context(|| {
+        format!(
+            "{}",
+            format!("Invalid {}: '{}' is not a valid field element", name, input).red()
+        )
+    })
+}
+
+// Parses a salt, or generates a random one if none was given
+fn parse_salt(input: Option<&str>) -> Result<Fp> {
+    match input {
+        Some(salt) => parse_field(salt, "salt"),
+        None => Ok(generate_salt()),
+    }
+}
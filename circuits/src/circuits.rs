@@ -1,50 +1,361 @@
+use crate::error::BitVeilError;
+use crate::merkle::{MerklePath, REGISTRY_DEPTH, Registry};
+use crate::poseidon::{self, PoseidonChip, PoseidonConfig};
+use blake2b_simd::Params as Blake2bParams;
 use halo2_proofs::{
     arithmetic::Field,
     circuit::{AssignedCell, Chip, Layouter, Region, SimpleFloorPlanner, Value},
     dev::MockProver,
-    pasta::{EqAffine, Fp},
+    pasta::{
+        EqAffine, Fp,
+        group::ff::{FromUniformBytes, PrimeField},
+    },
     plonk::{
-        Advice, Circuit, Column, ConstraintSystem, Error, Expression, Instance, ProvingKey,
-        Selector, SingleVerifier, VerifyingKey, create_proof, keygen_pk, keygen_vk, verify_proof,
+        Advice, Circuit, Column, ConstraintSystem, Error, Expression, Fixed, Instance, ProvingKey,
+        Selector, SingleVerifier, TableColumn, VerificationStrategy, VerifyingKey, create_proof,
+        keygen_pk, keygen_vk, verify_proof,
+    },
+    poly::{
+        Rotation,
+        commitment::{Guard, MSM, Params},
     },
-    poly::{Rotation, commitment::Params},
-    transcript::{Blake2bRead, Blake2bWrite, Challenge255},
+    transcript::{Blake2bRead, Blake2bWrite, Challenge255, EncodedChallenge, Transcript},
 };
-use rand_core::OsRng;
-use std::marker::PhantomData;
+use rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use std::{
+    io::{self, Read, Write},
+    marker::PhantomData,
+};
+
+// The length of our binary inputs when none is given
+pub const DEFAULT_LENGTH: usize = 32;
+
+// The longest binary inputs we support
+pub const MAX_LENGTH: usize = 4096;
+
+// Inputs are padded with zeros to a multiple of this many bits, so that similar lengths share keys
+const CHUNK_LENGTH: usize = 32;
+
+// The rows at the end of the circuit which halo2 keeps for blinding, plus a margin
+const RESERVED_ROWS: usize = 16;
+
+// The number of bits packed into each field element before the vectors are hashed
+const WORD_LENGTH: usize = 64;
+
+// The number of bits used to range check the gap between the threshold and the distance, so a
+// threshold proof only holds for thresholds below 2^THRESHOLD_BITS
+const THRESHOLD_BITS: usize = 16;
+pub const MAX_THRESHOLD: u64 = (1 << THRESHOLD_BITS) - 1;
+
+// Rows of the instance column: the distance (or the threshold it is below) followed by the
+// commitments to each vector
+const DISTANCE_ROW: usize = 0;
+const COMMITMENT_A_ROW: usize = 1;
+const COMMITMENT_B_ROW: usize = 2;
+const PUBLIC_INPUTS: usize = 3;
+
+// The row of the masked circuit's instance column which holds the number of bits valid in both
+// masks, after the rows it shares with the distance circuit
+const VALID_ROW: usize = 3;
+const MASKED_PUBLIC_INPUTS: usize = 4;
+
+// The most candidates a batch proof compares the probe with
+pub const MAX_CANDIDATES: usize = 64;
+
+// The most positions a rotation proof shifts the second vector by in each direction
+pub const MAX_SHIFTS: usize = 32;
+
+// The largest weight of a bit in a weighted proof, which keeps the weighted distance of the longest
+// vectors below 2^28
+pub const MAX_WEIGHT: u64 = u16::MAX as u64;
+
+// The number of bits of the numerator and the denominator of a similarity threshold
+const RATIO_BITS: usize = 16;
+pub const MAX_RATIO: u64 = (1 << RATIO_BITS) - 1;
+
+// The number of bits used to range check the gap between the two sides of the similarity
+// inequality. Both sides are below 2^29 for the longest vectors and the largest ratios, so a side
+// that is smaller than the other makes the gap wrap around to a field element that does not fit.
+const SIMILARITY_BITS: usize = 32;
 
-// The length of our binary inputs
-const BINARY_LENGTH: usize = 32;
+// The row of the similarity circuit's instance column which holds the denominator of the
+// threshold, whose numerator is in the distance row
+const DENOMINATOR_ROW: usize = 3;
+const SIMILARITY_PUBLIC_INPUTS: usize = 4;
+
+// The number of bits of each element of the integer vectors of the inner-product and embedding
+// circuits, whose range is checked with a lookup table holding every value up to MAX_ELEMENT
+const ELEMENT_BITS: usize = 8;
+pub const MAX_ELEMENT: u64 = (1 << ELEMENT_BITS) - 1;
+
+// The number of elements packed into each word that is committed to
+const ELEMENTS_PER_WORD: usize = WORD_LENGTH / ELEMENT_BITS;
+
+// The number of bits of the threshold of the embedding circuits. The squared L2 distance of two
+// vectors of MAX_LENGTH elements is below 2^28, so every distance has a threshold that fits.
+const EMBEDDING_THRESHOLD_BITS: usize = 32;
+pub const MAX_EMBEDDING_THRESHOLD: u64 = (1 << EMBEDDING_THRESHOLD_BITS) - 1;
+
+// The number of bits of each vector compared on a row of the popcount region. The lookup table of
+// the popcount circuit holds every pair of nibbles with the popcount of their XOR, which takes as
+// many rows as a table of bytes and their popcounts, where a table of pairs of bytes would need
+// 2^16 rows.
+const NIBBLE_BITS: usize = 4;
+const NIBBLE_VALUES: u64 = 1 << NIBBLE_BITS;
+
+// Rows of the instance column of a batch proof: the commitment to the probe followed by the
+// distance to and the commitment to each candidate
+const PROBE_COMMITMENT_ROW: usize = 0;
+
+// The row of the membership circuit's instance column which holds the root of the registry, in
+// place of the commitment to the enrolled template the probe is compared with
+const ROOT_ROW: usize = 2;
+
+// Session proofs expose the session nonce, the context id and the nullifier of vector B in that
+// context after the distance and the commitments to the two vectors
+const NONCE_ROW: usize = 3;
+const CONTEXT_ROW: usize = 4;
+const NULLIFIER_ROW: usize = 5;
+const SESSION_PUBLIC_INPUTS: usize = 6;
+
+fn batch_distance_row(candidate: usize) -> usize {
+    1 + 2 * candidate
+}
+
+fn batch_commitment_row(candidate: usize) -> usize {
+    2 + 2 * candidate
+}
 
 // Traits for the chip
 trait Instructions<F: Field>: Chip<F> {
     type Num;
 
-    // Loads private inputs into two advice columns and checks if the digits are binary values
-    fn load_private_and_check_binary(
+    // Loads the packed words of both vectors, decomposes them into bits which are checked to be
+    // binary, and sums the XOR of the bits. Returns the words of each vector and the distance.
+    fn hamming_distance(
+        &self,
+        layouter: impl Layouter<F>,
+        a: &[Value<F>],
+        b: &[Value<F>],
+        length: usize,
+    ) -> Result<HammingDistance<Self::Num>, Error>;
+
+    // Like `hamming_distance`, but only counts the bits where both masks are 1. Also returns the
+    // words of each mask and the number of bits valid in both masks.
+    #[allow(clippy::too_many_arguments)]
+    fn masked_hamming_distance(
+        &self,
+        layouter: impl Layouter<F>,
+        mask: &MaskConfig,
+        a: &[Value<F>],
+        b: &[Value<F>],
+        mask_a: &[Value<F>],
+        mask_b: &[Value<F>],
+        length: usize,
+    ) -> Result<MaskedHammingDistance<Self::Num>, Error>;
+
+    // Like `hamming_distance`, but multiplies the XOR of each pair of bits by the weight of its
+    // position before adding it to the distance. The weights are fixed when the keys are generated.
+    fn weighted_hamming_distance(
+        &self,
+        layouter: impl Layouter<F>,
+        weight: &WeightConfig,
+        a: &[Value<F>],
+        b: &[Value<F>],
+        weights: &[F],
+        length: usize,
+    ) -> Result<HammingDistance<Self::Num>, Error>;
+
+    // Compares the first vector with the bits of the second vector of another distance region,
+    // rotated by the given number of positions. The rotated bits are copies of the given cells, so
+    // the second vector is not witnessed again.
+    fn rotated_hamming_distance(
+        &self,
+        layouter: impl Layouter<F>,
+        a: &[Value<F>],
+        bits_b: &[Self::Num],
+        shift: isize,
+        length: usize,
+    ) -> Result<HammingDistance<Self::Num>, Error>;
+
+    // Loads the packed words of two integer vectors, decomposes them into elements whose range is
+    // checked with the lookup table, and sums the products of the elements. Returns the words of
+    // each vector and the inner product.
+    fn inner_product(
+        &self,
+        layouter: impl Layouter<F>,
+        elements: &ElementConfig,
+        s_inner_product: Selector,
+        a: &[Value<F>],
+        b: &[Value<F>],
+        length: usize,
+    ) -> Result<ElementSum<Self::Num>, Error>;
+
+    // Loads the packed words of both vectors like `hamming_distance`, but decomposes them into
+    // nibbles instead of bits and looks up the popcount of the XOR of each pair of nibbles in a
+    // table. Returns the words of each vector and the distance.
+    fn popcount_distance(
+        &self,
+        layouter: impl Layouter<F>,
+        popcount: &PopcountConfig,
+        a: &[Value<F>],
+        b: &[Value<F>],
+        length: usize,
+    ) -> Result<ElementSum<Self::Num>, Error>;
+
+    // Lays out two integer vectors like `inner_product`, but sums the absolute differences of the
+    // elements. Returns the words of each vector and the L1 distance.
+    fn l1_distance(
+        &self,
+        layouter: impl Layouter<F>,
+        elements: &ElementConfig,
+        embedding: &EmbeddingConfig,
+        a: &[Value<F>],
+        b: &[Value<F>],
+        length: usize,
+    ) -> Result<ElementSum<Self::Num>, Error>;
+
+    // Lays out two integer vectors like `inner_product`, but sums the squared differences of the
+    // elements. Returns the words of each vector and the squared L2 distance.
+    fn squared_l2_distance(
         &self,
         layouter: impl Layouter<F>,
-        column: usize,
-        value: [Value<F>; BINARY_LENGTH],
-    ) -> Result<Vec<Self::Num>, Error>;
+        elements: &ElementConfig,
+        embedding: &EmbeddingConfig,
+        a: &[Value<F>],
+        b: &[Value<F>],
+        length: usize,
+    ) -> Result<ElementSum<Self::Num>, Error>;
 
-    // Performs and XOR operation between two field elements
-    fn xor(
+    // Counts the positions where both bits are 1, copying the bits from the cells of a distance
+    // region
+    fn and(
         &self,
         layouter: impl Layouter<F>,
-        a: Self::Num,
-        b: Self::Num,
+        counts: &CountConfig,
+        a: &[Self::Num],
+        b: &[Self::Num],
     ) -> Result<Self::Num, Error>;
 
-    // Accumulates the column of XOR results into a single number
+    // Counts the positions where either bit is 1, copying the bits like `and`
+    fn or(
+        &self,
+        layouter: impl Layouter<F>,
+        counts: &CountConfig,
+        a: &[Self::Num],
+        b: &[Self::Num],
+    ) -> Result<Self::Num, Error>;
+
+    // Loads a single private value, such as the salt of a commitment
+    fn load_private(&self, layouter: impl Layouter<F>, value: Value<F>)
+    -> Result<Self::Num, Error>;
+
+    // Loads a private value and checks that it fits in the given number of bits
+    fn range_check(
+        &self,
+        layouter: impl Layouter<F>,
+        value: Value<F>,
+        num_bits: usize,
+    ) -> Result<Self::Num, Error>;
+
+    // Accumulates a list of values into their sum
     fn accumulator(
         &self,
         layouter: impl Layouter<F>,
-        values: [Self::Num; BINARY_LENGTH],
+        values: &[Self::Num],
+    ) -> Result<Self::Num, Error>;
+
+    // Multiplies a list of values together
+    fn product(
+        &self,
+        layouter: impl Layouter<F>,
+        s_product: Selector,
+        values: &[Self::Num],
+    ) -> Result<Self::Num, Error>;
+
+    // Constrains each value to equal the value at the same position of the other list
+    fn constrain_equal(
+        &self,
+        layouter: impl Layouter<F>,
+        a: &[Self::Num],
+        b: &[Self::Num],
+    ) -> Result<(), Error>;
+
+    // Constrains a value to equal a constant
+    fn constrain_constant(
+        &self,
+        layouter: impl Layouter<F>,
+        num: Self::Num,
+        constant: F,
+    ) -> Result<(), Error>;
+
+    // Commits to a binary vector by hashing the salt together with its packed words
+    fn commit(
+        &self,
+        layouter: impl Layouter<F>,
+        salt: Self::Num,
+        words: &[Self::Num],
+    ) -> Result<Self::Num, Error>;
+
+    // Hashes a leaf up a Merkle path given by the sibling and the position bit at each level, from
+    // the leaf up, and returns the root
+    fn merkle_root(
+        &self,
+        layouter: impl Layouter<F>,
+        s_swap: Selector,
+        leaf: Self::Num,
+        siblings: &[Value<F>],
+        positions: &[Value<F>],
+    ) -> Result<Self::Num, Error>;
+
+    // Derives the nullifier of a vector in a context by hashing the nullifier secret committed to
+    // with the vector together with its packed words and the context id
+    fn nullifier(
+        &self,
+        layouter: impl Layouter<F>,
+        secret: Self::Num,
+        words: &[Self::Num],
+        context: Self::Num,
     ) -> Result<Self::Num, Error>;
 
-    // Expose output of accumulated XORs as a public value
-    fn expose_public(&self, layouter: impl Layouter<F>, num: Self::Num) -> Result<(), Error>;
+    // Expose a value as a public input at the given row of the instance column
+    fn expose_public(
+        &self,
+        layouter: impl Layouter<F>,
+        num: Self::Num,
+        row: usize,
+    ) -> Result<(), Error>;
+}
+
+// The cells produced by the distance region: the packed words of each vector, which are
+// committed to, the bits of each vector, which rotated comparisons and bit counts copy, and the
+// Hamming distance between them
+struct HammingDistance<N> {
+    words_a: Vec<N>,
+    words_b: Vec<N>,
+    bits_a: Vec<N>,
+    bits_b: Vec<N>,
+    distance: N,
+}
+
+// The cells produced by an element region: the packed words of each vector, which are committed
+// to, and the sum over the pairs of elements, such as the inner product of the vectors
+struct ElementSum<N> {
+    words_a: Vec<N>,
+    words_b: Vec<N>,
+    sum: N,
+}
+
+// The cells produced by the masked distance region: the packed words of each vector and mask, the
+// Hamming distance over the bits valid in both masks and the number of those bits
+struct MaskedHammingDistance<N> {
+    words_a: Vec<N>,
+    words_b: Vec<N>,
+    words_mask_a: Vec<N>,
+    words_mask_b: Vec<N>,
+    distance: N,
+    valid: N,
 }
 
 // The chip which holds the circuit config
@@ -66,23 +377,159 @@ impl<F: Field> Chip<F> for BitVeilChip<F> {
     }
 }
 
-// The configuration of the circuit
+// The configuration of the circuit which compares two vectors, which every other circuit shares
 #[derive(Debug, Clone)]
 pub struct BitVeilConfig {
-    // We have 3 advice columns: one for each input, and one to accumulate the XOR results.
-    advice: [Column<Advice>; 3],
+    // We have 5 advice columns, which all hold one row per bit of the vectors: the bits of each
+    // input, the running sums packing the bits of each input into words, and the running sum of
+    // the XOR results which accumulates the distance.
+    advice: [Column<Advice>; 5],
 
-    // WE use 1 instance column for the public input
+    // WE use 1 instance column for the public inputs: the distance and the two commitments
     instance: Column<Instance>,
 
     // Selectors for choosing which operation to run at each row
-    s_binary_l: Selector,
-    s_binary_r: Selector,
-    s_xor: Selector,
+    s_distance: Selector,
+    s_word_start: Selector,
+    s_word: Selector,
+    s_binary: Selector,
+    s_pack: Selector,
     s_accumulator: Selector,
+
+    // The hash chip used for the commitments, which shares our advice columns
+    poseidon: PoseidonConfig,
+}
+
+// The configuration the masked circuit adds. Its 6 advice columns sit next to the distance region
+// with one row per bit: the bits of each mask, the running sums packing them into words, the
+// running sum of the XOR results where both masks are set, and the running sum of the bits where
+// both masks are set.
+#[derive(Debug, Clone)]
+pub struct MaskConfig {
+    advice: [Column<Advice>; 6],
+    s_masked: Selector,
+}
+
+// The configuration the weighted circuit adds: a fixed column holding the weight of each row of the
+// distance region, so the weights are part of the verifying key, and an advice column holding the
+// running sum of the weighted XOR results
+#[derive(Debug, Clone)]
+pub struct WeightConfig {
+    weight: Column<Fixed>,
+    advice: Column<Advice>,
+    s_weighted: Selector,
+}
+
+// The configuration the circuits over integer vectors add: a table column holding every element
+// value, which the elements on each row of an element region are looked up in, and the selectors of
+// the gates packing the elements into words. The region reuses the five advice columns, with
+// elements in place of bits and the sum over the pairs of elements in the last column.
+#[derive(Debug, Clone)]
+pub struct ElementConfig {
+    table: TableColumn,
+    s_element: Selector,
+    s_element_start: Selector,
+    s_element_word: Selector,
+}
+
+// The configuration the embedding circuits add: an advice column holding the absolute difference
+// of the elements on each row, which the L1 distance sums, and the selectors of the gates summing
+// the absolute and the squared differences
+#[derive(Debug, Clone)]
+pub struct EmbeddingConfig {
+    difference: Column<Advice>,
+    s_absolute: Selector,
+    s_squared: Selector,
+}
+
+// The configuration the popcount circuit adds: a lookup table holding every pair of nibbles with
+// the popcount of their XOR, which the nibbles and the distance added on each row of the popcount
+// region are looked up in, and the selectors of the gates packing the nibbles into words. The
+// region reuses the five advice columns, with nibbles in place of bits.
+#[derive(Debug, Clone)]
+pub struct PopcountConfig {
+    table: [TableColumn; 3],
+    s_popcount: Selector,
+    s_nibble_start: Selector,
+    s_nibble_word: Selector,
+}
+
+// The selectors the similarity circuits add, of the gates counting the AND and the OR of the bits
+#[derive(Debug, Clone)]
+pub struct CountConfig {
+    s_and: Selector,
+    s_or: Selector,
+}
+
+// The configurations of the circuits which add columns or gates to the ones every circuit shares.
+// Each holds the shared configuration and the parts its circuit adds, so the chip instructions
+// which lay out those parts are given them instead of looking them up.
+
+// The masked circuit adds the mask columns and their gates
+#[derive(Debug, Clone)]
+pub struct BitVeilMaskedConfig {
+    base: BitVeilConfig,
+    mask: MaskConfig,
+}
+
+// The weighted circuit adds the weight column and the weighted accumulator
+#[derive(Debug, Clone)]
+pub struct BitVeilWeightedConfig {
+    base: BitVeilConfig,
+    weight: WeightConfig,
+}
+
+// The rotation circuits add the product gate, which multiplies the gaps from the minimum
+#[derive(Debug, Clone)]
+pub struct BitVeilRotationConfig {
+    base: BitVeilConfig,
+    s_product: Selector,
+}
+
+// The similarity circuits add the product gate and the counting gates
+#[derive(Debug, Clone)]
+pub struct BitVeilSimilarityConfig {
+    base: BitVeilConfig,
+    s_product: Selector,
+    counts: CountConfig,
+}
+
+// The inner-product circuit adds the element region and the product accumulator
+#[derive(Debug, Clone)]
+pub struct BitVeilInnerProductConfig {
+    base: BitVeilConfig,
+    elements: ElementConfig,
+    s_inner_product: Selector,
+}
+
+// The embedding circuits add the element region and the difference gates
+#[derive(Debug, Clone)]
+pub struct BitVeilEmbeddingConfig {
+    base: BitVeilConfig,
+    elements: ElementConfig,
+    embedding: EmbeddingConfig,
+}
+
+// The popcount circuit adds the popcount table and the nibble gates
+#[derive(Debug, Clone)]
+pub struct BitVeilPopcountConfig {
+    base: BitVeilConfig,
+    popcount: PopcountConfig,
 }
 
-impl<F: Field> BitVeilChip<F> {
+// The membership circuit adds the swap gate of the Merkle path
+#[derive(Debug, Clone)]
+pub struct BitVeilMembershipConfig {
+    base: BitVeilConfig,
+    s_swap: Selector,
+}
+
+// The XOR of two bits, defined as (a + b - 2ab)
+fn xor<F: Field>(a: Expression<F>, b: Expression<F>) -> Expression<F> {
+    a.clone() + b.clone() - Expression::Constant(F::ONE + F::ONE) * a * b
+}
+
+impl<F: FromUniformBytes<64>> BitVeilChip<F> {
     fn construct(config: <Self as Chip<F>>::Config) -> Self {
         Self {
             config,
@@ -92,8 +539,9 @@ impl<F: Field> BitVeilChip<F> {
 
     fn configure(
         meta: &mut ConstraintSystem<F>,
-        advice: [Column<Advice>; 3],
+        advice: [Column<Advice>; 5],
         instance: Column<Instance>,
+        fixed: [Column<Fixed>; 4],
     ) -> <Self as Chip<F>>::Config {
         // Enable checking of equality for each of the columns
         meta.enable_equality(instance);
@@ -101,386 +549,7124 @@ impl<F: Field> BitVeilChip<F> {
             meta.enable_equality(*column);
         }
 
+        // The last fixed column holds constants, such as the starting value of the running sums
+        meta.enable_constant(fixed[3]);
+
         // The selectors we'll be using in the circuit
-        let s_binary_l = meta.selector();
-        let s_binary_r = meta.selector();
-        let s_xor = meta.selector();
+        let s_distance = meta.selector();
+        let s_word_start = meta.selector();
+        let s_word = meta.selector();
+        let s_binary = meta.selector();
+        let s_pack = meta.selector();
         let s_accumulator = meta.selector();
 
-        // Gate that checks that the value in the first column's cell is 0 or 1
-        meta.create_gate("is binary left", |meta| {
-            let value = meta.query_advice(advice[0], Rotation::cur());
-            let s_binary_l = meta.query_selector(s_binary_l);
+        // Gates that check that the bits of both vectors on each row of the distance region are 0
+        // or 1
+        meta.create_gate("is binary a", |meta| {
+            let a = meta.query_advice(advice[0], Rotation::cur());
+            let s_distance = meta.query_selector(s_distance);
+
+            vec![s_distance * (a.clone() * (Expression::Constant(F::ONE) - a))]
+        });
+
+        meta.create_gate("is binary b", |meta| {
+            let b = meta.query_advice(advice[1], Rotation::cur());
+            let s_distance = meta.query_selector(s_distance);
+
+            vec![s_distance * (b.clone() * (Expression::Constant(F::ONE) - b))]
+        });
 
-            vec![s_binary_l * (value.clone() * (Expression::Constant(F::ONE) - value))]
+        // This gate adds the XOR of the bits on each row to the running sum in the last column, so
+        // the sum on the row after the last bit is the Hamming distance
+        meta.create_gate("xor accumulator", |meta| {
+            let a = meta.query_advice(advice[0], Rotation::cur());
+            let b = meta.query_advice(advice[1], Rotation::cur());
+            let acc = meta.query_advice(advice[4], Rotation::cur());
+            let acc_next = meta.query_advice(advice[4], Rotation::next());
+            let s_distance = meta.query_selector(s_distance);
+
+            vec![s_distance * (acc + xor(a, b) - acc_next)]
         });
 
-        // Gate that checks that the value in the second column's cell is 0 or 1
-        meta.create_gate("is binary right", |meta| {
-            let value = meta.query_advice(advice[1], Rotation::cur());
-            let s_binary_r = meta.query_selector(s_binary_r);
+        // These gates pack the bits of each vector into words, most significant bit first. The
+        // sums start at the first bit of each word and are doubled before adding each following
+        // bit, so the sums on the last row of a word are the packed words that are committed to.
+        meta.create_gate("word start", |meta| {
+            let a = meta.query_advice(advice[0], Rotation::cur());
+            let b = meta.query_advice(advice[1], Rotation::cur());
+            let sum_a = meta.query_advice(advice[2], Rotation::cur());
+            let sum_b = meta.query_advice(advice[3], Rotation::cur());
+            let s_word_start = meta.query_selector(s_word_start);
 
-            vec![s_binary_r * (value.clone() * (Expression::Constant(F::ONE) - value))]
+            vec![
+                s_word_start.clone() * (sum_a - a),
+                s_word_start * (sum_b - b),
+            ]
         });
 
-        // This gate performs and XOR operation between two cells and outputs the the result to a third cell
-        meta.create_gate("xor", |meta| {
-            let lhs = meta.query_advice(advice[0], Rotation::cur());
-            let rhs = meta.query_advice(advice[1], Rotation::cur());
-            let out = meta.query_advice(advice[2], Rotation::cur());
-            let s_xor = meta.query_selector(s_xor);
+        meta.create_gate("pack word", |meta| {
+            let a = meta.query_advice(advice[0], Rotation::cur());
+            let b = meta.query_advice(advice[1], Rotation::cur());
+            let sum_a_prev = meta.query_advice(advice[2], Rotation::prev());
+            let sum_b_prev = meta.query_advice(advice[3], Rotation::prev());
+            let sum_a = meta.query_advice(advice[2], Rotation::cur());
+            let sum_b = meta.query_advice(advice[3], Rotation::cur());
+            let s_word = meta.query_selector(s_word);
 
-            // The XOR constraint is defined as (a + b - 2ab - out) == 0
+            let two = Expression::Constant(F::ONE + F::ONE);
             vec![
-                s_xor
-                    * (lhs.clone() + rhs.clone()
-                        - Expression::Constant(F::ONE + F::ONE) * lhs * rhs
-                        - out),
+                s_word.clone() * (two.clone() * sum_a_prev + a - sum_a),
+                s_word * (two * sum_b_prev + b - sum_b),
             ]
         });
 
-        // This gate accumulates all of the values from the column of results of the XOR gate above it
+        // Gate that checks that the value in the first column's cell is 0 or 1, used by the range
+        // check
+        meta.create_gate("is binary", |meta| {
+            let value = meta.query_advice(advice[0], Rotation::cur());
+            let s_binary = meta.query_selector(s_binary);
+
+            vec![s_binary * (value.clone() * (Expression::Constant(F::ONE) - value))]
+        });
+
+        // This gate packs the bits of the first column into a running sum in the second column,
+        // doubling the sum and adding the next bit on each row
+        meta.create_gate("pack", |meta| {
+            let bit = meta.query_advice(advice[0], Rotation::cur());
+            let sum = meta.query_advice(advice[1], Rotation::cur());
+            let sum_next = meta.query_advice(advice[1], Rotation::next());
+            let s_pack = meta.query_selector(s_pack);
+
+            vec![s_pack * (Expression::Constant(F::ONE + F::ONE) * sum + bit - sum_next)]
+        });
+
+        // This gate adds the value in the first column to the running sum in the second column, so
+        // it only queries two cells however many values are accumulated
         meta.create_gate("accumulator", |meta| {
-            let inputs_sum = (0..BINARY_LENGTH)
-                .map(|i| {
-                    meta.query_advice(advice[2], Rotation((i as i32) - (BINARY_LENGTH as i32)))
-                })
-                .fold(Expression::Constant(F::ZERO), |acc, e| acc + e);
-            let sum = meta.query_advice(advice[2], Rotation::cur());
+            let value = meta.query_advice(advice[0], Rotation::cur());
+            let sum = meta.query_advice(advice[1], Rotation::cur());
+            let sum_next = meta.query_advice(advice[1], Rotation::next());
             let s_accumulator = meta.query_selector(s_accumulator);
 
-            vec![s_accumulator * (inputs_sum - sum)]
+            vec![s_accumulator * (sum + value - sum_next)]
         });
 
+        let poseidon = PoseidonChip::configure(
+            meta,
+            [advice[0], advice[1], advice[2]],
+            [fixed[0], fixed[1], fixed[2]],
+        );
+
         BitVeilConfig {
             advice,
             instance,
-            s_binary_l,
-            s_binary_r,
-            s_xor,
+            s_distance,
+            s_word_start,
+            s_word,
+            s_binary,
+            s_pack,
             s_accumulator,
+            poseidon,
         }
     }
-}
 
-// This struct represents a number in the circuit, which wraps a cell
-#[derive(Clone, Debug)]
-struct Number<F: Field>(AssignedCell<F, F>);
+    // Adds the product gate of the rotation and similarity circuits to a configuration, which
+    // multiplies values like the accumulator gate adds them
+    fn configure_product(meta: &mut ConstraintSystem<F>, config: &BitVeilConfig) -> Selector {
+        let s_product = meta.selector();
 
-// Implement all of the chip traits. In this section, we'll be describing how Layouter will assign values to
-// various cells in the circuit.
-impl<F: Field> Instructions<F> for BitVeilChip<F> {
-    type Num = Number<F>;
+        meta.create_gate("product", |meta| {
+            let value = meta.query_advice(config.advice[0], Rotation::cur());
+            let product = meta.query_advice(config.advice[1], Rotation::cur());
+            let product_next = meta.query_advice(config.advice[1], Rotation::next());
+            let s_product = meta.query_selector(s_product);
 
-    // Loads private inputs into two advice columns and checks if the digits are binary values
-    fn load_private_and_check_binary(
-        &self,
-        mut layouter: impl Layouter<F>,
-        column: usize,
-        values: [Value<F>; BINARY_LENGTH],
-    ) -> Result<Vec<Self::Num>, Error> {
-        let config = self.config();
+            vec![s_product * (product * value - product_next)]
+        });
 
-        layouter.assign_region(
-            || "assign private values",
-            |mut region| {
-                values
-                    .iter()
-                    .enumerate()
-                    .map(|(i, value)| {
-                        // Check that each cell of the input is a binary value
-                        if column == 0 {
-                            config.s_binary_l.enable(&mut region, i)?;
-                        } else {
-                            config.s_binary_r.enable(&mut region, i)?;
-                        }
-                        // Assign the private input value to an advice cell
-                        region
-                            .assign_advice(
-                                || "assign private input",
-                                config.advice[column],
-                                i,
-                                || *value,
-                            )
-                            .map(Number)
-                    })
-                    .collect::<Result<Vec<Self::Num>, Error>>()
-            },
-        )
+        s_product
     }
 
-    // Performs and XOR operation between two field elements
-    fn xor(
-        &self,
-        mut layouter: impl Layouter<F>,
-        a: Self::Num,
-        b: Self::Num,
-    ) -> Result<Self::Num, Error> {
-        let config = self.config();
+    // Adds the gates of the similarity circuits to a configuration: the product gate, and gates
+    // that count the AND and the OR of the bits copied into the first two columns with a running
+    // sum in the last column, like the XOR accumulator of the distance region
+    fn configure_similarity(
+        meta: &mut ConstraintSystem<F>,
+        config: BitVeilConfig,
+    ) -> BitVeilSimilarityConfig {
+        let s_product = Self::configure_product(meta, &config);
+        let s_and = meta.selector();
+        let s_or = meta.selector();
 
-        layouter.assign_region(
-            || "assign xor region",
-            |mut region: Region<'_, F>| {
-                config.s_xor.enable(&mut region, 0)?;
+        meta.create_gate("and accumulator", |meta| {
+            let a = meta.query_advice(config.advice[0], Rotation::cur());
+            let b = meta.query_advice(config.advice[1], Rotation::cur());
+            let acc = meta.query_advice(config.advice[4], Rotation::cur());
+            let acc_next = meta.query_advice(config.advice[4], Rotation::next());
+            let s_and = meta.query_selector(s_and);
+
+            vec![s_and * (acc + a * b - acc_next)]
+        });
 
-                // Copy the left and right advice cell values
-                let a_val =
-                    a.0.copy_advice(|| "lhs", &mut region, config.advice[0], 0)?;
-                let b_val =
-                    b.0.copy_advice(|| "rhs", &mut region, config.advice[1], 0)?;
+        // The OR of two bits, defined as (a + b - ab)
+        meta.create_gate("or accumulator", |meta| {
+            let a = meta.query_advice(config.advice[0], Rotation::cur());
+            let b = meta.query_advice(config.advice[1], Rotation::cur());
+            let acc = meta.query_advice(config.advice[4], Rotation::cur());
+            let acc_next = meta.query_advice(config.advice[4], Rotation::next());
+            let s_or = meta.query_selector(s_or);
 
-                // Calculate the XOR result using arithmetic to match the gate
-                let xor_result = a_val
-                    .value()
-                    .and_then(|a| b_val.value().map(|b| *a + *b - (F::ONE + F::ONE) * *a * *b));
+            vec![s_or * (acc + a.clone() + b.clone() - a * b - acc_next)]
+        });
 
-                // Assign the result to the third advice cell
-                region
-                    .assign_advice(|| "a xor b", config.advice[2], 0, || xor_result)
-                    .map(Number)
-            },
-        )
+        BitVeilSimilarityConfig {
+            base: config,
+            s_product,
+            counts: CountConfig { s_and, s_or },
+        }
     }
 
-    // Accumulates the column of XOR results into a single number
-    fn accumulator(
-        &self,
-        mut layouter: impl Layouter<F>,
-        values: [Self::Num; BINARY_LENGTH],
-    ) -> Result<Self::Num, Error> {
-        let config = self.config();
+    // Adds the lookup table and the packing gates of the circuits over integer vectors to a
+    // configuration. The elements of both vectors are looked up in the table, which checks that
+    // they fit in ELEMENT_BITS bits, so the sums over them cannot wrap around.
+    fn configure_elements(meta: &mut ConstraintSystem<F>, config: &BitVeilConfig) -> ElementConfig {
+        let advice = config.advice;
+        let table = meta.lookup_table_column();
 
-        layouter.assign_region(
-            || "assign accumulator region",
-            |mut region: Region<'_, F>| {
-                config.s_accumulator.enable(&mut region, BINARY_LENGTH)?;
+        // The selector of the lookups has to be a complex selector. Rows where it is disabled look
+        // up zero, which is in the table.
+        let s_element = meta.complex_selector();
+        let s_element_start = meta.selector();
+        let s_element_word = meta.selector();
 
-                // Copy the result of the XOR values to the advice cells in the third column
-                for (i, value) in values.iter().enumerate() {
-                    value.0.copy_advice(
-                        || format!("output[{}]", i),
-                        &mut region,
-                        config.advice[2],
-                        i,
-                    )?;
-                }
+        meta.lookup(|meta| {
+            let a = meta.query_advice(advice[0], Rotation::cur());
+            let s_element = meta.query_selector(s_element);
 
-                // Calculate the accumulation of the XOR column results
-                let accumulation = values
-                    .iter()
-                    .map(|n| n.0.value())
-                    .fold(Value::known(F::ZERO), |acc, e| acc + e);
+            vec![(s_element * a, table)]
+        });
 
-                // Assign the accumulation result to an advice cell
-                region
-                    .assign_advice(
-                        || "accumulation result",
-                        config.advice[2],
-                        BINARY_LENGTH,
-                        || accumulation,
-                    )
-                    .map(Number)
-            },
-        )
-    }
+        meta.lookup(|meta| {
+            let b = meta.query_advice(advice[1], Rotation::cur());
+            let s_element = meta.query_selector(s_element);
 
-    // Expose output of accumulated XORs as a public value. Constrain the accumulation value from
-    // (column advice[2], row BINARY_LENGTH) to equal instance column value in row 0 which is the
-    // public input of the Hamming distance calculated outside the circuit
-    fn expose_public(&self, mut layouter: impl Layouter<F>, num: Self::Num) -> Result<(), Error> {
-        let config = self.config();
-        layouter.constrain_instance(num.0.cell(), config.instance, 0)
-    }
-}
+            vec![(s_element * b, table)]
+        });
 
-#[derive(Default, Clone)]
-pub struct BitVeilCircuit<F: Field> {
-    a: [Value<F>; BINARY_LENGTH],
-    b: [Value<F>; BINARY_LENGTH],
-}
+        // These gates pack the elements into words like the word gates pack bits, shifting the sum
+        // by ELEMENT_BITS bits before adding each following element
+        meta.create_gate("element word start", |meta| {
+            let a = meta.query_advice(advice[0], Rotation::cur());
+            let b = meta.query_advice(advice[1], Rotation::cur());
+            let sum_a = meta.query_advice(advice[2], Rotation::cur());
+            let sum_b = meta.query_advice(advice[3], Rotation::cur());
+            let s_element_start = meta.query_selector(s_element_start);
 
-impl<F: Field> Circuit<F> for BitVeilCircuit<F> {
-    type Config = BitVeilConfig;
-    type FloorPlanner = SimpleFloorPlanner;
+            vec![
+                s_element_start.clone() * (sum_a - a),
+                s_element_start * (sum_b - b),
+            ]
+        });
 
-    fn without_witnesses(&self) -> Self {
-        // Just outputs the default circuit if calling without witnesses
-        Self::default()
+        meta.create_gate("pack element word", |meta| {
+            let a = meta.query_advice(advice[0], Rotation::cur());
+            let b = meta.query_advice(advice[1], Rotation::cur());
+            let sum_a_prev = meta.query_advice(advice[2], Rotation::prev());
+            let sum_b_prev = meta.query_advice(advice[3], Rotation::prev());
+            let sum_a = meta.query_advice(advice[2], Rotation::cur());
+            let sum_b = meta.query_advice(advice[3], Rotation::cur());
+            let s_element_word = meta.query_selector(s_element_word);
+
+            let shift = Expression::Constant(F::from(1 << ELEMENT_BITS));
+            vec![
+                s_element_word.clone() * (shift.clone() * sum_a_prev + a - sum_a),
+                s_element_word * (shift * sum_b_prev + b - sum_b),
+            ]
+        });
+
+        ElementConfig {
+            table,
+            s_element,
+            s_element_start,
+            s_element_word,
+        }
     }
 
-    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
-        // Our configuration will include 3 advice columns (two for inputs, one for the output of the XOR operation)
-        let advice = [
-            meta.advice_column(),
-            meta.advice_column(),
-            meta.advice_column(),
-        ];
+    // Adds the product accumulator gate of the inner-product circuit to a configuration with the
+    // lookup table and the packing gates of the element region
+    fn configure_inner_product(
+        meta: &mut ConstraintSystem<F>,
+        config: BitVeilConfig,
+        elements: ElementConfig,
+    ) -> BitVeilInnerProductConfig {
+        let advice = config.advice;
+        let s_inner_product = meta.selector();
 
-        // We'll also have one instance column for the public input (calculated Hamming distance)
-        let instance = meta.instance_column();
+        // This gate adds the product of the elements on each row to the running sum in the last
+        // column, so the sum on the row after the last element is the inner product
+        meta.create_gate("product accumulator", |meta| {
+            let a = meta.query_advice(advice[0], Rotation::cur());
+            let b = meta.query_advice(advice[1], Rotation::cur());
+            let acc = meta.query_advice(advice[4], Rotation::cur());
+            let acc_next = meta.query_advice(advice[4], Rotation::next());
+            let s_inner_product = meta.query_selector(s_inner_product);
+
+            vec![s_inner_product * (acc + a * b - acc_next)]
+        });
 
-        BitVeilChip::configure(meta, advice, instance)
+        BitVeilInnerProductConfig {
+            base: config,
+            elements,
+            s_inner_product,
+        }
     }
 
-    fn synthesize(
-        &self,
-        config: Self::Config,
-        mut layouter: impl Layouter<F>,
-    ) -> Result<(), Error> {
-        let bitveil_chip = BitVeilChip::<F>::construct(config);
+    // Adds the gates summing the absolute and the squared differences of the elements to a
+    // configuration with the lookup table and the packing gates of the element region, whose table
+    // the absolute differences are looked up in. Both embedding circuits configure both gates, and
+    // only enable the one of their metric.
+    fn configure_embedding(
+        meta: &mut ConstraintSystem<F>,
+        config: BitVeilConfig,
+        elements: ElementConfig,
+    ) -> BitVeilEmbeddingConfig {
+        let advice = config.advice;
+        let table = elements.table;
 
-        // Load private variable vectors & check if each digit is binary
-        let a = bitveil_chip.load_private_and_check_binary(
-            layouter.namespace(|| "load a"),
-            0,
-            self.a,
-        )?;
-        let b = bitveil_chip.load_private_and_check_binary(
-            layouter.namespace(|| "load b"),
-            1,
-            self.b,
-        )?;
+        let difference = meta.advice_column();
+        meta.enable_equality(difference);
 
-        // Perform XOR on each row
-        let xor_results: Vec<Number<F>> = (0..BINARY_LENGTH)
-            .map(|i| {
-                bitveil_chip.xor(
-                    layouter.namespace(|| format!("xor[{}]", i)),
-                    a[i].clone(),
-                    b[i].clone(),
-                )
-            })
-            .collect::<Result<Vec<Number<F>>, Error>>()?;
-        let xor_slice: [Number<F>; BINARY_LENGTH] = xor_results.try_into().unwrap();
+        // The absolute difference is looked up in the table too, so it is a complex selector
+        let s_absolute = meta.complex_selector();
+        let s_squared = meta.selector();
 
-        // Accumulate the results of the XOR output column
-        let accumulate =
-            bitveil_chip.accumulator(layouter.namespace(|| "accumulate xor results"), xor_slice)?;
+        meta.lookup(|meta| {
+            let difference = meta.query_advice(difference, Rotation::cur());
+            let s_absolute = meta.query_selector(s_absolute);
 
-        // Ensure the accumulated value equals the public input (of the precalculated accumulation value)
-        bitveil_chip.expose_public(layouter.namespace(|| "expose accumulate"), accumulate)
-    }
-}
+            vec![(s_absolute * difference, table)]
+        });
 
-// Draws the layout of the circuit. Super useful for debugging.
-#[cfg(not(target_family = "wasm"))]
-pub fn draw_circuit<F: Field>(k: u32, circuit: &BitVeilCircuit<F>) {
-    use plotters::prelude::*;
-    let base = BitMapBackend::new("layout.png", (1600, 1600)).into_drawing_area();
-    base.fill(&WHITE).unwrap();
-    let base = base.titled("BitVeil Circuit", ("sans-serif", 24)).unwrap();
+        // The difference column holds a - b or b - a, since its square is the square of a - b.
+        // Only the one which is not negative is in the table, so it holds |a - b|, which this gate
+        // adds to the running sum in the last column.
+        meta.create_gate("absolute difference accumulator", |meta| {
+            let a = meta.query_advice(advice[0], Rotation::cur());
+            let b = meta.query_advice(advice[1], Rotation::cur());
+            let d = meta.query_advice(difference, Rotation::cur());
+            let acc = meta.query_advice(advice[4], Rotation::cur());
+            let acc_next = meta.query_advice(advice[4], Rotation::next());
+            let s_absolute = meta.query_selector(s_absolute);
 
-    halo2_proofs::dev::CircuitLayout::default()
-        .show_equality_constraints(true)
-        .render(k, circuit, &base)
-        .unwrap();
-}
+            let diff = a - b;
+            vec![
+                s_absolute.clone() * (d.clone() * d.clone() - diff.clone() * diff),
+                s_absolute * (acc + d - acc_next),
+            ]
+        });
 
-// Generates an empty circuit. Useful for generating the proving/verifying keys.
-pub fn empty_circuit() -> BitVeilCircuit<Fp> {
-    BitVeilCircuit {
-        a: [Value::unknown(); BINARY_LENGTH],
-        b: [Value::unknown(); BINARY_LENGTH],
+        // This gate adds the square of the difference of the elements on each row to the running
+        // sum in the last column
+        meta.create_gate("squared difference accumulator", |meta| {
+            let a = meta.query_advice(advice[0], Rotation::cur());
+            let b = meta.query_advice(advice[1], Rotation::cur());
+            let acc = meta.query_advice(advice[4], Rotation::cur());
+            let acc_next = meta.query_advice(advice[4], Rotation::next());
+            let s_squared = meta.query_selector(s_squared);
+
+            let diff = a - b;
+            vec![s_squared * (acc + diff.clone() * diff - acc_next)]
+        });
+
+        BitVeilEmbeddingConfig {
+            base: config,
+            elements,
+            embedding: EmbeddingConfig {
+                difference,
+                s_absolute,
+                s_squared,
+            },
+        }
     }
-}
 
-// Creates a circuit from two vector inputs
-pub fn create_circuit(a: Vec<u64>, b: Vec<u64>) -> BitVeilCircuit<Fp> {
-    assert_eq!(a.len(), b.len());
-    let len = a.len();
-    assert!(len <= BINARY_LENGTH);
-    let mut a_full = a;
-    a_full.resize(BINARY_LENGTH, 0u64);
-    let mut b_full = b;
-    b_full.resize(BINARY_LENGTH, 0u64);
+    // Adds the lookup table and the packing gates of the popcount circuit to a configuration. Each
+    // row of the popcount region holds a nibble of each vector, and the difference between the
+    // distance on the next row and on this row is looked up together with the nibbles, so it is
+    // the popcount of their XOR. The lookup also checks that the nibbles fit in NIBBLE_BITS bits.
+    fn configure_popcount(
+        meta: &mut ConstraintSystem<F>,
+        config: BitVeilConfig,
+    ) -> BitVeilPopcountConfig {
+        let advice = config.advice;
+        let table = [
+            meta.lookup_table_column(),
+            meta.lookup_table_column(),
+            meta.lookup_table_column(),
+        ];
 
-    // Put inputs into circuit-friendly form
-    let a_vec: [Value<Fp>; BINARY_LENGTH] = a_full
-        .iter()
-        .map(|f| Value::known(Fp::from(*f)))
-        .collect::<Vec<Value<Fp>>>()
-        .try_into()
-        .unwrap();
+        // Rows where the selector is disabled look up two zero nibbles with a popcount of zero,
+        // which is in the table
+        let s_popcount = meta.complex_selector();
+        let s_nibble_start = meta.selector();
+        let s_nibble_word = meta.selector();
 
-    let b_vec: [Value<Fp>; BINARY_LENGTH] = b_full
-        .iter()
-        .map(|f| Value::known(Fp::from(*f)))
-        .collect::<Vec<Value<Fp>>>()
-        .try_into()
-        .unwrap();
+        meta.lookup(|meta| {
+            let a = meta.query_advice(advice[0], Rotation::cur());
+            let b = meta.query_advice(advice[1], Rotation::cur());
+            let acc = meta.query_advice(advice[4], Rotation::cur());
+            let acc_next = meta.query_advice(advice[4], Rotation::next());
+            let s_popcount = meta.query_selector(s_popcount);
 
-    // Create circuit from inputs
-    BitVeilCircuit { a: a_vec, b: b_vec }
+            vec![
+                (s_popcount.clone() * a, table[0]),
+                (s_popcount.clone() * b, table[1]),
+                (s_popcount * (acc_next - acc), table[2]),
+            ]
+        });
+
+        // These gates pack the nibbles into words like the word gates pack bits, shifting the sum
+        // by NIBBLE_BITS bits before adding each following nibble
+        meta.create_gate("nibble word start", |meta| {
+            let a = meta.query_advice(advice[0], Rotation::cur());
+            let b = meta.query_advice(advice[1], Rotation::cur());
+            let sum_a = meta.query_advice(advice[2], Rotation::cur());
+            let sum_b = meta.query_advice(advice[3], Rotation::cur());
+            let s_nibble_start = meta.query_selector(s_nibble_start);
+
+            vec![
+                s_nibble_start.clone() * (sum_a - a),
+                s_nibble_start * (sum_b - b),
+            ]
+        });
+
+        meta.create_gate("pack nibble word", |meta| {
+            let a = meta.query_advice(advice[0], Rotation::cur());
+            let b = meta.query_advice(advice[1], Rotation::cur());
+            let sum_a_prev = meta.query_advice(advice[2], Rotation::prev());
+            let sum_b_prev = meta.query_advice(advice[3], Rotation::prev());
+            let sum_a = meta.query_advice(advice[2], Rotation::cur());
+            let sum_b = meta.query_advice(advice[3], Rotation::cur());
+            let s_nibble_word = meta.query_selector(s_nibble_word);
+
+            let shift = Expression::Constant(F::from(NIBBLE_VALUES));
+            vec![
+                s_nibble_word.clone() * (shift.clone() * sum_a_prev + a - sum_a),
+                s_nibble_word * (shift * sum_b_prev + b - sum_b),
+            ]
+        });
+
+        BitVeilPopcountConfig {
+            base: config,
+            popcount: PopcountConfig {
+                table,
+                s_popcount,
+                s_nibble_start,
+                s_nibble_word,
+            },
+        }
+    }
+
+    // Adds the swap gate of the membership circuit to a configuration. Each level of a Merkle path
+    // takes a row holding the node, its sibling and the position bit of the node, which is 1 when
+    // the node is the right child, followed by the node and the sibling in the order they are
+    // hashed into their parent.
+    fn configure_membership(
+        meta: &mut ConstraintSystem<F>,
+        config: BitVeilConfig,
+    ) -> BitVeilMembershipConfig {
+        let advice = config.advice;
+        let s_swap = meta.selector();
+
+        meta.create_gate("merkle swap", |meta| {
+            let node = meta.query_advice(advice[0], Rotation::cur());
+            let sibling = meta.query_advice(advice[1], Rotation::cur());
+            let position = meta.query_advice(advice[2], Rotation::cur());
+            let left = meta.query_advice(advice[3], Rotation::cur());
+            let right = meta.query_advice(advice[4], Rotation::cur());
+            let s_swap = meta.query_selector(s_swap);
+
+            // The left child is the node unless the position bit is set, and the right child is
+            // whichever of the two is not the left child
+            vec![
+                s_swap.clone()
+                    * (position.clone() * (Expression::Constant(F::ONE) - position.clone())),
+                s_swap.clone()
+                    * (node.clone() + position.clone() * (sibling.clone() - node.clone()) - left),
+                s_swap * (sibling.clone() + position * (node - sibling) - right),
+            ]
+        });
+
+        BitVeilMembershipConfig {
+            base: config,
+            s_swap,
+        }
+    }
+
+    // Fills the lookup table of the popcount circuit with every pair of nibbles and the popcount
+    // of their XOR
+    fn load_popcount_table(
+        &self,
+        mut layouter: impl Layouter<F>,
+        config: &PopcountConfig,
+    ) -> Result<(), Error> {
+        layouter.assign_table(
+            || "popcount table",
+            |mut table| {
+                for a in 0..NIBBLE_VALUES {
+                    for b in 0..NIBBLE_VALUES {
+                        let row = (a * NIBBLE_VALUES + b) as usize;
+                        let popcount = (a ^ b).count_ones() as u64;
+                        for (column, value) in config.table.iter().zip([a, b, popcount]) {
+                            table.assign_cell(
+                                || format!("popcount {} {}", a, b),
+                                *column,
+                                row,
+                                || Value::known(F::from(value)),
+                            )?;
+                        }
+                    }
+                }
+                Ok(())
+            },
+        )
+    }
+
+    // Fills the lookup table of the circuits over integer vectors with every element value
+    fn load_element_table(
+        &self,
+        mut layouter: impl Layouter<F>,
+        config: &ElementConfig,
+    ) -> Result<(), Error> {
+        layouter.assign_table(
+            || "element table",
+            |mut table| {
+                for value in 0..=MAX_ELEMENT {
+                    table.assign_cell(
+                        || format!("element {}", value),
+                        config.table,
+                        value as usize,
+                        || Value::known(F::from(value)),
+                    )?;
+                }
+                Ok(())
+            },
+        )
+    }
+
+    // Adds the weight column and the weighted accumulator gate of the weighted circuit to a
+    // configuration. The weighted distance is accumulated next to the distance region, whose gates
+    // still check the bits and pack them into words.
+    fn configure_weighted(
+        meta: &mut ConstraintSystem<F>,
+        config: BitVeilConfig,
+        weight: Column<Fixed>,
+        advice: Column<Advice>,
+    ) -> BitVeilWeightedConfig {
+        meta.enable_equality(advice);
+        let s_weighted = meta.selector();
+
+        // This gate adds the XOR of the bits times the weight of the row to the weighted distance
+        meta.create_gate("weighted xor accumulator", |meta| {
+            let a = meta.query_advice(config.advice[0], Rotation::cur());
+            let b = meta.query_advice(config.advice[1], Rotation::cur());
+            let weight = meta.query_fixed(weight);
+            let acc = meta.query_advice(advice, Rotation::cur());
+            let acc_next = meta.query_advice(advice, Rotation::next());
+            let s_weighted = meta.query_selector(s_weighted);
+
+            vec![s_weighted * (acc + xor(a, b) * weight - acc_next)]
+        });
+
+        BitVeilWeightedConfig {
+            base: config,
+            weight: WeightConfig {
+                weight,
+                advice,
+                s_weighted,
+            },
+        }
+    }
+
+    // Adds the mask columns and gates of the masked circuit to a configuration. The masked
+    // distance region is the distance region with the masks laid out next to it, so the bits of
+    // the vectors are still checked by the binary gates and packed by the word gates.
+    fn configure_masked(
+        meta: &mut ConstraintSystem<F>,
+        config: BitVeilConfig,
+        advice: [Column<Advice>; 6],
+    ) -> BitVeilMaskedConfig {
+        for column in &advice {
+            meta.enable_equality(*column);
+        }
+        let s_masked = meta.selector();
+
+        meta.create_gate("is binary mask a", |meta| {
+            let mask_a = meta.query_advice(advice[0], Rotation::cur());
+            let s_masked = meta.query_selector(s_masked);
+
+            vec![s_masked * (mask_a.clone() * (Expression::Constant(F::ONE) - mask_a))]
+        });
+
+        meta.create_gate("is binary mask b", |meta| {
+            let mask_b = meta.query_advice(advice[1], Rotation::cur());
+            let s_masked = meta.query_selector(s_masked);
+
+            vec![s_masked * (mask_b.clone() * (Expression::Constant(F::ONE) - mask_b))]
+        });
+
+        // This gate adds the XOR of the bits to the masked distance only where both masks are 1
+        meta.create_gate("masked xor accumulator", |meta| {
+            let a = meta.query_advice(config.advice[0], Rotation::cur());
+            let b = meta.query_advice(config.advice[1], Rotation::cur());
+            let mask_a = meta.query_advice(advice[0], Rotation::cur());
+            let mask_b = meta.query_advice(advice[1], Rotation::cur());
+            let acc = meta.query_advice(advice[4], Rotation::cur());
+            let acc_next = meta.query_advice(advice[4], Rotation::next());
+            let s_masked = meta.query_selector(s_masked);
+
+            vec![s_masked * (acc + xor(a, b) * mask_a * mask_b - acc_next)]
+        });
+
+        // This gate counts the bits where both masks are 1
+        meta.create_gate("valid accumulator", |meta| {
+            let mask_a = meta.query_advice(advice[0], Rotation::cur());
+            let mask_b = meta.query_advice(advice[1], Rotation::cur());
+            let valid = meta.query_advice(advice[5], Rotation::cur());
+            let valid_next = meta.query_advice(advice[5], Rotation::next());
+            let s_masked = meta.query_selector(s_masked);
+
+            vec![s_masked * (valid + mask_a * mask_b - valid_next)]
+        });
+
+        // The masks are packed into words like the vectors, on the rows where the distance region
+        // enables the word selectors. Every distance region of the masked circuit has masks, so
+        // these gates hold wherever the selectors are enabled.
+        meta.create_gate("mask word start", |meta| {
+            let mask_a = meta.query_advice(advice[0], Rotation::cur());
+            let mask_b = meta.query_advice(advice[1], Rotation::cur());
+            let sum_a = meta.query_advice(advice[2], Rotation::cur());
+            let sum_b = meta.query_advice(advice[3], Rotation::cur());
+            let s_word_start = meta.query_selector(config.s_word_start);
+
+            vec![
+                s_word_start.clone() * (sum_a - mask_a),
+                s_word_start * (sum_b - mask_b),
+            ]
+        });
+
+        meta.create_gate("pack mask word", |meta| {
+            let mask_a = meta.query_advice(advice[0], Rotation::cur());
+            let mask_b = meta.query_advice(advice[1], Rotation::cur());
+            let sum_a_prev = meta.query_advice(advice[2], Rotation::prev());
+            let sum_b_prev = meta.query_advice(advice[3], Rotation::prev());
+            let sum_a = meta.query_advice(advice[2], Rotation::cur());
+            let sum_b = meta.query_advice(advice[3], Rotation::cur());
+            let s_word = meta.query_selector(config.s_word);
+
+            let two = Expression::Constant(F::ONE + F::ONE);
+            vec![
+                s_word.clone() * (two.clone() * sum_a_prev + mask_a - sum_a),
+                s_word * (two * sum_b_prev + mask_b - sum_b),
+            ]
+        });
+
+        BitVeilMaskedConfig {
+            base: config,
+            mask: MaskConfig { advice, s_masked },
+        }
+    }
+
+    // Decomposes packed words into bits, most significant bit first
+    fn decompose(words: &[Value<F>], length: usize) -> Vec<Value<F>> {
+        words
+            .iter()
+            .zip(word_lengths(length))
+            .flat_map(|(word, num_bits)| {
+                (0..num_bits)
+                    .rev()
+                    .map(move |i| word.map(|v| bit_of(&v, i)))
+            })
+            .collect()
+    }
+
+    // Decomposes packed words into nibbles, first nibble first. The last word may hold fewer
+    // nibbles, like it holds fewer bits.
+    fn decompose_nibbles(words: &[Value<F>], length: usize) -> Vec<Value<F>> {
+        words
+            .iter()
+            .zip(word_lengths(length))
+            .flat_map(|(word, num_bits)| {
+                (0..num_bits / NIBBLE_BITS)
+                    .rev()
+                    .map(move |i| word.map(|v| nibble_of(&v, i)))
+            })
+            .collect()
+    }
+
+    // Decomposes packed words into elements of ELEMENT_BITS bits, first element first. The circuit
+    // length is a multiple of ELEMENTS_PER_WORD, so every word is full.
+    fn decompose_elements(words: &[Value<F>]) -> Vec<Value<F>> {
+        words
+            .iter()
+            .flat_map(|word| {
+                (0..ELEMENTS_PER_WORD)
+                    .rev()
+                    .map(move |i| word.map(|v| byte_of(&v, i)))
+            })
+            .collect()
+    }
+
+    // Lays out both integer vectors in a single region with one row per element, as described in
+    // `assign_elements`
+    #[allow(clippy::too_many_arguments)]
+    fn sum_elements(
+        &self,
+        mut layouter: impl Layouter<F>,
+        elements: &ElementConfig,
+        selector: Selector,
+        term: Option<Column<Advice>>,
+        a: &[Value<F>],
+        b: &[Value<F>],
+        length: usize,
+        op: impl Fn(F, F) -> F,
+    ) -> Result<ElementSum<Number<F>>, Error> {
+        let elements_a = Self::decompose_elements(a);
+        let elements_b = Self::decompose_elements(b);
+
+        layouter.assign_region(
+            || "sum elements",
+            |mut region: Region<'_, F>| {
+                self.assign_elements(
+                    &mut region,
+                    elements,
+                    selector,
+                    term,
+                    &elements_a,
+                    &elements_b,
+                    length,
+                    &op,
+                )
+            },
+        )
+    }
+
+    // Assigns the element region of two integer vectors given as elements. Each row holds the
+    // element of each vector, the sums packing them into words and the sum of the given operation
+    // on the pairs before the row, like the distance region. The row after the last element holds
+    // the sum. The result of the operation on each row is also assigned to the given column, for
+    // the gates which look it up.
+    #[allow(clippy::too_many_arguments)]
+    fn assign_elements(
+        &self,
+        region: &mut Region<'_, F>,
+        elements: &ElementConfig,
+        selector: Selector,
+        term: Option<Column<Advice>>,
+        elements_a: &[Value<F>],
+        elements_b: &[Value<F>],
+        length: usize,
+        op: impl Fn(F, F) -> F,
+    ) -> Result<ElementSum<Number<F>>, Error> {
+        let config = self.config();
+
+        // The sum starts from zero
+        let mut acc =
+            region.assign_advice_from_constant(|| "initial sum", config.advice[4], 0, F::ZERO)?;
+
+        let mut words_a = Vec::new();
+        let mut words_b = Vec::new();
+        let mut sum_a = Value::known(F::ZERO);
+        let mut sum_b = Value::known(F::ZERO);
+        let shift = F::from(1 << ELEMENT_BITS);
+        for (i, (element_a, element_b)) in elements_a.iter().zip(elements_b).enumerate() {
+            elements.s_element.enable(region, i)?;
+            selector.enable(region, i)?;
+            if i % ELEMENTS_PER_WORD == 0 {
+                elements.s_element_start.enable(region, i)?;
+                sum_a = Value::known(F::ZERO);
+                sum_b = Value::known(F::ZERO);
+            } else {
+                elements.s_element_word.enable(region, i)?;
+            }
+
+            let element_a =
+                region.assign_advice(|| format!("a[{}]", i), config.advice[0], i, || *element_a)?;
+            let element_b =
+                region.assign_advice(|| format!("b[{}]", i), config.advice[1], i, || *element_b)?;
+
+            // Add the elements to the words they are packed into
+            sum_a = sum_a * Value::known(shift) + element_a.value();
+            sum_b = sum_b * Value::known(shift) + element_b.value();
+            let word_a = region.assign_advice(|| "sum a", config.advice[2], i, || sum_a)?;
+            let word_b = region.assign_advice(|| "sum b", config.advice[3], i, || sum_b)?;
+            if i % ELEMENTS_PER_WORD == ELEMENTS_PER_WORD - 1 || i == length - 1 {
+                words_a.push(Number(word_a));
+                words_b.push(Number(word_b));
+            }
+
+            let value = element_a
+                .value()
+                .zip(element_b.value())
+                .map(|(a, b)| op(*a, *b));
+            if let Some(column) = term {
+                region.assign_advice(|| format!("term[{}]", i), column, i, || value)?;
+            }
+            acc = region.assign_advice(
+                || format!("sum[{}]", i + 1),
+                config.advice[4],
+                i + 1,
+                || acc.value().copied() + value,
+            )?;
+        }
+
+        Ok(ElementSum {
+            words_a,
+            words_b,
+            sum: Number(acc),
+        })
+    }
+
+    // Lays out a region which copies each pair of bits into the first two columns and sums the
+    // given operation on the pairs in the last column, for the AND and OR counting gates
+    fn count_bits(
+        &self,
+        mut layouter: impl Layouter<F>,
+        selector: Selector,
+        a: &[Number<F>],
+        b: &[Number<F>],
+        op: impl Fn(F, F) -> F,
+    ) -> Result<Number<F>, Error> {
+        let config = self.config();
+
+        layouter.assign_region(
+            || "count bits",
+            |mut region: Region<'_, F>| {
+                // The count starts from zero
+                let mut acc = region.assign_advice_from_constant(
+                    || "initial count",
+                    config.advice[4],
+                    0,
+                    F::ZERO,
+                )?;
+
+                for (i, (bit_a, bit_b)) in a.iter().zip(b).enumerate() {
+                    selector.enable(&mut region, i)?;
+
+                    let bit_a = bit_a.0.copy_advice(
+                        || format!("a[{}]", i),
+                        &mut region,
+                        config.advice[0],
+                        i,
+                    )?;
+                    let bit_b = bit_b.0.copy_advice(
+                        || format!("b[{}]", i),
+                        &mut region,
+                        config.advice[1],
+                        i,
+                    )?;
+
+                    let bits = bit_a.value().zip(bit_b.value());
+                    let next = acc.value().copied() + bits.map(|(a, b)| op(*a, *b));
+                    acc = region.assign_advice(
+                        || format!("count[{}]", i + 1),
+                        config.advice[4],
+                        i + 1,
+                        || next,
+                    )?;
+                }
+
+                Ok(Number(acc))
+            },
+        )
+    }
+
+    // Assigns the popcount region of two vectors given as nibbles, with one row per nibble. Each
+    // row holds the nibble of each vector, the sums packing them into words and the distance
+    // accumulated before the row, like the distance region. The row after the last nibble holds
+    // the distance.
+    fn assign_popcount(
+        &self,
+        region: &mut Region<'_, F>,
+        popcount: &PopcountConfig,
+        nibbles_a: &[Value<F>],
+        nibbles_b: &[Value<F>],
+        length: usize,
+    ) -> Result<ElementSum<Number<F>>, Error> {
+        let config = self.config();
+
+        // The distance starts from zero
+        let mut acc = region.assign_advice_from_constant(
+            || "initial distance",
+            config.advice[4],
+            0,
+            F::ZERO,
+        )?;
+
+        let mut words_a = Vec::new();
+        let mut words_b = Vec::new();
+        let mut row = 0;
+        let shift = Value::known(F::from(NIBBLE_VALUES));
+        for num_bits in word_lengths(length) {
+            let mut sum_a = Value::known(F::ZERO);
+            let mut sum_b = Value::known(F::ZERO);
+            let mut word = None;
+            for i in 0..num_bits / NIBBLE_BITS {
+                popcount.s_popcount.enable(region, row)?;
+                if i == 0 {
+                    popcount.s_nibble_start.enable(region, row)?;
+                } else {
+                    popcount.s_nibble_word.enable(region, row)?;
+                }
+
+                let nibble_a = region.assign_advice(
+                    || format!("a[{}]", row),
+                    config.advice[0],
+                    row,
+                    || nibbles_a[row],
+                )?;
+                let nibble_b = region.assign_advice(
+                    || format!("b[{}]", row),
+                    config.advice[1],
+                    row,
+                    || nibbles_b[row],
+                )?;
+
+                // Add the nibbles to the words they are packed into
+                sum_a = sum_a * shift + nibble_a.value();
+                sum_b = sum_b * shift + nibble_b.value();
+                let word_a = region.assign_advice(|| "sum a", config.advice[2], row, || sum_a)?;
+                let word_b = region.assign_advice(|| "sum b", config.advice[3], row, || sum_b)?;
+                word = Some((word_a, word_b));
+
+                let popcount = nibble_a
+                    .value()
+                    .zip(nibble_b.value())
+                    .map(|(a, b)| xor_popcount(*a, *b));
+                acc = region.assign_advice(
+                    || format!("distance[{}]", row + 1),
+                    config.advice[4],
+                    row + 1,
+                    || acc.value().copied() + popcount,
+                )?;
+                row += 1;
+            }
+
+            // The sums on the last row of each word hold the packed words
+            let (word_a, word_b) = word.ok_or(Error::Synthesis)?;
+            words_a.push(Number(word_a));
+            words_b.push(Number(word_b));
+        }
+
+        Ok(ElementSum {
+            words_a,
+            words_b,
+            sum: Number(acc),
+        })
+    }
+
+    // Assigns the distance region of two vectors given as bits, with one row per bit. Each row
+    // holds the bit of each vector, the sums packing them into words and the distance accumulated
+    // before the row. The row after the last bit holds the distance.
+    fn assign_distance(
+        &self,
+        region: &mut Region<'_, F>,
+        bits_a: &[Value<F>],
+        bits_b: &[Value<F>],
+        length: usize,
+    ) -> Result<HammingDistance<Number<F>>, Error> {
+        let config = self.config();
+
+        // The distance starts from zero
+        let mut acc = region.assign_advice_from_constant(
+            || "initial distance",
+            config.advice[4],
+            0,
+            F::ZERO,
+        )?;
+
+        let mut words_a = Vec::new();
+        let mut words_b = Vec::new();
+        let mut cells_a = Vec::new();
+        let mut cells_b = Vec::new();
+        let mut sum_a = Value::known(F::ZERO);
+        let mut sum_b = Value::known(F::ZERO);
+        for (i, (bit_a, bit_b)) in bits_a.iter().zip(bits_b).enumerate() {
+            config.s_distance.enable(region, i)?;
+            if i % WORD_LENGTH == 0 {
+                config.s_word_start.enable(region, i)?;
+                sum_a = Value::known(F::ZERO);
+                sum_b = Value::known(F::ZERO);
+            } else {
+                config.s_word.enable(region, i)?;
+            }
+
+            let bit_a =
+                region.assign_advice(|| format!("a[{}]", i), config.advice[0], i, || *bit_a)?;
+            let bit_b =
+                region.assign_advice(|| format!("b[{}]", i), config.advice[1], i, || *bit_b)?;
+
+            cells_a.push(Number(bit_a.clone()));
+            cells_b.push(Number(bit_b.clone()));
+
+            // Add the bits to the words they are packed into
+            sum_a = sum_a + sum_a + bit_a.value();
+            sum_b = sum_b + sum_b + bit_b.value();
+            let word_a = region.assign_advice(|| "sum a", config.advice[2], i, || sum_a)?;
+            let word_b = region.assign_advice(|| "sum b", config.advice[3], i, || sum_b)?;
+            if i % WORD_LENGTH == WORD_LENGTH - 1 || i == length - 1 {
+                words_a.push(Number(word_a));
+                words_b.push(Number(word_b));
+            }
+
+            // Calculate the XOR using arithmetic to match the gate, and add it to the distance
+            let xor = bit_a
+                .value()
+                .and_then(|a| bit_b.value().map(|b| *a + *b - (F::ONE + F::ONE) * *a * *b));
+            let next = acc.value().copied() + xor;
+            acc = region.assign_advice(
+                || format!("distance[{}]", i + 1),
+                config.advice[4],
+                i + 1,
+                || next,
+            )?;
+        }
+
+        Ok(HammingDistance {
+            words_a,
+            words_b,
+            bits_a: cells_a,
+            bits_b: cells_b,
+            distance: Number(acc),
+        })
+    }
+
+    // Assigns the distance region of two vectors given as bits with the bits of each mask next to
+    // each row, along with the sums packing the masks into words, the masked distance and the
+    // number of valid bits accumulated before the row. The plain distance of the region is left
+    // unused.
+    #[allow(clippy::too_many_arguments)]
+    fn assign_masked(
+        &self,
+        region: &mut Region<'_, F>,
+        mask: &MaskConfig,
+        bits_a: &[Value<F>],
+        bits_b: &[Value<F>],
+        bits_mask_a: &[Value<F>],
+        bits_mask_b: &[Value<F>],
+        length: usize,
+    ) -> Result<MaskedHammingDistance<Number<F>>, Error> {
+        let HammingDistance {
+            words_a, words_b, ..
+        } = self.assign_distance(region, bits_a, bits_b, length)?;
+
+        // The masked distance and the number of valid bits start from zero
+        let mut acc = region.assign_advice_from_constant(
+            || "initial masked distance",
+            mask.advice[4],
+            0,
+            F::ZERO,
+        )?;
+        let mut valid = region.assign_advice_from_constant(
+            || "initial valid bits",
+            mask.advice[5],
+            0,
+            F::ZERO,
+        )?;
+
+        let mut words_mask_a = Vec::new();
+        let mut words_mask_b = Vec::new();
+        let mut sum_a = Value::known(F::ZERO);
+        let mut sum_b = Value::known(F::ZERO);
+        let rows = bits_a.iter().zip(bits_b);
+        let masks = bits_mask_a.iter().zip(bits_mask_b);
+        for (i, ((bit_a, bit_b), (bit_mask_a, bit_mask_b))) in rows.zip(masks).enumerate() {
+            mask.s_masked.enable(region, i)?;
+            if i % WORD_LENGTH == 0 {
+                sum_a = Value::known(F::ZERO);
+                sum_b = Value::known(F::ZERO);
+            }
+
+            let bit_mask_a = region.assign_advice(
+                || format!("mask a[{}]", i),
+                mask.advice[0],
+                i,
+                || *bit_mask_a,
+            )?;
+            let bit_mask_b = region.assign_advice(
+                || format!("mask b[{}]", i),
+                mask.advice[1],
+                i,
+                || *bit_mask_b,
+            )?;
+
+            // Add the mask bits to the words they are packed into
+            sum_a = sum_a + sum_a + bit_mask_a.value();
+            sum_b = sum_b + sum_b + bit_mask_b.value();
+            let word_a = region.assign_advice(|| "mask sum a", mask.advice[2], i, || sum_a)?;
+            let word_b = region.assign_advice(|| "mask sum b", mask.advice[3], i, || sum_b)?;
+            if i % WORD_LENGTH == WORD_LENGTH - 1 || i == length - 1 {
+                words_mask_a.push(Number(word_a));
+                words_mask_b.push(Number(word_b));
+            }
+
+            // Add the XOR and the bit itself to the sums only where both masks are 1
+            let both = bit_mask_a.value().copied() * bit_mask_b.value();
+            let xor = bit_a.and_then(|a| bit_b.map(|b| a + b - (F::ONE + F::ONE) * a * b));
+            let next = acc.value().copied() + xor * both;
+            acc = region.assign_advice(
+                || format!("masked distance[{}]", i + 1),
+                mask.advice[4],
+                i + 1,
+                || next,
+            )?;
+            let next = valid.value().copied() + both;
+            valid = region.assign_advice(
+                || format!("valid bits[{}]", i + 1),
+                mask.advice[5],
+                i + 1,
+                || next,
+            )?;
+        }
+
+        Ok(MaskedHammingDistance {
+            words_a,
+            words_b,
+            words_mask_a,
+            words_mask_b,
+            distance: Number(acc),
+            valid: Number(valid),
+        })
+    }
+
+    // Assigns the distance region of two vectors given as bits with the weight of each row in the
+    // fixed column and the weighted distance accumulated before the row next to it. The plain
+    // distance of the region is left unused.
+    fn assign_weighted(
+        &self,
+        region: &mut Region<'_, F>,
+        weight: &WeightConfig,
+        bits_a: &[Value<F>],
+        bits_b: &[Value<F>],
+        weights: &[F],
+        length: usize,
+    ) -> Result<HammingDistance<Number<F>>, Error> {
+        let distance = self.assign_distance(region, bits_a, bits_b, length)?;
+
+        let mut acc = region.assign_advice_from_constant(
+            || "initial weighted distance",
+            weight.advice,
+            0,
+            F::ZERO,
+        )?;
+        for (i, (bit_a, bit_b)) in bits_a.iter().zip(bits_b).enumerate() {
+            weight.s_weighted.enable(region, i)?;
+            region.assign_fixed(
+                || format!("weight[{}]", i),
+                weight.weight,
+                i,
+                || Value::known(weights[i]),
+            )?;
+
+            let xor = bit_a.and_then(|a| bit_b.map(|b| a + b - (F::ONE + F::ONE) * a * b));
+            let next = acc.value().copied() + xor * Value::known(weights[i]);
+            acc = region.assign_advice(
+                || format!("weighted distance[{}]", i + 1),
+                weight.advice,
+                i + 1,
+                || next,
+            )?;
+        }
+
+        Ok(HammingDistance {
+            distance: Number(acc),
+            ..distance
+        })
+    }
 }
 
-// Generates setup parameters using k, which is the number of rows of the circuit
-// can fit in and must be a power of two
-pub fn generate_setup_params(k: u32) -> Params<EqAffine> {
-    Params::<EqAffine>::new(k)
+// This struct represents a number in the circuit, which wraps a cell
+#[derive(Clone, Debug)]
+struct Number<F: Field>(AssignedCell<F, F>);
+
+// Returns bit `i` of a field element, counting from the least significant bit
+fn bit_of<F: PrimeField>(value: &F, i: usize) -> F {
+    let repr = value.to_repr();
+    F::from(((repr.as_ref()[i / 8] >> (i % 8)) & 1) as u64)
 }
 
-// Generates the verifying and proving keys. We can pass in an empty circuit to generate these
-pub fn generate_keys(
-    params: &Params<EqAffine>,
-    circuit: &BitVeilCircuit<Fp>,
-) -> (ProvingKey<EqAffine>, VerifyingKey<EqAffine>) {
-    // just to emphasize that for vk, pk we don't need to know the value of `x`
-    let vk = keygen_vk(params, circuit).expect("vk should not fail");
-    let pk = keygen_pk(params, vk.clone(), circuit).expect("pk should not fail");
-    (pk, vk)
+// Returns byte `i` of a field element, counting from the least significant byte
+fn byte_of<F: PrimeField>(value: &F, i: usize) -> F {
+    let repr = value.to_repr();
+    F::from(repr.as_ref()[i] as u64)
 }
 
-// Calculates the hamming distance between two vectors
-pub fn calculate_hamming_distance(a: Vec<u64>, b: Vec<u64>) -> Vec<Fp> {
-    let hamming_dist = a
-        .iter()
-        .zip(&b)
-        .map(|(&x, &y)| ((x ^ y) & 1) as u64)
-        .sum::<u64>();
-    vec![Fp::from(hamming_dist)]
+// Returns nibble `i` of a field element, counting from the least significant nibble
+fn nibble_of<F: PrimeField>(value: &F, i: usize) -> F {
+    let repr = value.to_repr();
+    F::from(((repr.as_ref()[i / 2] >> (NIBBLE_BITS * (i % 2))) & 0xf) as u64)
 }
 
-// Runs the mock prover and prints any errors
-pub fn run_mock_prover(k: u32, circuit: &BitVeilCircuit<Fp>, pub_input: &Vec<Fp>) {
-    let prover =
-        MockProver::run(k, circuit, vec![pub_input.clone()]).expect("Mock prover should run");
-    let res = prover.verify();
-    match res {
-        Ok(()) => println!("MockProver OK"),
-        Err(e) => println!("err {:#?}", e),
-    }
+// Returns the popcount of the XOR of two nibbles
+fn xor_popcount<F: PrimeField>(a: F, b: F) -> F {
+    let xor = a.to_repr().as_ref()[0] ^ b.to_repr().as_ref()[0];
+    F::from(xor.count_ones() as u64)
 }
 
-// Generates a proof
-pub fn generate_proof(
-    params: &Params<EqAffine>,
-    pk: &ProvingKey<EqAffine>,
-    circuit: BitVeilCircuit<Fp>,
-    pub_input: &Vec<Fp>,
-) -> Vec<u8> {
-    println!("Generating proof...");
-    let mut transcript = Blake2bWrite::<_, _, Challenge255<_>>::init(vec![]);
-    create_proof(
-        params,
-        pk,
-        &[circuit],
-        &[&[pub_input]],
-        OsRng,
-        &mut transcript,
-    )
-    .expect("Prover should not fail");
-    transcript.finalize()
+// Returns |a - b| for two elements. The difference is not negative exactly when it fits in the
+// least significant byte, since both elements do.
+fn absolute_difference<F: PrimeField>(a: F, b: F) -> F {
+    let difference = a - b;
+    match difference.to_repr().as_ref()[1..]
+        .iter()
+        .all(|byte| *byte == 0)
+    {
+        true => difference,
+        false => -difference,
+    }
 }
 
-// Verifies the proof
-pub fn verify(
-    params: &Params<EqAffine>,
-    vk: &VerifyingKey<EqAffine>,
-    pub_input: &Vec<Fp>,
-    proof: Vec<u8>,
-) -> Result<(), Error> {
-    println!("Verifying proof...");
-    let strategy = SingleVerifier::new(&params);
-    let mut transcript = Blake2bRead::<_, _, Challenge255<_>>::init(&proof[..]);
-    verify_proof(params, vk, strategy, &[&[pub_input]], &mut transcript)
+// Implement all of the chip traits. In this section, we'll be describing how Layouter will assign values to
+// various cells in the circuit.
+impl<F: FromUniformBytes<64>> Instructions<F> for BitVeilChip<F> {
+    type Num = Number<F>;
+
+    // Lays out both vectors in a single region with one row per bit, as described in
+    // `assign_distance`
+    fn hamming_distance(
+        &self,
+        mut layouter: impl Layouter<F>,
+        a: &[Value<F>],
+        b: &[Value<F>],
+        length: usize,
+    ) -> Result<HammingDistance<Self::Num>, Error> {
+        let bits_a = Self::decompose(a, length);
+        let bits_b = Self::decompose(b, length);
+
+        layouter.assign_region(
+            || "hamming distance",
+            |mut region: Region<'_, F>| self.assign_distance(&mut region, &bits_a, &bits_b, length),
+        )
+    }
+
+    // Lays out the distance region of both vectors with the masks next to it, as described in
+    // `assign_masked`
+    fn masked_hamming_distance(
+        &self,
+        mut layouter: impl Layouter<F>,
+        mask: &MaskConfig,
+        a: &[Value<F>],
+        b: &[Value<F>],
+        mask_a: &[Value<F>],
+        mask_b: &[Value<F>],
+        length: usize,
+    ) -> Result<MaskedHammingDistance<Self::Num>, Error> {
+        let bits_a = Self::decompose(a, length);
+        let bits_b = Self::decompose(b, length);
+        let bits_mask_a = Self::decompose(mask_a, length);
+        let bits_mask_b = Self::decompose(mask_b, length);
+
+        layouter.assign_region(
+            || "masked hamming distance",
+            |mut region: Region<'_, F>| {
+                self.assign_masked(
+                    &mut region,
+                    mask,
+                    &bits_a,
+                    &bits_b,
+                    &bits_mask_a,
+                    &bits_mask_b,
+                    length,
+                )
+            },
+        )
+    }
+
+    // Lays out the distance region of both vectors with the weighted distance next to it, as
+    // described in `assign_weighted`
+    fn weighted_hamming_distance(
+        &self,
+        mut layouter: impl Layouter<F>,
+        weight: &WeightConfig,
+        a: &[Value<F>],
+        b: &[Value<F>],
+        weights: &[F],
+        length: usize,
+    ) -> Result<HammingDistance<Self::Num>, Error> {
+        let bits_a = Self::decompose(a, length);
+        let bits_b = Self::decompose(b, length);
+
+        layouter.assign_region(
+            || "weighted hamming distance",
+            |mut region: Region<'_, F>| {
+                self.assign_weighted(&mut region, weight, &bits_a, &bits_b, weights, length)
+            },
+        )
+    }
+
+    // Lays out a distance region whose second vector holds the given bits rotated by `shift`
+    // positions, so row `i` compares bit `i` of the first vector with bit `i + shift` of the
+    // second, wrapping around at the end. Each rotated bit is constrained to equal the cell it was
+    // copied from.
+    fn rotated_hamming_distance(
+        &self,
+        mut layouter: impl Layouter<F>,
+        a: &[Value<F>],
+        bits_b: &[Self::Num],
+        shift: isize,
+        length: usize,
+    ) -> Result<HammingDistance<Self::Num>, Error> {
+        let bits_a = Self::decompose(a, length);
+        let rotated: Vec<&Number<F>> = (0..length)
+            .map(|i| &bits_b[(i as isize + shift).rem_euclid(length as isize) as usize])
+            .collect();
+        let values: Vec<Value<F>> = rotated.iter().map(|bit| bit.0.value().copied()).collect();
+
+        layouter.assign_region(
+            || "rotated hamming distance",
+            |mut region: Region<'_, F>| {
+                let distance = self.assign_distance(&mut region, &bits_a, &values, length)?;
+                for (bit, source) in distance.bits_b.iter().zip(&rotated) {
+                    region.constrain_equal(bit.0.cell(), source.0.cell())?;
+                }
+                Ok(distance)
+            },
+        )
+    }
+
+    // Lays out both vectors in a single region with one row per nibble, as described in
+    // `assign_popcount`
+    fn popcount_distance(
+        &self,
+        mut layouter: impl Layouter<F>,
+        popcount: &PopcountConfig,
+        a: &[Value<F>],
+        b: &[Value<F>],
+        length: usize,
+    ) -> Result<ElementSum<Self::Num>, Error> {
+        let nibbles_a = Self::decompose_nibbles(a, length);
+        let nibbles_b = Self::decompose_nibbles(b, length);
+
+        layouter.assign_region(
+            || "popcount distance",
+            |mut region: Region<'_, F>| {
+                self.assign_popcount(&mut region, popcount, &nibbles_a, &nibbles_b, length)
+            },
+        )
+    }
+
+    // Sums the products of the elements in an element region, as described in `sum_elements`
+    fn inner_product(
+        &self,
+        layouter: impl Layouter<F>,
+        elements: &ElementConfig,
+        s_inner_product: Selector,
+        a: &[Value<F>],
+        b: &[Value<F>],
+        length: usize,
+    ) -> Result<ElementSum<Self::Num>, Error> {
+        self.sum_elements(
+            layouter,
+            elements,
+            s_inner_product,
+            None,
+            a,
+            b,
+            length,
+            |a, b| a * b,
+        )
+    }
+
+    // Sums the absolute differences of the elements in an element region, which also holds each
+    // absolute difference in the difference column so it can be looked up
+    fn l1_distance(
+        &self,
+        layouter: impl Layouter<F>,
+        elements: &ElementConfig,
+        embedding: &EmbeddingConfig,
+        a: &[Value<F>],
+        b: &[Value<F>],
+        length: usize,
+    ) -> Result<ElementSum<Self::Num>, Error> {
+        self.sum_elements(
+            layouter,
+            elements,
+            embedding.s_absolute,
+            Some(embedding.difference),
+            a,
+            b,
+            length,
+            absolute_difference,
+        )
+    }
+
+    // Sums the squared differences of the elements in an element region
+    fn squared_l2_distance(
+        &self,
+        layouter: impl Layouter<F>,
+        elements: &ElementConfig,
+        embedding: &EmbeddingConfig,
+        a: &[Value<F>],
+        b: &[Value<F>],
+        length: usize,
+    ) -> Result<ElementSum<Self::Num>, Error> {
+        self.sum_elements(
+            layouter,
+            elements,
+            embedding.s_squared,
+            None,
+            a,
+            b,
+            length,
+            |a, b| (a - b).square(),
+        )
+    }
+
+    // Counts the AND of each pair of bits in its own region, as described in `count_bits`
+    fn and(
+        &self,
+        layouter: impl Layouter<F>,
+        counts: &CountConfig,
+        a: &[Self::Num],
+        b: &[Self::Num],
+    ) -> Result<Self::Num, Error> {
+        self.count_bits(layouter, counts.s_and, a, b, |a, b| a * b)
+    }
+
+    // Counts the OR of each pair of bits in its own region, as described in `count_bits`
+    fn or(
+        &self,
+        layouter: impl Layouter<F>,
+        counts: &CountConfig,
+        a: &[Self::Num],
+        b: &[Self::Num],
+    ) -> Result<Self::Num, Error> {
+        self.count_bits(layouter, counts.s_or, a, b, |a, b| a + b - a * b)
+    }
+
+    // Loads a single private value, such as the salt of a commitment
+    fn load_private(
+        &self,
+        mut layouter: impl Layouter<F>,
+        value: Value<F>,
+    ) -> Result<Self::Num, Error> {
+        let config = self.config();
+
+        layouter.assign_region(
+            || "load private",
+            |mut region| {
+                region
+                    .assign_advice(|| "private input", config.advice[0], 0, || value)
+                    .map(Number)
+            },
+        )
+    }
+
+    // Loads a private value and checks that it fits in the given number of bits. The value is
+    // decomposed into bits, which are checked to be binary and packed back together with a running
+    // sum whose last value is the value.
+    fn range_check(
+        &self,
+        mut layouter: impl Layouter<F>,
+        value: Value<F>,
+        num_bits: usize,
+    ) -> Result<Self::Num, Error> {
+        let config = self.config();
+
+        // Decompose the value into bits, most significant bit first
+        let bits: Vec<Value<F>> = (0..num_bits)
+            .rev()
+            .map(|i| value.map(|v| bit_of(&v, i)))
+            .collect();
+
+        layouter.assign_region(
+            || "range check",
+            |mut region: Region<'_, F>| {
+                let mut sum = region.assign_advice_from_constant(
+                    || "initial sum",
+                    config.advice[1],
+                    0,
+                    F::ZERO,
+                )?;
+
+                for (i, bit) in bits.iter().enumerate() {
+                    config.s_binary.enable(&mut region, i)?;
+                    config.s_pack.enable(&mut region, i)?;
+
+                    let bit = region.assign_advice(
+                        || format!("bit[{}]", i),
+                        config.advice[0],
+                        i,
+                        || *bit,
+                    )?;
+
+                    let next = sum.value().and_then(|s| bit.value().map(|b| *s + *s + *b));
+                    sum = region.assign_advice(
+                        || format!("sum[{}]", i + 1),
+                        config.advice[1],
+                        i + 1,
+                        || next,
+                    )?;
+                }
+
+                Ok(Number(sum))
+            },
+        )
+    }
+
+    // Accumulates a list of values into their sum. The values are copied into the first column
+    // with the running sum before each value next to it, and the row after the last value holds
+    // the sum.
+    fn accumulator(
+        &self,
+        mut layouter: impl Layouter<F>,
+        values: &[Self::Num],
+    ) -> Result<Self::Num, Error> {
+        let config = self.config();
+
+        layouter.assign_region(
+            || "assign accumulator region",
+            |mut region: Region<'_, F>| {
+                // The running sum starts from zero
+                let mut sum = region.assign_advice_from_constant(
+                    || "initial sum",
+                    config.advice[1],
+                    0,
+                    F::ZERO,
+                )?;
+
+                for (i, value) in values.iter().enumerate() {
+                    config.s_accumulator.enable(&mut region, i)?;
+
+                    let value = value.0.copy_advice(
+                        || format!("value[{}]", i),
+                        &mut region,
+                        config.advice[0],
+                        i,
+                    )?;
+
+                    let next = sum.value().copied() + value.value();
+                    sum = region.assign_advice(
+                        || format!("sum[{}]", i + 1),
+                        config.advice[1],
+                        i + 1,
+                        || next,
+                    )?;
+                }
+
+                Ok(Number(sum))
+            },
+        )
+    }
+
+    // Multiplies a list of values with a running product laid out like the running sum of the
+    // accumulator
+    fn product(
+        &self,
+        mut layouter: impl Layouter<F>,
+        s_product: Selector,
+        values: &[Self::Num],
+    ) -> Result<Self::Num, Error> {
+        let config = self.config();
+
+        layouter.assign_region(
+            || "assign product region",
+            |mut region: Region<'_, F>| {
+                // The running product starts from one
+                let mut product = region.assign_advice_from_constant(
+                    || "initial product",
+                    config.advice[1],
+                    0,
+                    F::ONE,
+                )?;
+
+                for (i, value) in values.iter().enumerate() {
+                    s_product.enable(&mut region, i)?;
+
+                    let value = value.0.copy_advice(
+                        || format!("value[{}]", i),
+                        &mut region,
+                        config.advice[0],
+                        i,
+                    )?;
+
+                    let next = product.value().copied() * value.value();
+                    product = region.assign_advice(
+                        || format!("product[{}]", i + 1),
+                        config.advice[1],
+                        i + 1,
+                        || next,
+                    )?;
+                }
+
+                Ok(Number(product))
+            },
+        )
+    }
+
+    // Constrains each value to equal the value at the same position of the other list. The
+    // equality constraints are added in an empty region, since the layouter itself cannot add them.
+    fn constrain_equal(
+        &self,
+        mut layouter: impl Layouter<F>,
+        a: &[Self::Num],
+        b: &[Self::Num],
+    ) -> Result<(), Error> {
+        layouter.assign_region(
+            || "constrain equal",
+            |mut region: Region<'_, F>| {
+                for (x, y) in a.iter().zip(b) {
+                    region.constrain_equal(x.0.cell(), y.0.cell())?;
+                }
+                Ok(())
+            },
+        )
+    }
+
+    // Constrains a value to equal a constant, which is placed in the fixed column for constants
+    fn constrain_constant(
+        &self,
+        mut layouter: impl Layouter<F>,
+        num: Self::Num,
+        constant: F,
+    ) -> Result<(), Error> {
+        layouter.assign_region(
+            || "constrain constant",
+            |mut region: Region<'_, F>| region.constrain_constant(num.0.cell(), constant),
+        )
+    }
+
+    // Commits to a binary vector by hashing the salt together with its packed words
+    fn commit(
+        &self,
+        mut layouter: impl Layouter<F>,
+        salt: Self::Num,
+        words: &[Self::Num],
+    ) -> Result<Self::Num, Error> {
+        let mut inputs = vec![salt.0];
+        inputs.extend(words.iter().map(|word| word.0.clone()));
+
+        let poseidon_chip = PoseidonChip::<F>::construct(self.config().poseidon.clone());
+        poseidon_chip
+            .hash(layouter.namespace(|| "hash words"), &inputs)
+            .map(Number)
+    }
+
+    // Hashes the node at each level together with its sibling, ordered by the swap gate in a region
+    // of their own, so the root only matches the public one if the leaf is in the tree
+    fn merkle_root(
+        &self,
+        mut layouter: impl Layouter<F>,
+        s_swap: Selector,
+        leaf: Self::Num,
+        siblings: &[Value<F>],
+        positions: &[Value<F>],
+    ) -> Result<Self::Num, Error> {
+        let config = self.config();
+        let poseidon_chip = PoseidonChip::<F>::construct(config.poseidon.clone());
+
+        let mut node = leaf;
+        for (level, (sibling, position)) in siblings.iter().zip(positions).enumerate() {
+            let children = layouter.assign_region(
+                || format!("swap level {}", level),
+                |mut region: Region<'_, F>| {
+                    s_swap.enable(&mut region, 0)?;
+
+                    let node = node
+                        .0
+                        .copy_advice(|| "node", &mut region, config.advice[0], 0)?;
+                    region.assign_advice(|| "sibling", config.advice[1], 0, || *sibling)?;
+                    region.assign_advice(|| "position", config.advice[2], 0, || *position)?;
+
+                    let node = node.value().copied();
+                    let left = node + *position * (*sibling - node);
+                    let right = *sibling + *position * (node - *sibling);
+                    Ok([
+                        region.assign_advice(|| "left", config.advice[3], 0, || left)?,
+                        region.assign_advice(|| "right", config.advice[4], 0, || right)?,
+                    ])
+                },
+            )?;
+
+            node = poseidon_chip
+                .hash(
+                    layouter.namespace(|| format!("hash level {}", level)),
+                    &children,
+                )
+                .map(Number)?;
+        }
+        Ok(node)
+    }
+
+    // Derives the nullifier of a vector in a context, which is the same for every proof about the
+    // vector and its secret in that context, whatever salt they are committed to with, and which
+    // cannot be computed for another context without the secret
+    fn nullifier(
+        &self,
+        mut layouter: impl Layouter<F>,
+        secret: Self::Num,
+        words: &[Self::Num],
+        context: Self::Num,
+    ) -> Result<Self::Num, Error> {
+        let mut inputs = vec![secret.0];
+        inputs.extend(words.iter().map(|word| word.0.clone()));
+        inputs.push(context.0);
+
+        let poseidon_chip = PoseidonChip::<F>::construct(self.config().poseidon.clone());
+        poseidon_chip
+            .hash(
+                layouter.namespace(|| "hash secret, words and context"),
+                &inputs,
+            )
+            .map(Number)
+    }
+
+    // Expose a value as a public input. Constrain the cell to equal the instance column value in
+    // the given row, which holds a value calculated outside the circuit (the Hamming distance in
+    // row 0 and the commitments to the two vectors in the rows after it)
+    fn expose_public(
+        &self,
+        mut layouter: impl Layouter<F>,
+        num: Self::Num,
+        row: usize,
+    ) -> Result<(), Error> {
+        let config = self.config();
+        layouter.constrain_instance(num.0.cell(), config.instance, row)
+    }
+}
+
+// The circuit which proves the Hamming distance between two committed vectors. The vectors are
+// given as packed words of WORD_LENGTH bits which are decomposed into bits in the circuit. The
+// length of the vectors is a multiple of CHUNK_LENGTH and is fixed when the keys are generated.
+#[derive(Clone, Debug)]
+pub struct BitVeilCircuit<F: Field> {
+    length: usize,
+    a: Vec<Value<F>>,
+    b: Vec<Value<F>>,
+
+    // The salts which blind the commitments to each vector
+    salt_a: Value<F>,
+    salt_b: Value<F>,
+}
+
+impl<F: FromUniformBytes<64>> Circuit<F> for BitVeilCircuit<F> {
+    type Config = BitVeilConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        // Outputs a circuit of the same length with all of the values unknown
+        Self {
+            length: self.length,
+            a: vec![Value::unknown(); self.a.len()],
+            b: vec![Value::unknown(); self.b.len()],
+            salt_a: Value::unknown(),
+            salt_b: Value::unknown(),
+        }
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+        // Our configuration will include 5 advice columns (the bits of each input, the words they
+        // are packed into, and the running sum of the XOR results)
+        let advice = [
+            meta.advice_column(),
+            meta.advice_column(),
+            meta.advice_column(),
+            meta.advice_column(),
+            meta.advice_column(),
+        ];
+
+        // We'll also have one instance column for the public inputs (calculated Hamming distance
+        // and the vector commitments)
+        let instance = meta.instance_column();
+
+        // Three fixed columns hold the round constants of the hash and one holds other constants
+        let fixed = [
+            meta.fixed_column(),
+            meta.fixed_column(),
+            meta.fixed_column(),
+            meta.fixed_column(),
+        ];
+
+        BitVeilChip::configure(meta, advice, instance, fixed)
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<F>,
+    ) -> Result<(), Error> {
+        let bitveil_chip = BitVeilChip::<F>::construct(config);
+
+        let accumulate =
+            self.synthesize_distance(&bitveil_chip, layouter.namespace(|| "distance"))?;
+
+        // Ensure the accumulated value equals the public input (of the precalculated accumulation
+        // value)
+        bitveil_chip.expose_public(
+            layouter.namespace(|| "expose accumulate"),
+            accumulate,
+            DISTANCE_ROW,
+        )
+    }
+}
+
+impl<F: FromUniformBytes<64>> BitVeilCircuit<F> {
+    // Computes the Hamming distance between the vectors and exposes the commitments to them,
+    // returning the distance so it can either be exposed or compared against a threshold
+    fn synthesize_distance(
+        &self,
+        bitveil_chip: &BitVeilChip<F>,
+        mut layouter: impl Layouter<F>,
+    ) -> Result<Number<F>, Error> {
+        // Load the packed words of both vectors, decompose them into binary digits and accumulate
+        // the XOR of each pair of digits
+        let HammingDistance {
+            words_a,
+            words_b,
+            distance,
+            ..
+        } = bitveil_chip.hamming_distance(
+            layouter.namespace(|| "hamming distance"),
+            &self.a,
+            &self.b,
+            self.length,
+        )?;
+
+        self.synthesize_commitments(bitveil_chip, layouter, &words_a, &words_b)?;
+        Ok(distance)
+    }
+
+    // Commits to both vectors and exposes the commitments, which ties the proof to the inputs
+    fn synthesize_commitments(
+        &self,
+        bitveil_chip: &BitVeilChip<F>,
+        mut layouter: impl Layouter<F>,
+        words_a: &[Number<F>],
+        words_b: &[Number<F>],
+    ) -> Result<(), Error> {
+        self.synthesize_commitment_a(bitveil_chip, layouter.namespace(|| "a"), words_a)?;
+        let commitment_b =
+            self.synthesize_commitment_b(bitveil_chip, layouter.namespace(|| "b"), words_b)?;
+        bitveil_chip.expose_public(
+            layouter.namespace(|| "expose commitment b"),
+            commitment_b,
+            COMMITMENT_B_ROW,
+        )
+    }
+
+    // Commits to the first vector and exposes the commitment
+    fn synthesize_commitment_a(
+        &self,
+        bitveil_chip: &BitVeilChip<F>,
+        mut layouter: impl Layouter<F>,
+        words_a: &[Number<F>],
+    ) -> Result<(), Error> {
+        let salt_a =
+            bitveil_chip.load_private(layouter.namespace(|| "load salt a"), self.salt_a)?;
+        let commitment_a =
+            bitveil_chip.commit(layouter.namespace(|| "commit a"), salt_a, words_a)?;
+        bitveil_chip.expose_public(
+            layouter.namespace(|| "expose commitment a"),
+            commitment_a,
+            COMMITMENT_A_ROW,
+        )
+    }
+
+    // Commits to the second vector, returning the commitment for the caller to expose or, in the
+    // membership circuit, to hash up a Merkle path
+    fn synthesize_commitment_b(
+        &self,
+        bitveil_chip: &BitVeilChip<F>,
+        mut layouter: impl Layouter<F>,
+        words_b: &[Number<F>],
+    ) -> Result<Number<F>, Error> {
+        let salt_b =
+            bitveil_chip.load_private(layouter.namespace(|| "load salt b"), self.salt_b)?;
+        bitveil_chip.commit(layouter.namespace(|| "commit b"), salt_b, words_b)
+    }
+}
+
+// A variant of the circuit which keeps the distance private and only proves that it is at most a
+// public threshold
+#[derive(Clone, Debug)]
+pub struct BitVeilThresholdCircuit<F: Field> {
+    inner: BitVeilCircuit<F>,
+    threshold: Value<F>,
+}
+
+impl<F: FromUniformBytes<64>> Circuit<F> for BitVeilThresholdCircuit<F> {
+    type Config = BitVeilConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self {
+            inner: self.inner.without_witnesses(),
+            threshold: Value::unknown(),
+        }
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+        // Uses the same columns and gates as the circuit which exposes the distance
+        BitVeilCircuit::configure(meta)
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<F>,
+    ) -> Result<(), Error> {
+        let bitveil_chip = BitVeilChip::<F>::construct(config);
+
+        let distance = self
+            .inner
+            .synthesize_distance(&bitveil_chip, layouter.namespace(|| "distance"))?;
+        synthesize_threshold(
+            &bitveil_chip,
+            layouter,
+            distance,
+            self.threshold,
+            THRESHOLD_BITS,
+        )
+    }
+}
+
+// Proves that a distance is at most the threshold and exposes the threshold in its place. The
+// threshold must fit in the given number of bits.
+fn synthesize_threshold<F: FromUniformBytes<64>>(
+    bitveil_chip: &BitVeilChip<F>,
+    mut layouter: impl Layouter<F>,
+    distance: Number<F>,
+    threshold: Value<F>,
+    num_bits: usize,
+) -> Result<(), Error> {
+    // The gap between the threshold and the distance must fit in num_bits bits. If the distance
+    // were above the threshold the gap would wrap around to a huge field element.
+    let gap = distance.0.value().and_then(|d| threshold.map(|t| t - *d));
+    let gap = bitveil_chip.range_check(layouter.namespace(|| "check gap"), gap, num_bits)?;
+
+    // Only the sum of the distance and the gap, which is the threshold, is made public
+    let threshold = bitveil_chip.accumulator(layouter.namespace(|| "add gap"), &[distance, gap])?;
+    bitveil_chip.expose_public(
+        layouter.namespace(|| "expose threshold"),
+        threshold,
+        DISTANCE_ROW,
+    )
+}
+
+// A variant of the circuit which binds the proof to a session nonce, such as a challenge from the
+// verifier, and derives a nullifier for vector B in a context such as the relying party. The
+// nonce is a public input, and halo2 commits to the public inputs in the transcript, so a proof
+// only verifies for the nonce it was generated with and cannot be replayed in another session.
+// Vector B is committed to together with a nullifier secret which only its holder knows, and the
+// nullifier is hashed from the same secret, the words of vector B and the context, so it is the
+// same for every proof about the committed vector in the same context, and a relying party which
+// records them can tell when the vector is used again. Without the secret, nobody who knows the
+// vector can compute its nullifier in a context it was not used in.
+#[derive(Clone, Debug)]
+pub struct BitVeilSessionCircuit<F: Field> {
+    inner: BitVeilCircuit<F>,
+    secret: Value<F>,
+    nonce: Value<F>,
+    context: Value<F>,
+}
+
+impl<F: FromUniformBytes<64>> Circuit<F> for BitVeilSessionCircuit<F> {
+    type Config = BitVeilConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self {
+            inner: self.inner.without_witnesses(),
+            secret: Value::unknown(),
+            nonce: Value::unknown(),
+            context: Value::unknown(),
+        }
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+        // Uses the same columns and gates as the circuit which exposes the distance
+        BitVeilCircuit::configure(meta)
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<F>,
+    ) -> Result<(), Error> {
+        let bitveil_chip = BitVeilChip::<F>::construct(config);
+
+        let HammingDistance {
+            words_a,
+            words_b,
+            distance,
+            ..
+        } = bitveil_chip.hamming_distance(
+            layouter.namespace(|| "hamming distance"),
+            &self.inner.a,
+            &self.inner.b,
+            self.inner.length,
+        )?;
+        bitveil_chip.expose_public(
+            layouter.namespace(|| "expose distance"),
+            distance,
+            DISTANCE_ROW,
+        )?;
+        self.inner
+            .synthesize_commitment_a(&bitveil_chip, layouter.namespace(|| "a"), &words_a)?;
+
+        // The secret is committed to with vector B, in front of its words
+        let secret =
+            bitveil_chip.load_private(layouter.namespace(|| "load secret"), self.secret)?;
+        let salt_b =
+            bitveil_chip.load_private(layouter.namespace(|| "load salt b"), self.inner.salt_b)?;
+        let mut committed = vec![secret.clone()];
+        committed.extend(words_b.iter().cloned());
+        let commitment_b =
+            bitveil_chip.commit(layouter.namespace(|| "commit b"), salt_b, &committed)?;
+        bitveil_chip.expose_public(
+            layouter.namespace(|| "expose commitment b"),
+            commitment_b,
+            COMMITMENT_B_ROW,
+        )?;
+
+        let nonce = bitveil_chip.load_private(layouter.namespace(|| "load nonce"), self.nonce)?;
+        bitveil_chip.expose_public(layouter.namespace(|| "expose nonce"), nonce, NONCE_ROW)?;
+
+        // The nullifier is hashed from the same secret and word cells as are committed to and the
+        // distance is measured against, so it can only be the nullifier of the committed vector
+        let context =
+            bitveil_chip.load_private(layouter.namespace(|| "load context"), self.context)?;
+        bitveil_chip.expose_public(
+            layouter.namespace(|| "expose context"),
+            context.clone(),
+            CONTEXT_ROW,
+        )?;
+        let nullifier = bitveil_chip.nullifier(
+            layouter.namespace(|| "nullifier"),
+            secret,
+            &words_b,
+            context,
+        )?;
+        bitveil_chip.expose_public(
+            layouter.namespace(|| "expose nullifier"),
+            nullifier,
+            NULLIFIER_ROW,
+        )
+    }
+}
+
+// A circuit which proves that a probe is within a public threshold of some template enrolled in a
+// registry, without revealing which. The template is committed to like the second vector of the
+// threshold circuit, but the commitment stays private as the leaf of a Merkle path, and the root
+// of the registry is exposed in its place.
+#[derive(Clone, Debug)]
+pub struct BitVeilMembershipCircuit<F: Field> {
+    inner: BitVeilCircuit<F>,
+    threshold: Value<F>,
+
+    // The sibling and the position bit at each level of the path from the template's leaf
+    siblings: Vec<Value<F>>,
+    positions: Vec<Value<F>>,
+}
+
+impl<F: FromUniformBytes<64>> Circuit<F> for BitVeilMembershipCircuit<F> {
+    type Config = BitVeilMembershipConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self {
+            inner: self.inner.without_witnesses(),
+            threshold: Value::unknown(),
+            siblings: vec![Value::unknown(); self.siblings.len()],
+            positions: vec![Value::unknown(); self.positions.len()],
+        }
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+        // The columns and gates of the threshold circuit, plus the swap gate of the Merkle path
+        let config = BitVeilCircuit::configure(meta);
+        BitVeilChip::configure_membership(meta, config)
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<F>,
+    ) -> Result<(), Error> {
+        let bitveil_chip = BitVeilChip::<F>::construct(config.base);
+
+        let HammingDistance {
+            words_a,
+            words_b,
+            distance,
+            ..
+        } = bitveil_chip.hamming_distance(
+            layouter.namespace(|| "hamming distance"),
+            &self.inner.a,
+            &self.inner.b,
+            self.inner.length,
+        )?;
+
+        self.inner
+            .synthesize_commitment_a(&bitveil_chip, layouter.namespace(|| "a"), &words_a)?;
+
+        // The commitment to the template is only hashed up the path, so the template stays hidden
+        // among the others in the registry
+        let leaf = self.inner.synthesize_commitment_b(
+            &bitveil_chip,
+            layouter.namespace(|| "b"),
+            &words_b,
+        )?;
+        let root = bitveil_chip.merkle_root(
+            layouter.namespace(|| "registry root"),
+            config.s_swap,
+            leaf,
+            &self.siblings,
+            &self.positions,
+        )?;
+        bitveil_chip.expose_public(layouter.namespace(|| "expose root"), root, ROOT_ROW)?;
+
+        synthesize_threshold(
+            &bitveil_chip,
+            layouter,
+            distance,
+            self.threshold,
+            THRESHOLD_BITS,
+        )
+    }
+}
+
+// A variant of the circuit which compares four bits of each vector per row, looking up the
+// popcount of the XOR of each pair of nibbles instead of checking every bit with the binary and
+// XOR gates. It takes the same packed words, and proves the same distance for the same
+// commitments, in a quarter of the rows of the distance region.
+#[derive(Clone, Debug)]
+pub struct BitVeilPopcountCircuit<F: Field> {
+    inner: BitVeilCircuit<F>,
+}
+
+impl<F: FromUniformBytes<64>> Circuit<F> for BitVeilPopcountCircuit<F> {
+    type Config = BitVeilPopcountConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self {
+            inner: self.inner.without_witnesses(),
+        }
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+        // The columns and gates of the circuit which compares two vectors, plus the lookup table
+        // and the gates of the popcount region
+        let config = BitVeilCircuit::configure(meta);
+        BitVeilChip::configure_popcount(meta, config)
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<F>,
+    ) -> Result<(), Error> {
+        let bitveil_chip = BitVeilChip::<F>::construct(config.base);
+        bitveil_chip.load_popcount_table(
+            layouter.namespace(|| "load popcount table"),
+            &config.popcount,
+        )?;
+
+        let ElementSum {
+            words_a,
+            words_b,
+            sum: distance,
+        } = bitveil_chip.popcount_distance(
+            layouter.namespace(|| "popcount distance"),
+            &config.popcount,
+            &self.inner.a,
+            &self.inner.b,
+            self.inner.length,
+        )?;
+
+        self.inner.synthesize_commitments(
+            &bitveil_chip,
+            layouter.namespace(|| "commitments"),
+            &words_a,
+            &words_b,
+        )?;
+        bitveil_chip.expose_public(
+            layouter.namespace(|| "expose distance"),
+            distance,
+            DISTANCE_ROW,
+        )
+    }
+}
+
+// A circuit which compares one probe vector with several candidate vectors in a single proof, for
+// 1:N identification. The commitment to the probe is exposed first, followed by the distance to
+// and the commitment to each candidate.
+#[derive(Clone, Debug)]
+pub struct BitVeilBatchCircuit<F: Field> {
+    length: usize,
+    probe: Vec<Value<F>>,
+    candidates: Vec<Vec<Value<F>>>,
+
+    // The salts which blind the commitments to the probe and to each candidate
+    salt_probe: Value<F>,
+    salts: Vec<Value<F>>,
+}
+
+impl<F: FromUniformBytes<64>> Circuit<F> for BitVeilBatchCircuit<F> {
+    type Config = BitVeilConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self {
+            length: self.length,
+            probe: vec![Value::unknown(); self.probe.len()],
+            candidates: vec![vec![Value::unknown(); self.probe.len()]; self.candidates.len()],
+            salt_probe: Value::unknown(),
+            salts: vec![Value::unknown(); self.salts.len()],
+        }
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+        // Uses the same columns and gates as the circuit which compares two vectors
+        BitVeilCircuit::configure(meta)
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<F>,
+    ) -> Result<(), Error> {
+        let bitveil_chip = BitVeilChip::<F>::construct(config);
+
+        let mut probe_words: Option<Vec<Number<F>>> = None;
+        for (i, (candidate, salt)) in self.candidates.iter().zip(&self.salts).enumerate() {
+            // Each comparison decomposes the probe again in its own distance region
+            let HammingDistance {
+                words_a,
+                words_b,
+                distance,
+                ..
+            } = bitveil_chip.hamming_distance(
+                layouter.namespace(|| format!("hamming distance {}", i)),
+                &self.probe,
+                candidate,
+                self.length,
+            )?;
+            bitveil_chip.expose_public(
+                layouter.namespace(|| format!("expose distance {}", i)),
+                distance,
+                batch_distance_row(i),
+            )?;
+
+            // Every comparison must use the same probe, so its words are constrained to equal
+            // the words loaded by the first comparison, which are the ones committed to
+            match &probe_words {
+                Some(first) => bitveil_chip.constrain_equal(
+                    layouter.namespace(|| format!("same probe {}", i)),
+                    first,
+                    &words_a,
+                )?,
+                None => probe_words = Some(words_a),
+            }
+
+            let salt = bitveil_chip
+                .load_private(layouter.namespace(|| format!("load salt {}", i)), *salt)?;
+            let commitment = bitveil_chip.commit(
+                layouter.namespace(|| format!("commit candidate {}", i)),
+                salt,
+                &words_b,
+            )?;
+            bitveil_chip.expose_public(
+                layouter.namespace(|| format!("expose commitment {}", i)),
+                commitment,
+                batch_commitment_row(i),
+            )?;
+        }
+
+        let salt_probe =
+            bitveil_chip.load_private(layouter.namespace(|| "load probe salt"), self.salt_probe)?;
+        let commitment = bitveil_chip.commit(
+            layouter.namespace(|| "commit probe"),
+            salt_probe,
+            &probe_words.unwrap_or_default(),
+        )?;
+        bitveil_chip.expose_public(
+            layouter.namespace(|| "expose probe commitment"),
+            commitment,
+            PROBE_COMMITMENT_ROW,
+        )
+    }
+}
+
+// A circuit for iris codes and other templates with occlusion masks, which proves the Hamming
+// distance over the bits valid in both masks together with the number of those bits. The
+// commitment to each vector also covers its mask, so the masks cannot be changed to hide bits.
+#[derive(Clone, Debug)]
+pub struct BitVeilMaskedCircuit<F: Field> {
+    length: usize,
+    a: Vec<Value<F>>,
+    b: Vec<Value<F>>,
+    mask_a: Vec<Value<F>>,
+    mask_b: Vec<Value<F>>,
+
+    // The salts which blind the commitments to each vector and its mask
+    salt_a: Value<F>,
+    salt_b: Value<F>,
+}
+
+impl<F: FromUniformBytes<64>> Circuit<F> for BitVeilMaskedCircuit<F> {
+    type Config = BitVeilMaskedConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self {
+            length: self.length,
+            a: vec![Value::unknown(); self.a.len()],
+            b: vec![Value::unknown(); self.b.len()],
+            mask_a: vec![Value::unknown(); self.mask_a.len()],
+            mask_b: vec![Value::unknown(); self.mask_b.len()],
+            salt_a: Value::unknown(),
+            salt_b: Value::unknown(),
+        }
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+        // The columns and gates of the circuit which compares two vectors, plus 6 advice columns
+        // for the masks
+        let config = BitVeilCircuit::configure(meta);
+        let advice = [
+            meta.advice_column(),
+            meta.advice_column(),
+            meta.advice_column(),
+            meta.advice_column(),
+            meta.advice_column(),
+            meta.advice_column(),
+        ];
+
+        BitVeilChip::configure_masked(meta, config, advice)
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<F>,
+    ) -> Result<(), Error> {
+        let bitveil_chip = BitVeilChip::<F>::construct(config.base);
+
+        let MaskedHammingDistance {
+            words_a,
+            words_b,
+            words_mask_a,
+            words_mask_b,
+            distance,
+            valid,
+        } = bitveil_chip.masked_hamming_distance(
+            layouter.namespace(|| "masked hamming distance"),
+            &config.mask,
+            &self.a,
+            &self.b,
+            &self.mask_a,
+            &self.mask_b,
+            self.length,
+        )?;
+
+        bitveil_chip.expose_public(
+            layouter.namespace(|| "expose distance"),
+            distance,
+            DISTANCE_ROW,
+        )?;
+        bitveil_chip.expose_public(layouter.namespace(|| "expose valid bits"), valid, VALID_ROW)?;
+
+        // Commit to each vector together with its mask
+        let salt_a =
+            bitveil_chip.load_private(layouter.namespace(|| "load salt a"), self.salt_a)?;
+        let commitment_a = bitveil_chip.commit(
+            layouter.namespace(|| "commit a"),
+            salt_a,
+            &[words_a, words_mask_a].concat(),
+        )?;
+        bitveil_chip.expose_public(
+            layouter.namespace(|| "expose commitment a"),
+            commitment_a,
+            COMMITMENT_A_ROW,
+        )?;
+
+        let salt_b =
+            bitveil_chip.load_private(layouter.namespace(|| "load salt b"), self.salt_b)?;
+        let commitment_b = bitveil_chip.commit(
+            layouter.namespace(|| "commit b"),
+            salt_b,
+            &[words_b, words_mask_b].concat(),
+        )?;
+        bitveil_chip.expose_public(
+            layouter.namespace(|| "expose commitment b"),
+            commitment_b,
+            COMMITMENT_B_ROW,
+        )
+    }
+}
+
+// A circuit for templates whose bits matter unequally, which proves the sum of the weights of the
+// positions where the vectors differ. The weights are public and fixed when the keys are generated,
+// so they are bound into the verifying key instead of being public inputs of each proof.
+#[derive(Clone, Debug)]
+pub struct BitVeilWeightedCircuit<F: Field> {
+    inner: BitVeilCircuit<F>,
+
+    // The weight of each bit of the circuit, which is zero for the padding
+    weights: Vec<F>,
+}
+
+impl<F: FromUniformBytes<64>> Circuit<F> for BitVeilWeightedCircuit<F> {
+    type Config = BitVeilWeightedConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        // The weights are not witnesses, so the empty circuit keeps them
+        Self {
+            inner: self.inner.without_witnesses(),
+            weights: self.weights.clone(),
+        }
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+        // The columns and gates of the circuit which compares two vectors, plus a fixed column for
+        // the weights and an advice column for the weighted distance
+        let config = BitVeilCircuit::configure(meta);
+        let weight = meta.fixed_column();
+        let advice = meta.advice_column();
+
+        BitVeilChip::configure_weighted(meta, config, weight, advice)
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<F>,
+    ) -> Result<(), Error> {
+        let bitveil_chip = BitVeilChip::<F>::construct(config.base);
+
+        let HammingDistance {
+            words_a,
+            words_b,
+            distance,
+            ..
+        } = bitveil_chip.weighted_hamming_distance(
+            layouter.namespace(|| "weighted hamming distance"),
+            &config.weight,
+            &self.inner.a,
+            &self.inner.b,
+            &self.weights,
+            self.inner.length,
+        )?;
+
+        self.inner.synthesize_commitments(
+            &bitveil_chip,
+            layouter.namespace(|| "commitments"),
+            &words_a,
+            &words_b,
+        )?;
+        bitveil_chip.expose_public(
+            layouter.namespace(|| "expose weighted distance"),
+            distance,
+            DISTANCE_ROW,
+        )
+    }
+}
+
+// The set similarities of two binary vectors which a similarity circuit can prove a threshold for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SimilarityMetric {
+    // |a AND b| / |a OR b|, also known as the Tanimoto coefficient
+    Jaccard,
+    // 2 |a AND b| / (|a| + |b|)
+    Dice,
+}
+
+impl SimilarityMetric {
+    pub fn kind(self) -> CircuitKind {
+        match self {
+            SimilarityMetric::Jaccard => CircuitKind::Jaccard,
+            SimilarityMetric::Dice => CircuitKind::Dice,
+        }
+    }
+}
+
+impl std::fmt::Display for SimilarityMetric {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.kind().fmt(f)
+    }
+}
+
+impl std::str::FromStr for SimilarityMetric {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "jaccard" | "tanimoto" => Ok(SimilarityMetric::Jaccard),
+            "dice" => Ok(SimilarityMetric::Dice),
+            _ => Err(format!(
+                "unknown similarity '{}', expected jaccard, tanimoto or dice",
+                s
+            )),
+        }
+    }
+}
+
+// A circuit which proves that the set similarity of two committed vectors is at least a public
+// threshold T/D, without revealing the similarity. Dividing in the circuit is avoided by checking
+// the cross-multiplied inequality, which for Jaccard is |a AND b| * D >= T * |a OR b|.
+#[derive(Clone, Debug)]
+pub struct BitVeilSimilarityCircuit<F: Field> {
+    inner: BitVeilCircuit<F>,
+    metric: SimilarityMetric,
+
+    // The numerator T and the denominator D of the threshold
+    numerator: Value<F>,
+    denominator: Value<F>,
+}
+
+impl<F: FromUniformBytes<64>> Circuit<F> for BitVeilSimilarityCircuit<F> {
+    type Config = BitVeilSimilarityConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self {
+            inner: self.inner.without_witnesses(),
+            metric: self.metric,
+            numerator: Value::unknown(),
+            denominator: Value::unknown(),
+        }
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+        // The columns and gates of the circuit which compares two vectors, plus the gates which
+        // count the AND and the OR of the bits and multiply the counts by the threshold
+        let config = BitVeilCircuit::configure(meta);
+        BitVeilChip::configure_similarity(meta, config)
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<F>,
+    ) -> Result<(), Error> {
+        let bitveil_chip = BitVeilChip::<F>::construct(config.base);
+
+        // The distance region checks the bits and packs them into the words that are committed
+        // to, and the counts copy the bits from it
+        let HammingDistance {
+            words_a,
+            words_b,
+            bits_a,
+            bits_b,
+            ..
+        } = bitveil_chip.hamming_distance(
+            layouter.namespace(|| "hamming distance"),
+            &self.inner.a,
+            &self.inner.b,
+            self.inner.length,
+        )?;
+        self.inner.synthesize_commitments(
+            &bitveil_chip,
+            layouter.namespace(|| "commitments"),
+            &words_a,
+            &words_b,
+        )?;
+        let counts = &config.counts;
+        let and = bitveil_chip.and(layouter.namespace(|| "count and"), counts, &bits_a, &bits_b)?;
+        let or = bitveil_chip.or(layouter.namespace(|| "count or"), counts, &bits_a, &bits_b)?;
+
+        // The threshold is public, and both of its parts fit in RATIO_BITS bits so the sides of
+        // the inequality cannot wrap around
+        let numerator = bitveil_chip.range_check(
+            layouter.namespace(|| "check numerator"),
+            self.numerator,
+            RATIO_BITS,
+        )?;
+        let denominator = bitveil_chip.range_check(
+            layouter.namespace(|| "check denominator"),
+            self.denominator,
+            RATIO_BITS,
+        )?;
+        bitveil_chip.expose_public(
+            layouter.namespace(|| "expose numerator"),
+            numerator.clone(),
+            DISTANCE_ROW,
+        )?;
+        bitveil_chip.expose_public(
+            layouter.namespace(|| "expose denominator"),
+            denominator.clone(),
+            DENOMINATOR_ROW,
+        )?;
+
+        // Dice compares 2 |a AND b| with |a| + |b|, which is |a AND b| + |a OR b|
+        let (intersection, total) = match self.metric {
+            SimilarityMetric::Jaccard => (and, or),
+            SimilarityMetric::Dice => (
+                bitveil_chip.accumulator(
+                    layouter.namespace(|| "double and"),
+                    &[and.clone(), and.clone()],
+                )?,
+                bitveil_chip.accumulator(layouter.namespace(|| "add counts"), &[and, or])?,
+            ),
+        };
+        let lhs = bitveil_chip.product(
+            layouter.namespace(|| "multiply by denominator"),
+            config.s_product,
+            &[intersection, denominator],
+        )?;
+        let rhs = bitveil_chip.product(
+            layouter.namespace(|| "multiply by numerator"),
+            config.s_product,
+            &[numerator, total],
+        )?;
+
+        // The left side is at least the right side, since the gap between them fits in
+        // SIMILARITY_BITS bits
+        let gap = lhs.0.value().zip(rhs.0.value()).map(|(l, r)| *l - *r);
+        let gap =
+            bitveil_chip.range_check(layouter.namespace(|| "check gap"), gap, SIMILARITY_BITS)?;
+        let sum = bitveil_chip.accumulator(layouter.namespace(|| "add gap"), &[rhs, gap])?;
+        bitveil_chip.constrain_equal(layouter.namespace(|| "gap to lhs"), &[sum], &[lhs])
+    }
+}
+
+// A circuit which proves the inner product of two committed vectors of small integers, such as face
+// embeddings quantized to 8 bits. Each element is between 0 and MAX_ELEMENT, and ELEMENTS_PER_WORD
+// elements are packed into each word that is committed to, first element in the most significant
+// bits. Binary vectors are integer vectors of 0s and 1s, whose inner product counts the positions
+// where both are 1, but they are packed differently from the bits of the other circuits.
+#[derive(Clone, Debug)]
+pub struct BitVeilInnerProductCircuit<F: Field> {
+    // The words hold packed elements instead of bits, and the length counts elements
+    inner: BitVeilCircuit<F>,
+}
+
+impl<F: FromUniformBytes<64>> Circuit<F> for BitVeilInnerProductCircuit<F> {
+    type Config = BitVeilInnerProductConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self {
+            inner: self.inner.without_witnesses(),
+        }
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+        // The columns and gates of the circuit which compares two vectors, plus the lookup table
+        // and the gates of the inner-product region
+        let config = BitVeilCircuit::configure(meta);
+        let elements = BitVeilChip::configure_elements(meta, &config);
+        BitVeilChip::configure_inner_product(meta, config, elements)
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<F>,
+    ) -> Result<(), Error> {
+        let bitveil_chip = BitVeilChip::<F>::construct(config.base);
+        bitveil_chip.load_element_table(
+            layouter.namespace(|| "load element table"),
+            &config.elements,
+        )?;
+
+        let ElementSum {
+            words_a,
+            words_b,
+            sum: inner_product,
+        } = bitveil_chip.inner_product(
+            layouter.namespace(|| "inner product"),
+            &config.elements,
+            config.s_inner_product,
+            &self.inner.a,
+            &self.inner.b,
+            self.inner.length,
+        )?;
+
+        self.inner.synthesize_commitments(
+            &bitveil_chip,
+            layouter.namespace(|| "commitments"),
+            &words_a,
+            &words_b,
+        )?;
+        bitveil_chip.expose_public(
+            layouter.namespace(|| "expose inner product"),
+            inner_product,
+            DISTANCE_ROW,
+        )
+    }
+}
+
+// The distances between two integer vectors which an embedding circuit can prove a threshold for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmbeddingMetric {
+    // The sum of |a[i] - b[i]|, also known as the Manhattan distance
+    L1,
+    // The sum of (a[i] - b[i])^2, the square of the Euclidean distance
+    L2,
+}
+
+impl EmbeddingMetric {
+    pub fn kind(self) -> CircuitKind {
+        match self {
+            EmbeddingMetric::L1 => CircuitKind::L1,
+            EmbeddingMetric::L2 => CircuitKind::L2,
+        }
+    }
+}
+
+impl std::fmt::Display for EmbeddingMetric {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.kind().fmt(f)
+    }
+}
+
+impl std::str::FromStr for EmbeddingMetric {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "l1" | "manhattan" => Ok(EmbeddingMetric::L1),
+            "l2" | "euclidean" => Ok(EmbeddingMetric::L2),
+            _ => Err(format!(
+                "unknown distance '{}', expected l1, manhattan, l2 or euclidean",
+                s
+            )),
+        }
+    }
+}
+
+// A circuit which proves that the L1 or the squared L2 distance between two committed integer
+// vectors, such as quantized face embeddings, is at most a public threshold. The vectors are
+// packed and committed to like in the inner-product circuit, so the same commitment works for
+// both. Signed elements such as int8 embeddings are shifted by 128 into the range of the table,
+// which leaves every difference unchanged.
+#[derive(Clone, Debug)]
+pub struct BitVeilEmbeddingCircuit<F: Field> {
+    // The words hold packed elements instead of bits, and the length counts elements
+    inner: BitVeilCircuit<F>,
+    metric: EmbeddingMetric,
+    threshold: Value<F>,
+}
+
+impl<F: FromUniformBytes<64>> Circuit<F> for BitVeilEmbeddingCircuit<F> {
+    type Config = BitVeilEmbeddingConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self {
+            inner: self.inner.without_witnesses(),
+            metric: self.metric,
+            threshold: Value::unknown(),
+        }
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+        // The columns and gates of the circuit which compares two vectors, plus the lookup table
+        // and the gates of the element region for both distances
+        let config = BitVeilCircuit::configure(meta);
+        let elements = BitVeilChip::configure_elements(meta, &config);
+        BitVeilChip::configure_embedding(meta, config, elements)
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<F>,
+    ) -> Result<(), Error> {
+        let bitveil_chip = BitVeilChip::<F>::construct(config.base);
+        bitveil_chip.load_element_table(
+            layouter.namespace(|| "load element table"),
+            &config.elements,
+        )?;
+
+        let ElementSum {
+            words_a,
+            words_b,
+            sum: distance,
+        } = match self.metric {
+            EmbeddingMetric::L1 => bitveil_chip.l1_distance(
+                layouter.namespace(|| "l1 distance"),
+                &config.elements,
+                &config.embedding,
+                &self.inner.a,
+                &self.inner.b,
+                self.inner.length,
+            )?,
+            EmbeddingMetric::L2 => bitveil_chip.squared_l2_distance(
+                layouter.namespace(|| "squared l2 distance"),
+                &config.elements,
+                &config.embedding,
+                &self.inner.a,
+                &self.inner.b,
+                self.inner.length,
+            )?,
+        };
+
+        self.inner.synthesize_commitments(
+            &bitveil_chip,
+            layouter.namespace(|| "commitments"),
+            &words_a,
+            &words_b,
+        )?;
+        synthesize_threshold(
+            &bitveil_chip,
+            layouter,
+            distance,
+            self.threshold,
+            EMBEDDING_THRESHOLD_BITS,
+        )
+    }
+}
+
+// A circuit for templates such as iris codes, which may be captured at a slight rotation. It
+// compares the first vector with the second vector cyclically shifted by each of -shifts..=shifts
+// positions and proves the smallest of those distances, without revealing which shift gave it.
+#[derive(Clone, Debug)]
+pub struct BitVeilRotationCircuit<F: Field> {
+    inner: BitVeilCircuit<F>,
+    shifts: usize,
+
+    // The smallest distance over all of the shifts
+    minimum: Value<F>,
+}
+
+impl<F: FromUniformBytes<64>> Circuit<F> for BitVeilRotationCircuit<F> {
+    type Config = BitVeilRotationConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self {
+            inner: self.inner.without_witnesses(),
+            shifts: self.shifts,
+            minimum: Value::unknown(),
+        }
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+        // The columns and gates of the circuit which compares two vectors, plus the product gate
+        let config = BitVeilCircuit::configure(meta);
+        BitVeilRotationConfig {
+            s_product: BitVeilChip::configure_product(meta, &config),
+            base: config,
+        }
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<F>,
+    ) -> Result<(), Error> {
+        let bitveil_chip = BitVeilChip::<F>::construct(config.base);
+
+        let minimum = self.synthesize_minimum(
+            &bitveil_chip,
+            config.s_product,
+            layouter.namespace(|| "minimum distance"),
+        )?;
+        bitveil_chip.expose_public(
+            layouter.namespace(|| "expose minimum"),
+            minimum,
+            DISTANCE_ROW,
+        )
+    }
+}
+
+impl<F: FromUniformBytes<64>> BitVeilRotationCircuit<F> {
+    // Computes the distance at every shift and exposes the commitments to both vectors, returning
+    // the smallest distance so it can either be exposed or compared against a threshold
+    fn synthesize_minimum(
+        &self,
+        bitveil_chip: &BitVeilChip<F>,
+        s_product: Selector,
+        mut layouter: impl Layouter<F>,
+    ) -> Result<Number<F>, Error> {
+        let length = self.inner.length;
+
+        // The comparison without a shift holds the bits of the second vector, which the other
+        // comparisons are wired to, and the words of both vectors which are committed to
+        let HammingDistance {
+            words_a,
+            words_b,
+            bits_b,
+            distance,
+            ..
+        } = bitveil_chip.hamming_distance(
+            layouter.namespace(|| "hamming distance"),
+            &self.inner.a,
+            &self.inner.b,
+            length,
+        )?;
+        self.inner.synthesize_commitments(
+            bitveil_chip,
+            layouter.namespace(|| "commitments"),
+            &words_a,
+            &words_b,
+        )?;
+
+        let shifts = self.shifts as isize;
+        let mut distances = vec![distance];
+        for shift in (-shifts..=shifts).filter(|shift| *shift != 0) {
+            let rotated = bitveil_chip.rotated_hamming_distance(
+                layouter.namespace(|| format!("hamming distance at shift {}", shift)),
+                &self.inner.a,
+                &bits_b,
+                shift,
+                length,
+            )?;
+
+            // Every comparison must use the first vector that is committed to
+            bitveil_chip.constrain_equal(
+                layouter.namespace(|| format!("same a at shift {}", shift)),
+                &words_a,
+                &rotated.words_a,
+            )?;
+            distances.push(rotated.distance);
+        }
+
+        // The minimum is at most every distance, since the gap from it to each distance fits in
+        // THRESHOLD_BITS bits, and equals one of them, since the product of the gaps is zero
+        let minimum =
+            bitveil_chip.load_private(layouter.namespace(|| "load minimum"), self.minimum)?;
+        let mut gaps = Vec::new();
+        for (i, distance) in distances.into_iter().enumerate() {
+            let gap = distance
+                .0
+                .value()
+                .and_then(|d| self.minimum.map(|m| *d - m));
+            let gap = bitveil_chip.range_check(
+                layouter.namespace(|| format!("check gap {}", i)),
+                gap,
+                THRESHOLD_BITS,
+            )?;
+            let sum = bitveil_chip.accumulator(
+                layouter.namespace(|| format!("add gap {}", i)),
+                &[minimum.clone(), gap.clone()],
+            )?;
+            bitveil_chip.constrain_equal(
+                layouter.namespace(|| format!("gap {} from minimum", i)),
+                &[sum],
+                &[distance],
+            )?;
+            gaps.push(gap);
+        }
+        let product =
+            bitveil_chip.product(layouter.namespace(|| "multiply gaps"), s_product, &gaps)?;
+        bitveil_chip.constrain_constant(
+            layouter.namespace(|| "some gap is zero"),
+            product,
+            F::ZERO,
+        )?;
+
+        Ok(minimum)
+    }
+}
+
+// A variant of the rotation circuit which keeps the smallest distance private and only proves
+// that it is at most a public threshold
+#[derive(Clone, Debug)]
+pub struct BitVeilRotationThresholdCircuit<F: Field> {
+    inner: BitVeilRotationCircuit<F>,
+    threshold: Value<F>,
+}
+
+impl<F: FromUniformBytes<64>> Circuit<F> for BitVeilRotationThresholdCircuit<F> {
+    type Config = BitVeilRotationConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self {
+            inner: self.inner.without_witnesses(),
+            threshold: Value::unknown(),
+        }
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+        // Uses the same columns and gates as the rotation circuit which exposes the distance
+        BitVeilRotationCircuit::configure(meta)
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<F>,
+    ) -> Result<(), Error> {
+        let bitveil_chip = BitVeilChip::<F>::construct(config.base);
+
+        let minimum = self.inner.synthesize_minimum(
+            &bitveil_chip,
+            config.s_product,
+            layouter.namespace(|| "minimum distance"),
+        )?;
+        synthesize_threshold(
+            &bitveil_chip,
+            layouter,
+            minimum,
+            self.threshold,
+            THRESHOLD_BITS,
+        )
+    }
+}
+
+// Draws the layout of the circuit. Super useful for debugging.
+#[cfg(not(target_family = "wasm"))]
+pub fn draw_circuit<F: FromUniformBytes<64>>(
+    k: u32,
+    circuit: &BitVeilCircuit<F>,
+) -> Result<(), BitVeilError> {
+    use plotters::prelude::*;
+    let draw_error = |e: DrawingAreaErrorKind<_>| BitVeilError::Draw(e.to_string());
+    let base = BitMapBackend::new("layout.png", (1600, 1600)).into_drawing_area();
+    base.fill(&WHITE).map_err(draw_error)?;
+    let base = base
+        .titled("BitVeil Circuit", ("sans-serif", 24))
+        .map_err(draw_error)?;
+
+    halo2_proofs::dev::CircuitLayout::default()
+        .show_equality_constraints(true)
+        .render(k, circuit, &base)
+        .map_err(draw_error)
+}
+
+// Rounds a vector length up to the length of the circuit which handles it
+pub fn circuit_length(len: usize) -> usize {
+    len.max(1).div_ceil(CHUNK_LENGTH) * CHUNK_LENGTH
+}
+
+// The number of bits in each packed word of a circuit for vectors of the given circuit length,
+// which are all WORD_LENGTH bits long except for a shorter last word
+fn word_lengths(length: usize) -> impl Iterator<Item = usize> {
+    (0..length)
+        .step_by(WORD_LENGTH)
+        .map(move |start| WORD_LENGTH.min(length - start))
+}
+
+// The rows used to load the salt of a commitment and hash it together with the given number of
+// packed words
+fn commitment_rows(words: usize) -> usize {
+    let absorbs = (words + 1).div_ceil(poseidon::RATE);
+    1 + absorbs * poseidon::ROWS_PER_ABSORB
+}
+
+// Picks the smallest k whose 2^k rows fit the circuit (including the threshold check) for vectors
+// of the given length, which also fits the weighted circuit. The row count is an upper bound which
+// assumes no two regions share rows.
+pub fn k_for_length(len: usize) -> u32 {
+    let n = circuit_length(len);
+
+    let distance_rows = n + 1;
+    let commitment_rows = 2 * commitment_rows(n.div_ceil(WORD_LENGTH));
+    let threshold_rows = THRESHOLD_BITS + 4;
+
+    // Leave room for the blinding rows
+    let reserved_rows = RESERVED_ROWS;
+
+    let rows = distance_rows + commitment_rows + threshold_rows + reserved_rows;
+    rows.next_power_of_two().trailing_zeros()
+}
+
+// Picks the smallest k whose 2^k rows fit a batch circuit comparing a probe with the given number
+// of candidates, which has a distance region per candidate and a commitment per vector
+pub fn k_for_batch(len: usize, candidates: usize) -> u32 {
+    let n = circuit_length(len);
+    let candidates = candidates.max(1);
+
+    let distance_rows = candidates * (n + 1);
+    let commitment_rows = (candidates + 1) * commitment_rows(n.div_ceil(WORD_LENGTH));
+
+    let rows = distance_rows + commitment_rows + RESERVED_ROWS;
+    rows.next_power_of_two().trailing_zeros()
+}
+
+// Picks the smallest k whose 2^k rows fit the masked circuit, whose commitments hash the words of
+// each mask along with the words of the vector
+pub fn k_for_masked(len: usize) -> u32 {
+    let n = circuit_length(len);
+
+    let distance_rows = n + 1;
+    let commitment_rows = 2 * commitment_rows(2 * n.div_ceil(WORD_LENGTH));
+
+    let rows = distance_rows + commitment_rows + RESERVED_ROWS;
+    rows.next_power_of_two().trailing_zeros()
+}
+
+// Picks the smallest k whose 2^k rows fit the rotation circuit (including the threshold check),
+// which has a distance region per shift and checks the gap from the minimum to each distance
+pub fn k_for_rotation(len: usize, shifts: usize) -> u32 {
+    let n = circuit_length(len);
+    let comparisons = 2 * shifts + 1;
+
+    let distance_rows = comparisons * (n + 1);
+    let commitment_rows = 2 * commitment_rows(n.div_ceil(WORD_LENGTH));
+    let minimum_rows = 1 + comparisons * (THRESHOLD_BITS + 5) + comparisons + 1;
+    let threshold_rows = THRESHOLD_BITS + 4;
+
+    let rows = distance_rows + commitment_rows + minimum_rows + threshold_rows + RESERVED_ROWS;
+    rows.next_power_of_two().trailing_zeros()
+}
+
+// Picks the smallest k whose 2^k rows fit a similarity circuit, which counts the AND and the OR of
+// the bits in regions as long as the distance region and checks the inequality of the threshold
+pub fn k_for_similarity(len: usize) -> u32 {
+    let n = circuit_length(len);
+
+    let count_rows = 3 * (n + 1);
+    let commitment_rows = 2 * commitment_rows(n.div_ceil(WORD_LENGTH));
+    let inequality_rows = 2 * (RATIO_BITS + 1) + (SIMILARITY_BITS + 1) + 5 * 3;
+
+    let rows = count_rows + commitment_rows + inequality_rows + RESERVED_ROWS;
+    rows.next_power_of_two().trailing_zeros()
+}
+
+// Picks the smallest k whose 2^k rows fit an inner-product circuit for integer vectors of the given
+// length. The lookup table takes a row per element value in its own column, so it only needs more
+// rows than the regions for short vectors.
+pub fn k_for_inner_product(len: usize) -> u32 {
+    let n = circuit_length(len);
+
+    let inner_product_rows = n + 1;
+    let commitment_rows = 2 * commitment_rows(n / ELEMENTS_PER_WORD);
+    let table_rows = MAX_ELEMENT as usize + 1;
+
+    let rows = (inner_product_rows + commitment_rows).max(table_rows) + RESERVED_ROWS;
+    rows.next_power_of_two().trailing_zeros()
+}
+
+// Picks the smallest k whose 2^k rows fit the popcount circuit for vectors of the given length,
+// which has a row per nibble instead of a row per bit. The lookup table takes a row per pair of
+// nibbles in its own columns, so it only needs more rows than the regions for short vectors.
+pub fn k_for_popcount(len: usize) -> u32 {
+    let n = circuit_length(len);
+
+    let popcount_rows = n / NIBBLE_BITS + 1;
+    let commitment_rows = 2 * commitment_rows(n.div_ceil(WORD_LENGTH));
+    let table_rows = (NIBBLE_VALUES * NIBBLE_VALUES) as usize;
+
+    let rows = (popcount_rows + commitment_rows).max(table_rows) + RESERVED_ROWS;
+    rows.next_power_of_two().trailing_zeros()
+}
+
+// Picks the smallest k whose 2^k rows fit an embedding circuit for integer vectors of the given
+// length, which adds the threshold check to the rows of the inner-product circuit
+pub fn k_for_embedding(len: usize) -> u32 {
+    let n = circuit_length(len);
+
+    let distance_rows = n + 1;
+    let commitment_rows = 2 * commitment_rows(n / ELEMENTS_PER_WORD);
+    let threshold_rows = EMBEDDING_THRESHOLD_BITS + 4;
+    let table_rows = MAX_ELEMENT as usize + 1;
+
+    let rows = (distance_rows + commitment_rows + threshold_rows).max(table_rows) + RESERVED_ROWS;
+    rows.next_power_of_two().trailing_zeros()
+}
+
+// Picks the smallest k whose 2^k rows fit the membership circuit for vectors of the given length,
+// which adds a swap row and a hash per level of the registry to the rows of the threshold circuit
+pub fn k_for_membership(len: usize) -> u32 {
+    let n = circuit_length(len);
+
+    let distance_rows = n + 1;
+    let commitment_rows = 2 * commitment_rows(n.div_ceil(WORD_LENGTH));
+    let threshold_rows = THRESHOLD_BITS + 4;
+    let path_rows = REGISTRY_DEPTH * (1 + poseidon::ROWS_PER_ABSORB);
+
+    let rows = distance_rows + commitment_rows + threshold_rows + path_rows + RESERVED_ROWS;
+    rows.next_power_of_two().trailing_zeros()
+}
+
+// Picks the smallest k whose 2^k rows fit the session circuit for vectors of the given length,
+// which hashes the words of vector B into the nullifier as well as committing to them, and adds
+// the nullifier secret, the nonce and the context to the rows of the circuit
+pub fn k_for_session(len: usize) -> u32 {
+    let n = circuit_length(len);
+    let words = n.div_ceil(WORD_LENGTH);
+
+    let distance_rows = n + 1;
+    // The commitment to vector B and the nullifier both hash the secret as well, and the nullifier
+    // hashes the context in place of a salt
+    let commitment_rows = commitment_rows(words) + 2 * commitment_rows(words + 1);
+    let session_rows = 3;
+
+    let rows = distance_rows + commitment_rows + session_rows + RESERVED_ROWS;
+    rows.next_power_of_two().trailing_zeros()
+}
+
+// Returns the k the setup parameters were generated with, since halo2 keeps it private
+pub fn params_k(params: &Params<EqAffine>) -> u32 {
+    params.get_g().len().trailing_zeros()
+}
+
+// Generates an empty circuit for vectors of the given length. Useful for generating the
+// proving/verifying keys.
+pub fn empty_circuit(length: usize) -> BitVeilCircuit<Fp> {
+    let length = circuit_length(length);
+    let words = length.div_ceil(WORD_LENGTH);
+    BitVeilCircuit {
+        length,
+        a: vec![Value::unknown(); words],
+        b: vec![Value::unknown(); words],
+        salt_a: Value::unknown(),
+        salt_b: Value::unknown(),
+    }
+}
+
+// Generates an empty threshold circuit for generating the proving/verifying keys
+pub fn empty_threshold_circuit(length: usize) -> BitVeilThresholdCircuit<Fp> {
+    BitVeilThresholdCircuit {
+        inner: empty_circuit(length),
+        threshold: Value::unknown(),
+    }
+}
+
+// Generates an empty batch circuit comparing a probe with the given number of candidates
+pub fn empty_batch_circuit(length: usize, candidates: usize) -> BitVeilBatchCircuit<Fp> {
+    let length = circuit_length(length);
+    let words = length.div_ceil(WORD_LENGTH);
+    BitVeilBatchCircuit {
+        length,
+        probe: vec![Value::unknown(); words],
+        candidates: vec![vec![Value::unknown(); words]; candidates],
+        salt_probe: Value::unknown(),
+        salts: vec![Value::unknown(); candidates],
+    }
+}
+
+// Generates an empty masked circuit for generating the proving/verifying keys
+pub fn empty_masked_circuit(length: usize) -> BitVeilMaskedCircuit<Fp> {
+    let length = circuit_length(length);
+    let words = length.div_ceil(WORD_LENGTH);
+    BitVeilMaskedCircuit {
+        length,
+        a: vec![Value::unknown(); words],
+        b: vec![Value::unknown(); words],
+        mask_a: vec![Value::unknown(); words],
+        mask_b: vec![Value::unknown(); words],
+        salt_a: Value::unknown(),
+        salt_b: Value::unknown(),
+    }
+}
+
+// Generates an empty rotation circuit comparing the vectors at the given number of shifts in
+// each direction
+pub fn empty_rotation_circuit(length: usize, shifts: usize) -> BitVeilRotationCircuit<Fp> {
+    BitVeilRotationCircuit {
+        inner: empty_circuit(length),
+        shifts,
+        minimum: Value::unknown(),
+    }
+}
+
+// Generates an empty rotation threshold circuit for generating the proving/verifying keys
+pub fn empty_rotation_threshold_circuit(
+    length: usize,
+    shifts: usize,
+) -> BitVeilRotationThresholdCircuit<Fp> {
+    BitVeilRotationThresholdCircuit {
+        inner: empty_rotation_circuit(length, shifts),
+        threshold: Value::unknown(),
+    }
+}
+
+// Generates an empty weighted circuit, whose keys depend on the weights
+pub fn empty_weighted_circuit(length: usize, weights: &[u64]) -> BitVeilWeightedCircuit<Fp> {
+    BitVeilWeightedCircuit {
+        inner: empty_circuit(length),
+        weights: weight_column(weights, length),
+    }
+}
+
+// Pads the weights of vectors of the given length with zeros to the circuit length
+fn weight_column(weights: &[u64], length: usize) -> Vec<Fp> {
+    let mut column: Vec<Fp> = weights.iter().map(|weight| Fp::from(*weight)).collect();
+    column.resize(circuit_length(length), Fp::zero());
+    column
+}
+
+// Generates an empty similarity circuit for generating the proving/verifying keys
+pub fn empty_similarity_circuit(
+    length: usize,
+    metric: SimilarityMetric,
+) -> BitVeilSimilarityCircuit<Fp> {
+    BitVeilSimilarityCircuit {
+        inner: empty_circuit(length),
+        metric,
+        numerator: Value::unknown(),
+        denominator: Value::unknown(),
+    }
+}
+
+// Generates an empty inner-product circuit for integer vectors of the given length
+pub fn empty_inner_product_circuit(length: usize) -> BitVeilInnerProductCircuit<Fp> {
+    let length = circuit_length(length);
+    let words = length / ELEMENTS_PER_WORD;
+    BitVeilInnerProductCircuit {
+        inner: BitVeilCircuit {
+            length,
+            a: vec![Value::unknown(); words],
+            b: vec![Value::unknown(); words],
+            salt_a: Value::unknown(),
+            salt_b: Value::unknown(),
+        },
+    }
+}
+
+// Generates an empty popcount circuit for generating the proving/verifying keys
+pub fn empty_popcount_circuit(length: usize) -> BitVeilPopcountCircuit<Fp> {
+    BitVeilPopcountCircuit {
+        inner: empty_circuit(length),
+    }
+}
+
+// Generates an empty embedding circuit for integer vectors of the given length
+pub fn empty_embedding_circuit(
+    length: usize,
+    metric: EmbeddingMetric,
+) -> BitVeilEmbeddingCircuit<Fp> {
+    BitVeilEmbeddingCircuit {
+        inner: empty_inner_product_circuit(length).inner,
+        metric,
+        threshold: Value::unknown(),
+    }
+}
+
+// Generates an empty session circuit for generating the proving/verifying keys
+pub fn empty_session_circuit(length: usize) -> BitVeilSessionCircuit<Fp> {
+    BitVeilSessionCircuit {
+        inner: empty_circuit(length),
+        secret: Value::unknown(),
+        nonce: Value::unknown(),
+        context: Value::unknown(),
+    }
+}
+
+// Generates an empty membership circuit, whose path always has REGISTRY_DEPTH levels
+pub fn empty_membership_circuit(length: usize) -> BitVeilMembershipCircuit<Fp> {
+    BitVeilMembershipCircuit {
+        inner: empty_circuit(length),
+        threshold: Value::unknown(),
+        siblings: vec![Value::unknown(); REGISTRY_DEPTH],
+        positions: vec![Value::unknown(); REGISTRY_DEPTH],
+    }
+}
+
+// Checks that a vector only contains binary values
+pub fn check_binary_vector(bits: &[u64], vector: &'static str) -> Result<(), BitVeilError> {
+    match bits.iter().position(|bit| *bit > 1) {
+        Some(position) => Err(BitVeilError::NonBinaryInput {
+            vector,
+            position,
+            value: bits[position],
+        }),
+        None => Ok(()),
+    }
+}
+
+// Checks that both vectors have the same supported length and only contain binary values
+pub fn check_binary_vectors(a: &[u64], b: &[u64]) -> Result<(), BitVeilError> {
+    if a.len() != b.len() || a.is_empty() || a.len() > MAX_LENGTH {
+        return Err(BitVeilError::InvalidLength {
+            a: a.len(),
+            b: b.len(),
+        });
+    }
+    check_binary_vector(a, "A")?;
+    check_binary_vector(b, "B")
+}
+
+// Checks that every element of an integer vector is at most MAX_ELEMENT
+pub fn check_integer_vector(values: &[u64], vector: &'static str) -> Result<(), BitVeilError> {
+    match values.iter().position(|value| *value > MAX_ELEMENT) {
+        Some(position) => Err(BitVeilError::ElementOutOfRange {
+            vector,
+            position,
+            value: values[position],
+        }),
+        None => Ok(()),
+    }
+}
+
+// Checks that both integer vectors have the same supported length and only contain elements of at
+// most MAX_ELEMENT
+pub fn check_integer_vectors(a: &[u64], b: &[u64]) -> Result<(), BitVeilError> {
+    if a.len() != b.len() || a.is_empty() || a.len() > MAX_LENGTH {
+        return Err(BitVeilError::InvalidLength {
+            a: a.len(),
+            b: b.len(),
+        });
+    }
+    check_integer_vector(a, "A")?;
+    check_integer_vector(b, "B")
+}
+
+// Creates a circuit from two vector inputs and the salts of their commitments
+pub fn create_circuit(
+    a: Vec<u64>,
+    b: Vec<u64>,
+    salt_a: Fp,
+    salt_b: Fp,
+) -> Result<BitVeilCircuit<Fp>, BitVeilError> {
+    check_binary_vectors(&a, &b)?;
+
+    // Put inputs into circuit-friendly form, packing the bits into the words the circuit decomposes
+    let a_vec: Vec<Value<Fp>> = pack_bits(&a).into_iter().map(Value::known).collect();
+    let b_vec: Vec<Value<Fp>> = pack_bits(&b).into_iter().map(Value::known).collect();
+
+    // Create circuit from inputs
+    Ok(BitVeilCircuit {
+        length: circuit_length(a.len()),
+        a: a_vec,
+        b: b_vec,
+        salt_a: Value::known(salt_a),
+        salt_b: Value::known(salt_b),
+    })
+}
+
+// Creates a circuit from two vectors of packed words, such as the bytes of a stored template, each
+// holding the bits of the vector most significant bit first
+pub fn create_packed_circuit<W: PackedWord>(
+    a: &[W],
+    b: &[W],
+    length: usize,
+    salt_a: Fp,
+    salt_b: Fp,
+) -> Result<BitVeilCircuit<Fp>, BitVeilError> {
+    create_circuit(
+        unpack_words(a, length, "A")?,
+        unpack_words(b, length, "B")?,
+        salt_a,
+        salt_b,
+    )
+}
+
+// Creates a circuit which proves that the distance between two vectors is at most the threshold
+pub fn create_threshold_circuit(
+    a: Vec<u64>,
+    b: Vec<u64>,
+    salt_a: Fp,
+    salt_b: Fp,
+    threshold: u64,
+) -> Result<BitVeilThresholdCircuit<Fp>, BitVeilError> {
+    if threshold > MAX_THRESHOLD {
+        return Err(BitVeilError::InvalidThreshold(threshold));
+    }
+    // The proof could not be generated, so fail before running the prover
+    let distance = a.iter().zip(&b).filter(|(x, y)| x != y).count() as u64;
+    if distance > threshold {
+        return Err(BitVeilError::ThresholdExceeded {
+            distance,
+            threshold,
+        });
+    }
+
+    Ok(BitVeilThresholdCircuit {
+        inner: create_circuit(a, b, salt_a, salt_b)?,
+        threshold: Value::known(Fp::from(threshold)),
+    })
+}
+
+// Creates a circuit which proves the distance between two vectors in the session with the given
+// nonce, committing to vector B together with its nullifier secret and deriving its nullifier in
+// the given context
+pub fn create_session_circuit(
+    a: Vec<u64>,
+    b: Vec<u64>,
+    salt_a: Fp,
+    salt_b: Fp,
+    secret: Fp,
+    nonce: Fp,
+    context: Fp,
+) -> Result<BitVeilSessionCircuit<Fp>, BitVeilError> {
+    Ok(BitVeilSessionCircuit {
+        inner: create_circuit(a, b, salt_a, salt_b)?,
+        secret: Value::known(secret),
+        nonce: Value::known(nonce),
+        context: Value::known(context),
+    })
+}
+
+// Creates a circuit which proves that the distance between a probe and the template at the end of
+// the path is at most the threshold
+pub fn create_membership_circuit(
+    probe: Vec<u64>,
+    template: Vec<u64>,
+    salt_probe: Fp,
+    salt_template: Fp,
+    threshold: u64,
+    path: &MerklePath,
+) -> Result<BitVeilMembershipCircuit<Fp>, BitVeilError> {
+    let BitVeilThresholdCircuit { inner, threshold } =
+        create_threshold_circuit(probe, template, salt_probe, salt_template, threshold)?;
+
+    Ok(BitVeilMembershipCircuit {
+        inner,
+        threshold,
+        siblings: path.siblings().iter().map(|s| Value::known(*s)).collect(),
+        positions: (0..REGISTRY_DEPTH)
+            .map(|level| Value::known(Fp::from(path.is_right(level) as u64)))
+            .collect(),
+    })
+}
+
+// Checks that both vectors and both masks have the same supported length and only contain binary
+// values
+pub fn check_masked_vectors(
+    a: &[u64],
+    b: &[u64],
+    mask_a: &[u64],
+    mask_b: &[u64],
+) -> Result<(), BitVeilError> {
+    check_binary_vectors(a, b)?;
+    for mask in [mask_a, mask_b] {
+        if mask.len() != a.len() {
+            return Err(BitVeilError::InvalidLength {
+                a: a.len(),
+                b: mask.len(),
+            });
+        }
+    }
+    check_binary_vector(mask_a, "mask A")?;
+    check_binary_vector(mask_b, "mask B")
+}
+
+// Creates a masked circuit from two vectors, their masks and the salts of their commitments
+pub fn create_masked_circuit(
+    a: Vec<u64>,
+    b: Vec<u64>,
+    mask_a: Vec<u64>,
+    mask_b: Vec<u64>,
+    salt_a: Fp,
+    salt_b: Fp,
+) -> Result<BitVeilMaskedCircuit<Fp>, BitVeilError> {
+    check_masked_vectors(&a, &b, &mask_a, &mask_b)?;
+
+    let pack = |bits: &[u64]| -> Vec<Value<Fp>> {
+        pack_bits(bits).into_iter().map(Value::known).collect()
+    };
+    Ok(BitVeilMaskedCircuit {
+        length: circuit_length(a.len()),
+        a: pack(&a),
+        b: pack(&b),
+        mask_a: pack(&mask_a),
+        mask_b: pack(&mask_b),
+        salt_a: Value::known(salt_a),
+        salt_b: Value::known(salt_b),
+    })
+}
+
+// Checks that there is one weight of at most MAX_WEIGHT for each bit of vectors of the given length
+pub fn check_weights(weights: &[u64], length: usize) -> Result<(), BitVeilError> {
+    if weights.len() != length || length == 0 || length > MAX_LENGTH {
+        return Err(BitVeilError::InvalidWeights {
+            weights: weights.len(),
+            length,
+        });
+    }
+    match weights.iter().position(|weight| *weight > MAX_WEIGHT) {
+        Some(position) => Err(BitVeilError::InvalidWeight {
+            position,
+            weight: weights[position],
+        }),
+        None => Ok(()),
+    }
+}
+
+// Creates a weighted circuit from two vectors, the salts of their commitments and the weight of
+// each bit
+pub fn create_weighted_circuit(
+    a: Vec<u64>,
+    b: Vec<u64>,
+    salt_a: Fp,
+    salt_b: Fp,
+    weights: &[u64],
+) -> Result<BitVeilWeightedCircuit<Fp>, BitVeilError> {
+    check_weights(weights, a.len())?;
+
+    Ok(BitVeilWeightedCircuit {
+        weights: weight_column(weights, a.len()),
+        inner: create_circuit(a, b, salt_a, salt_b)?,
+    })
+}
+
+// Creates a popcount circuit from two vector inputs and the salts of their commitments
+pub fn create_popcount_circuit(
+    a: Vec<u64>,
+    b: Vec<u64>,
+    salt_a: Fp,
+    salt_b: Fp,
+) -> Result<BitVeilPopcountCircuit<Fp>, BitVeilError> {
+    Ok(BitVeilPopcountCircuit {
+        inner: create_circuit(a, b, salt_a, salt_b)?,
+    })
+}
+
+// Creates an inner-product circuit from two integer vectors and the salts of their commitments
+pub fn create_inner_product_circuit(
+    a: Vec<u64>,
+    b: Vec<u64>,
+    salt_a: Fp,
+    salt_b: Fp,
+) -> Result<BitVeilInnerProductCircuit<Fp>, BitVeilError> {
+    check_integer_vectors(&a, &b)?;
+
+    Ok(BitVeilInnerProductCircuit {
+        inner: BitVeilCircuit {
+            length: circuit_length(a.len()),
+            a: pack_elements(&a).into_iter().map(Value::known).collect(),
+            b: pack_elements(&b).into_iter().map(Value::known).collect(),
+            salt_a: Value::known(salt_a),
+            salt_b: Value::known(salt_b),
+        },
+    })
+}
+
+// Creates an embedding circuit which proves that the distance between two integer vectors is at
+// most the threshold
+pub fn create_embedding_circuit(
+    a: Vec<u64>,
+    b: Vec<u64>,
+    salt_a: Fp,
+    salt_b: Fp,
+    metric: EmbeddingMetric,
+    threshold: u64,
+) -> Result<BitVeilEmbeddingCircuit<Fp>, BitVeilError> {
+    if threshold > MAX_EMBEDDING_THRESHOLD {
+        return Err(BitVeilError::InvalidEmbeddingThreshold(threshold));
+    }
+    // The proof could not be generated, so fail before running the prover
+    let distance = calculate_embedding_distance(&a, &b, metric)?;
+    if distance > threshold {
+        return Err(BitVeilError::EmbeddingThresholdExceeded {
+            metric,
+            distance,
+            threshold,
+        });
+    }
+
+    Ok(BitVeilEmbeddingCircuit {
+        inner: create_inner_product_circuit(a, b, salt_a, salt_b)?.inner,
+        metric,
+        threshold: Value::known(Fp::from(threshold)),
+    })
+}
+
+// Checks that a similarity threshold T/D is at most 1 and that D is between 1 and MAX_RATIO
+pub fn check_ratio(numerator: u64, denominator: u64) -> Result<(), BitVeilError> {
+    if denominator == 0 || denominator > MAX_RATIO || numerator > denominator {
+        return Err(BitVeilError::InvalidRatio {
+            numerator,
+            denominator,
+        });
+    }
+    Ok(())
+}
+
+// Creates a similarity circuit which proves that the similarity of two vectors is at least the
+// threshold numerator / denominator
+pub fn create_similarity_circuit(
+    a: Vec<u64>,
+    b: Vec<u64>,
+    salt_a: Fp,
+    salt_b: Fp,
+    metric: SimilarityMetric,
+    numerator: u64,
+    denominator: u64,
+) -> Result<BitVeilSimilarityCircuit<Fp>, BitVeilError> {
+    check_ratio(numerator, denominator)?;
+    // The proof could not be generated, so fail before running the prover
+    let similarity = calculate_similarity(&a, &b, metric)?;
+    if similarity.0 * denominator < numerator * similarity.1 {
+        return Err(BitVeilError::SimilarityBelowThreshold {
+            similarity,
+            threshold: (numerator, denominator),
+        });
+    }
+
+    Ok(BitVeilSimilarityCircuit {
+        inner: create_circuit(a, b, salt_a, salt_b)?,
+        metric,
+        numerator: Value::known(Fp::from(numerator)),
+        denominator: Value::known(Fp::from(denominator)),
+    })
+}
+
+// Checks that vectors of the given length can be rotated by the given number of shifts. The
+// circuit rotates the vectors within the circuit length, so the length must not need padding, and
+// each direction has fewer shifts than half the length so no two shifts coincide.
+pub fn check_rotation(length: usize, shifts: usize) -> Result<(), BitVeilError> {
+    if shifts == 0
+        || shifts > MAX_SHIFTS
+        || length != circuit_length(length)
+        || 2 * shifts >= length
+    {
+        return Err(BitVeilError::InvalidShifts { shifts, length });
+    }
+    Ok(())
+}
+
+// The smallest distance between the first vector and the second vector rotated by each of
+// -shifts..=shifts positions, where rotating by `shift` compares bit `i` of the first vector with
+// bit `i + shift` of the second
+fn rotated_distance(a: &[u64], b: &[u64], shifts: usize) -> Result<u64, BitVeilError> {
+    check_binary_vectors(a, b)?;
+    check_rotation(a.len(), shifts)?;
+
+    let n = a.len() as isize;
+    let shifts = shifts as isize;
+    Ok((-shifts..=shifts)
+        .map(|shift| {
+            a.iter()
+                .enumerate()
+                .map(|(i, x)| x ^ b[(i as isize + shift).rem_euclid(n) as usize])
+                .sum::<u64>()
+        })
+        .min()
+        .unwrap_or_default())
+}
+
+// Creates a rotation circuit from two vectors and the salts of their commitments, which compares
+// them at each of -shifts..=shifts positions
+pub fn create_rotation_circuit(
+    a: Vec<u64>,
+    b: Vec<u64>,
+    salt_a: Fp,
+    salt_b: Fp,
+    shifts: usize,
+) -> Result<BitVeilRotationCircuit<Fp>, BitVeilError> {
+    let minimum = rotated_distance(&a, &b, shifts)?;
+
+    Ok(BitVeilRotationCircuit {
+        inner: create_circuit(a, b, salt_a, salt_b)?,
+        shifts,
+        minimum: Value::known(Fp::from(minimum)),
+    })
+}
+
+// Creates a rotation circuit which proves the smallest distance is at most the threshold
+pub fn create_rotation_threshold_circuit(
+    a: Vec<u64>,
+    b: Vec<u64>,
+    salt_a: Fp,
+    salt_b: Fp,
+    shifts: usize,
+    threshold: u64,
+) -> Result<BitVeilRotationThresholdCircuit<Fp>, BitVeilError> {
+    if threshold > MAX_THRESHOLD {
+        return Err(BitVeilError::InvalidThreshold(threshold));
+    }
+    // The proof could not be generated, so fail before running the prover
+    let distance = rotated_distance(&a, &b, shifts)?;
+    if distance > threshold {
+        return Err(BitVeilError::ThresholdExceeded {
+            distance,
+            threshold,
+        });
+    }
+
+    Ok(BitVeilRotationThresholdCircuit {
+        inner: create_rotation_circuit(a, b, salt_a, salt_b, shifts)?,
+        threshold: Value::known(Fp::from(threshold)),
+    })
+}
+
+// Checks that a batch has a supported number of candidates with one salt each, and that every
+// candidate is a binary vector of the same length as the probe
+pub fn check_batch(
+    probe: &[u64],
+    candidates: &[Vec<u64>],
+    salts: usize,
+) -> Result<(), BitVeilError> {
+    if candidates.is_empty() || candidates.len() > MAX_CANDIDATES || candidates.len() != salts {
+        return Err(BitVeilError::InvalidCandidates {
+            candidates: candidates.len(),
+            salts,
+        });
+    }
+    for candidate in candidates {
+        if candidate.len() != probe.len() || probe.is_empty() || probe.len() > MAX_LENGTH {
+            return Err(BitVeilError::InvalidLength {
+                a: probe.len(),
+                b: candidate.len(),
+            });
+        }
+        check_binary_vector(candidate, "candidate")?;
+    }
+    check_binary_vector(probe, "probe")
+}
+
+// Creates a circuit which compares a probe with each of the candidates, given the salts of the
+// commitments to the probe and to each candidate
+pub fn create_batch_circuit(
+    probe: Vec<u64>,
+    candidates: Vec<Vec<u64>>,
+    salt_probe: Fp,
+    salts: Vec<Fp>,
+) -> Result<BitVeilBatchCircuit<Fp>, BitVeilError> {
+    check_batch(&probe, &candidates, salts.len())?;
+
+    let pack = |bits: &[u64]| -> Vec<Value<Fp>> {
+        pack_bits(bits).into_iter().map(Value::known).collect()
+    };
+    Ok(BitVeilBatchCircuit {
+        length: circuit_length(probe.len()),
+        probe: pack(&probe),
+        candidates: candidates.iter().map(|candidate| pack(candidate)).collect(),
+        salt_probe: Value::known(salt_probe),
+        salts: salts.into_iter().map(Value::known).collect(),
+    })
+}
+
+// Generates setup parameters using k, which is the number of rows of the circuit
+// can fit in and must be a power of two
+pub fn generate_setup_params(k: u32) -> Params<EqAffine> {
+    Params::<EqAffine>::new(k)
+}
+
+// Reads setup parameters written by `Params::write`
+pub fn read_params<R: Read>(reader: &mut R) -> Result<Params<EqAffine>, BitVeilError> {
+    Params::<EqAffine>::read(reader).map_err(BitVeilError::ParamsDecode)
+}
+
+// Generates the verifying and proving keys. We can pass in an empty circuit to generate these
+pub fn generate_keys<C: Circuit<Fp>>(
+    params: &Params<EqAffine>,
+    circuit: &C,
+) -> Result<(ProvingKey<EqAffine>, VerifyingKey<EqAffine>), BitVeilError> {
+    // just to emphasize that for vk, pk we don't need to know the value of `x`
+    let vk = keygen_vk(params, circuit).map_err(BitVeilError::KeyGen)?;
+    let pk = keygen_pk(params, vk.clone(), circuit).map_err(BitVeilError::KeyGen)?;
+    Ok((pk, vk))
+}
+
+// The circuits which keys can be generated for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CircuitKind {
+    Distance,
+    Threshold,
+    Batch,
+    Masked,
+    Rotation,
+    #[serde(rename = "rotation-threshold")]
+    RotationThreshold,
+    Weighted,
+    Jaccard,
+    Dice,
+    InnerProduct,
+    L1,
+    L2,
+    Popcount,
+    Membership,
+    Session,
+}
+
+impl CircuitKind {
+    fn id(self) -> u8 {
+        match self {
+            CircuitKind::Distance => 0,
+            CircuitKind::Threshold => 1,
+            CircuitKind::Batch => 2,
+            CircuitKind::Masked => 3,
+            CircuitKind::Rotation => 4,
+            CircuitKind::RotationThreshold => 5,
+            CircuitKind::Weighted => 6,
+            CircuitKind::Jaccard => 7,
+            CircuitKind::Dice => 8,
+            CircuitKind::InnerProduct => 9,
+            CircuitKind::L1 => 10,
+            CircuitKind::L2 => 11,
+            CircuitKind::Popcount => 12,
+            CircuitKind::Membership => 13,
+            CircuitKind::Session => 14,
+        }
+    }
+
+    fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(CircuitKind::Distance),
+            1 => Some(CircuitKind::Threshold),
+            2 => Some(CircuitKind::Batch),
+            3 => Some(CircuitKind::Masked),
+            4 => Some(CircuitKind::Rotation),
+            5 => Some(CircuitKind::RotationThreshold),
+            6 => Some(CircuitKind::Weighted),
+            7 => Some(CircuitKind::Jaccard),
+            8 => Some(CircuitKind::Dice),
+            9 => Some(CircuitKind::InnerProduct),
+            10 => Some(CircuitKind::L1),
+            11 => Some(CircuitKind::L2),
+            12 => Some(CircuitKind::Popcount),
+            13 => Some(CircuitKind::Membership),
+            14 => Some(CircuitKind::Session),
+            _ => None,
+        }
+    }
+
+    // Whether the circuit compares the vectors at several shifts, which the keys record
+    pub fn is_rotation(self) -> bool {
+        matches!(self, CircuitKind::Rotation | CircuitKind::RotationThreshold)
+    }
+
+    // The similarity a similarity circuit proves a threshold for
+    pub fn similarity(self) -> Option<SimilarityMetric> {
+        match self {
+            CircuitKind::Jaccard => Some(SimilarityMetric::Jaccard),
+            CircuitKind::Dice => Some(SimilarityMetric::Dice),
+            _ => None,
+        }
+    }
+
+    // The distance an embedding circuit proves a threshold for
+    pub fn embedding(self) -> Option<EmbeddingMetric> {
+        match self {
+            CircuitKind::L1 => Some(EmbeddingMetric::L1),
+            CircuitKind::L2 => Some(EmbeddingMetric::L2),
+            _ => None,
+        }
+    }
+
+    // The number of public inputs of a proof, which for batch proofs depends on the number of
+    // candidates
+    pub fn public_inputs(self, candidates: usize) -> usize {
+        match self {
+            CircuitKind::Distance
+            | CircuitKind::Threshold
+            | CircuitKind::Rotation
+            | CircuitKind::RotationThreshold
+            | CircuitKind::Weighted
+            | CircuitKind::InnerProduct
+            | CircuitKind::L1
+            | CircuitKind::L2
+            | CircuitKind::Popcount
+            | CircuitKind::Membership => PUBLIC_INPUTS,
+            CircuitKind::Batch => 1 + 2 * candidates,
+            CircuitKind::Masked => MASKED_PUBLIC_INPUTS,
+            CircuitKind::Jaccard | CircuitKind::Dice => SIMILARITY_PUBLIC_INPUTS,
+            CircuitKind::Session => SESSION_PUBLIC_INPUTS,
+        }
+    }
+}
+
+impl std::fmt::Display for CircuitKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CircuitKind::Distance => write!(f, "distance"),
+            CircuitKind::Threshold => write!(f, "threshold"),
+            CircuitKind::Batch => write!(f, "batch"),
+            CircuitKind::Masked => write!(f, "masked"),
+            CircuitKind::Rotation => write!(f, "rotation"),
+            CircuitKind::RotationThreshold => write!(f, "rotation-threshold"),
+            CircuitKind::Weighted => write!(f, "weighted"),
+            CircuitKind::Jaccard => write!(f, "jaccard"),
+            CircuitKind::Dice => write!(f, "dice"),
+            CircuitKind::InnerProduct => write!(f, "inner-product"),
+            CircuitKind::L1 => write!(f, "l1"),
+            CircuitKind::L2 => write!(f, "l2"),
+            CircuitKind::Popcount => write!(f, "popcount"),
+            CircuitKind::Membership => write!(f, "membership"),
+            CircuitKind::Session => write!(f, "session"),
+        }
+    }
+}
+
+// Hashes a value with Blake2b into a 32-byte fingerprint
+fn fingerprint(personal: &[u8], data: &[u8]) -> [u8; 32] {
+    let hash = Blake2bParams::new()
+        .hash_length(32)
+        .personal(personal)
+        .hash(data);
+    hash.as_bytes().try_into().unwrap()
+}
+
+// Fingerprints the setup parameters, so keys and proofs can be tied to the params they need
+pub fn params_fingerprint(params: &Params<EqAffine>) -> [u8; 32] {
+    let mut buf = Vec::new();
+    params
+        .write(&mut buf)
+        .expect("writing to a vec should not fail");
+    fingerprint(b"BitVeil-Params", &buf)
+}
+
+// Fingerprints a verifying key using its pinned representation, which covers the domain, the
+// constraint system and the commitments to the fixed columns and the permutation
+pub fn vk_fingerprint(vk: &VerifyingKey<EqAffine>) -> [u8; 32] {
+    fingerprint(b"BitVeil-VK", format!("{:?}", vk.pinned()).as_bytes())
+}
+
+// The weighted metric for the weights recorded in a key
+fn weighted(weights: &[u64]) -> WeightedHamming {
+    WeightedHamming {
+        weights: weights.to_vec(),
+    }
+}
+
+// Something done with the empty circuit of a kind and the smallest k that fits it, such as
+// generating its keys. The circuits of the kinds have different types, so this is a trait with a
+// method generic over the circuit instead of a closure.
+trait WithEmptyCircuit {
+    type Output;
+
+    fn call<C: Circuit<Fp>>(self, circuit: C, k: u32) -> Self::Output;
+}
+
+// Passes the empty circuit of a metric and its k on
+fn with_metric<M: DistanceMetric, W: WithEmptyCircuit>(
+    metric: M,
+    length: usize,
+    with: W,
+) -> W::Output {
+    with.call(metric.empty_circuit(length), metric.k(length))
+}
+
+impl CircuitKind {
+    // Builds the empty circuit of the kind for vectors of the given length, along with the smallest
+    // k that fits it, and passes both to `with`. The circuits of metrics come from their
+    // DistanceMetric implementation.
+    fn with_empty_circuit<W: WithEmptyCircuit>(
+        self,
+        length: usize,
+        candidates: usize,
+        shifts: usize,
+        weights: &[u64],
+        with: W,
+    ) -> W::Output {
+        match self {
+            CircuitKind::Distance => with_metric(Hamming, length, with),
+            CircuitKind::Threshold => {
+                with.call(empty_threshold_circuit(length), k_for_length(length))
+            }
+            CircuitKind::Batch => with.call(
+                empty_batch_circuit(length, candidates),
+                k_for_batch(length, candidates),
+            ),
+            CircuitKind::Masked => with.call(empty_masked_circuit(length), k_for_masked(length)),
+            CircuitKind::Rotation => with_metric(RotatedHamming { shifts }, length, with),
+            CircuitKind::RotationThreshold => with.call(
+                empty_rotation_threshold_circuit(length, shifts),
+                k_for_rotation(length, shifts),
+            ),
+            CircuitKind::Weighted => with_metric(weighted(weights), length, with),
+            CircuitKind::Jaccard => with.call(
+                empty_similarity_circuit(length, SimilarityMetric::Jaccard),
+                k_for_similarity(length),
+            ),
+            CircuitKind::Dice => with.call(
+                empty_similarity_circuit(length, SimilarityMetric::Dice),
+                k_for_similarity(length),
+            ),
+            CircuitKind::InnerProduct => with_metric(InnerProduct, length, with),
+            CircuitKind::L1 => with.call(
+                empty_embedding_circuit(length, EmbeddingMetric::L1),
+                k_for_embedding(length),
+            ),
+            CircuitKind::L2 => with.call(
+                empty_embedding_circuit(length, EmbeddingMetric::L2),
+                k_for_embedding(length),
+            ),
+            CircuitKind::Popcount => with_metric(PopcountHamming, length, with),
+            CircuitKind::Membership => {
+                with.call(empty_membership_circuit(length), k_for_membership(length))
+            }
+            CircuitKind::Session => with.call(empty_session_circuit(length), k_for_session(length)),
+        }
+    }
+
+    // The smallest k that fits the circuit of the kind for vectors of the given length, which
+    // does not depend on the weights of a weighted circuit
+    pub fn k(self, length: usize, candidates: usize, shifts: usize) -> u32 {
+        struct MinK;
+
+        impl WithEmptyCircuit for MinK {
+            type Output = u32;
+
+            fn call<C: Circuit<Fp>>(self, _circuit: C, k: u32) -> u32 {
+                k
+            }
+        }
+
+        self.with_empty_circuit(length, candidates, shifts, &[], MinK)
+    }
+}
+
+// Generates the verifying key of an empty circuit
+struct KeygenVk<'a>(&'a Params<EqAffine>);
+
+impl WithEmptyCircuit for KeygenVk<'_> {
+    type Output = Result<VerifyingKey<EqAffine>, BitVeilError>;
+
+    fn call<C: Circuit<Fp>>(self, circuit: C, _k: u32) -> Self::Output {
+        keygen_vk(self.0, &circuit).map_err(BitVeilError::KeyGen)
+    }
+}
+
+// Generates the proving key of an empty circuit from its verifying key
+struct KeygenPk<'a>(&'a Params<EqAffine>, VerifyingKey<EqAffine>);
+
+impl WithEmptyCircuit for KeygenPk<'_> {
+    type Output = Result<ProvingKey<EqAffine>, BitVeilError>;
+
+    fn call<C: Circuit<Fp>>(self, circuit: C, _k: u32) -> Self::Output {
+        keygen_pk(self.0, self.1, &circuit).map_err(BitVeilError::KeyGen)
+    }
+}
+
+// Generates the verifying key of a circuit from its empty circuit
+fn circuit_vk(
+    params: &Params<EqAffine>,
+    kind: CircuitKind,
+    length: usize,
+    candidates: usize,
+    shifts: usize,
+    weights: &[u64],
+) -> Result<VerifyingKey<EqAffine>, BitVeilError> {
+    kind.with_empty_circuit(length, candidates, shifts, weights, KeygenVk(params))
+}
+
+// Generates the proving key of a circuit from its verifying key and its empty circuit
+fn circuit_pk(
+    params: &Params<EqAffine>,
+    vk: VerifyingKey<EqAffine>,
+    kind: CircuitKind,
+    length: usize,
+    candidates: usize,
+    shifts: usize,
+    weights: &[u64],
+) -> Result<ProvingKey<EqAffine>, BitVeilError> {
+    kind.with_empty_circuit(length, candidates, shifts, weights, KeygenPk(params, vk))
+}
+
+const KEY_MAGIC: &[u8; 4] = b"BVK\0";
+const KEY_VERSION: u8 = 4;
+
+// Describes the keys of a circuit as stored in `verifying_key.bin` and `proving_key.bin`.
+// halo2_proofs 0.3 can neither serialize nor deserialize keys, so the file records what the keys
+// were generated for along with fingerprints of the params and the verifying key. Loading a key
+// regenerates it for the recorded circuit and rejects it unless both fingerprints match.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyInfo {
+    pub kind: CircuitKind,
+    pub length: usize,
+    // The number of candidates of a batch circuit, which is 1 for the other circuits
+    pub candidates: usize,
+    // The number of shifts in each direction of a rotation circuit, which is 0 for the other
+    // circuits
+    pub shifts: usize,
+    pub k: u32,
+    pub params_hash: [u8; 32],
+    pub vk_hash: [u8; 32],
+    // The weight of each bit of a weighted circuit, which is empty for the other circuits. The
+    // weights are needed to regenerate the keys, and the verifying key fingerprint covers them.
+    pub weights: Vec<u64>,
+}
+
+impl KeyInfo {
+    pub fn new(
+        params: &Params<EqAffine>,
+        kind: CircuitKind,
+        length: usize,
+        candidates: usize,
+        shifts: usize,
+        weights: &[u64],
+        vk: &VerifyingKey<EqAffine>,
+    ) -> Self {
+        KeyInfo {
+            kind,
+            length: circuit_length(length),
+            candidates,
+            shifts,
+            k: params_k(params),
+            params_hash: params_fingerprint(params),
+            vk_hash: vk_fingerprint(vk),
+            weights: weights.to_vec(),
+        }
+    }
+
+    // Generates the verifying key of a circuit and the key info describing it, after checking the
+    // shifts of rotation circuits and the weights of weighted circuits
+    pub fn generate(
+        params: &Params<EqAffine>,
+        kind: CircuitKind,
+        length: usize,
+        candidates: usize,
+        shifts: usize,
+        weights: &[u64],
+    ) -> Result<(Self, VerifyingKey<EqAffine>), BitVeilError> {
+        if kind.is_rotation() {
+            check_rotation(length, shifts)?;
+        }
+        if kind == CircuitKind::Weighted {
+            check_weights(weights, length)?;
+        }
+        let vk = circuit_vk(params, kind, length, candidates, shifts, weights)?;
+        let info = KeyInfo::new(params, kind, length, candidates, shifts, weights, &vk);
+        Ok((info, vk))
+    }
+
+    // Writes the magic, the format version, the circuit kind, the length, the number of candidates,
+    // the number of shifts and k as little-endian integers, followed by both fingerprints and the
+    // weights prefixed by their count
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<(), BitVeilError> {
+        writer.write_all(KEY_MAGIC)?;
+        writer.write_all(&[KEY_VERSION, self.kind.id()])?;
+        writer.write_all(&(self.length as u32).to_le_bytes())?;
+        writer.write_all(&(self.candidates as u32).to_le_bytes())?;
+        writer.write_all(&(self.shifts as u32).to_le_bytes())?;
+        writer.write_all(&self.k.to_le_bytes())?;
+        writer.write_all(&self.params_hash)?;
+        writer.write_all(&self.vk_hash)?;
+        writer.write_all(&(self.weights.len() as u32).to_le_bytes())?;
+        for weight in &self.weights {
+            writer.write_all(&(*weight as u32).to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    pub fn read<R: Read>(reader: &mut R) -> Result<Self, BitVeilError> {
+        Self::read_fields(reader).map_err(BitVeilError::KeyDecode)
+    }
+
+    fn read_fields<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != KEY_MAGIC {
+            return Err(invalid("not a BitVeil key file"));
+        }
+
+        let mut header = [0u8; 2];
+        reader.read_exact(&mut header)?;
+        if header[0] != KEY_VERSION {
+            return Err(invalid("unsupported key file version"));
+        }
+        let kind = CircuitKind::from_id(header[1]).ok_or_else(|| invalid("unknown circuit"))?;
+
+        let mut word = [0u8; 4];
+        reader.read_exact(&mut word)?;
+        let length = u32::from_le_bytes(word) as usize;
+        if length == 0 || length > MAX_LENGTH {
+            return Err(invalid("unsupported vector length"));
+        }
+        reader.read_exact(&mut word)?;
+        let candidates = u32::from_le_bytes(word) as usize;
+        let max_candidates = match kind {
+            CircuitKind::Batch => MAX_CANDIDATES,
+            _ => 1,
+        };
+        if candidates == 0 || candidates > max_candidates {
+            return Err(invalid("unsupported number of candidates"));
+        }
+        reader.read_exact(&mut word)?;
+        let shifts = u32::from_le_bytes(word) as usize;
+        if kind.is_rotation() != (shifts > 0) || shifts > MAX_SHIFTS {
+            return Err(invalid("unsupported number of shifts"));
+        }
+        reader.read_exact(&mut word)?;
+        let k = u32::from_le_bytes(word);
+
+        let mut params_hash = [0u8; 32];
+        reader.read_exact(&mut params_hash)?;
+        let mut vk_hash = [0u8; 32];
+        reader.read_exact(&mut vk_hash)?;
+
+        reader.read_exact(&mut word)?;
+        let count = u32::from_le_bytes(word) as usize;
+        let expected = match kind {
+            CircuitKind::Weighted => count > 0 && circuit_length(count) == length,
+            _ => count == 0,
+        };
+        if !expected {
+            return Err(invalid("unexpected number of weights"));
+        }
+        let weights = (0..count)
+            .map(|_| {
+                reader.read_exact(&mut word)?;
+                match u32::from_le_bytes(word) as u64 {
+                    weight if weight <= MAX_WEIGHT => Ok(weight),
+                    _ => Err(invalid("weight is too large")),
+                }
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        Ok(KeyInfo {
+            kind,
+            length,
+            candidates,
+            shifts,
+            k,
+            params_hash,
+            vk_hash,
+            weights,
+        })
+    }
+
+    // Checks that the params are the ones the keys were generated with
+    fn check_params(&self, params: &Params<EqAffine>) -> Result<(), BitVeilError> {
+        if params_k(params) != self.k {
+            return Err(BitVeilError::KeyMismatch(format!(
+                "key was generated for k={} but the params have k={}",
+                self.k,
+                params_k(params)
+            )));
+        }
+        if params_fingerprint(params) != self.params_hash {
+            return Err(BitVeilError::KeyMismatch(
+                "key was generated with different params".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    // Regenerates the verifying key, rejecting it if the params or the circuit differ from the
+    // ones the key was generated with
+    pub fn verifying_key(
+        &self,
+        params: &Params<EqAffine>,
+    ) -> Result<VerifyingKey<EqAffine>, BitVeilError> {
+        self.check_params(params)?;
+        let vk = circuit_vk(
+            params,
+            self.kind,
+            self.length,
+            self.candidates,
+            self.shifts,
+            &self.weights,
+        )?;
+
+        if vk_fingerprint(&vk) != self.vk_hash {
+            return Err(BitVeilError::KeyMismatch(
+                "verifying key does not match the circuit".to_string(),
+            ));
+        }
+        Ok(vk)
+    }
+
+    // Regenerates the proving key from the checked verifying key
+    pub fn proving_key(
+        &self,
+        params: &Params<EqAffine>,
+    ) -> Result<ProvingKey<EqAffine>, BitVeilError> {
+        let vk = self.verifying_key(params)?;
+        self.keygen_pk(params, vk)
+    }
+
+    // Generates the proving key of the circuit from its verifying key
+    fn keygen_pk(
+        &self,
+        params: &Params<EqAffine>,
+        vk: VerifyingKey<EqAffine>,
+    ) -> Result<ProvingKey<EqAffine>, BitVeilError> {
+        circuit_pk(
+            params,
+            vk,
+            self.kind,
+            self.length,
+            self.candidates,
+            self.shifts,
+            &self.weights,
+        )
+    }
+}
+
+// Serializes byte arrays as hex strings in JSON
+mod hex_bytes {
+    use serde::{Deserialize, Deserializer, Serializer, de::Error};
+
+    pub fn serialize<S: Serializer, T: AsRef<[u8]>>(bytes: &T, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(&hex::encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>, T: TryFrom<Vec<u8>>>(
+        d: D,
+    ) -> Result<T, D::Error> {
+        let bytes = hex::decode(String::deserialize(d)?).map_err(D::Error::custom)?;
+        T::try_from(bytes).map_err(|_| D::Error::custom("invalid length"))
+    }
+}
+
+// Serializes field elements as the hex strings of `fp_to_hex` in JSON
+mod hex_fields {
+    use super::{fp_from_hex, fp_to_hex};
+    use halo2_proofs::pasta::Fp;
+    use serde::{Deserialize, Deserializer, Serializer, de::Error};
+
+    pub fn serialize<S: Serializer>(fields: &[Fp], s: S) -> Result<S::Ok, S::Error> {
+        s.collect_seq(fields.iter().map(fp_to_hex))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<Fp>, D::Error> {
+        Vec::<String>::deserialize(d)?
+            .iter()
+            .map(|x| fp_from_hex(x).ok_or_else(|| D::Error::custom("invalid field element")))
+            .collect()
+    }
+}
+
+const BUNDLE_MAGIC: &[u8; 4] = b"BVP\0";
+pub const BUNDLE_VERSION: u8 = 4;
+
+// The longest domain tag a proof can be bound to, in bytes
+pub const MAX_DOMAIN_LENGTH: usize = 256;
+
+// A proof together with everything needed to check it: the circuit it was generated for, the params
+// and verifying key it needs, the domain its transcript is bound to and its public inputs. Bundles
+// are written in a binary format by `write` and as JSON by `to_json`, and `decode` reads either.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProofBundle {
+    pub version: u8,
+    pub circuit: CircuitKind,
+    pub length: usize,
+    pub candidates: usize,
+    pub shifts: usize,
+    pub k: u32,
+    #[serde(with = "hex_bytes")]
+    pub params_hash: [u8; 32],
+    #[serde(with = "hex_bytes")]
+    pub vk_hash: [u8; 32],
+    // The domain tag absorbed into the transcript, which is empty for proofs bound to no domain
+    pub domain: String,
+    #[serde(with = "hex_fields")]
+    pub public_inputs: Vec<Fp>,
+    #[serde(with = "hex_bytes")]
+    pub proof: Vec<u8>,
+}
+
+impl ProofBundle {
+    pub fn new(info: &KeyInfo, domain: &str, public_inputs: Vec<Fp>, proof: Vec<u8>) -> Self {
+        ProofBundle {
+            version: BUNDLE_VERSION,
+            circuit: info.kind,
+            length: info.length,
+            candidates: info.candidates,
+            shifts: info.shifts,
+            k: info.k,
+            params_hash: info.params_hash,
+            vk_hash: info.vk_hash,
+            domain: domain.to_string(),
+            public_inputs,
+            proof,
+        }
+    }
+
+    // The distance, or the threshold for threshold proofs and the numerator of the threshold for
+    // similarity proofs. The accessors for the commitments to both vectors apply to every proof
+    // but batch proofs.
+    pub fn distance(&self) -> Fp {
+        self.public_inputs[DISTANCE_ROW]
+    }
+
+    pub fn commitment_a(&self) -> Fp {
+        self.public_inputs[COMMITMENT_A_ROW]
+    }
+
+    pub fn commitment_b(&self) -> Fp {
+        self.public_inputs[COMMITMENT_B_ROW]
+    }
+
+    // The denominator of the threshold of a similarity proof
+    pub fn denominator(&self) -> Fp {
+        self.public_inputs[DENOMINATOR_ROW]
+    }
+
+    // The number of bits valid in both masks of a masked proof
+    pub fn valid_bits(&self) -> Fp {
+        self.public_inputs[VALID_ROW]
+    }
+
+    // The root of the registry a membership proof was generated against
+    pub fn registry_root(&self) -> Fp {
+        self.public_inputs[ROOT_ROW]
+    }
+
+    // The nonce of the session a session proof was generated for
+    pub fn nonce(&self) -> Fp {
+        self.public_inputs[NONCE_ROW]
+    }
+
+    // The id of the context the nullifier of a session proof was derived in
+    pub fn context(&self) -> Fp {
+        self.public_inputs[CONTEXT_ROW]
+    }
+
+    // The nullifier of vector B of a session proof, which a relying party records to detect reuse
+    pub fn nullifier(&self) -> Fp {
+        self.public_inputs[NULLIFIER_ROW]
+    }
+
+    // The commitment to the probe of a batch proof
+    pub fn probe_commitment(&self) -> Fp {
+        self.public_inputs[PROBE_COMMITMENT_ROW]
+    }
+
+    // The distance between the probe and each candidate of a batch proof
+    pub fn batch_distances(&self) -> Vec<Fp> {
+        (0..self.candidates)
+            .map(|i| self.public_inputs[batch_distance_row(i)])
+            .collect()
+    }
+
+    // The commitment to each candidate of a batch proof
+    pub fn candidate_commitments(&self) -> Vec<Fp> {
+        (0..self.candidates)
+            .map(|i| self.public_inputs[batch_commitment_row(i)])
+            .collect()
+    }
+
+    // Writes the magic, the version, the circuit, the length, the number of candidates, the number
+    // of shifts and k, both fingerprints, then the domain, the public inputs and the proof, each
+    // prefixed by their length as a little-endian u32
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<(), BitVeilError> {
+        writer.write_all(BUNDLE_MAGIC)?;
+        writer.write_all(&[self.version, self.circuit.id()])?;
+        writer.write_all(&(self.length as u32).to_le_bytes())?;
+        writer.write_all(&(self.candidates as u32).to_le_bytes())?;
+        writer.write_all(&(self.shifts as u32).to_le_bytes())?;
+        writer.write_all(&self.k.to_le_bytes())?;
+        writer.write_all(&self.params_hash)?;
+        writer.write_all(&self.vk_hash)?;
+        writer.write_all(&(self.domain.len() as u32).to_le_bytes())?;
+        writer.write_all(self.domain.as_bytes())?;
+        writer.write_all(&(self.public_inputs.len() as u32).to_le_bytes())?;
+        for input in &self.public_inputs {
+            writer.write_all(&input.to_repr())?;
+        }
+        writer.write_all(&(self.proof.len() as u32).to_le_bytes())?;
+        writer.write_all(&self.proof)?;
+        Ok(())
+    }
+
+    pub fn read<R: Read>(reader: &mut R) -> Result<Self, BitVeilError> {
+        Self::read_fields(reader).map_err(BitVeilError::ProofDecode)
+    }
+
+    fn read_fields<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+        let read_u32 = |reader: &mut R| -> io::Result<u32> {
+            let mut word = [0u8; 4];
+            reader.read_exact(&mut word)?;
+            Ok(u32::from_le_bytes(word))
+        };
+
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != BUNDLE_MAGIC {
+            return Err(invalid("not a BitVeil proof bundle"));
+        }
+
+        let mut header = [0u8; 2];
+        reader.read_exact(&mut header)?;
+        let circuit = CircuitKind::from_id(header[1]).ok_or_else(|| invalid("unknown circuit"))?;
+        let length = read_u32(reader)? as usize;
+        let candidates = read_u32(reader)? as usize;
+        let shifts = read_u32(reader)? as usize;
+        let k = read_u32(reader)?;
+
+        let mut params_hash = [0u8; 32];
+        reader.read_exact(&mut params_hash)?;
+        let mut vk_hash = [0u8; 32];
+        reader.read_exact(&mut vk_hash)?;
+
+        let domain_len = read_u32(reader)? as usize;
+        if domain_len > MAX_DOMAIN_LENGTH {
+            return Err(invalid("domain tag too long"));
+        }
+        let mut domain = vec![0u8; domain_len];
+        reader.read_exact(&mut domain)?;
+        let domain = String::from_utf8(domain).map_err(|_| invalid("domain tag is not UTF-8"))?;
+
+        let count = read_u32(reader)?;
+        if candidates == 0
+            || candidates > MAX_CANDIDATES
+            || count as usize != circuit.public_inputs(candidates)
+        {
+            return Err(invalid("unexpected number of public inputs"));
+        }
+        let public_inputs = (0..count)
+            .map(|_| {
+                let mut repr = [0u8; 32];
+                reader.read_exact(&mut repr)?;
+                fp_from_bytes(&repr).ok_or_else(|| invalid("invalid public input"))
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let mut proof = Vec::new();
+        let proof_len = read_u32(reader)? as u64;
+        reader.take(proof_len).read_to_end(&mut proof)?;
+        if proof.len() as u64 != proof_len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+
+        let bundle = ProofBundle {
+            version: header[0],
+            circuit,
+            length,
+            candidates,
+            shifts,
+            k,
+            params_hash,
+            vk_hash,
+            domain,
+            public_inputs,
+            proof,
+        };
+        bundle.check_version()?;
+        Ok(bundle)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("bundles should serialize")
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, BitVeilError> {
+        let bundle: ProofBundle =
+            serde_json::from_str(json).map_err(|e| BitVeilError::ProofDecode(e.into()))?;
+        if bundle.candidates == 0
+            || bundle.candidates > MAX_CANDIDATES
+            || bundle.public_inputs.len() != bundle.circuit.public_inputs(bundle.candidates)
+        {
+            return Err(BitVeilError::ProofDecode(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "unexpected number of public inputs",
+            )));
+        }
+        if bundle.domain.len() > MAX_DOMAIN_LENGTH {
+            return Err(BitVeilError::ProofDecode(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "domain tag too long",
+            )));
+        }
+        bundle.check_version().map_err(BitVeilError::ProofDecode)?;
+        Ok(bundle)
+    }
+
+    // Reads a bundle in either format, telling them apart by the magic of the binary format
+    pub fn decode(bytes: &[u8]) -> Result<Self, BitVeilError> {
+        if bytes.starts_with(BUNDLE_MAGIC) {
+            Self::read(&mut &bytes[..])
+        } else {
+            let json = std::str::from_utf8(bytes).map_err(|e| {
+                BitVeilError::ProofDecode(io::Error::new(io::ErrorKind::InvalidData, e))
+            })?;
+            Self::from_json(json)
+        }
+    }
+
+    fn check_version(&self) -> io::Result<()> {
+        if self.version != BUNDLE_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported proof bundle version {}", self.version),
+            ));
+        }
+        Ok(())
+    }
+
+    // Checks that the proof was generated with the keys described by the key info
+    pub fn check_key(&self, info: &KeyInfo) -> Result<(), BitVeilError> {
+        let mismatch = |what: &str| {
+            Err(BitVeilError::KeyMismatch(format!(
+                "proof was generated with a different {}",
+                what
+            )))
+        };
+        if self.circuit != info.kind {
+            return mismatch("circuit");
+        }
+        if self.length != info.length {
+            return mismatch("vector length");
+        }
+        if self.candidates != info.candidates {
+            return mismatch("number of candidates");
+        }
+        if self.shifts != info.shifts {
+            return mismatch("number of shifts");
+        }
+        if self.k != info.k || self.params_hash != info.params_hash {
+            return mismatch("params");
+        }
+        if self.vk_hash != info.vk_hash {
+            return mismatch("verifying key");
+        }
+        Ok(())
+    }
+
+    // Checks that the proof is bound to the given domain, which a verifier requires even though a
+    // proof only verifies for the domain its transcript was started with, so a bundle claiming
+    // another domain fails with a clear error instead of a failed verification
+    pub fn check_domain(&self, domain: &str) -> Result<(), BitVeilError> {
+        if self.domain != domain {
+            return Err(BitVeilError::DomainMismatch {
+                expected: domain.to_string(),
+                found: self.domain.clone(),
+            });
+        }
+        Ok(())
+    }
+}
+
+// Calculates the hamming distance between two vectors
+pub fn calculate_hamming_distance(a: Vec<u64>, b: Vec<u64>) -> Result<Vec<Fp>, BitVeilError> {
+    check_binary_vectors(&a, &b)?;
+    let hamming_dist = a.iter().zip(&b).map(|(&x, &y)| x ^ y).sum::<u64>();
+    Ok(vec![Fp::from(hamming_dist)])
+}
+
+// Generates a random salt for a vector commitment
+pub fn generate_salt() -> Fp {
+    Fp::random(OsRng)
+}
+
+// Generates a random nullifier secret, which the holder of a vector keeps and commits to with it
+// in session proofs
+pub fn generate_nullifier_secret() -> Fp {
+    Fp::random(OsRng)
+}
+
+// Generates a random session nonce, for a verifier to challenge the prover with
+pub fn generate_nonce() -> Fp {
+    Fp::random(OsRng)
+}
+
+// Maps a context label, such as the name of a relying party, to the context id nullifiers are
+// derived in by hashing it with Blake2b
+pub fn context_id(label: &str) -> Fp {
+    let hash = Blake2bParams::new()
+        .hash_length(64)
+        .personal(b"BitVeil-Context")
+        .hash(label.as_bytes());
+    Fp::from_uniform_bytes(hash.as_array())
+}
+
+// Derives the nullifier of a binary vector in a context, matching the nullifier computed in the
+// session circuit. The nullifier secret is followed by the vector packed into words like for a
+// commitment and then the context, so the nullifier does not change when the vector is committed
+// to again with another salt.
+pub fn derive_nullifier(bits: &[u64], secret: Fp, context: Fp) -> Result<Fp, BitVeilError> {
+    check_packable_vector(bits)?;
+
+    let mut inputs = vec![secret];
+    inputs.extend(pack_bits(bits));
+    inputs.push(context);
+    Ok(poseidon::hash(&inputs))
+}
+
+// Commits to the second vector of a session proof with a salt, matching the commitment computed in
+// the session circuit, which hashes the nullifier secret in front of the packed words
+pub fn commit_session_vector(bits: &[u64], salt: Fp, secret: Fp) -> Result<Fp, BitVeilError> {
+    check_packable_vector(bits)?;
+
+    let mut inputs = vec![salt, secret];
+    inputs.extend(pack_bits(bits));
+    Ok(poseidon::hash(&inputs))
+}
+
+// Commits to a binary vector with a salt, matching the commitment computed in the circuit. The
+// vector is padded with zeros to the circuit length and packed into words before being hashed.
+pub fn commit_vector(bits: &[u64], salt: Fp) -> Result<Fp, BitVeilError> {
+    check_packable_vector(bits)?;
+
+    let mut inputs = vec![salt];
+    inputs.extend(pack_bits(bits));
+    Ok(poseidon::hash(&inputs))
+}
+
+fn check_packable_vector(bits: &[u64]) -> Result<(), BitVeilError> {
+    if bits.is_empty() || bits.len() > MAX_LENGTH {
+        return Err(BitVeilError::InvalidLength {
+            a: bits.len(),
+            b: bits.len(),
+        });
+    }
+    check_binary_vector(bits, "being committed")
+}
+
+// Commits to an integer vector with a salt, matching the commitment computed in the inner-product
+// and embedding circuits. The vector is padded with zeros to the circuit length and its elements
+// are packed into words before being hashed.
+pub fn commit_integer_vector(values: &[u64], salt: Fp) -> Result<Fp, BitVeilError> {
+    if values.is_empty() || values.len() > MAX_LENGTH {
+        return Err(BitVeilError::InvalidLength {
+            a: values.len(),
+            b: values.len(),
+        });
+    }
+    check_integer_vector(values, "being committed")?;
+
+    let mut inputs = vec![salt];
+    inputs.extend(pack_elements(values));
+    Ok(poseidon::hash(&inputs))
+}
+
+// Commits to a binary vector and its mask with a salt, matching the commitment computed in the
+// masked circuit. The words of the mask are hashed after the words of the vector.
+pub fn commit_masked_vector(bits: &[u64], mask: &[u64], salt: Fp) -> Result<Fp, BitVeilError> {
+    if bits.is_empty() || bits.len() > MAX_LENGTH || mask.len() != bits.len() {
+        return Err(BitVeilError::InvalidLength {
+            a: bits.len(),
+            b: mask.len(),
+        });
+    }
+    check_binary_vector(bits, "being committed")?;
+    check_binary_vector(mask, "mask being committed")?;
+
+    let mut inputs = vec![salt];
+    inputs.extend(pack_bits(bits));
+    inputs.extend(pack_bits(mask));
+    Ok(poseidon::hash(&inputs))
+}
+
+// Calculates the Hamming distance over the bits valid in both masks, and the number of those bits
+pub fn calculate_masked_distance(
+    a: &[u64],
+    b: &[u64],
+    mask_a: &[u64],
+    mask_b: &[u64],
+) -> Result<(Fp, Fp), BitVeilError> {
+    check_masked_vectors(a, b, mask_a, mask_b)?;
+    let valid: Vec<bool> = mask_a.iter().zip(mask_b).map(|(x, y)| x & y == 1).collect();
+    let distance = a
+        .iter()
+        .zip(b)
+        .zip(&valid)
+        .filter(|((x, y), valid)| **valid && x != y)
+        .count();
+    let valid = valid.iter().filter(|valid| **valid).count();
+    Ok((Fp::from(distance as u64), Fp::from(valid as u64)))
+}
+
+// Calculates the sum of the weights of the positions where the vectors differ
+pub fn calculate_weighted_distance(
+    a: &[u64],
+    b: &[u64],
+    weights: &[u64],
+) -> Result<Fp, BitVeilError> {
+    check_binary_vectors(a, b)?;
+    check_weights(weights, a.len())?;
+    let distance = a
+        .iter()
+        .zip(b)
+        .zip(weights)
+        .map(|((x, y), weight)| (x ^ y) * weight)
+        .sum::<u64>();
+    Ok(Fp::from(distance))
+}
+
+// Calculates the inner product of two integer vectors
+pub fn calculate_inner_product(a: &[u64], b: &[u64]) -> Result<Fp, BitVeilError> {
+    check_integer_vectors(a, b)?;
+    let inner_product = a.iter().zip(b).map(|(x, y)| x * y).sum::<u64>();
+    Ok(Fp::from(inner_product))
+}
+
+// Calculates the L1 or the squared L2 distance between two integer vectors
+pub fn calculate_embedding_distance(
+    a: &[u64],
+    b: &[u64],
+    metric: EmbeddingMetric,
+) -> Result<u64, BitVeilError> {
+    check_integer_vectors(a, b)?;
+    let differences = a.iter().zip(b).map(|(x, y)| x.abs_diff(*y));
+    Ok(match metric {
+        EmbeddingMetric::L1 => differences.sum(),
+        EmbeddingMetric::L2 => differences.map(|d| d * d).sum(),
+    })
+}
+
+// Calculates the similarity of two vectors as a numerator and a denominator: |a AND b| and
+// |a OR b| for Jaccard, and 2 |a AND b| and |a| + |b| for Dice. Two vectors without any set bits
+// have a denominator of zero, and clear every threshold.
+pub fn calculate_similarity(
+    a: &[u64],
+    b: &[u64],
+    metric: SimilarityMetric,
+) -> Result<(u64, u64), BitVeilError> {
+    check_binary_vectors(a, b)?;
+    let and = a.iter().zip(b).map(|(x, y)| x & y).sum::<u64>();
+    let or = a.iter().zip(b).map(|(x, y)| x | y).sum::<u64>();
+    Ok(match metric {
+        SimilarityMetric::Jaccard => (and, or),
+        SimilarityMetric::Dice => (2 * and, and + or),
+    })
+}
+
+// Calculates the smallest Hamming distance between the first vector and the second vector rotated
+// by each of -shifts..=shifts positions
+pub fn calculate_rotated_distance(a: &[u64], b: &[u64], shifts: usize) -> Result<Fp, BitVeilError> {
+    rotated_distance(a, b, shifts).map(Fp::from)
+}
+
+// Pads a binary vector with zeros to the circuit length and packs it into the words the circuit
+// decomposes, most significant bit first
+fn pack_bits(bits: &[u64]) -> Vec<Fp> {
+    let mut padded = bits.to_vec();
+    padded.resize(circuit_length(bits.len()), 0u64);
+
+    padded
+        .chunks(WORD_LENGTH)
+        .map(|word| {
+            word.iter()
+                .fold(Fp::zero(), |acc, bit| acc.double() + Fp::from(*bit))
+        })
+        .collect()
+}
+
+// Pads an integer vector with zeros to the circuit length and packs ELEMENTS_PER_WORD elements into
+// each word the inner-product circuit decomposes, first element in the most significant bits
+fn pack_elements(values: &[u64]) -> Vec<Fp> {
+    let mut padded = values.to_vec();
+    padded.resize(circuit_length(values.len()), 0u64);
+
+    padded
+        .chunks(ELEMENTS_PER_WORD)
+        .map(|word| {
+            Fp::from(
+                word.iter()
+                    .fold(0u64, |acc, value| (acc << ELEMENT_BITS) | value),
+            )
+        })
+        .collect()
+}
+
+// Words that binary vectors can be packed into, most significant bit first
+pub trait PackedWord: Copy {
+    const BITS: usize;
+
+    fn to_u64(self) -> u64;
+}
+
+macro_rules! impl_packed_word {
+    ($($word:ty),*) => {
+        $(
+            impl PackedWord for $word {
+                const BITS: usize = <$word>::BITS as usize;
+
+                fn to_u64(self) -> u64 {
+                    self as u64
+                }
+            }
+        )*
+    };
+}
+
+impl_packed_word!(u8, u16, u32, u64);
+
+// Unpacks the first `length` bits of a vector of packed words, most significant bit first. The
+// words must hold exactly `length` bits rounded up to a whole word, with the unused bits zero.
+pub fn unpack_words<W: PackedWord>(
+    words: &[W],
+    length: usize,
+    vector: &'static str,
+) -> Result<Vec<u64>, BitVeilError> {
+    if words.len() != length.div_ceil(W::BITS) {
+        return Err(BitVeilError::InvalidPackedLength {
+            vector,
+            words: words.len(),
+            length,
+        });
+    }
+
+    let bits: Vec<u64> = words
+        .iter()
+        .flat_map(|word| (0..W::BITS).rev().map(move |i| (word.to_u64() >> i) & 1))
+        .collect();
+    if bits[length..].contains(&1) {
+        return Err(BitVeilError::NonZeroPadding { vector });
+    }
+    Ok(bits[..length].to_vec())
+}
+
+// Commits to a vector of packed words with a salt, matching `commit_vector` on its bits
+pub fn commit_packed<W: PackedWord>(
+    words: &[W],
+    length: usize,
+    salt: Fp,
+) -> Result<Fp, BitVeilError> {
+    commit_vector(&unpack_words(words, length, "being committed")?, salt)
+}
+
+// Builds the public inputs of the circuit from the distance and the commitments to both vectors
+pub fn public_inputs(hamming_dist: &[Fp], commitment_a: Fp, commitment_b: Fp) -> Vec<Fp> {
+    let mut inputs = vec![Fp::zero(); PUBLIC_INPUTS];
+    inputs[DISTANCE_ROW] = hamming_dist[0];
+    inputs[COMMITMENT_A_ROW] = commitment_a;
+    inputs[COMMITMENT_B_ROW] = commitment_b;
+    inputs
+}
+
+// Builds the public inputs of the masked circuit from the masked distance, the number of bits
+// valid in both masks and the commitments to both vectors and their masks
+pub fn masked_public_inputs(
+    distance: Fp,
+    valid: Fp,
+    commitment_a: Fp,
+    commitment_b: Fp,
+) -> Vec<Fp> {
+    let mut inputs = vec![Fp::zero(); MASKED_PUBLIC_INPUTS];
+    inputs[DISTANCE_ROW] = distance;
+    inputs[COMMITMENT_A_ROW] = commitment_a;
+    inputs[COMMITMENT_B_ROW] = commitment_b;
+    inputs[VALID_ROW] = valid;
+    inputs
+}
+
+// Builds the public inputs of a similarity circuit from the numerator and the denominator of the
+// threshold and the commitments to both vectors
+pub fn similarity_public_inputs(
+    numerator: Fp,
+    denominator: Fp,
+    commitment_a: Fp,
+    commitment_b: Fp,
+) -> Vec<Fp> {
+    let mut inputs = vec![Fp::zero(); SIMILARITY_PUBLIC_INPUTS];
+    inputs[DISTANCE_ROW] = numerator;
+    inputs[COMMITMENT_A_ROW] = commitment_a;
+    inputs[COMMITMENT_B_ROW] = commitment_b;
+    inputs[DENOMINATOR_ROW] = denominator;
+    inputs
+}
+
+// Builds the public inputs of a batch circuit from the commitment to the probe and the distance to
+// and the commitment to each candidate
+pub fn batch_public_inputs(
+    probe_commitment: Fp,
+    distances: &[Fp],
+    candidate_commitments: &[Fp],
+) -> Vec<Fp> {
+    let mut inputs = vec![Fp::zero(); CircuitKind::Batch.public_inputs(distances.len())];
+    inputs[PROBE_COMMITMENT_ROW] = probe_commitment;
+    for (i, (distance, commitment)) in distances.iter().zip(candidate_commitments).enumerate() {
+        inputs[batch_distance_row(i)] = *distance;
+        inputs[batch_commitment_row(i)] = *commitment;
+    }
+    inputs
+}
+
+// Builds the public inputs of the membership circuit from the threshold, the commitment to the
+// probe and the root of the registry
+pub fn membership_public_inputs(threshold: Fp, probe_commitment: Fp, root: Fp) -> Vec<Fp> {
+    let mut inputs = vec![Fp::zero(); PUBLIC_INPUTS];
+    inputs[DISTANCE_ROW] = threshold;
+    inputs[COMMITMENT_A_ROW] = probe_commitment;
+    inputs[ROOT_ROW] = root;
+    inputs
+}
+
+// Builds the public inputs of the session circuit from the distance, the commitments to the two
+// vectors, the nonce, the context and the nullifier of vector B in that context
+pub fn session_public_inputs(
+    distance: Fp,
+    commitment_a: Fp,
+    commitment_b: Fp,
+    nonce: Fp,
+    context: Fp,
+    nullifier: Fp,
+) -> Vec<Fp> {
+    let mut inputs = vec![Fp::zero(); SESSION_PUBLIC_INPUTS];
+    inputs[DISTANCE_ROW] = distance;
+    inputs[COMMITMENT_A_ROW] = commitment_a;
+    inputs[COMMITMENT_B_ROW] = commitment_b;
+    inputs[NONCE_ROW] = nonce;
+    inputs[CONTEXT_ROW] = context;
+    inputs[NULLIFIER_ROW] = nullifier;
+    inputs
+}
+
+// Encodes a field element as a hex string of its little-endian representation
+pub fn fp_to_hex(value: &Fp) -> String {
+    hex::encode(value.to_repr())
+}
+
+// Decodes a field element from the hex string produced by `fp_to_hex`. Shorter strings are
+// padded with zero bytes, so "05" decodes to 5.
+pub fn fp_from_hex(value: &str) -> Option<Fp> {
+    let mut bytes = hex::decode(value.trim_start_matches("0x")).ok()?;
+    if bytes.len() > 32 {
+        return None;
+    }
+    bytes.resize(32, 0);
+    fp_from_bytes(&bytes)
+}
+
+// Decodes a field element from its 32-byte little-endian representation
+pub fn fp_from_bytes(bytes: &[u8]) -> Option<Fp> {
+    let repr: [u8; 32] = bytes.try_into().ok()?;
+    Fp::from_repr(repr).into()
+}
+
+// Runs the mock prover, returning the constraints which are not satisfied
+pub fn run_mock_prover<C: Circuit<Fp>>(
+    k: u32,
+    circuit: &C,
+    pub_input: &[Fp],
+) -> Result<(), BitVeilError> {
+    let prover =
+        MockProver::run(k, circuit, vec![pub_input.to_vec()]).map_err(BitVeilError::Synthesis)?;
+    prover.verify().map_err(BitVeilError::Unsatisfied)
+}
+
+// Checks that a domain tag is short enough to be recorded in a proof bundle
+pub fn check_domain(domain: &str) -> Result<(), BitVeilError> {
+    if domain.len() > MAX_DOMAIN_LENGTH {
+        return Err(BitVeilError::InvalidDomain(domain.len()));
+    }
+    Ok(())
+}
+
+// Binds a transcript to a domain tag, such as an application id and protocol version, by
+// absorbing the Blake2b hash of the tag before anything else. Every challenge then depends on the
+// tag, so a proof only verifies in the domain it was generated in. An empty tag absorbs nothing,
+// which leaves the transcript of proofs bound to no domain as it was.
+fn bind_domain<E: EncodedChallenge<EqAffine>, T: Transcript<EqAffine, E>>(
+    transcript: &mut T,
+    domain: &str,
+) -> Result<(), BitVeilError> {
+    if domain.is_empty() {
+        return Ok(());
+    }
+    let hash = Blake2bParams::new()
+        .hash_length(64)
+        .personal(b"BitVeil-Domain")
+        .hash(domain.as_bytes());
+    transcript.common_scalar(Fp::from_uniform_bytes(hash.as_array()))?;
+    Ok(())
+}
+
+// Generates a proof whose transcript is bound to the domain
+pub fn generate_proof<C: Circuit<Fp>>(
+    params: &Params<EqAffine>,
+    pk: &ProvingKey<EqAffine>,
+    circuit: C,
+    pub_input: &[Fp],
+    domain: &str,
+) -> Result<Vec<u8>, BitVeilError> {
+    generate_proof_with_rng(params, pk, circuit, pub_input, domain, OsRng)
+}
+
+// Generates a proof with the blinding factors drawn from the given RNG. Proofs are only
+// zero-knowledge with a cryptographically secure RNG, but a seeded one makes them reproducible
+// byte for byte, such as for test vectors or comparing the native and WASM builds.
+pub fn generate_proof_with_rng<C: Circuit<Fp>, R: RngCore>(
+    params: &Params<EqAffine>,
+    pk: &ProvingKey<EqAffine>,
+    circuit: C,
+    pub_input: &[Fp],
+    domain: &str,
+    rng: R,
+) -> Result<Vec<u8>, BitVeilError> {
+    let mut transcript = Blake2bWrite::<_, _, Challenge255<_>>::init(vec![]);
+    bind_domain(&mut transcript, domain)?;
+    create_proof(
+        params,
+        pk,
+        &[circuit],
+        &[&[pub_input]],
+        rng,
+        &mut transcript,
+    )
+    .map_err(BitVeilError::Synthesis)?;
+    Ok(transcript.finalize())
+}
+
+// Verifies the proof in the domain it must be bound to
+pub fn verify(
+    params: &Params<EqAffine>,
+    vk: &VerifyingKey<EqAffine>,
+    pub_input: &[Fp],
+    proof: Vec<u8>,
+    domain: &str,
+) -> Result<(), BitVeilError> {
+    verify_single(params, vk, pub_input, &proof, domain)
+}
+
+fn verify_single(
+    params: &Params<EqAffine>,
+    vk: &VerifyingKey<EqAffine>,
+    pub_input: &[Fp],
+    proof: &[u8],
+    domain: &str,
+) -> Result<(), BitVeilError> {
+    let strategy = SingleVerifier::new(params);
+    let mut transcript = Blake2bRead::<_, _, Challenge255<_>>::init(proof);
+    bind_domain(&mut transcript, domain)?;
+    verify_proof(params, vk, strategy, &[&[pub_input]], &mut transcript)
+        .map_err(BitVeilError::VerificationFailed)
+}
+
+// A verification strategy which returns the multi-scalar multiplication a proof is checked with
+// instead of evaluating it, like the one behind halo2's BatchVerifier. halo2's batch verifier
+// starts every transcript itself, so it cannot verify proofs bound to a domain.
+struct AccumulatorStrategy<'params> {
+    msm: MSM<'params, EqAffine>,
+}
+
+impl<'params> VerificationStrategy<'params, EqAffine> for AccumulatorStrategy<'params> {
+    type Output = MSM<'params, EqAffine>;
+
+    fn process<E: EncodedChallenge<EqAffine>>(
+        self,
+        f: impl FnOnce(MSM<'params, EqAffine>) -> Result<Guard<'params, EqAffine, E>, Error>,
+    ) -> Result<Self::Output, Error> {
+        let guard = f(self.msm)?;
+        Ok(guard.use_challenges())
+    }
+}
+
+// Verifies many proofs for the same verifying key and domain at once. Each proof is checked with
+// the AccumulatorStrategy above rather than halo2's BatchVerifier, and its multi-scalar
+// multiplication is scaled by a random factor and added to a combined one, which is evaluated
+// once and is much cheaper than verifying the proofs one by one. The batch check only tells
+// whether every proof is valid, so if it fails each proof is verified on its own to report the
+// indices of the ones that failed.
+pub fn verify_batch(
+    params: &Params<EqAffine>,
+    vk: &VerifyingKey<EqAffine>,
+    proofs: &[(Vec<Fp>, Vec<u8>)],
+    domain: &str,
+) -> Result<(), BitVeilError> {
+    let accumulate = || -> Result<MSM<'_, EqAffine>, BitVeilError> {
+        let mut acc = params.empty_msm();
+        for (pub_input, proof) in proofs {
+            let strategy = AccumulatorStrategy {
+                msm: params.empty_msm(),
+            };
+            let mut transcript = Blake2bRead::<_, _, Challenge255<_>>::init(&proof[..]);
+            bind_domain(&mut transcript, domain)?;
+            let msm = verify_proof(params, vk, strategy, &[&[pub_input]], &mut transcript)
+                .map_err(BitVeilError::VerificationFailed)?;
+
+            // Scaling the combined check by a random factor keeps a failing proof from cancelling
+            // out with the others
+            acc.scale(Fp::random(OsRng));
+            acc.add_msm(&msm);
+        }
+        Ok(acc)
+    };
+    if accumulate().is_ok_and(|msm| msm.eval()) {
+        return Ok(());
+    }
+
+    let failed: Vec<usize> = proofs
+        .iter()
+        .enumerate()
+        .filter(|(_, (pub_input, proof))| {
+            verify_single(params, vk, pub_input, proof, domain).is_err()
+        })
+        .map(|(i, _)| i)
+        .collect();
+    Err(BitVeilError::BatchVerificationFailed(failed))
+}
+
+// Verifies a threshold proof, which shows that the distance between the committed vectors is at
+// most the threshold without revealing the distance itself
+pub fn verify_threshold(
+    params: &Params<EqAffine>,
+    vk: &VerifyingKey<EqAffine>,
+    threshold: u64,
+    commitment_a: Fp,
+    commitment_b: Fp,
+    proof: Vec<u8>,
+    domain: &str,
+) -> Result<(), BitVeilError> {
+    let pub_input = public_inputs(&[Fp::from(threshold)], commitment_a, commitment_b);
+    verify(params, vk, &pub_input, proof, domain)
+}
+
+// A metric between two committed vectors, which keygen, proving and verifying are generic over.
+// Each metric names the circuit that proves it, calculates outside the circuit the value the
+// circuit exposes, and encodes the public inputs of its proofs. Only the circuits whose proofs
+// claim the value of a metric implement it: distance, popcount, weighted, rotation and inner
+// product. Adding a metric means implementing this trait for it and giving its circuit a
+// `CircuitKind`, which is how key files and proof bundles record it.
+pub trait DistanceMetric: Sized {
+    type Circuit: Circuit<Fp>;
+
+    // The circuit recorded in the keys and proofs of the metric
+    const KIND: CircuitKind;
+
+    // Recovers the metric from keys generated for it, whose kind is `Self::KIND`
+    fn from_key_info(info: &KeyInfo) -> Self;
+
+    // The number of shifts the keys record, which only rotation metrics have
+    fn shifts(&self) -> usize {
+        0
+    }
+
+    // The weights the keys record, which only weighted metrics have
+    fn weights(&self) -> &[u64] {
+        &[]
+    }
+
+    // The smallest k that fits the circuit for vectors of the given length
+    fn k(&self, length: usize) -> u32;
+
+    // The circuit without witnesses, from which the keys are generated
+    fn empty_circuit(&self, length: usize) -> Self::Circuit;
+
+    // The circuit proving the metric between two vectors committed to with the given salts
+    fn create_circuit(
+        &self,
+        a: Vec<u64>,
+        b: Vec<u64>,
+        salt_a: Fp,
+        salt_b: Fp,
+    ) -> Result<Self::Circuit, BitVeilError>;
+
+    // Calculates the value the circuit exposes, which is what a proof claims
+    fn calculate(&self, a: &[u64], b: &[u64]) -> Result<Fp, BitVeilError>;
+
+    // Commits to a vector like the circuit does
+    fn commit(&self, vector: &[u64], salt: Fp) -> Result<Fp, BitVeilError> {
+        commit_vector(vector, salt)
+    }
+
+    // Builds the public inputs of a proof from the claimed value and the commitments to both
+    // vectors
+    fn public_inputs(&self, value: Fp, commitment_a: Fp, commitment_b: Fp) -> Vec<Fp> {
+        public_inputs(&[value], commitment_a, commitment_b)
+    }
+}
+
+// The Hamming distance, proven with one row per bit
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Hamming;
+
+impl DistanceMetric for Hamming {
+    type Circuit = BitVeilCircuit<Fp>;
+    const KIND: CircuitKind = CircuitKind::Distance;
+
+    fn from_key_info(_info: &KeyInfo) -> Self {
+        Hamming
+    }
+
+    fn k(&self, length: usize) -> u32 {
+        k_for_length(length)
+    }
+
+    fn empty_circuit(&self, length: usize) -> Self::Circuit {
+        empty_circuit(length)
+    }
+
+    fn create_circuit(
+        &self,
+        a: Vec<u64>,
+        b: Vec<u64>,
+        salt_a: Fp,
+        salt_b: Fp,
+    ) -> Result<Self::Circuit, BitVeilError> {
+        create_circuit(a, b, salt_a, salt_b)
+    }
+
+    fn calculate(&self, a: &[u64], b: &[u64]) -> Result<Fp, BitVeilError> {
+        Ok(calculate_hamming_distance(a.to_vec(), b.to_vec())?[0])
+    }
+}
+
+// The Hamming distance, proven with one lookup per nibble
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PopcountHamming;
+
+impl DistanceMetric for PopcountHamming {
+    type Circuit = BitVeilPopcountCircuit<Fp>;
+    const KIND: CircuitKind = CircuitKind::Popcount;
+
+    fn from_key_info(_info: &KeyInfo) -> Self {
+        PopcountHamming
+    }
+
+    fn k(&self, length: usize) -> u32 {
+        k_for_popcount(length)
+    }
+
+    fn empty_circuit(&self, length: usize) -> Self::Circuit {
+        empty_popcount_circuit(length)
+    }
+
+    fn create_circuit(
+        &self,
+        a: Vec<u64>,
+        b: Vec<u64>,
+        salt_a: Fp,
+        salt_b: Fp,
+    ) -> Result<Self::Circuit, BitVeilError> {
+        create_popcount_circuit(a, b, salt_a, salt_b)
+    }
+
+    fn calculate(&self, a: &[u64], b: &[u64]) -> Result<Fp, BitVeilError> {
+        Ok(calculate_hamming_distance(a.to_vec(), b.to_vec())?[0])
+    }
+}
+
+// The sum of the weights of the bits where the vectors differ, with one weight per bit
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WeightedHamming {
+    pub weights: Vec<u64>,
+}
+
+impl DistanceMetric for WeightedHamming {
+    type Circuit = BitVeilWeightedCircuit<Fp>;
+    const KIND: CircuitKind = CircuitKind::Weighted;
+
+    fn from_key_info(info: &KeyInfo) -> Self {
+        WeightedHamming {
+            weights: info.weights.clone(),
+        }
+    }
+
+    fn weights(&self) -> &[u64] {
+        &self.weights
+    }
+
+    fn k(&self, length: usize) -> u32 {
+        k_for_length(length)
+    }
+
+    fn empty_circuit(&self, length: usize) -> Self::Circuit {
+        empty_weighted_circuit(length, &self.weights)
+    }
+
+    fn create_circuit(
+        &self,
+        a: Vec<u64>,
+        b: Vec<u64>,
+        salt_a: Fp,
+        salt_b: Fp,
+    ) -> Result<Self::Circuit, BitVeilError> {
+        create_weighted_circuit(a, b, salt_a, salt_b, &self.weights)
+    }
+
+    fn calculate(&self, a: &[u64], b: &[u64]) -> Result<Fp, BitVeilError> {
+        calculate_weighted_distance(a, b, &self.weights)
+    }
+}
+
+// The smallest Hamming distance between the first vector and the second rotated by each shift from
+// -shifts to shifts bits
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RotatedHamming {
+    pub shifts: usize,
+}
+
+impl DistanceMetric for RotatedHamming {
+    type Circuit = BitVeilRotationCircuit<Fp>;
+    const KIND: CircuitKind = CircuitKind::Rotation;
+
+    fn from_key_info(info: &KeyInfo) -> Self {
+        RotatedHamming {
+            shifts: info.shifts,
+        }
+    }
+
+    fn shifts(&self) -> usize {
+        self.shifts
+    }
+
+    fn k(&self, length: usize) -> u32 {
+        k_for_rotation(length, self.shifts)
+    }
+
+    fn empty_circuit(&self, length: usize) -> Self::Circuit {
+        empty_rotation_circuit(length, self.shifts)
+    }
+
+    fn create_circuit(
+        &self,
+        a: Vec<u64>,
+        b: Vec<u64>,
+        salt_a: Fp,
+        salt_b: Fp,
+    ) -> Result<Self::Circuit, BitVeilError> {
+        create_rotation_circuit(a, b, salt_a, salt_b, self.shifts)
+    }
+
+    fn calculate(&self, a: &[u64], b: &[u64]) -> Result<Fp, BitVeilError> {
+        calculate_rotated_distance(a, b, self.shifts)
+    }
+}
+
+// The inner product of two vectors of integers from 0 to MAX_ELEMENT
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct InnerProduct;
+
+impl DistanceMetric for InnerProduct {
+    type Circuit = BitVeilInnerProductCircuit<Fp>;
+    const KIND: CircuitKind = CircuitKind::InnerProduct;
+
+    fn from_key_info(_info: &KeyInfo) -> Self {
+        InnerProduct
+    }
+
+    fn k(&self, length: usize) -> u32 {
+        k_for_inner_product(length)
+    }
+
+    fn empty_circuit(&self, length: usize) -> Self::Circuit {
+        empty_inner_product_circuit(length)
+    }
+
+    fn create_circuit(
+        &self,
+        a: Vec<u64>,
+        b: Vec<u64>,
+        salt_a: Fp,
+        salt_b: Fp,
+    ) -> Result<Self::Circuit, BitVeilError> {
+        create_inner_product_circuit(a, b, salt_a, salt_b)
+    }
+
+    fn calculate(&self, a: &[u64], b: &[u64]) -> Result<Fp, BitVeilError> {
+        calculate_inner_product(a, b)
+    }
+
+    fn commit(&self, vector: &[u64], salt: Fp) -> Result<Fp, BitVeilError> {
+        commit_integer_vector(vector, salt)
+    }
+}
+
+// Builds the public inputs of a proof of the metric between two vectors committed to with the
+// given salts
+pub fn metric_public_inputs<M: DistanceMetric>(
+    metric: &M,
+    a: &[u64],
+    b: &[u64],
+    salt_a: Fp,
+    salt_b: Fp,
+) -> Result<Vec<Fp>, BitVeilError> {
+    Ok(metric.public_inputs(
+        metric.calculate(a, b)?,
+        metric.commit(a, salt_a)?,
+        metric.commit(b, salt_b)?,
+    ))
+}
+
+// Runs the mock prover on the circuit of a metric, at the smallest k that fits it unless one is
+// given
+pub fn run_metric_mock_prover<M: DistanceMetric>(
+    metric: &M,
+    k: Option<u32>,
+    a: Vec<u64>,
+    b: Vec<u64>,
+    salt_a: Fp,
+    salt_b: Fp,
+) -> Result<(), BitVeilError> {
+    let pub_input = metric_public_inputs(metric, &a, &b, salt_a, salt_b)?;
+    let k = k.unwrap_or_else(|| metric.k(a.len()));
+    let circuit = metric.create_circuit(a, b, salt_a, salt_b)?;
+    run_mock_prover(k, &circuit, &pub_input)
+}
+
+// The constructors which generate the keys of a circuit, which the prover and the verifier share.
+// Each validates the shifts and weights and generates the verifying key with `KeyInfo::generate`,
+// and the type builds itself from the keys in `from_keys`.
+macro_rules! impl_key_constructors {
+    ($($keys:ty),*) => {
+        $(
+            impl $keys {
+                // Generates the keys of the circuit for vectors of the given length
+                pub fn new(
+                    params: Params<EqAffine>,
+                    kind: CircuitKind,
+                    length: usize,
+                ) -> Result<Self, BitVeilError> {
+                    Self::generate(params, kind, length, 1, 0, Vec::new())
+                }
+
+                // Generates the keys of the batch circuit comparing a probe with the given number
+                // of candidates of the given length
+                pub fn new_batch(
+                    params: Params<EqAffine>,
+                    length: usize,
+                    candidates: usize,
+                ) -> Result<Self, BitVeilError> {
+                    Self::generate(params, CircuitKind::Batch, length, candidates, 0, Vec::new())
+                }
+
+                // Generates the keys of the rotation circuit, or of its threshold variant,
+                // comparing vectors of the given length at the given number of shifts in each
+                // direction
+                pub fn new_rotation(
+                    params: Params<EqAffine>,
+                    length: usize,
+                    shifts: usize,
+                    threshold: bool,
+                ) -> Result<Self, BitVeilError> {
+                    let kind = match threshold {
+                        true => CircuitKind::RotationThreshold,
+                        false => CircuitKind::Rotation,
+                    };
+                    Self::generate(params, kind, length, 1, shifts, Vec::new())
+                }
+
+                // Generates the keys of the similarity circuit for vectors of the given length
+                pub fn new_similarity(
+                    params: Params<EqAffine>,
+                    length: usize,
+                    metric: SimilarityMetric,
+                ) -> Result<Self, BitVeilError> {
+                    Self::generate(params, metric.kind(), length, 1, 0, Vec::new())
+                }
+
+                // Generates the keys of the embedding circuit for integer vectors of the given
+                // length
+                pub fn new_embedding(
+                    params: Params<EqAffine>,
+                    length: usize,
+                    metric: EmbeddingMetric,
+                ) -> Result<Self, BitVeilError> {
+                    Self::generate(params, metric.kind(), length, 1, 0, Vec::new())
+                }
+
+                // Generates the keys of the weighted circuit, which compares vectors with one bit
+                // per weight
+                pub fn new_weighted(
+                    params: Params<EqAffine>,
+                    weights: Vec<u64>,
+                ) -> Result<Self, BitVeilError> {
+                    Self::generate(params, CircuitKind::Weighted, weights.len(), 1, 0, weights)
+                }
+
+                // Generates the keys of the circuit of a metric for vectors of the given length
+                pub fn new_metric<M: DistanceMetric>(
+                    params: Params<EqAffine>,
+                    metric: &M,
+                    length: usize,
+                ) -> Result<Self, BitVeilError> {
+                    Self::generate(
+                        params,
+                        M::KIND,
+                        length,
+                        1,
+                        metric.shifts(),
+                        metric.weights().to_vec(),
+                    )
+                }
+
+                fn generate(
+                    params: Params<EqAffine>,
+                    kind: CircuitKind,
+                    length: usize,
+                    candidates: usize,
+                    shifts: usize,
+                    weights: Vec<u64>,
+                ) -> Result<Self, BitVeilError> {
+                    let (info, vk) =
+                        KeyInfo::generate(&params, kind, length, candidates, shifts, &weights)?;
+                    Self::from_keys(params, info, vk)
+                }
+            }
+        )*
+    };
+}
+
+impl_key_constructors!(BitVeilProver, BitVeilVerifier);
+
+// Generates proofs for one circuit, keeping the params and the proving key so a long-running
+// service only builds the keys once. Proofs are bound to no domain unless one is set with
+// `with_domain`.
+pub struct BitVeilProver {
+    params: Params<EqAffine>,
+    info: KeyInfo,
+    pk: ProvingKey<EqAffine>,
+    domain: String,
+}
+
+impl BitVeilProver {
+    // Builds the prover from newly generated keys, deriving the proving key from the verifying key
+    fn from_keys(
+        params: Params<EqAffine>,
+        info: KeyInfo,
+        vk: VerifyingKey<EqAffine>,
+    ) -> Result<Self, BitVeilError> {
+        let pk = info.keygen_pk(&params, vk)?;
+        Ok(BitVeilProver {
+            params,
+            info,
+            pk,
+            domain: String::new(),
+        })
+    }
+
+    // Loads the proving key described by a key file, checking it against the params
+    pub fn from_key_info(params: Params<EqAffine>, info: KeyInfo) -> Result<Self, BitVeilError> {
+        let pk = info.proving_key(&params)?;
+        Ok(BitVeilProver {
+            params,
+            info,
+            pk,
+            domain: String::new(),
+        })
+    }
+
+    // Binds the proofs of the prover to a domain tag, such as an application id and protocol
+    // version, which the verifier must be given too
+    pub fn with_domain(mut self, domain: &str) -> Result<Self, BitVeilError> {
+        check_domain(domain)?;
+        self.domain = domain.to_string();
+        Ok(self)
+    }
+
+    pub fn domain(&self) -> &str {
+        &self.domain
+    }
+
+    pub fn key_info(&self) -> &KeyInfo {
+        &self.info
+    }
+
+    pub fn params(&self) -> &Params<EqAffine> {
+        &self.params
+    }
+
+    // Proves a circuit for the public inputs in the prover's domain and bundles the proof
+    fn prove_circuit<C: Circuit<Fp>>(
+        &self,
+        circuit: C,
+        pub_input: Vec<Fp>,
+    ) -> Result<ProofBundle, BitVeilError> {
+        let proof = generate_proof(&self.params, &self.pk, circuit, &pub_input, &self.domain)?;
+        Ok(ProofBundle::new(&self.info, &self.domain, pub_input, proof))
+    }
+
+    fn check_circuit(&self, kind: CircuitKind, a: &[u64]) -> Result<(), BitVeilError> {
+        if self.info.kind != kind {
+            return Err(BitVeilError::KeyMismatch(format!(
+                "prover was created for {} proofs, not {} proofs",
+                self.info.kind, kind
+            )));
+        }
+        if circuit_length(a.len()) != self.info.length {
+            return Err(BitVeilError::KeyMismatch(format!(
+                "prover was created for vectors of length {}, got {}",
+                self.info.length,
+                a.len()
+            )));
+        }
+        Ok(())
+    }
+
+    // Proves the metric the keys were generated for between two vectors, committed to with the
+    // given salts
+    pub fn prove_metric<M: DistanceMetric>(
+        &self,
+        a: Vec<u64>,
+        b: Vec<u64>,
+        salt_a: Fp,
+        salt_b: Fp,
+    ) -> Result<ProofBundle, BitVeilError> {
+        self.check_circuit(M::KIND, &a)?;
+        let metric = M::from_key_info(&self.info);
+        let pub_input = metric_public_inputs(&metric, &a, &b, salt_a, salt_b)?;
+        let circuit = metric.create_circuit(a, b, salt_a, salt_b)?;
+        self.prove_circuit(circuit, pub_input)
+    }
+
+    // Proves the distance between two vectors, committed to with the given salts. The popcount
+    // circuit proves the same distance, so this also generates proofs with its keys.
+    pub fn prove(
+        &self,
+        a: Vec<u64>,
+        b: Vec<u64>,
+        salt_a: Fp,
+        salt_b: Fp,
+    ) -> Result<ProofBundle, BitVeilError> {
+        match self.info.kind {
+            CircuitKind::Popcount => self.prove_metric::<PopcountHamming>(a, b, salt_a, salt_b),
+            _ => self.prove_metric::<Hamming>(a, b, salt_a, salt_b),
+        }
+    }
+
+    // Proves that the distance between two vectors is at most the threshold
+    pub fn prove_threshold(
+        &self,
+        a: Vec<u64>,
+        b: Vec<u64>,
+        salt_a: Fp,
+        salt_b: Fp,
+        threshold: u64,
+    ) -> Result<ProofBundle, BitVeilError> {
+        self.check_circuit(CircuitKind::Threshold, &a)?;
+        let pub_input = public_inputs(
+            &[Fp::from(threshold)],
+            commit_vector(&a, salt_a)?,
+            commit_vector(&b, salt_b)?,
+        );
+        let circuit = create_threshold_circuit(a, b, salt_a, salt_b, threshold)?;
+        self.prove_circuit(circuit, pub_input)
+    }
+
+    // Proves the distance between two vectors in the session with the given nonce, along with the
+    // nullifier of vector B in the given context, which is derived from the nullifier secret that
+    // vector B is committed to with
+    #[allow(clippy::too_many_arguments)]
+    pub fn prove_session(
+        &self,
+        a: Vec<u64>,
+        b: Vec<u64>,
+        salt_a: Fp,
+        salt_b: Fp,
+        secret: Fp,
+        nonce: Fp,
+        context: Fp,
+    ) -> Result<ProofBundle, BitVeilError> {
+        self.check_circuit(CircuitKind::Session, &a)?;
+        let pub_input = session_public_inputs(
+            calculate_hamming_distance(a.clone(), b.clone())?[0],
+            commit_vector(&a, salt_a)?,
+            commit_session_vector(&b, salt_b, secret)?,
+            nonce,
+            context,
+            derive_nullifier(&b, secret, context)?,
+        );
+        let circuit = create_session_circuit(a, b, salt_a, salt_b, secret, nonce, context)?;
+        self.prove_circuit(circuit, pub_input)
+    }
+
+    // Proves that the distance between the probe and a template enrolled in the registry is at
+    // most the threshold. The template is found in the registry by its commitment.
+    pub fn prove_membership(
+        &self,
+        probe: Vec<u64>,
+        template: Vec<u64>,
+        salt_probe: Fp,
+        salt_template: Fp,
+        threshold: u64,
+        registry: &Registry,
+    ) -> Result<ProofBundle, BitVeilError> {
+        self.check_circuit(CircuitKind::Membership, &probe)?;
+        let leaf = commit_vector(&template, salt_template)?;
+        let index = registry.position(leaf).ok_or(BitVeilError::NotEnrolled)?;
+        let path = registry.path(index)?;
+
+        let pub_input = membership_public_inputs(
+            Fp::from(threshold),
+            commit_vector(&probe, salt_probe)?,
+            path.root(leaf),
+        );
+        let circuit = create_membership_circuit(
+            probe,
+            template,
+            salt_probe,
+            salt_template,
+            threshold,
+            &path,
+        )?;
+        self.prove_circuit(circuit, pub_input)
+    }
+
+    // Proves the distance between two vectors over the bits valid in both masks, along with the
+    // number of those bits
+    pub fn prove_masked(
+        &self,
+        a: Vec<u64>,
+        b: Vec<u64>,
+        mask_a: Vec<u64>,
+        mask_b: Vec<u64>,
+        salt_a: Fp,
+        salt_b: Fp,
+    ) -> Result<ProofBundle, BitVeilError> {
+        self.check_circuit(CircuitKind::Masked, &a)?;
+        let (distance, valid) = calculate_masked_distance(&a, &b, &mask_a, &mask_b)?;
+        let pub_input = masked_public_inputs(
+            distance,
+            valid,
+            commit_masked_vector(&a, &mask_a, salt_a)?,
+            commit_masked_vector(&b, &mask_b, salt_b)?,
+        );
+        let circuit = create_masked_circuit(a, b, mask_a, mask_b, salt_a, salt_b)?;
+        self.prove_circuit(circuit, pub_input)
+    }
+
+    // Proves the smallest distance between the first vector and the second vector rotated by each
+    // of the shifts the keys were generated for
+    pub fn prove_rotation(
+        &self,
+        a: Vec<u64>,
+        b: Vec<u64>,
+        salt_a: Fp,
+        salt_b: Fp,
+    ) -> Result<ProofBundle, BitVeilError> {
+        self.prove_metric::<RotatedHamming>(a, b, salt_a, salt_b)
+    }
+
+    // Proves that the smallest distance over the shifts is at most the threshold
+    pub fn prove_rotation_threshold(
+        &self,
+        a: Vec<u64>,
+        b: Vec<u64>,
+        salt_a: Fp,
+        salt_b: Fp,
+        threshold: u64,
+    ) -> Result<ProofBundle, BitVeilError> {
+        self.check_circuit(CircuitKind::RotationThreshold, &a)?;
+        let pub_input = public_inputs(
+            &[Fp::from(threshold)],
+            commit_vector(&a, salt_a)?,
+            commit_vector(&b, salt_b)?,
+        );
+        let circuit =
+            create_rotation_threshold_circuit(a, b, salt_a, salt_b, self.info.shifts, threshold)?;
+        self.prove_circuit(circuit, pub_input)
+    }
+
+    // Proves the sum of the weights of the positions where two vectors differ, using the weights
+    // the keys were generated for
+    pub fn prove_weighted(
+        &self,
+        a: Vec<u64>,
+        b: Vec<u64>,
+        salt_a: Fp,
+        salt_b: Fp,
+    ) -> Result<ProofBundle, BitVeilError> {
+        self.prove_metric::<WeightedHamming>(a, b, salt_a, salt_b)
+    }
+
+    // Proves the inner product of two integer vectors, committed to with the given salts
+    pub fn prove_inner_product(
+        &self,
+        a: Vec<u64>,
+        b: Vec<u64>,
+        salt_a: Fp,
+        salt_b: Fp,
+    ) -> Result<ProofBundle, BitVeilError> {
+        self.prove_metric::<InnerProduct>(a, b, salt_a, salt_b)
+    }
+
+    // Proves that the distance the keys were generated for between two integer vectors is at most
+    // the threshold
+    pub fn prove_embedding(
+        &self,
+        a: Vec<u64>,
+        b: Vec<u64>,
+        salt_a: Fp,
+        salt_b: Fp,
+        threshold: u64,
+    ) -> Result<ProofBundle, BitVeilError> {
+        let metric = self.info.kind.embedding().ok_or_else(|| {
+            BitVeilError::KeyMismatch(format!(
+                "prover was created for {} proofs, not embedding proofs",
+                self.info.kind
+            ))
+        })?;
+        self.check_circuit(metric.kind(), &a)?;
+        let pub_input = public_inputs(
+            &[Fp::from(threshold)],
+            commit_integer_vector(&a, salt_a)?,
+            commit_integer_vector(&b, salt_b)?,
+        );
+        let circuit = create_embedding_circuit(a, b, salt_a, salt_b, metric, threshold)?;
+        self.prove_circuit(circuit, pub_input)
+    }
+
+    // Proves that the similarity the keys were generated for is at least the threshold
+    // numerator / denominator
+    pub fn prove_similarity(
+        &self,
+        a: Vec<u64>,
+        b: Vec<u64>,
+        salt_a: Fp,
+        salt_b: Fp,
+        numerator: u64,
+        denominator: u64,
+    ) -> Result<ProofBundle, BitVeilError> {
+        let metric = self.info.kind.similarity().ok_or_else(|| {
+            BitVeilError::KeyMismatch(format!(
+                "prover was created for {} proofs, not similarity proofs",
+                self.info.kind
+            ))
+        })?;
+        self.check_circuit(metric.kind(), &a)?;
+        let pub_input = similarity_public_inputs(
+            Fp::from(numerator),
+            Fp::from(denominator),
+            commit_vector(&a, salt_a)?,
+            commit_vector(&b, salt_b)?,
+        );
+        let circuit =
+            create_similarity_circuit(a, b, salt_a, salt_b, metric, numerator, denominator)?;
+        self.prove_circuit(circuit, pub_input)
+    }
+
+    // Proves the distance between a probe and each of the candidates in one proof
+    pub fn prove_batch(
+        &self,
+        probe: Vec<u64>,
+        candidates: Vec<Vec<u64>>,
+        salt_probe: Fp,
+        salts: Vec<Fp>,
+    ) -> Result<ProofBundle, BitVeilError> {
+        self.check_circuit(CircuitKind::Batch, &probe)?;
+        if candidates.len() != self.info.candidates {
+            return Err(BitVeilError::KeyMismatch(format!(
+                "prover was created for {} candidates, got {}",
+                self.info.candidates,
+                candidates.len()
+            )));
+        }
+        check_batch(&probe, &candidates, salts.len())?;
+
+        let distances: Vec<Fp> = candidates
+            .iter()
+            .map(|candidate| calculate_hamming_distance(probe.clone(), candidate.clone()))
+            .map(|distance| distance.map(|distance| distance[0]))
+            .collect::<Result<_, _>>()?;
+        let commitments: Vec<Fp> = candidates
+            .iter()
+            .zip(&salts)
+            .map(|(candidate, salt)| commit_vector(candidate, *salt))
+            .collect::<Result<_, _>>()?;
+        let pub_input =
+            batch_public_inputs(commit_vector(&probe, salt_probe)?, &distances, &commitments);
+
+        let circuit = create_batch_circuit(probe, candidates, salt_probe, salts)?;
+        self.prove_circuit(circuit, pub_input)
+    }
+}
+
+// Verifies proofs for one circuit, keeping the params and the verifying key between proofs. Only
+// proofs bound to no domain verify unless a domain is set with `with_domain`.
+pub struct BitVeilVerifier {
+    params: Params<EqAffine>,
+    info: KeyInfo,
+    vk: VerifyingKey<EqAffine>,
+    domain: String,
+}
+
+impl BitVeilVerifier {
+    // Builds the verifier from newly generated keys
+    fn from_keys(
+        params: Params<EqAffine>,
+        info: KeyInfo,
+        vk: VerifyingKey<EqAffine>,
+    ) -> Result<Self, BitVeilError> {
+        Ok(BitVeilVerifier {
+            params,
+            info,
+            vk,
+            domain: String::new(),
+        })
+    }
+
+    // Loads the verifying key described by a key file, checking it against the params
+    pub fn from_key_info(params: Params<EqAffine>, info: KeyInfo) -> Result<Self, BitVeilError> {
+        let vk = info.verifying_key(&params)?;
+        Ok(BitVeilVerifier {
+            params,
+            info,
+            vk,
+            domain: String::new(),
+        })
+    }
+
+    // Only accepts proofs bound to the given domain tag
+    pub fn with_domain(mut self, domain: &str) -> Result<Self, BitVeilError> {
+        check_domain(domain)?;
+        self.domain = domain.to_string();
+        Ok(self)
+    }
+
+    pub fn domain(&self) -> &str {
+        &self.domain
+    }
+
+    pub fn key_info(&self) -> &KeyInfo {
+        &self.info
+    }
+
+    pub fn params(&self) -> &Params<EqAffine> {
+        &self.params
+    }
+
+    // Verifies a proof bundle generated with this verifier's keys in its domain. The caller still
+    // has to check that the bundle's public inputs are the ones it expects.
+    pub fn verify(&self, bundle: &ProofBundle) -> Result<(), BitVeilError> {
+        bundle.check_key(&self.info)?;
+        bundle.check_domain(&self.domain)?;
+        verify(
+            &self.params,
+            &self.vk,
+            &bundle.public_inputs,
+            bundle.proof.clone(),
+            &self.domain,
+        )
+    }
+
+    // Verifies many proof bundles generated with this verifier's keys in its domain at once,
+    // reporting the indices of the bundles which were generated with other keys or in another
+    // domain or failed verification
+    pub fn verify_batch(&self, bundles: &[ProofBundle]) -> Result<(), BitVeilError> {
+        let (matching, mut failed): (Vec<usize>, Vec<usize>) = (0..bundles.len()).partition(|i| {
+            bundles[*i].check_key(&self.info).is_ok()
+                && bundles[*i].check_domain(&self.domain).is_ok()
+        });
+
+        let proofs: Vec<(Vec<Fp>, Vec<u8>)> = matching
+            .iter()
+            .map(|i| (bundles[*i].public_inputs.clone(), bundles[*i].proof.clone()))
+            .collect();
+        match verify_batch(&self.params, &self.vk, &proofs, &self.domain) {
+            Ok(()) => {}
+            Err(BitVeilError::BatchVerificationFailed(indices)) => {
+                failed.extend(indices.into_iter().map(|i| matching[i]))
+            }
+            Err(e) => return Err(e),
+        }
+
+        if failed.is_empty() {
+            return Ok(());
+        }
+        failed.sort_unstable();
+        Err(BitVeilError::BatchVerificationFailed(failed))
+    }
+}
+
+// Soundness tests, which give the mock prover witnesses that a malicious prover could assign and
+// check that the gate meant to catch each of them is the one that fails
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use halo2_proofs::{
+        dev::{FailureLocation, VerifyFailure},
+        plonk::Any,
+    };
+
+    const LENGTH: usize = 8;
+    const A: [u64; LENGTH] = [1, 0, 1, 1, 0, 0, 1, 0];
+    const B: [u64; LENGTH] = [1, 1, 0, 1, 0, 1, 1, 1];
+    const DISTANCE: u64 = 4;
+
+    // Masks which are both 1 at five positions, where A and B differ once
+    const MASK_A: [u64; LENGTH] = [1, 1, 0, 1, 1, 1, 1, 0];
+    const MASK_B: [u64; LENGTH] = [1, 0, 1, 1, 1, 1, 1, 1];
+    const MASKED_DISTANCE: u64 = 1;
+    const VALID: u64 = 5;
+
+    const WEIGHTS: [u64; LENGTH] = [3, 1, 4, 1, 5, 9, 2, 6];
+
+    const ELEMENTS_A: [u64; LENGTH] = [3, 200, 0, 255, 17, 64, 9, 128];
+    const ELEMENTS_B: [u64; LENGTH] = [5, 100, 0, 250, 30, 64, 1, 255];
+
+    // Large enough for the popcount table
+    const K: u32 = 9;
+
+    // The configuration of a circuit that malicious regions are laid out with: the shared
+    // configuration the chip is constructed from, and the lookup tables the circuit loads
+    trait Layout: Clone {
+        fn base(&self) -> &BitVeilConfig;
+
+        fn load(&self, _chip: &BitVeilChip<Fp>, _layouter: impl Layouter<Fp>) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    impl Layout for BitVeilConfig {
+        fn base(&self) -> &BitVeilConfig {
+            self
+        }
+    }
+
+    macro_rules! impl_layout {
+        ($($config:ty),*) => {
+            $(
+                impl Layout for $config {
+                    fn base(&self) -> &BitVeilConfig {
+                        &self.base
+                    }
+                }
+            )*
+        };
+    }
+
+    impl_layout!(
+        BitVeilMaskedConfig,
+        BitVeilWeightedConfig,
+        BitVeilRotationConfig,
+        BitVeilSimilarityConfig,
+        BitVeilMembershipConfig
+    );
+
+    impl Layout for BitVeilPopcountConfig {
+        fn base(&self) -> &BitVeilConfig {
+            &self.base
+        }
+
+        fn load(&self, chip: &BitVeilChip<Fp>, layouter: impl Layouter<Fp>) -> Result<(), Error> {
+            chip.load_popcount_table(layouter, &self.popcount)
+        }
+    }
+
+    impl Layout for BitVeilInnerProductConfig {
+        fn base(&self) -> &BitVeilConfig {
+            &self.base
+        }
+
+        fn load(&self, chip: &BitVeilChip<Fp>, layouter: impl Layouter<Fp>) -> Result<(), Error> {
+            chip.load_element_table(layouter, &self.elements)
+        }
+    }
+
+    impl Layout for BitVeilEmbeddingConfig {
+        fn base(&self) -> &BitVeilConfig {
+            &self.base
+        }
+
+        fn load(&self, chip: &BitVeilChip<Fp>, layouter: impl Layouter<Fp>) -> Result<(), Error> {
+            chip.load_element_table(layouter, &self.elements)
+        }
+    }
+
+    type Assign<T> = fn(&BitVeilChip<Fp>, &T, &mut Region<'_, Fp>) -> Result<(), Error>;
+
+    // An advice cell of the configuration `T`, given by its column and its row in the region
+    type Cell<T> = (fn(&T) -> Column<Advice>, usize);
+
+    // A circuit with the configuration of `C` and a single region laid out by `rows`, which can
+    // hold any witness, unlike the regions the chip lays out from valid vectors, with the given
+    // cell overwritten afterwards. The lookup tables `C` configures are loaded as usual.
+    struct Malicious<C: Circuit<Fp>> {
+        rows: Assign<C::Config>,
+        tampered: Option<(Cell<C::Config>, u64)>,
+        _circuit: PhantomData<C>,
+    }
+
+    impl<C: Circuit<Fp>> Malicious<C> {
+        fn new(rows: Assign<C::Config>, tampered: Option<(Cell<C::Config>, u64)>) -> Self {
+            Self {
+                rows,
+                tampered,
+                _circuit: PhantomData,
+            }
+        }
+    }
+
+    impl<C: Circuit<Fp>> Circuit<Fp> for Malicious<C>
+    where
+        C::Config: Layout,
+    {
+        type Config = C::Config;
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self {
+            Self::new(self.rows, self.tampered)
+        }
+
+        fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+            C::configure(meta)
+        }
+
+        fn synthesize(
+            &self,
+            config: Self::Config,
+            mut layouter: impl Layouter<Fp>,
+        ) -> Result<(), Error> {
+            let bitveil_chip = BitVeilChip::<Fp>::construct(config.base().clone());
+            config.load(&bitveil_chip, layouter.namespace(|| "load tables"))?;
+            layouter.assign_region(
+                || "malicious",
+                |mut region: Region<'_, Fp>| {
+                    (self.rows)(&bitveil_chip, &config, &mut region)?;
+                    match self.tampered {
+                        Some(((column, row), value)) => {
+                            tamper(&mut region, column(&config), row, value)
+                        }
+                        None => Ok(()),
+                    }
+                },
+            )
+        }
+    }
+
+    fn bits(vector: &[u64]) -> Vec<Value<Fp>> {
+        vector
+            .iter()
+            .map(|bit| Value::known(Fp::from(*bit)))
+            .collect()
+    }
+
+    // The elements of an integer vector, which the element regions take one per row like bits
+    fn elements(vector: &[u64]) -> Vec<Value<Fp>> {
+        bits(vector)
+    }
+
+    // Splits a vector into nibbles, most significant bit first, like the popcount region
+    fn nibbles(vector: &[u64]) -> Vec<Value<Fp>> {
+        vector
+            .chunks(NIBBLE_BITS)
+            .map(|nibble| Value::known(Fp::from(nibble.iter().fold(0, |acc, bit| 2 * acc + bit))))
+            .collect()
+    }
+
+    // Assigns a cell again with another value, as a prover deviating from the honest witness would
+    fn tamper(
+        region: &mut Region<'_, Fp>,
+        column: Column<Advice>,
+        row: usize,
+        value: u64,
+    ) -> Result<(), Error> {
+        region.assign_advice(|| "tampered", column, row, || Value::known(Fp::from(value)))?;
+        Ok(())
+    }
+
+    // Runs the mock prover on a circuit which must not be satisfied, returning the failures
+    fn failures<C: Circuit<Fp>>(circuit: &C, pub_input: &[Fp]) -> Vec<VerifyFailure> {
+        failures_at(K, circuit, pub_input)
+    }
+
+    fn failures_at<C: Circuit<Fp>>(k: u32, circuit: &C, pub_input: &[Fp]) -> Vec<VerifyFailure> {
+        match run_mock_prover(k, circuit, pub_input) {
+            Err(BitVeilError::Unsatisfied(failures)) => failures,
+            Ok(()) => panic!("the mock prover accepted a malicious witness"),
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    // The gates and lookups which are not satisfied, each with the row of the region it fails at,
+    // sorted and without repeats. Lookups have no name and are listed as "lookup".
+    fn failing_rows(failures: &[VerifyFailure]) -> Vec<(String, usize)> {
+        let mut rows: Vec<(String, usize)> = failures
+            .iter()
+            .map(|failure| match failure {
+                // Displayed as "Constraint 0 in gate 3 ('is binary a')"
+                VerifyFailure::ConstraintNotSatisfied {
+                    constraint,
+                    location: FailureLocation::InRegion { offset, .. },
+                    ..
+                } => {
+                    let constraint = constraint.to_string();
+                    let name = constraint.rsplit("('").next().unwrap_or_default();
+                    (name.trim_end_matches("')").to_string(), *offset)
+                }
+                VerifyFailure::Lookup {
+                    location: FailureLocation::InRegion { offset, .. },
+                    ..
+                } => ("lookup".to_string(), *offset),
+                // Any other failure fails the comparison with the expected gates
+                failure => (failure.to_string(), usize::MAX),
+            })
+            .collect();
+        rows.sort();
+        rows.dedup();
+        rows
+    }
+
+    // How a malicious witness deviates from the honest rows of a table of cases
+    enum Tamper<T> {
+        // Overwrites a cell of the honest rows with another value
+        Cell(fn(&T) -> Column<Advice>, usize, u64),
+        // Lays out the rows from inputs the chip is never given, such as bits which are not binary
+        Rows(Assign<T>),
+    }
+
+    // A malicious witness and the gates it fails, each with the row of the region it fails at
+    struct Case<T> {
+        tamper: Tamper<T>,
+        fails: &'static [(&'static str, usize)],
+    }
+
+    // Checks that the honest rows are accepted, and that each case fails the expected gates at the
+    // expected rows of the same layout and nothing else
+    fn assert_cases<C: Circuit<Fp>>(honest: Assign<C::Config>, cases: &[Case<C::Config>])
+    where
+        C::Config: Layout,
+    {
+        run_mock_prover(K, &Malicious::<C>::new(honest, None), &[]).unwrap();
+        for (i, case) in cases.iter().enumerate() {
+            let circuit = match case.tamper {
+                Tamper::Cell(column, row, value) => {
+                    Malicious::<C>::new(honest, Some(((column, row), value)))
+                }
+                Tamper::Rows(rows) => Malicious::<C>::new(rows, None),
+            };
+            let failures = failures(&circuit, &[]);
+            let rows = failing_rows(&failures);
+            let rows: Vec<_> = rows
+                .iter()
+                .map(|(name, row)| (name.as_str(), *row))
+                .collect();
+            assert_eq!(rows, case.fails, "case {}: {:#?}", i, failures);
+        }
+    }
+
+    fn assert_copies(failures: &[VerifyFailure]) {
+        assert!(
+            failures
+                .iter()
+                .all(|failure| matches!(failure, VerifyFailure::Permutation { .. })),
+            "{:#?}",
+            failures
+        );
+    }
+
+    // Checks that the only failures are copy constraints, and that one of them is the public input
+    // at the given row, which differs from the cell exposed as it
+    fn assert_instance(failures: &[VerifyFailure], row: usize) {
+        let instance = (Any::Instance, 0).into();
+        let location = FailureLocation::OutsideRegion { row };
+        assert_copies(failures);
+        assert!(
+            failures.iter().any(|failure| match failure {
+                VerifyFailure::Permutation {
+                    column,
+                    location: l,
+                } => {
+                    *column == instance && *l == location
+                }
+                _ => false,
+            }),
+            "{:#?}",
+            failures
+        );
+    }
+
+    fn distance_inputs(distance: u64) -> Vec<Fp> {
+        let salt_a = Fp::from(1);
+        let salt_b = Fp::from(2);
+        public_inputs(
+            &[Fp::from(distance)],
+            commit_vector(&A, salt_a).unwrap(),
+            commit_vector(&B, salt_b).unwrap(),
+        )
+    }
+
+    #[test]
+    fn honest_witnesses_are_accepted() {
+        let circuit = create_circuit(A.to_vec(), B.to_vec(), Fp::from(1), Fp::from(2)).unwrap();
+        run_mock_prover(K, &circuit, &distance_inputs(DISTANCE)).unwrap();
+    }
+
+    fn distance_rows(
+        chip: &BitVeilChip<Fp>,
+        _: &BitVeilConfig,
+        region: &mut Region<'_, Fp>,
+    ) -> Result<(), Error> {
+        chip.assign_distance(region, &bits(&A), &bits(&B), LENGTH)?;
+        Ok(())
+    }
+
+    #[test]
+    fn malicious_distance_rows_fail_their_gates() {
+        assert_cases::<BitVeilCircuit<Fp>>(
+            distance_rows,
+            &[
+                Case {
+                    tamper: Tamper::Rows(|chip, _, region| {
+                        let mut a = bits(&A);
+                        a[2] = Value::known(Fp::from(2));
+                        chip.assign_distance(region, &a, &bits(&B), LENGTH)?;
+                        Ok(())
+                    }),
+                    fails: &[("is binary a", 2)],
+                },
+                Case {
+                    tamper: Tamper::Rows(|chip, _, region| {
+                        let mut b = bits(&B);
+                        b[5] = -Value::known(Fp::one());
+                        chip.assign_distance(region, &bits(&A), &b, LENGTH)?;
+                        Ok(())
+                    }),
+                    fails: &[("is binary b", 5)],
+                },
+                // The bits at position 1 differ, but the distance after them claims their XOR is 0
+                Case {
+                    tamper: Tamper::Cell(|config| config.advice[4], 2, 0),
+                    fails: &[("xor accumulator", 1), ("xor accumulator", 2)],
+                },
+                Case {
+                    tamper: Tamper::Cell(|config| config.advice[4], LENGTH, DISTANCE - 1),
+                    fails: &[("xor accumulator", LENGTH - 1)],
+                },
+                // The first sum of a word starts the word, and the following sum adds to it
+                Case {
+                    tamper: Tamper::Cell(|config| config.advice[2], 0, 0),
+                    fails: &[("pack word", 1), ("word start", 0)],
+                },
+                Case {
+                    tamper: Tamper::Cell(|config| config.advice[3], LENGTH - 1, 0),
+                    fails: &[("pack word", LENGTH - 1)],
+                },
+            ],
+        );
+    }
+
+    // Lays out two rows of the accumulator gate, adding the given values to a sum starting at 0
+    fn sum_rows(chip: &BitVeilChip<Fp>, region: &mut Region<'_, Fp>) -> Result<(), Error> {
+        let config = chip.config();
+        region.assign_advice_from_constant(|| "initial sum", config.advice[1], 0, Fp::zero())?;
+        for (i, (value, sum)) in [(3, 3), (5, 8)].into_iter().enumerate() {
+            config.s_accumulator.enable(region, i)?;
+            tamper(region, config.advice[0], i, value)?;
+            tamper(region, config.advice[1], i + 1, sum)?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn wrong_sum_fails_accumulator() {
+        assert_cases::<BitVeilCircuit<Fp>>(
+            |chip, _, region| sum_rows(chip, region),
+            &[Case {
+                tamper: Tamper::Cell(|config| config.advice[1], 2, 9),
+                fails: &[("accumulator", 1)],
+            }],
+        );
+    }
+
+    #[test]
+    fn wrong_public_inputs_fail_the_instance() {
+        let circuit = create_circuit(A.to_vec(), B.to_vec(), Fp::from(1), Fp::from(2)).unwrap();
+        assert_instance(
+            &failures(&circuit, &distance_inputs(DISTANCE + 1)),
+            DISTANCE_ROW,
+        );
+
+        let mut pub_input = distance_inputs(DISTANCE);
+        pub_input[COMMITMENT_B_ROW] = commit_vector(&A, Fp::from(2)).unwrap();
+        assert_instance(&failures(&circuit, &pub_input), COMMITMENT_B_ROW);
+    }
+
+    #[test]
+    fn threshold_below_the_distance_fails_the_instance() {
+        // The gap between the threshold and the distance wraps around and fails the range check,
+        // which leaves the threshold it sums to different from the public one
+        let circuit = BitVeilThresholdCircuit {
+            inner: create_circuit(A.to_vec(), B.to_vec(), Fp::from(1), Fp::from(2)).unwrap(),
+            threshold: Value::known(Fp::from(DISTANCE - 1)),
+        };
+        assert_instance(
+            &failures(&circuit, &distance_inputs(DISTANCE - 1)),
+            DISTANCE_ROW,
+        );
+    }
+
+    fn popcount_rows(
+        chip: &BitVeilChip<Fp>,
+        config: &BitVeilPopcountConfig,
+        region: &mut Region<'_, Fp>,
+    ) -> Result<(), Error> {
+        let (a, b) = (nibbles(&A), nibbles(&B));
+        chip.assign_popcount(region, &config.popcount, &a, &b, LENGTH)?;
+        Ok(())
+    }
+
+    #[test]
+    fn malicious_popcount_rows_fail_their_gates() {
+        assert_cases::<BitVeilPopcountCircuit<Fp>>(
+            popcount_rows,
+            &[
+                Case {
+                    tamper: Tamper::Rows(|chip, config, region| {
+                        let mut a = nibbles(&A);
+                        a[1] = Value::known(Fp::from(NIBBLE_VALUES));
+                        chip.assign_popcount(region, &config.popcount, &a, &nibbles(&B), LENGTH)?;
+                        Ok(())
+                    }),
+                    fails: &[("lookup", 1)],
+                },
+                Case {
+                    tamper: Tamper::Cell(|config| config.base.advice[4], 1, 0),
+                    fails: &[("lookup", 0), ("lookup", 1)],
+                },
+                Case {
+                    tamper: Tamper::Cell(|config| config.base.advice[2], 0, 0),
+                    fails: &[("nibble word start", 0), ("pack nibble word", 1)],
+                },
+                Case {
+                    tamper: Tamper::Cell(|config| config.base.advice[3], 1, 0),
+                    fails: &[("pack nibble word", 1)],
+                },
+            ],
+        );
+    }
+
+    fn masked_rows(
+        chip: &BitVeilChip<Fp>,
+        config: &BitVeilMaskedConfig,
+        region: &mut Region<'_, Fp>,
+        mask_a: &[Value<Fp>],
+        mask_b: &[Value<Fp>],
+    ) -> Result<(), Error> {
+        let (a, b) = (bits(&A), bits(&B));
+        chip.assign_masked(region, &config.mask, &a, &b, mask_a, mask_b, LENGTH)?;
+        Ok(())
+    }
+
+    fn weighted_rows(
+        chip: &BitVeilChip<Fp>,
+        config: &BitVeilWeightedConfig,
+        region: &mut Region<'_, Fp>,
+    ) -> Result<(), Error> {
+        let (a, b, weights) = (bits(&A), bits(&B), WEIGHTS.map(Fp::from));
+        chip.assign_weighted(region, &config.weight, &a, &b, &weights, LENGTH)?;
+        Ok(())
+    }
+
+    #[test]
+    fn malicious_masked_rows_fail_their_gates() {
+        assert_cases::<BitVeilMaskedCircuit<Fp>>(
+            |chip, config, region| {
+                masked_rows(chip, config, region, &bits(&MASK_A), &bits(&MASK_B))
+            },
+            &[
+                Case {
+                    tamper: Tamper::Rows(|chip, config, region| {
+                        let mut mask_a = bits(&MASK_A);
+                        mask_a[3] = Value::known(Fp::from(2));
+                        masked_rows(chip, config, region, &mask_a, &bits(&MASK_B))
+                    }),
+                    fails: &[("is binary mask a", 3)],
+                },
+                Case {
+                    tamper: Tamper::Rows(|chip, config, region| {
+                        let mut mask_b = bits(&MASK_B);
+                        mask_b[6] = -Value::known(Fp::one());
+                        masked_rows(chip, config, region, &bits(&MASK_A), &mask_b)
+                    }),
+                    fails: &[("is binary mask b", 6)],
+                },
+                Case {
+                    tamper: Tamper::Cell(
+                        |config| config.mask.advice[4],
+                        LENGTH,
+                        MASKED_DISTANCE - 1,
+                    ),
+                    fails: &[("masked xor accumulator", LENGTH - 1)],
+                },
+                Case {
+                    tamper: Tamper::Cell(|config| config.mask.advice[5], LENGTH, VALID + 1),
+                    fails: &[("valid accumulator", LENGTH - 1)],
+                },
+                Case {
+                    tamper: Tamper::Cell(|config| config.mask.advice[2], 0, 0),
+                    fails: &[("mask word start", 0), ("pack mask word", 1)],
+                },
+                Case {
+                    tamper: Tamper::Cell(|config| config.mask.advice[3], LENGTH - 1, 0),
+                    fails: &[("pack mask word", LENGTH - 1)],
+                },
+            ],
+        );
+    }
+
+    #[test]
+    fn malicious_weighted_rows_fail_their_gates() {
+        assert_cases::<BitVeilWeightedCircuit<Fp>>(
+            weighted_rows,
+            &[
+                Case {
+                    tamper: Tamper::Cell(|config| config.weight.advice, LENGTH, 0),
+                    fails: &[("weighted xor accumulator", LENGTH - 1)],
+                },
+                // The weights are fixed by the keys, so a distance weighted otherwise than the
+                // fixed column fails too. The bits at position 1 differ, and their weight becomes
+                // 2 instead of 1.
+                Case {
+                    tamper: Tamper::Rows(|chip, config, region| {
+                        weighted_rows(chip, config, region)?;
+                        let weight = config.weight.weight;
+                        region.assign_fixed(
+                            || "tampered",
+                            weight,
+                            1,
+                            || Value::known(Fp::from(2)),
+                        )?;
+                        Ok(())
+                    }),
+                    fails: &[("weighted xor accumulator", 1)],
+                },
+            ],
+        );
+    }
+
+    // Lays out rows counting the bits of A and B with the given selector, like the regions of
+    // `count_bits`, with the given count after each row
+    fn count_rows(
+        chip: &BitVeilChip<Fp>,
+        region: &mut Region<'_, Fp>,
+        selector: Selector,
+        counts: &[u64],
+    ) -> Result<(), Error> {
+        let config = chip.config();
+        region.assign_advice_from_constant(|| "initial count", config.advice[4], 0, Fp::zero())?;
+        for (i, count) in counts.iter().enumerate() {
+            selector.enable(region, i)?;
+            tamper(region, config.advice[0], i, A[i])?;
+            tamper(region, config.advice[1], i, B[i])?;
+            tamper(region, config.advice[4], i + 1, *count)?;
+        }
+        Ok(())
+    }
+
+    // The running count of an operation on the bits of A and B
+    fn counts(op: fn(u64, u64) -> u64) -> Vec<u64> {
+        A.iter()
+            .zip(&B)
+            .scan(0, |count, (a, b)| {
+                *count += op(*a, *b);
+                Some(*count)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn wrong_counts_fail_and_and_or_accumulators() {
+        // Each count claims the other operation, which differs where the bits of A and B differ
+        assert_cases::<BitVeilSimilarityCircuit<Fp>>(
+            |chip, config, region| {
+                count_rows(chip, region, config.counts.s_and, &counts(|a, b| a & b))
+            },
+            &[Case {
+                tamper: Tamper::Rows(|chip, config, region| {
+                    count_rows(chip, region, config.counts.s_and, &counts(|a, b| a | b))
+                }),
+                fails: &[
+                    ("and accumulator", 1),
+                    ("and accumulator", 2),
+                    ("and accumulator", 5),
+                    ("and accumulator", 7),
+                ],
+            }],
+        );
+        assert_cases::<BitVeilSimilarityCircuit<Fp>>(
+            |chip, config, region| {
+                count_rows(chip, region, config.counts.s_or, &counts(|a, b| a | b))
+            },
+            &[Case {
+                tamper: Tamper::Rows(|chip, config, region| {
+                    count_rows(chip, region, config.counts.s_or, &counts(|a, b| a & b))
+                }),
+                fails: &[
+                    ("or accumulator", 1),
+                    ("or accumulator", 2),
+                    ("or accumulator", 5),
+                    ("or accumulator", 7),
+                ],
+            }],
+        );
+    }
+
+    #[test]
+    fn wrong_product_fails_product() {
+        assert_cases::<BitVeilRotationCircuit<Fp>>(
+            |_, config, region| {
+                let advice = config.base.advice;
+                region.assign_advice_from_constant(
+                    || "initial product",
+                    advice[1],
+                    0,
+                    Fp::one(),
+                )?;
+                for (i, (value, product)) in [(3, 3), (5, 15)].into_iter().enumerate() {
+                    config.s_product.enable(region, i)?;
+                    tamper(region, advice[0], i, value)?;
+                    tamper(region, advice[1], i + 1, product)?;
+                }
+                Ok(())
+            },
+            &[Case {
+                tamper: Tamper::Cell(|config| config.base.advice[1], 2, 14),
+                fails: &[("product", 1)],
+            }],
+        );
+    }
+
+    #[test]
+    fn wrong_minimum_fails_the_gaps() {
+        // The rotation circuit only takes vectors of whole chunks
+        let a = [A, B, A, B].concat();
+        let b = [B, A, B, B].concat();
+        let circuit =
+            create_rotation_circuit(a.clone(), b.clone(), Fp::from(1), Fp::from(2), 1).unwrap();
+        let k = k_for_rotation(a.len(), 1);
+        let minimum = rotated_distance(&a, &b, 1).unwrap();
+        let inputs = |minimum| {
+            public_inputs(
+                &[Fp::from(minimum)],
+                commit_vector(&a, Fp::from(1)).unwrap(),
+                commit_vector(&b, Fp::from(2)).unwrap(),
+            )
+        };
+        run_mock_prover(k, &circuit, &inputs(minimum)).unwrap();
+
+        // The gap from a minimum above some distance wraps around, so its range check sums to
+        // another gap, which does not add up to the distance
+        let above = BitVeilRotationCircuit {
+            minimum: Value::known(Fp::from(minimum + 1)),
+            ..circuit.clone()
+        };
+        assert_copies(&failures_at(k, &above, &inputs(minimum + 1)));
+
+        // Every gap from a minimum below all of the distances is not zero, nor is their product
+        let below = BitVeilRotationCircuit {
+            minimum: Value::known(Fp::from(minimum - 1)),
+            ..circuit
+        };
+        assert_copies(&failures_at(k, &below, &inputs(minimum - 1)));
+    }
+
+    fn inner_product_rows(
+        chip: &BitVeilChip<Fp>,
+        config: &BitVeilInnerProductConfig,
+        region: &mut Region<'_, Fp>,
+    ) -> Result<(), Error> {
+        let (a, b) = (elements(&ELEMENTS_A), elements(&ELEMENTS_B));
+        inner_product_elements(chip, config, region, &a, &b)
+    }
+
+    fn inner_product_elements(
+        chip: &BitVeilChip<Fp>,
+        config: &BitVeilInnerProductConfig,
+        region: &mut Region<'_, Fp>,
+        a: &[Value<Fp>],
+        b: &[Value<Fp>],
+    ) -> Result<(), Error> {
+        let (elements, selector) = (&config.elements, config.s_inner_product);
+        chip.assign_elements(region, elements, selector, None, a, b, LENGTH, |a, b| a * b)?;
+        Ok(())
+    }
+
+    #[test]
+    fn malicious_inner_product_rows_fail_their_gates() {
+        assert_cases::<BitVeilInnerProductCircuit<Fp>>(
+            inner_product_rows,
+            &[
+                Case {
+                    tamper: Tamper::Rows(|chip, config, region| {
+                        let mut a = elements(&ELEMENTS_A);
+                        a[4] = Value::known(Fp::from(MAX_ELEMENT + 1));
+                        inner_product_elements(chip, config, region, &a, &elements(&ELEMENTS_B))
+                    }),
+                    fails: &[("lookup", 4)],
+                },
+                Case {
+                    tamper: Tamper::Cell(|config| config.base.advice[2], 0, 0),
+                    fails: &[("element word start", 0), ("pack element word", 1)],
+                },
+                Case {
+                    tamper: Tamper::Cell(|config| config.base.advice[3], LENGTH - 1, 0),
+                    fails: &[("pack element word", LENGTH - 1)],
+                },
+                Case {
+                    tamper: Tamper::Cell(|config| config.base.advice[4], LENGTH, 0),
+                    fails: &[("product accumulator", LENGTH - 1)],
+                },
+            ],
+        );
+    }
+
+    // Lays out the element region of the L1 distance with the given operation in the difference
+    // column, which the honest region fills with the absolute differences
+    fn l1_rows(
+        chip: &BitVeilChip<Fp>,
+        config: &BitVeilEmbeddingConfig,
+        region: &mut Region<'_, Fp>,
+        difference: fn(Fp, Fp) -> Fp,
+    ) -> Result<(), Error> {
+        let embedding = &config.embedding;
+        let (a, b) = (elements(&ELEMENTS_A), elements(&ELEMENTS_B));
+        chip.assign_elements(
+            region,
+            &config.elements,
+            embedding.s_absolute,
+            Some(embedding.difference),
+            &a,
+            &b,
+            LENGTH,
+            difference,
+        )?;
+        Ok(())
+    }
+
+    fn squared_l2_rows(
+        chip: &BitVeilChip<Fp>,
+        config: &BitVeilEmbeddingConfig,
+        region: &mut Region<'_, Fp>,
+    ) -> Result<(), Error> {
+        let (elements_a, elements_b) = (elements(&ELEMENTS_A), elements(&ELEMENTS_B));
+        let (elements, selector) = (&config.elements, config.embedding.s_squared);
+        chip.assign_elements(
+            region,
+            elements,
+            selector,
+            None,
+            &elements_a,
+            &elements_b,
+            LENGTH,
+            |a, b| (a - b).square(),
+        )?;
+        Ok(())
+    }
+
+    #[test]
+    fn malicious_l1_rows_fail_their_gates() {
+        assert_cases::<BitVeilEmbeddingCircuit<Fp>>(
+            |chip, config, region| l1_rows(chip, config, region, absolute_difference),
+            &[
+                // The difference of the first elements is 2, and the sum after them agrees with it
+                Case {
+                    tamper: Tamper::Cell(|config| config.embedding.difference, 0, 3),
+                    fails: &[("absolute difference accumulator", 0)],
+                },
+                Case {
+                    tamper: Tamper::Cell(|config| config.base.advice[4], LENGTH, 0),
+                    fails: &[("absolute difference accumulator", LENGTH - 1)],
+                },
+                // Summing a - b satisfies the gate, which only checks the square of the
+                // difference, but where a < b, at elements 0, 4 and 7, the difference wraps around
+                // and is not in the table
+                Case {
+                    tamper: Tamper::Rows(|chip, config, region| {
+                        l1_rows(chip, config, region, |a, b| a - b)
+                    }),
+                    fails: &[("lookup", 0), ("lookup", 4), ("lookup", 7)],
+                },
+            ],
+        );
+    }
+
+    #[test]
+    fn wrong_squared_distance_fails_squared_difference_accumulator() {
+        assert_cases::<BitVeilEmbeddingCircuit<Fp>>(
+            squared_l2_rows,
+            &[Case {
+                tamper: Tamper::Cell(|config| config.base.advice[4], LENGTH, 0),
+                fails: &[("squared difference accumulator", LENGTH - 1)],
+            }],
+        );
+    }
+
+    // A registry with the template B enrolled at index 1, next to the probe A under other salts
+    fn enrolled() -> Registry {
+        let mut registry = Registry::new();
+        for (vector, salt) in [(&A, 3), (&B, 2), (&A, 4)] {
+            registry
+                .insert(commit_vector(vector, Fp::from(salt)).unwrap())
+                .unwrap();
+        }
+        registry
+    }
+
+    fn membership_circuit(template: &[u64], path: &MerklePath) -> BitVeilMembershipCircuit<Fp> {
+        let (probe, template) = (A.to_vec(), template.to_vec());
+        create_membership_circuit(probe, template, Fp::from(1), Fp::from(2), DISTANCE, path)
+            .unwrap()
+    }
+
+    fn membership_inputs(root: Fp) -> Vec<Fp> {
+        let commitment_a = commit_vector(&A, Fp::from(1)).unwrap();
+        membership_public_inputs(Fp::from(DISTANCE), commitment_a, root)
+    }
+
+    #[test]
+    fn enrolled_template_is_accepted() {
+        let registry = enrolled();
+        let circuit = membership_circuit(&B, &registry.path(1).unwrap());
+        run_mock_prover(
+            k_for_membership(LENGTH),
+            &circuit,
+            &membership_inputs(registry.root()),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn wrong_root_fails_the_instance() {
+        let registry = enrolled();
+        let circuit = membership_circuit(&B, &registry.path(1).unwrap());
+        let mut other = enrolled();
+        other.remove(0).unwrap();
+        assert_instance(
+            &failures_at(
+                k_for_membership(LENGTH),
+                &circuit,
+                &membership_inputs(other.root()),
+            ),
+            ROOT_ROW,
+        );
+    }
+
+    #[test]
+    fn leaf_off_the_path_fails_the_instance() {
+        // The probe is within the threshold of itself, but is not enrolled under the salt of B
+        let registry = enrolled();
+        let circuit = membership_circuit(&A, &registry.path(1).unwrap());
+        assert_instance(
+            &failures_at(
+                k_for_membership(LENGTH),
+                &circuit,
+                &membership_inputs(registry.root()),
+            ),
+            ROOT_ROW,
+        );
+
+        // B is enrolled, but not at the index of the path
+        let circuit = membership_circuit(&B, &registry.path(2).unwrap());
+        assert_instance(
+            &failures_at(
+                k_for_membership(LENGTH),
+                &circuit,
+                &membership_inputs(registry.root()),
+            ),
+            ROOT_ROW,
+        );
+    }
+
+    #[test]
+    fn wrong_swap_bit_fails_the_instance() {
+        // Hashing B as the left child at the first level, where it is the right child
+        let registry = enrolled();
+        let mut circuit = membership_circuit(&B, &registry.path(1).unwrap());
+        circuit.positions[0] = Value::known(Fp::zero());
+        assert_instance(
+            &failures_at(
+                k_for_membership(LENGTH),
+                &circuit,
+                &membership_inputs(registry.root()),
+            ),
+            ROOT_ROW,
+        );
+    }
+
+    // Lays out one row of the swap gate with the given node, sibling, position bit and children
+    fn swap_row(
+        config: &BitVeilMembershipConfig,
+        region: &mut Region<'_, Fp>,
+        cells: [u64; 5],
+    ) -> Result<(), Error> {
+        config.s_swap.enable(region, 0)?;
+        for (column, value) in config.base.advice.into_iter().zip(cells) {
+            tamper(region, column, 0, value)?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn wrong_children_fail_merkle_swap() {
+        assert_cases::<BitVeilMembershipCircuit<Fp>>(
+            |_, config, region| swap_row(config, region, [5, 7, 1, 7, 5]),
+            &[
+                // The children are consistent with the position 2, which is not a bit
+                Case {
+                    tamper: Tamper::Rows(|_, config, region| {
+                        swap_row(config, region, [5, 7, 2, 9, 3])
+                    }),
+                    fails: &[("merkle swap", 0)],
+                },
+                // The position bit is set, but the node stays the left child
+                Case {
+                    tamper: Tamper::Rows(|_, config, region| {
+                        swap_row(config, region, [5, 7, 1, 5, 7])
+                    }),
+                    fails: &[("merkle swap", 0)],
+                },
+            ],
+        );
+    }
+
+    const SECRET: u64 = 9;
+
+    fn session_circuit(secret: Fp, context: Fp) -> BitVeilSessionCircuit<Fp> {
+        create_session_circuit(
+            A.to_vec(),
+            B.to_vec(),
+            Fp::from(1),
+            Fp::from(2),
+            secret,
+            Fp::from(5),
+            context,
+        )
+        .unwrap()
+    }
+
+    fn session_inputs(commitment_b: Fp, context: Fp, nullifier: Fp) -> Vec<Fp> {
+        let commitment_a = commit_vector(&A, Fp::from(1)).unwrap();
+        session_public_inputs(
+            Fp::from(DISTANCE),
+            commitment_a,
+            commitment_b,
+            Fp::from(5),
+            context,
+            nullifier,
+        )
+    }
+
+    fn session_commitment(bits: &[u64], secret: Fp) -> Fp {
+        commit_session_vector(bits, Fp::from(2), secret).unwrap()
+    }
+
+    #[test]
+    fn session_proof_reveals_the_nullifier_of_b() {
+        let secret = Fp::from(SECRET);
+        let context = context_id("bank.example");
+        let nullifier = derive_nullifier(&B, secret, context).unwrap();
+        run_mock_prover(
+            k_for_session(LENGTH),
+            &session_circuit(secret, context),
+            &session_inputs(session_commitment(&B, secret), context, nullifier),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn wrong_nullifier_fails_the_instance() {
+        let secret = Fp::from(SECRET);
+        let context = context_id("bank.example");
+        let circuit = session_circuit(secret, context);
+        for nullifier in [
+            derive_nullifier(&A, secret, context).unwrap(),
+            derive_nullifier(&B, secret, context_id("shop.example")).unwrap(),
+            // The nullifier of vector B under a secret it was not committed to with
+            derive_nullifier(&B, Fp::zero(), context).unwrap(),
+            commit_vector(&B, context).unwrap(),
+        ] {
+            assert_instance(
+                &failures_at(
+                    k_for_session(LENGTH),
+                    &circuit,
+                    &session_inputs(session_commitment(&B, secret), context, nullifier),
+                ),
+                NULLIFIER_ROW,
+            );
+        }
+    }
+
+    #[test]
+    fn commitment_to_another_vector_fails_the_instance() {
+        // Vector A committed to with the secret of vector B, or vector B with another secret
+        let secret = Fp::from(SECRET);
+        let context = context_id("bank.example");
+        let circuit = session_circuit(secret, context);
+        let nullifier = derive_nullifier(&B, secret, context).unwrap();
+        for commitment_b in [
+            session_commitment(&A, secret),
+            session_commitment(&B, Fp::zero()),
+            commit_vector(&B, Fp::from(2)).unwrap(),
+        ] {
+            assert_instance(
+                &failures_at(
+                    k_for_session(LENGTH),
+                    &circuit,
+                    &session_inputs(commitment_b, context, nullifier),
+                ),
+                COMMITMENT_B_ROW,
+            );
+        }
+    }
+
+    #[test]
+    fn wrong_context_fails_the_instance() {
+        // The nullifier of the public context, from a proof in another context
+        let secret = Fp::from(SECRET);
+        let context = context_id("shop.example");
+        let circuit = session_circuit(secret, context_id("bank.example"));
+        let failures = failures_at(
+            k_for_session(LENGTH),
+            &circuit,
+            &session_inputs(
+                session_commitment(&B, secret),
+                context,
+                derive_nullifier(&B, secret, context).unwrap(),
+            ),
+        );
+        assert_instance(&failures, CONTEXT_ROW);
+        assert_instance(&failures, NULLIFIER_ROW);
+    }
 }
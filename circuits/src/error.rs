@@ -0,0 +1,321 @@
+use crate::circuits::{
+    EmbeddingMetric, MAX_CANDIDATES, MAX_DOMAIN_LENGTH, MAX_ELEMENT, MAX_EMBEDDING_THRESHOLD,
+    MAX_LENGTH, MAX_RATIO, MAX_SHIFTS, MAX_THRESHOLD, MAX_WEIGHT,
+};
+use crate::merkle::MAX_ENROLLED;
+use halo2_proofs::{dev::VerifyFailure, plonk};
+use std::{fmt, io};
+
+// Errors returned by the proving API instead of panicking, so a bad input only fails the call
+// that received it
+#[derive(Debug)]
+pub enum BitVeilError {
+    // The vectors differ in length, or their length is outside 1..=MAX_LENGTH
+    InvalidLength {
+        a: usize,
+        b: usize,
+    },
+    // A vector contains a value other than 0 or 1
+    NonBinaryInput {
+        vector: &'static str,
+        position: usize,
+        value: u64,
+    },
+    // An integer vector contains an element larger than MAX_ELEMENT
+    ElementOutOfRange {
+        vector: &'static str,
+        position: usize,
+        value: u64,
+    },
+    // The packed words of a vector do not hold the given number of bits
+    InvalidPackedLength {
+        vector: &'static str,
+        words: usize,
+        length: usize,
+    },
+    // The packed words of a vector have bits set after the last bit of the vector
+    NonZeroPadding {
+        vector: &'static str,
+    },
+    // A batch has no candidates, more than MAX_CANDIDATES, or not one salt per candidate
+    InvalidCandidates {
+        candidates: usize,
+        salts: usize,
+    },
+    // A rotation has no shifts, more than MAX_SHIFTS, at least half the vector length, or vectors
+    // whose length is padded in the circuit
+    InvalidShifts {
+        shifts: usize,
+        length: usize,
+    },
+    // The weights are not one per bit of the vectors
+    InvalidWeights {
+        weights: usize,
+        length: usize,
+    },
+    // A weight is larger than MAX_WEIGHT
+    InvalidWeight {
+        position: usize,
+        weight: u64,
+    },
+    // The threshold is too large for the range check of the threshold circuit
+    InvalidThreshold(u64),
+    // The distance is above the threshold, so no valid threshold proof exists
+    ThresholdExceeded {
+        distance: u64,
+        threshold: u64,
+    },
+    // The threshold is too large for the range check of the embedding circuits
+    InvalidEmbeddingThreshold(u64),
+    // The distance between two integer vectors is above the threshold, so no valid embedding
+    // proof exists
+    EmbeddingThresholdExceeded {
+        metric: EmbeddingMetric,
+        distance: u64,
+        threshold: u64,
+    },
+    // A similarity threshold above 1, or whose denominator is 0 or larger than MAX_RATIO
+    InvalidRatio {
+        numerator: u64,
+        denominator: u64,
+    },
+    // The similarity is below the threshold, so no valid similarity proof exists. Both are given
+    // as a numerator and a denominator.
+    SimilarityBelowThreshold {
+        similarity: (u64, u64),
+        threshold: (u64, u64),
+    },
+    // Bytes or hex that do not encode a field element, such as a salt or a commitment
+    InvalidFieldElement(&'static str),
+    // The registry already holds MAX_ENROLLED templates
+    RegistryFull,
+    // An index past the last template of the registry
+    InvalidRegistryIndex {
+        index: usize,
+        len: usize,
+    },
+    // The commitment to the template is not in the registry, so no membership proof exists
+    NotEnrolled,
+    ParamsDecode(io::Error),
+    KeyDecode(io::Error),
+    ProofDecode(io::Error),
+    RegistryDecode(io::Error),
+    // A key or proof that was generated for other params, another circuit or another length
+    KeyMismatch(String),
+    // A domain tag longer than MAX_DOMAIN_LENGTH bytes
+    InvalidDomain(usize),
+    // A proof bound to another domain than the verifier's, where an empty domain means none
+    DomainMismatch {
+        expected: String,
+        found: String,
+    },
+    KeyGen(plonk::Error),
+    Synthesis(plonk::Error),
+    // The constraints reported by the mock prover as not satisfied
+    Unsatisfied(Vec<VerifyFailure>),
+    VerificationFailed(plonk::Error),
+    // The indices of the proofs in a batch which failed verification
+    BatchVerificationFailed(Vec<usize>),
+    Draw(String),
+    Io(io::Error),
+}
+
+impl fmt::Display for BitVeilError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BitVeilError::InvalidLength { a, b } if a == b => {
+                write!(f, "vectors must have 1 to {} bits, got {}", MAX_LENGTH, a)
+            }
+            BitVeilError::InvalidLength { a, b } => write!(
+                f,
+                "both vectors must have the same length of 1 to {} bits, got {} and {}",
+                MAX_LENGTH, a, b
+            ),
+            BitVeilError::NonBinaryInput {
+                vector,
+                position,
+                value,
+            } => write!(
+                f,
+                "vector {} contains non-binary value {} at position {}",
+                vector, value, position
+            ),
+            BitVeilError::ElementOutOfRange {
+                vector,
+                position,
+                value,
+            } => write!(
+                f,
+                "vector {} contains value {} at position {}, elements must be 0 to {}",
+                vector, value, position, MAX_ELEMENT
+            ),
+            BitVeilError::InvalidPackedLength {
+                vector,
+                words,
+                length,
+            } => write!(
+                f,
+                "vector {} has {} packed words, which do not hold exactly {} bits",
+                vector, words, length
+            ),
+            BitVeilError::NonZeroPadding { vector } => write!(
+                f,
+                "vector {} has bits set after its last bit in the packed words",
+                vector
+            ),
+            BitVeilError::InvalidCandidates { candidates, salts } => write!(
+                f,
+                "a batch needs 1 to {} candidates with one salt each, got {} candidates and {} salts",
+                MAX_CANDIDATES, candidates, salts
+            ),
+            BitVeilError::InvalidShifts { shifts, length } => write!(
+                f,
+                "a rotation needs 1 to {} shifts, fewer than half the length of vectors whose length \
+                 is a multiple of 32 bits, got {} shifts for {} bits",
+                MAX_SHIFTS, shifts, length
+            ),
+            BitVeilError::InvalidWeights { weights, length } => write!(
+                f,
+                "a weighted comparison needs one weight per bit of vectors of 1 to {} bits, got {} \
+                 weights for {} bits",
+                MAX_LENGTH, weights, length
+            ),
+            BitVeilError::InvalidWeight { position, weight } => write!(
+                f,
+                "weight {} at position {} is too large, weights must be at most {}",
+                weight, position, MAX_WEIGHT
+            ),
+            BitVeilError::InvalidThreshold(threshold) => write!(
+                f,
+                "threshold {} is too large, thresholds must be at most {}",
+                threshold, MAX_THRESHOLD
+            ),
+            BitVeilError::ThresholdExceeded {
+                distance,
+                threshold,
+            } => write!(
+                f,
+                "hamming distance {} exceeds the threshold {}",
+                distance, threshold
+            ),
+            BitVeilError::InvalidEmbeddingThreshold(threshold) => write!(
+                f,
+                "threshold {} is too large, embedding thresholds must be at most {}",
+                threshold, MAX_EMBEDDING_THRESHOLD
+            ),
+            BitVeilError::EmbeddingThresholdExceeded {
+                metric,
+                distance,
+                threshold,
+            } => write!(
+                f,
+                "{} distance {} exceeds the threshold {}",
+                metric, distance, threshold
+            ),
+            BitVeilError::InvalidRatio {
+                numerator,
+                denominator,
+            } => write!(
+                f,
+                "similarity threshold {}/{} is invalid, thresholds must be at most 1 with a \
+                 denominator of 1 to {}",
+                numerator, denominator, MAX_RATIO
+            ),
+            BitVeilError::SimilarityBelowThreshold {
+                similarity,
+                threshold,
+            } => write!(
+                f,
+                "similarity {}/{} is below the threshold {}/{}",
+                similarity.0, similarity.1, threshold.0, threshold.1
+            ),
+            BitVeilError::InvalidFieldElement(name) => {
+                write!(f, "{} is not a valid field element", name)
+            }
+            BitVeilError::RegistryFull => write!(
+                f,
+                "the registry is full, it holds at most {} templates",
+                MAX_ENROLLED
+            ),
+            BitVeilError::InvalidRegistryIndex { index, len } => write!(
+                f,
+                "index {} is not in the registry, which has {} entries",
+                index, len
+            ),
+            BitVeilError::NotEnrolled => write!(f, "the template is not enrolled in the registry"),
+            BitVeilError::ParamsDecode(e) => write!(f, "failed to read params: {}", e),
+            BitVeilError::KeyDecode(e) => write!(f, "failed to read key: {}", e),
+            BitVeilError::ProofDecode(e) => write!(f, "failed to read proof: {}", e),
+            BitVeilError::RegistryDecode(e) => write!(f, "failed to read registry: {}", e),
+            BitVeilError::KeyMismatch(msg) => write!(f, "{}", msg),
+            BitVeilError::InvalidDomain(len) => write!(
+                f,
+                "domain tags must have at most {} bytes, got {}",
+                MAX_DOMAIN_LENGTH, len
+            ),
+            BitVeilError::DomainMismatch { expected, found } => {
+                let domain = |d: &String| match d.is_empty() {
+                    true => "no domain".to_string(),
+                    false => format!("the domain '{}'", d),
+                };
+                write!(
+                    f,
+                    "proof is bound to {}, expected {}",
+                    domain(found),
+                    domain(expected)
+                )
+            }
+            BitVeilError::KeyGen(e) => write!(f, "failed to generate keys: {}", e),
+            BitVeilError::Synthesis(e) => write!(f, "failed to generate proof: {}", e),
+            BitVeilError::Unsatisfied(failures) => {
+                write!(f, "{} constraints are not satisfied", failures.len())?;
+                for failure in failures {
+                    write!(f, "\n  {}", failure)?;
+                }
+                Ok(())
+            }
+            BitVeilError::VerificationFailed(e) => write!(f, "proof verification failed: {}", e),
+            BitVeilError::BatchVerificationFailed(indices) => write!(
+                f,
+                "{} proofs failed verification, at indices {}",
+                indices.len(),
+                indices
+                    .iter()
+                    .map(|i| i.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            BitVeilError::Draw(msg) => write!(f, "failed to draw circuit: {}", msg),
+            BitVeilError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for BitVeilError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BitVeilError::ParamsDecode(e)
+            | BitVeilError::KeyDecode(e)
+            | BitVeilError::ProofDecode(e)
+            | BitVeilError::RegistryDecode(e)
+            | BitVeilError::Io(e) => Some(e),
+            BitVeilError::KeyGen(e)
+            | BitVeilError::Synthesis(e)
+            | BitVeilError::VerificationFailed(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for BitVeilError {
+    fn from(e: io::Error) -> Self {
+        BitVeilError::Io(e)
+    }
+}
+
+// Lets the wasm exports return BitVeilError, which JS receives as an Error with the message
+impl From<BitVeilError> for wasm_bindgen::JsValue {
+    fn from(e: BitVeilError) -> Self {
+        js_sys::Error::new(&e.to_string()).into()
+    }
+}
@@ -1,2 +1,5 @@
 pub mod circuits;
+pub mod error;
+pub mod merkle;
+pub mod poseidon;
 pub mod wasm;
@@ -1,15 +1,12 @@
 #[cfg(not(target_family = "wasm"))]
-fn main() {
+fn main() -> Result<(), circuits::error::BitVeilError> {
     use circuits::circuits::{
-        calculate_hamming_distance, create_circuit, draw_circuit, empty_circuit, generate_keys,
-        generate_proof, generate_setup_params, run_mock_prover, verify,
+        calculate_hamming_distance, commit_vector, create_circuit, draw_circuit, empty_circuit,
+        generate_keys, generate_proof, generate_salt, generate_setup_params, k_for_length,
+        public_inputs, run_mock_prover, verify,
     };
 
-    // Size of the circuit. Circuit must fit within 2^k rows.
-    // k=8 provides 256 rows which is sufficient for this circuit
-    let k = 8;
-
-    // Input values to generate a proof with (must be 32 bits for the circuit)
+    // Input values to generate a proof with (shorter vectors are padded to a multiple of 32 bits)
     let a_vec: Vec<u64> = vec![
         1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0,
         1, 0,
@@ -22,18 +19,35 @@ fn main() {
     println!("Vector A: {:?}", a_vec);
     println!("Vector B: {:?}", b_vec);
 
-    let hamming_dist = calculate_hamming_distance(a_vec.clone(), b_vec.clone());
+    // Size of the circuit. Circuit must fit within 2^k rows, which depends on the vector length.
+    // k=8 provides 256 rows which is sufficient for 32-bit vectors and their commitments
+    let length = a_vec.len();
+    let k = k_for_length(length);
+    println!("Circuit size: k={}", k);
+
+    let hamming_dist = calculate_hamming_distance(a_vec.clone(), b_vec.clone())?;
     println!("Hamming distance: {:?}", hamming_dist);
 
+    // Commit to both vectors with random salts
+    let salt_a = generate_salt();
+    let salt_b = generate_salt();
+    let pub_input = public_inputs(
+        &hamming_dist,
+        commit_vector(&a_vec, salt_a)?,
+        commit_vector(&b_vec, salt_b)?,
+    );
+    println!("Public inputs: {:?}", pub_input);
+
     // Create circuit
-    let bitveil_circuit = create_circuit(a_vec, b_vec);
+    let bitveil_circuit = create_circuit(a_vec, b_vec, salt_a, salt_b)?;
 
     // Items that are useful for debugging issues
     println!("\nDrawing circuit layout...");
-    draw_circuit(k, &bitveil_circuit);
+    draw_circuit(k, &bitveil_circuit)?;
 
     println!("\nRunning mock prover...");
-    run_mock_prover(k, &bitveil_circuit, &hamming_dist);
+    run_mock_prover(k, &bitveil_circuit, &pub_input)?;
+    println!("MockProver OK");
 
     // Generate setup params
     println!("\nGenerating setup parameters...");
@@ -41,17 +55,17 @@ fn main() {
 
     // Generate proving and verifying keys
     println!("Generating proving and verifying keys...");
-    let empty_circuit = empty_circuit();
-    let (pk, vk) = generate_keys(&params, &empty_circuit);
+    let empty_circuit = empty_circuit(length);
+    let (pk, vk) = generate_keys(&params, &empty_circuit)?;
 
     // Generate proof
     println!("Generating zero-knowledge proof...");
-    let proof = generate_proof(&params, &pk, bitveil_circuit, &hamming_dist);
+    let proof = generate_proof(&params, &pk, bitveil_circuit, &pub_input, "")?;
     println!("Proof size: {} bytes", proof.len());
 
     // Verify proof
     println!("Verifying proof...");
-    let verify = verify(&params, &vk, &hamming_dist, proof);
+    let verify = verify(&params, &vk, &pub_input, proof, "");
     println!("Verification result: {:?}", verify);
 
     if verify.is_ok() {
@@ -59,4 +73,5 @@ fn main() {
     } else {
         println!("\n✗ FAILED: Proof verification failed!");
     }
+    Ok(())
 }
@@ -0,0 +1,373 @@
+use crate::circuits::fp_from_bytes;
+use crate::error::BitVeilError;
+use crate::poseidon;
+use halo2_proofs::pasta::{Fp, group::ff::PrimeField};
+use std::{
+    io::{self, Read, Write},
+    sync::OnceLock,
+};
+
+// The depth of the registry tree. Every path has exactly this many siblings, so a path can never
+// end at an inner node posing as a leaf, and the registry holds up to 2^REGISTRY_DEPTH templates.
+pub const REGISTRY_DEPTH: usize = 20;
+pub const MAX_ENROLLED: usize = 1 << REGISTRY_DEPTH;
+
+const REGISTRY_MAGIC: &[u8; 4] = b"BVR\0";
+const REGISTRY_VERSION: u8 = 1;
+
+// Hashes two sibling nodes into their parent with the Poseidon hash the circuit uses
+pub fn hash_node(left: Fp, right: Fp) -> Fp {
+    poseidon::hash(&[left, right])
+}
+
+// The root of an empty subtree of each height from 0 to REGISTRY_DEPTH, which are hashed once.
+// Empty leaves are zero, which no commitment hashes to.
+fn empty_roots() -> &'static [Fp] {
+    static EMPTY_ROOTS: OnceLock<Vec<Fp>> = OnceLock::new();
+    EMPTY_ROOTS.get_or_init(|| {
+        let mut roots = vec![Fp::zero()];
+        for height in 0..REGISTRY_DEPTH {
+            roots.push(hash_node(roots[height], roots[height]));
+        }
+        roots
+    })
+}
+
+// The siblings of a leaf from the leaf up to the root, together with its index, whose bits tell
+// at each level whether the node is the left or the right child
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MerklePath {
+    index: usize,
+    siblings: Vec<Fp>,
+}
+
+impl MerklePath {
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn siblings(&self) -> &[Fp] {
+        &self.siblings
+    }
+
+    // Whether the node on the path at the given level is the right child of its parent
+    pub fn is_right(&self, level: usize) -> bool {
+        (self.index >> level) & 1 == 1
+    }
+
+    // Hashes the leaf up the path, giving the root of the tree the path was taken from if the leaf
+    // is the one at the index
+    pub fn root(&self, leaf: Fp) -> Fp {
+        self.siblings
+            .iter()
+            .enumerate()
+            .fold(leaf, |node, (level, sibling)| match self.is_right(level) {
+                true => hash_node(*sibling, node),
+                false => hash_node(node, *sibling),
+            })
+    }
+}
+
+// A registry of enrolled templates, stored as a Merkle tree of the commitments to them. Templates
+// are enrolled at the next free index and removed by zeroing their leaf, so the indices of the
+// other templates never change.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Registry {
+    // The nodes of each level of the tree from the leaves up, leaving out the empty subtrees to the
+    // right of the last leaf. The top level holds the root unless the registry is empty. Changing
+    // a leaf only hashes the nodes on its path again.
+    levels: Vec<Vec<Fp>>,
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Self {
+            levels: vec![Vec::new(); REGISTRY_DEPTH + 1],
+        }
+    }
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Builds the tree of the given leaves level by level
+    fn from_leaves(leaves: Vec<Fp>) -> Self {
+        let empty = empty_roots();
+        let mut levels = vec![leaves];
+        for height in 0..REGISTRY_DEPTH {
+            let parents = levels[height]
+                .chunks(2)
+                .map(|pair| hash_node(pair[0], pair.get(1).copied().unwrap_or(empty[height])))
+                .collect();
+            levels.push(parents);
+        }
+        Self { levels }
+    }
+
+    // The number of indices taken, including those of removed templates
+    pub fn len(&self) -> usize {
+        self.leaves().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.leaves().is_empty()
+    }
+
+    pub fn leaves(&self) -> &[Fp] {
+        &self.levels[0]
+    }
+
+    // Enrolls the commitment to a template, returning its index
+    pub fn insert(&mut self, commitment: Fp) -> Result<usize, BitVeilError> {
+        if self.len() == MAX_ENROLLED {
+            return Err(BitVeilError::RegistryFull);
+        }
+        self.levels[0].push(commitment);
+        let index = self.len() - 1;
+        self.rehash(index);
+        Ok(index)
+    }
+
+    // Replaces the commitment at an index, such as when a template is enrolled again
+    pub fn update(&mut self, index: usize, commitment: Fp) -> Result<(), BitVeilError> {
+        let len = self.len();
+        let leaf = self.levels[0]
+            .get_mut(index)
+            .ok_or(BitVeilError::InvalidRegistryIndex { index, len })?;
+        *leaf = commitment;
+        self.rehash(index);
+        Ok(())
+    }
+
+    // Hashes the nodes on the path from the leaf at an index to the root again, adding the parent
+    // of each level if the leaf is the first one under it
+    fn rehash(&mut self, mut index: usize) {
+        for (height, empty) in empty_roots()[..REGISTRY_DEPTH].iter().enumerate() {
+            let level = &self.levels[height];
+            let left = index & !1;
+            let right = level.get(left + 1).copied().unwrap_or(*empty);
+            let parent = hash_node(level[left], right);
+
+            index >>= 1;
+            let parents = &mut self.levels[height + 1];
+            match parents.get_mut(index) {
+                Some(node) => *node = parent,
+                None => parents.push(parent),
+            }
+        }
+    }
+
+    // Removes the template at an index by replacing its commitment with an empty leaf
+    pub fn remove(&mut self, index: usize) -> Result<(), BitVeilError> {
+        self.update(index, Fp::zero())
+    }
+
+    // The index of a commitment, if it is enrolled
+    pub fn position(&self, commitment: Fp) -> Option<usize> {
+        if commitment == Fp::zero() {
+            return None;
+        }
+        self.leaves().iter().position(|leaf| *leaf == commitment)
+    }
+
+    pub fn root(&self) -> Fp {
+        match self.levels[REGISTRY_DEPTH].first() {
+            Some(root) => *root,
+            None => empty_roots()[REGISTRY_DEPTH],
+        }
+    }
+
+    // The path from the leaf at an index to the root
+    pub fn path(&self, index: usize) -> Result<MerklePath, BitVeilError> {
+        if index >= self.len() {
+            return Err(BitVeilError::InvalidRegistryIndex {
+                index,
+                len: self.len(),
+            });
+        }
+        let empty = empty_roots();
+        let siblings = (0..REGISTRY_DEPTH)
+            .map(|height| {
+                let sibling = (index >> height) ^ 1;
+                self.levels[height]
+                    .get(sibling)
+                    .copied()
+                    .unwrap_or(empty[height])
+            })
+            .collect();
+        Ok(MerklePath { index, siblings })
+    }
+
+    // Writes the magic, the format version and the depth, then the leaves prefixed by their count
+    // as a little-endian u32
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<(), BitVeilError> {
+        writer.write_all(REGISTRY_MAGIC)?;
+        writer.write_all(&[REGISTRY_VERSION, REGISTRY_DEPTH as u8])?;
+        writer.write_all(&(self.len() as u32).to_le_bytes())?;
+        for leaf in self.leaves() {
+            writer.write_all(&leaf.to_repr())?;
+        }
+        Ok(())
+    }
+
+    pub fn read<R: Read>(reader: &mut R) -> Result<Self, BitVeilError> {
+        Self::read_fields(reader).map_err(BitVeilError::RegistryDecode)
+    }
+
+    fn read_fields<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != REGISTRY_MAGIC {
+            return Err(invalid("not a BitVeil registry file"));
+        }
+
+        let mut header = [0u8; 2];
+        reader.read_exact(&mut header)?;
+        if header[0] != REGISTRY_VERSION {
+            return Err(invalid("unsupported registry file version"));
+        }
+        if header[1] as usize != REGISTRY_DEPTH {
+            return Err(invalid("unsupported registry depth"));
+        }
+
+        let mut word = [0u8; 4];
+        reader.read_exact(&mut word)?;
+        let count = u32::from_le_bytes(word) as usize;
+        if count > MAX_ENROLLED {
+            return Err(invalid("too many templates"));
+        }
+        let leaves = (0..count)
+            .map(|_| {
+                let mut repr = [0u8; 32];
+                reader.read_exact(&mut repr)?;
+                fp_from_bytes(&repr).ok_or_else(|| invalid("invalid commitment"))
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        Ok(Registry::from_leaves(leaves))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry(len: u64) -> Registry {
+        let mut registry = Registry::new();
+        for i in 1..=len {
+            assert_eq!(registry.insert(Fp::from(i)).unwrap(), i as usize - 1);
+        }
+        registry
+    }
+
+    #[test]
+    fn empty_registry_has_the_empty_root() {
+        let registry = Registry::new();
+        assert!(registry.is_empty());
+        assert_eq!(registry.root(), empty_roots()[REGISTRY_DEPTH]);
+    }
+
+    #[test]
+    fn root_matches_the_tree_padded_with_empty_subtrees() {
+        let registry = registry(3);
+        let mut root = hash_node(
+            hash_node(Fp::from(1), Fp::from(2)),
+            hash_node(Fp::from(3), Fp::zero()),
+        );
+        for empty in &empty_roots()[2..REGISTRY_DEPTH] {
+            root = hash_node(root, *empty);
+        }
+        assert_eq!(registry.root(), root);
+    }
+
+    #[test]
+    fn inserting_leaves_builds_the_same_tree_as_hashing_them_at_once() {
+        for len in 1..=5 {
+            let registry = registry(len);
+            assert_eq!(registry, Registry::from_leaves(registry.leaves().to_vec()));
+        }
+    }
+
+    #[test]
+    fn every_path_hashes_to_the_root() {
+        let registry = registry(5);
+        for (index, leaf) in registry.leaves().iter().enumerate() {
+            let path = registry.path(index).unwrap();
+            assert_eq!(path.index(), index);
+            assert_eq!(path.siblings().len(), REGISTRY_DEPTH);
+            assert_eq!(path.root(*leaf), registry.root());
+
+            // Another leaf, or the same leaf on the other side of its sibling, gives another root
+            assert_ne!(path.root(Fp::from(42)), registry.root());
+            let flipped = MerklePath {
+                index: index ^ 1,
+                ..path
+            };
+            assert_ne!(flipped.root(*leaf), registry.root());
+        }
+    }
+
+    #[test]
+    fn indices_past_the_end_are_rejected() {
+        let mut registry = registry(3);
+        assert!(matches!(
+            registry.path(3),
+            Err(BitVeilError::InvalidRegistryIndex { index: 3, len: 3 })
+        ));
+        assert!(matches!(
+            registry.update(3, Fp::from(7)),
+            Err(BitVeilError::InvalidRegistryIndex { index: 3, len: 3 })
+        ));
+        assert!(matches!(
+            registry.remove(4),
+            Err(BitVeilError::InvalidRegistryIndex { index: 4, len: 3 })
+        ));
+        assert!(matches!(
+            Registry::new().path(0),
+            Err(BitVeilError::InvalidRegistryIndex { index: 0, len: 0 })
+        ));
+    }
+
+    #[test]
+    fn update_and_remove_keep_the_other_indices() {
+        let mut registry = registry(3);
+        let root = registry.root();
+
+        registry.update(1, Fp::from(7)).unwrap();
+        assert_eq!(registry.position(Fp::from(7)), Some(1));
+        assert_eq!(registry.position(Fp::from(2)), None);
+        assert_ne!(registry.root(), root);
+
+        registry.remove(1).unwrap();
+        assert_eq!(registry.len(), 3);
+        assert_eq!(
+            registry,
+            Registry::from_leaves(vec![Fp::from(1), Fp::zero(), Fp::from(3)])
+        );
+        assert_eq!(registry.position(Fp::from(7)), None);
+        assert_eq!(registry.position(Fp::from(3)), Some(2));
+        // The empty leaf is never reported as enrolled
+        assert_eq!(registry.position(Fp::zero()), None);
+
+        let path = registry.path(2).unwrap();
+        assert_eq!(path.root(Fp::from(3)), registry.root());
+    }
+
+    #[test]
+    fn registry_round_trips_through_its_file_format() {
+        let registry = registry(4);
+        let mut bytes = Vec::new();
+        registry.write(&mut bytes).unwrap();
+        assert_eq!(Registry::read(&mut &bytes[..]).unwrap(), registry);
+
+        bytes[0] = b'X';
+        assert!(matches!(
+            Registry::read(&mut &bytes[..]),
+            Err(BitVeilError::RegistryDecode(_))
+        ));
+    }
+}
@@ -0,0 +1,654 @@
+use blake2b_simd::Params as Blake2bParams;
+use halo2_proofs::{
+    arithmetic::Field,
+    circuit::{AssignedCell, Chip, Layouter, Region, Value},
+    pasta::group::ff::{FromUniformBytes, PrimeField},
+    plonk::{Advice, Column, ConstraintSystem, Error, Expression, Fixed, Selector},
+    poly::Rotation,
+};
+use std::{
+    any::{Any, TypeId},
+    array,
+    collections::HashMap,
+    marker::PhantomData,
+    sync::{Mutex, OnceLock, PoisonError},
+};
+
+// Poseidon permutation over a width-3 state (rate 2, capacity 1) with the x^5 S-box, following the
+// Poseidon paper (Grassi, Khovratovich, Rechberger, Roy and Schofnegger, USENIX Security 2021).
+// Its constants are generated differently from the reference instances, so its hashes are not
+// interchangeable with them. The parameters are chosen as follows:
+//
+// - x^5 is a permutation of the Pallas base field, since 5 does not divide p - 1.
+// - 8 full and 56 partial rounds are the round numbers of the paper for 128-bit security with a
+//   width of 3 and x^5 over a 255-bit field, including its security margin of 2 full rounds and
+//   7.5% more partial rounds. P128Pow5T3, the instance of halo2_gadgets over the same field, uses
+//   them too.
+// - The paper only asks of the round constants that they have no structure an attack could use,
+//   and draws them from the Grain LFSR. These are drawn from Blake2b instead, keyed by the round
+//   and the position, and reduce 512 bits modulo p, with a bias below 2^-256.
+// - The MDS matrix is the Cauchy matrix 1 / (i + j + MDS_OFFSET). A Cauchy matrix is MDS when all
+//   of the sums are distinct from each other and from zero. It must also not let an invariant
+//   subspace trail through any number of partial rounds, which Algorithm 1 of Grassi,
+//   Rechberger and Schofnegger (ToSC 2021) rules out when the minimal polynomials of M^i for i up
+//   to 2 * WIDTH are irreducible of degree WIDTH. MDS_OFFSET is the smallest offset from WIDTH
+//   whose matrix passes, which the tests check.
+//
+// The test vectors come from scripts/poseidon_reference.py, which implements the same
+// construction in Python without sharing any code with this module.
+pub const WIDTH: usize = 3;
+pub const RATE: usize = 2;
+const FULL_ROUNDS: usize = 8;
+const PARTIAL_ROUNDS: usize = 56;
+const ROUNDS: usize = FULL_ROUNDS + PARTIAL_ROUNDS;
+const MDS_OFFSET: usize = 7;
+
+// Rows taken by a single absorb-and-permute step: the message row, the sum row and one row per
+// round
+pub const ROWS_PER_ABSORB: usize = 2 + ROUNDS;
+
+fn is_full_round(round: usize) -> bool {
+    !(FULL_ROUNDS / 2..FULL_ROUNDS / 2 + PARTIAL_ROUNDS).contains(&round)
+}
+
+fn pow5<F: Field>(x: F) -> F {
+    x.square().square() * x
+}
+
+// Derives the round constants by hashing the round and position with Blake2b
+fn round_constants<F: FromUniformBytes<64>>() -> Vec<[F; WIDTH]> {
+    (0..ROUNDS)
+        .map(|round| {
+            array::from_fn(|i| {
+                let hash = Blake2bParams::new()
+                    .hash_length(64)
+                    .personal(b"BitVeil-Poseidon")
+                    .to_state()
+                    .update(&(round as u64).to_le_bytes())
+                    .update(&(i as u64).to_le_bytes())
+                    .finalize();
+                F::from_uniform_bytes(hash.as_array())
+            })
+        })
+        .collect()
+}
+
+fn mds<F: PrimeField>() -> [[F; WIDTH]; WIDTH] {
+    cauchy(MDS_OFFSET)
+}
+
+// The Cauchy matrix with x_i = i and y_j = offset + j, whose sums are distinct and not zero
+fn cauchy<F: PrimeField>(offset: usize) -> [[F; WIDTH]; WIDTH] {
+    array::from_fn(|i| array::from_fn(|j| F::from((i + j + offset) as u64).invert().unwrap()))
+}
+
+// The round constants and the MDS matrix of a field, which are derived once and shared by every
+// hash of that field in and out of the circuit
+struct Constants<F> {
+    rc: Vec<[F; WIDTH]>,
+    mds: [[F; WIDTH]; WIDTH],
+}
+
+fn constants<F: FromUniformBytes<64>>() -> &'static Constants<F> {
+    // A static cannot be generic, so the constants of each field are leaked into a map keyed by the
+    // type of the field
+    type Cache = HashMap<TypeId, &'static (dyn Any + Send + Sync)>;
+    static CONSTANTS: OnceLock<Mutex<Cache>> = OnceLock::new();
+
+    let mut cache = CONSTANTS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    let constants = *cache.entry(TypeId::of::<F>()).or_insert_with(|| {
+        Box::leak(Box::new(Constants::<F> {
+            rc: round_constants(),
+            mds: mds(),
+        }))
+    });
+    constants
+        .downcast_ref()
+        .expect("the constants are keyed by their field")
+}
+
+// The capacity element encodes the input length so inputs of different lengths never collide
+fn initial_state<F: PrimeField>(len: usize) -> [F; WIDTH] {
+    [F::ZERO, F::ZERO, F::from_u128((len as u128) << 64)]
+}
+
+// Applies the permutation with the round constants and the MDS matrix of the field
+fn permute<F: PrimeField>(state: &mut [F; WIDTH], rc: &[[F; WIDTH]], mds: &[[F; WIDTH]; WIDTH]) {
+    for (round, rc) in rc.iter().enumerate() {
+        let sboxed: [F; WIDTH] = array::from_fn(|i| {
+            let x = state[i] + rc[i];
+            if is_full_round(round) || i == 0 {
+                pow5(x)
+            } else {
+                x
+            }
+        });
+        *state = array::from_fn(|i| {
+            sboxed
+                .iter()
+                .zip(mds[i])
+                .fold(F::ZERO, |acc, (x, m)| acc + *x * m)
+        });
+    }
+}
+
+// Hashes a sequence of field elements outside of the circuit
+pub fn hash<F: FromUniformBytes<64>>(inputs: &[F]) -> F {
+    let Constants { rc, mds } = constants::<F>();
+
+    let mut state = initial_state::<F>(inputs.len());
+    for chunk in inputs.chunks(RATE) {
+        for (s, x) in state.iter_mut().zip(chunk) {
+            *s += *x;
+        }
+        permute(&mut state, rc, mds);
+    }
+    state[0]
+}
+
+// The chip which computes Poseidon hashes in the circuit
+pub struct PoseidonChip<F: Field> {
+    config: PoseidonConfig,
+    _marker: PhantomData<F>,
+}
+
+impl<F: Field> Chip<F> for PoseidonChip<F> {
+    type Config = PoseidonConfig;
+    type Loaded = ();
+
+    fn config(&self) -> &Self::Config {
+        &self.config
+    }
+
+    fn loaded(&self) -> &Self::Loaded {
+        &()
+    }
+}
+
+// The configuration of the hash chip. The state columns are shared with the other chips.
+#[derive(Debug, Clone)]
+pub struct PoseidonConfig {
+    state: [Column<Advice>; WIDTH],
+    rc: [Column<Fixed>; WIDTH],
+
+    s_absorb: Selector,
+    s_full: Selector,
+    s_partial: Selector,
+}
+
+impl<F: FromUniformBytes<64>> PoseidonChip<F> {
+    pub fn construct(config: PoseidonConfig) -> Self {
+        Self {
+            config,
+            _marker: PhantomData,
+        }
+    }
+
+    // Requires a constant column to be enabled in the constraint system for the initial state
+    pub fn configure(
+        meta: &mut ConstraintSystem<F>,
+        state: [Column<Advice>; WIDTH],
+        rc: [Column<Fixed>; WIDTH],
+    ) -> PoseidonConfig {
+        for column in &state {
+            meta.enable_equality(*column);
+        }
+
+        let s_absorb = meta.selector();
+        let s_full = meta.selector();
+        let s_partial = meta.selector();
+
+        let mds = constants::<F>().mds;
+
+        // Adds the message in the next row to the rate portion of the state, writing the result
+        // two rows down: (s0 + m0, s1 + m1, s2)
+        meta.create_gate("poseidon absorb", |meta| {
+            let s_absorb = meta.query_selector(s_absorb);
+            (0..WIDTH)
+                .map(|i| {
+                    let cur = meta.query_advice(state[i], Rotation::cur());
+                    let out = meta.query_advice(state[i], Rotation(2));
+                    if i < RATE {
+                        let msg = meta.query_advice(state[i], Rotation::next());
+                        s_absorb.clone() * (cur + msg - out)
+                    } else {
+                        s_absorb.clone() * (cur - out)
+                    }
+                })
+                .collect::<Vec<_>>()
+        });
+
+        // The round gates add the round constants, apply the S-box and multiply by the MDS matrix
+        let round_gate = |meta: &mut ConstraintSystem<F>, name, selector, full: bool| {
+            meta.create_gate(name, |meta| {
+                let selector = meta.query_selector(selector);
+                let sboxed: Vec<Expression<F>> = (0..WIDTH)
+                    .map(|i| {
+                        let x =
+                            meta.query_advice(state[i], Rotation::cur()) + meta.query_fixed(rc[i]);
+                        if full || i == 0 {
+                            x.clone() * x.clone() * x.clone() * x.clone() * x
+                        } else {
+                            x
+                        }
+                    })
+                    .collect();
+                (0..WIDTH)
+                    .map(|i| {
+                        let next = meta.query_advice(state[i], Rotation::next());
+                        let mixed = sboxed
+                            .iter()
+                            .zip(mds[i])
+                            .fold(Expression::Constant(F::ZERO), |acc, (x, m)| {
+                                acc + x.clone() * Expression::Constant(m)
+                            });
+                        selector.clone() * (mixed - next)
+                    })
+                    .collect::<Vec<_>>()
+            });
+        };
+        round_gate(meta, "poseidon full round", s_full, true);
+        round_gate(meta, "poseidon partial round", s_partial, false);
+
+        PoseidonConfig {
+            state,
+            rc,
+            s_absorb,
+            s_full,
+            s_partial,
+        }
+    }
+
+    // Hashes the given cells in their own region, as described in `assign_hash`
+    pub fn hash(
+        &self,
+        mut layouter: impl Layouter<F>,
+        inputs: &[AssignedCell<F, F>],
+    ) -> Result<AssignedCell<F, F>, Error> {
+        layouter.assign_region(
+            || "poseidon hash",
+            |mut region: Region<'_, F>| self.assign_hash(&mut region, inputs),
+        )
+    }
+
+    // Assigns the rows hashing the given cells, constraining the result to match `hash` above. The
+    // first row holds the initial state, and each chunk of the inputs adds ROWS_PER_ABSORB rows:
+    // the message, the state with the message added in and the state after each round.
+    fn assign_hash(
+        &self,
+        region: &mut Region<'_, F>,
+        inputs: &[AssignedCell<F, F>],
+    ) -> Result<AssignedCell<F, F>, Error> {
+        let config = self.config();
+        let Constants { rc, mds } = constants::<F>();
+
+        // Load the initial state from constants
+        let mut state = initial_state::<F>(inputs.len())
+            .iter()
+            .enumerate()
+            .map(|(i, value)| {
+                region.assign_advice_from_constant(
+                    || format!("initial state[{}]", i),
+                    config.state[i],
+                    0,
+                    *value,
+                )
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        let mut offset = 0;
+        for chunk in inputs.chunks(RATE) {
+            config.s_absorb.enable(region, offset)?;
+
+            // Copy the message into the row below the state, padding with zeros
+            for i in 0..RATE {
+                match chunk.get(i) {
+                    Some(input) => {
+                        input.copy_advice(
+                            || format!("message[{}]", i),
+                            region,
+                            config.state[i],
+                            offset + 1,
+                        )?;
+                    }
+                    None => {
+                        region.assign_advice_from_constant(
+                            || format!("padding[{}]", i),
+                            config.state[i],
+                            offset + 1,
+                            F::ZERO,
+                        )?;
+                    }
+                }
+            }
+
+            // Assign the state with the message added in
+            offset += 2;
+            state = (0..WIDTH)
+                .map(|i| {
+                    let mut sum = state[i].value().copied();
+                    if let Some(input) = chunk.get(i) {
+                        sum = sum + input.value();
+                    }
+                    region.assign_advice(
+                        || format!("absorbed[{}]", i),
+                        config.state[i],
+                        offset,
+                        || sum,
+                    )
+                })
+                .collect::<Result<Vec<_>, Error>>()?;
+
+            // Apply the rounds of the permutation, one row per round
+            for (round, rc) in rc.iter().enumerate() {
+                if is_full_round(round) {
+                    config.s_full.enable(region, offset)?;
+                } else {
+                    config.s_partial.enable(region, offset)?;
+                }
+                for (i, rc) in rc.iter().enumerate() {
+                    region.assign_fixed(
+                        || format!("round constant[{}]", i),
+                        config.rc[i],
+                        offset,
+                        || Value::known(*rc),
+                    )?;
+                }
+
+                let sboxed: Vec<Value<F>> = (0..WIDTH)
+                    .map(|i| {
+                        let x = state[i].value().map(|x| *x + rc[i]);
+                        if is_full_round(round) || i == 0 {
+                            x.map(pow5)
+                        } else {
+                            x
+                        }
+                    })
+                    .collect();
+
+                offset += 1;
+                state = (0..WIDTH)
+                    .map(|i| {
+                        let mixed = sboxed
+                            .iter()
+                            .zip(mds[i])
+                            .fold(Value::known(F::ZERO), |acc, (x, m)| acc + x.map(|x| x * m));
+                        region.assign_advice(
+                            || format!("round {} state[{}]", round, i),
+                            config.state[i],
+                            offset,
+                            || mixed,
+                        )
+                    })
+                    .collect::<Result<Vec<_>, Error>>()?;
+            }
+        }
+
+        Ok(state[0].clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use halo2_proofs::{
+        circuit::SimpleFloorPlanner,
+        dev::{MockProver, VerifyFailure},
+        pasta::Fp,
+        plonk::{Circuit, Instance},
+    };
+
+    // A circuit which hashes its inputs with the chip and exposes the hash. A prover deviating
+    // from the honest witness can assign a cell of the hash region again, given as a state column
+    // and a row.
+    struct HashCircuit {
+        inputs: Vec<Fp>,
+        tamper: Option<(usize, usize)>,
+    }
+
+    impl Circuit<Fp> for HashCircuit {
+        type Config = (PoseidonConfig, Column<Instance>);
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self {
+            HashCircuit {
+                inputs: self.inputs.clone(),
+                tamper: self.tamper,
+            }
+        }
+
+        fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+            let state = array::from_fn(|_| meta.advice_column());
+            let rc = array::from_fn(|_| meta.fixed_column());
+            let constants = meta.fixed_column();
+            meta.enable_constant(constants);
+            let instance = meta.instance_column();
+            meta.enable_equality(instance);
+            (PoseidonChip::configure(meta, state, rc), instance)
+        }
+
+        fn synthesize(
+            &self,
+            (config, instance): Self::Config,
+            mut layouter: impl Layouter<Fp>,
+        ) -> Result<(), Error> {
+            let inputs = layouter.assign_region(
+                || "load inputs",
+                |mut region: Region<'_, Fp>| {
+                    self.inputs
+                        .iter()
+                        .enumerate()
+                        .map(|(i, input)| {
+                            region.assign_advice(
+                                || format!("input[{}]", i),
+                                config.state[0],
+                                i,
+                                || Value::known(*input),
+                            )
+                        })
+                        .collect::<Result<Vec<_>, Error>>()
+                },
+            )?;
+            let chip = PoseidonChip::construct(config.clone());
+            let hash = layouter.assign_region(
+                || "hash",
+                |mut region: Region<'_, Fp>| {
+                    let hash = chip.assign_hash(&mut region, &inputs)?;
+                    if let Some((column, row)) = self.tamper {
+                        region.assign_advice(
+                            || "tampered",
+                            config.state[column],
+                            row,
+                            || Value::known(Fp::from(7)),
+                        )?;
+                    }
+                    Ok(hash)
+                },
+            )?;
+            layouter.constrain_instance(hash.cell(), instance, 0)
+        }
+    }
+
+    fn inputs(len: u64) -> Vec<Fp> {
+        (1..=len).map(Fp::from).collect()
+    }
+
+    #[test]
+    fn chip_matches_hash() {
+        for len in 1..=3 {
+            let circuit = HashCircuit {
+                inputs: inputs(len),
+                tamper: None,
+            };
+            let expected = hash(&circuit.inputs);
+            let prover = MockProver::run(8, &circuit, vec![vec![expected]]).unwrap();
+            assert_eq!(prover.verify(), Ok(()), "{} inputs", len);
+
+            let prover = MockProver::run(8, &circuit, vec![vec![expected + Fp::one()]]).unwrap();
+            assert!(prover.verify().is_err(), "{} inputs", len);
+        }
+    }
+
+    // The names of the gates failing in the hash of two inputs, which is a single absorb, with the
+    // given cell tampered
+    fn failing_gates(column: usize, row: usize) -> Vec<String> {
+        let circuit = HashCircuit {
+            inputs: inputs(2),
+            tamper: Some((column, row)),
+        };
+        let expected = hash(&circuit.inputs);
+        let prover = MockProver::run(8, &circuit, vec![vec![expected]]).unwrap();
+        let mut gates: Vec<String> = prover
+            .verify()
+            .unwrap_err()
+            .iter()
+            .filter_map(|failure| match failure {
+                // Displayed as "Constraint 0 in gate 0 ('poseidon absorb')"
+                VerifyFailure::ConstraintNotSatisfied { constraint, .. } => constraint
+                    .to_string()
+                    .rsplit("('")
+                    .next()
+                    .map(|name| name.trim_end_matches("')").to_string()),
+                _ => None,
+            })
+            .collect();
+        gates.sort();
+        gates.dedup();
+        gates
+    }
+
+    // Row 2 holds the state with the message added in, which is also the input of the first round,
+    // and round r maps row 2 + r to row 3 + r
+    #[test]
+    fn tampered_state_fails_the_gate_of_its_rows() {
+        assert_eq!(
+            failing_gates(0, 2),
+            ["poseidon absorb", "poseidon full round"]
+        );
+        assert_eq!(failing_gates(1, 3), ["poseidon full round"]);
+        assert_eq!(
+            failing_gates(2, 3 + FULL_ROUNDS / 2),
+            ["poseidon partial round"]
+        );
+    }
+
+    // Polynomials over the field, lowest degree first and without trailing zeros
+    type Poly = Vec<Fp>;
+
+    fn trim(mut a: Poly) -> Poly {
+        while a.last() == Some(&Fp::zero()) {
+            a.pop();
+        }
+        a
+    }
+
+    fn rem(a: &[Fp], b: &[Fp]) -> Poly {
+        let lead = b.last().unwrap().invert().unwrap();
+        let mut r = a.to_vec();
+        while r.len() >= b.len() {
+            let c = *r.last().unwrap() * lead;
+            let shift = r.len() - b.len();
+            for (i, coefficient) in b.iter().enumerate() {
+                r[shift + i] -= c * coefficient;
+            }
+            r.pop();
+        }
+        trim(r)
+    }
+
+    fn mul_mod(a: &[Fp], b: &[Fp], modulus: &[Fp]) -> Poly {
+        let mut product = vec![Fp::zero(); a.len() + b.len()];
+        for (i, x) in a.iter().enumerate() {
+            for (j, y) in b.iter().enumerate() {
+                product[i + j] += *x * y;
+            }
+        }
+        rem(&product, modulus)
+    }
+
+    fn gcd(mut a: Poly, mut b: Poly) -> Poly {
+        while !b.is_empty() {
+            (a, b) = (b.clone(), rem(&a, &b));
+        }
+        a
+    }
+
+    fn mul(a: &[[Fp; WIDTH]; WIDTH], b: &[[Fp; WIDTH]; WIDTH]) -> [[Fp; WIDTH]; WIDTH] {
+        array::from_fn(|i| array::from_fn(|j| (0..WIDTH).map(|k| a[i][k] * b[k][j]).sum()))
+    }
+
+    // Whether the characteristic polynomial of a 3x3 matrix is irreducible, which for a cubic is
+    // whether it has no root, so that it is also the minimal polynomial. It has a root exactly
+    // when it shares a factor with x^p - x.
+    fn irreducible(m: &[[Fp; WIDTH]; WIDTH]) -> bool {
+        let trace = m[0][0] + m[1][1] + m[2][2];
+        let minors = m[0][0] * m[1][1] - m[0][1] * m[1][0] + m[0][0] * m[2][2] - m[0][2] * m[2][0]
+            + m[1][1] * m[2][2]
+            - m[1][2] * m[2][1];
+        let det = m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0]);
+        let charpoly = vec![-det, minors, -trace, Fp::one()];
+
+        // x^(p - 1) by square and multiply over the bits of p - 1, most significant first
+        let x = vec![Fp::zero(), Fp::one()];
+        let p_minus_one = (-Fp::one()).to_repr();
+        let mut power = vec![Fp::one()];
+        for byte in p_minus_one.as_ref().iter().rev() {
+            for bit in (0..8).rev() {
+                power = mul_mod(&power, &power, &charpoly);
+                if (byte >> bit) & 1 == 1 {
+                    power = mul_mod(&power, &x, &charpoly);
+                }
+            }
+        }
+        let mut frobenius = mul_mod(&power, &x, &charpoly);
+        frobenius.resize(frobenius.len().max(2), Fp::zero());
+        frobenius[1] -= Fp::one();
+
+        gcd(charpoly, trim(frobenius)).len() == 1
+    }
+
+    fn no_invariant_subspaces(m: &[[Fp; WIDTH]; WIDTH]) -> bool {
+        let mut power = *m;
+        (1..=2 * WIDTH).all(|_| {
+            let result = irreducible(&power);
+            power = mul(&power, m);
+            result
+        })
+    }
+
+    #[test]
+    fn mds_has_no_invariant_subspaces() {
+        assert!(no_invariant_subspaces(&mds()));
+
+        // Every smaller offset, including the offset of WIDTH this instance used at first, gives
+        // a matrix with some power whose characteristic polynomial has a root, and so an
+        // eigenvector spanning a subspace it maps to itself
+        for offset in WIDTH..MDS_OFFSET {
+            assert!(
+                !no_invariant_subspaces(&cauchy(offset)),
+                "offset {}",
+                offset
+            );
+        }
+    }
+
+    // The outputs of scripts/poseidon_reference.py for the inputs 1, (1, 2) and (1, 2, 3), which
+    // change if the round constants, the MDS matrix, the round counts or the length encoding do,
+    // and with them every commitment
+    #[test]
+    fn hash_matches_test_vectors() {
+        let expected = [
+            "0x3fa02ad4a6b43c5441b033e2f9cfff2977d57d81389d64f4d3c445486f7863e2",
+            "0x0935c51b7af7ba3077bf03c4249be7e7c1ec7819a1111a9843d929fd0424bb47",
+            "0x05c2a91e707ead7e8782c131f6c6cfb795f4ae28ee4c4508452f5a928ecbabd4",
+        ];
+        for (len, expected) in (1..=3).zip(expected) {
+            assert_eq!(format!("{:?}", hash(&inputs(len))), expected);
+        }
+    }
+}
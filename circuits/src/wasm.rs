@@ -1,12 +1,18 @@
 use crate::circuits::{
-    calculate_hamming_distance, create_circuit, empty_circuit, generate_keys, generate_proof,
-    generate_setup_params, verify,
-};
-use halo2_proofs::{
-    pasta::{EqAffine, Fp},
-    plonk::keygen_vk,
-    poly::commitment::Params,
+    BitVeilProver, BitVeilVerifier, CircuitKind, DistanceMetric, EmbeddingMetric, Hamming,
+    InnerProduct, KeyInfo, MAX_LENGTH, PopcountHamming, ProofBundle, RotatedHamming,
+    SimilarityMetric, WeightedHamming, batch_public_inputs, check_binary_vectors,
+    commit_integer_vector, commit_masked_vector, commit_packed, commit_session_vector,
+    commit_vector, context_id, derive_nullifier, fp_from_bytes, generate_nonce,
+    generate_nullifier_secret, generate_salt, generate_setup_params, k_for_batch, k_for_embedding,
+    k_for_inner_product, k_for_length, k_for_masked, k_for_membership, k_for_popcount,
+    k_for_rotation, k_for_session, k_for_similarity, masked_public_inputs,
+    membership_public_inputs, public_inputs, read_params, session_public_inputs,
+    similarity_public_inputs, unpack_words,
 };
+use crate::error::BitVeilError;
+use crate::merkle::Registry;
+use halo2_proofs::pasta::{Fp, group::ff::PrimeField};
 use js_sys::Uint8Array;
 use std::io::BufReader;
 use wasm_bindgen::prelude::*;
@@ -26,108 +32,1643 @@ extern "C" {
     fn error(s: &str);
 }
 
-fn copy_vec_to_uint8array(vec: &Vec<u8>) -> Uint8Array {
+fn copy_vec_to_uint8array(vec: &[u8]) -> Uint8Array {
     let uint8_array = Uint8Array::new_with_length(vec.len() as u32);
-    uint8_array.copy_from(&vec);
+    uint8_array.copy_from(vec);
     uint8_array
 }
 
 #[wasm_bindgen]
-pub fn setup_params(k: u32) -> Uint8Array {
+pub fn setup_params(k: u32) -> Result<Uint8Array, BitVeilError> {
     log("Generating setup parameters...");
-    let params: Params<EqAffine> = generate_setup_params(k);
+    let params = generate_setup_params(k);
+    let mut buf = Vec::new();
+    params.write(&mut buf)?;
+    Ok(copy_vec_to_uint8array(&buf))
+}
+
+// Generates the keys of a circuit for vectors of the given length, returning the key file which
+// BitVeilProver and BitVeilVerifier take in place of the keys
+#[wasm_bindgen]
+pub fn setup_keys(
+    params_bytes: &[u8],
+    length: u32,
+    threshold: bool,
+) -> Result<Uint8Array, BitVeilError> {
+    let length = length as usize;
+    if length == 0 || length > MAX_LENGTH {
+        return Err(BitVeilError::InvalidLength {
+            a: length,
+            b: length,
+        });
+    }
+    let params = read_params(&mut BufReader::new(params_bytes))?;
+
+    log("Generating keys...");
+    let kind = if threshold {
+        CircuitKind::Threshold
+    } else {
+        CircuitKind::Distance
+    };
+    let verifier = BitVeilVerifier::new(params, kind, length)?;
+
+    let mut buf = Vec::new();
+    verifier.key_info().write(&mut buf)?;
+    Ok(copy_vec_to_uint8array(&buf))
+}
+
+// Generates the keys of a batch circuit comparing one probe with the given number of candidates of
+// the given length, returning the key file
+#[wasm_bindgen]
+pub fn setup_batch_keys(
+    params_bytes: &[u8],
+    length: u32,
+    candidates: u32,
+) -> Result<Uint8Array, BitVeilError> {
+    let length = length as usize;
+    if length == 0 || length > MAX_LENGTH {
+        return Err(BitVeilError::InvalidLength {
+            a: length,
+            b: length,
+        });
+    }
+    let params = read_params(&mut BufReader::new(params_bytes))?;
+
+    log("Generating batch keys...");
+    let verifier = BitVeilVerifier::new_batch(params, length, candidates as usize)?;
+
+    let mut buf = Vec::new();
+    verifier.key_info().write(&mut buf)?;
+    Ok(copy_vec_to_uint8array(&buf))
+}
+
+// Generates the keys of a masked circuit for vectors of the given length, returning the key file
+#[wasm_bindgen]
+pub fn setup_masked_keys(params_bytes: &[u8], length: u32) -> Result<Uint8Array, BitVeilError> {
+    let length = length as usize;
+    if length == 0 || length > MAX_LENGTH {
+        return Err(BitVeilError::InvalidLength {
+            a: length,
+            b: length,
+        });
+    }
+    let params = read_params(&mut BufReader::new(params_bytes))?;
+
+    log("Generating masked keys...");
+    let verifier = BitVeilVerifier::new(params, CircuitKind::Masked, length)?;
+
     let mut buf = Vec::new();
-    params.write(&mut buf).expect("Failed to write params");
-    copy_vec_to_uint8array(&buf)
+    verifier.key_info().write(&mut buf)?;
+    Ok(copy_vec_to_uint8array(&buf))
+}
+
+// Generates the keys of a rotation circuit comparing vectors of the given length at each shift from
+// -shifts to shifts bits, returning the key file
+#[wasm_bindgen]
+pub fn setup_rotation_keys(
+    params_bytes: &[u8],
+    length: u32,
+    shifts: u32,
+    threshold: bool,
+) -> Result<Uint8Array, BitVeilError> {
+    let params = read_params(&mut BufReader::new(params_bytes))?;
+
+    log("Generating rotation keys...");
+    let verifier =
+        BitVeilVerifier::new_rotation(params, length as usize, shifts as usize, threshold)?;
+
+    let mut buf = Vec::new();
+    verifier.key_info().write(&mut buf)?;
+    Ok(copy_vec_to_uint8array(&buf))
+}
+
+// Generates the keys of a weighted circuit with one weight per bit of the vectors, returning the
+// key file
+#[wasm_bindgen]
+pub fn setup_weighted_keys(
+    params_bytes: &[u8],
+    weights: &[u32],
+) -> Result<Uint8Array, BitVeilError> {
+    let params = read_params(&mut BufReader::new(params_bytes))?;
+
+    log("Generating weighted keys...");
+    let weights = weights.iter().map(|weight| *weight as u64).collect();
+    let verifier = BitVeilVerifier::new_weighted(params, weights)?;
+
+    let mut buf = Vec::new();
+    verifier.key_info().write(&mut buf)?;
+    Ok(copy_vec_to_uint8array(&buf))
+}
+
+// Generates the keys of a similarity circuit for vectors of the given length, returning the key
+// file. The keys prove the Dice similarity if `dice` is set, and the Jaccard similarity otherwise.
+#[wasm_bindgen]
+pub fn setup_similarity_keys(
+    params_bytes: &[u8],
+    length: u32,
+    dice: bool,
+) -> Result<Uint8Array, BitVeilError> {
+    let params = read_params(&mut BufReader::new(params_bytes))?;
+    let metric = if dice {
+        SimilarityMetric::Dice
+    } else {
+        SimilarityMetric::Jaccard
+    };
+
+    log(&format!("Generating {} similarity keys...", metric));
+    let verifier = BitVeilVerifier::new_similarity(params, length as usize, metric)?;
+
+    let mut buf = Vec::new();
+    verifier.key_info().write(&mut buf)?;
+    Ok(copy_vec_to_uint8array(&buf))
+}
+
+// Generates the keys of an inner-product circuit for integer vectors of the given length, returning
+// the key file
+#[wasm_bindgen]
+pub fn setup_inner_product_keys(
+    params_bytes: &[u8],
+    length: u32,
+) -> Result<Uint8Array, BitVeilError> {
+    let length = length as usize;
+    if length == 0 || length > MAX_LENGTH {
+        return Err(BitVeilError::InvalidLength {
+            a: length,
+            b: length,
+        });
+    }
+    let params = read_params(&mut BufReader::new(params_bytes))?;
+
+    log("Generating inner-product keys...");
+    let verifier = BitVeilVerifier::new(params, CircuitKind::InnerProduct, length)?;
+
+    let mut buf = Vec::new();
+    verifier.key_info().write(&mut buf)?;
+    Ok(copy_vec_to_uint8array(&buf))
+}
+
+// Generates the keys of an embedding circuit for integer vectors of the given length, returning the
+// key file. The keys prove the squared L2 distance if `l2` is set, and the L1 distance otherwise.
+#[wasm_bindgen]
+pub fn setup_embedding_keys(
+    params_bytes: &[u8],
+    length: u32,
+    l2: bool,
+) -> Result<Uint8Array, BitVeilError> {
+    let length = length as usize;
+    if length == 0 || length > MAX_LENGTH {
+        return Err(BitVeilError::InvalidLength {
+            a: length,
+            b: length,
+        });
+    }
+    let params = read_params(&mut BufReader::new(params_bytes))?;
+    let metric = if l2 {
+        EmbeddingMetric::L2
+    } else {
+        EmbeddingMetric::L1
+    };
+
+    log(&format!("Generating {} embedding keys...", metric));
+    let verifier = BitVeilVerifier::new_embedding(params, length, metric)?;
+
+    let mut buf = Vec::new();
+    verifier.key_info().write(&mut buf)?;
+    Ok(copy_vec_to_uint8array(&buf))
+}
+
+// Generates the keys of a popcount circuit for vectors of the given length, returning the key file.
+// BitVeilProver.prove and BitVeilVerifier.verify take these keys like the ones from setup_keys.
+#[wasm_bindgen]
+pub fn setup_popcount_keys(params_bytes: &[u8], length: u32) -> Result<Uint8Array, BitVeilError> {
+    let length = length as usize;
+    if length == 0 || length > MAX_LENGTH {
+        return Err(BitVeilError::InvalidLength {
+            a: length,
+            b: length,
+        });
+    }
+    let params = read_params(&mut BufReader::new(params_bytes))?;
+
+    log("Generating popcount keys...");
+    let verifier = BitVeilVerifier::new(params, CircuitKind::Popcount, length)?;
+
+    let mut buf = Vec::new();
+    verifier.key_info().write(&mut buf)?;
+    Ok(copy_vec_to_uint8array(&buf))
+}
+
+// Generates the keys of a membership circuit for vectors of the given length, returning the key
+// file
+#[wasm_bindgen]
+pub fn setup_membership_keys(params_bytes: &[u8], length: u32) -> Result<Uint8Array, BitVeilError> {
+    let length = length as usize;
+    if length == 0 || length > MAX_LENGTH {
+        return Err(BitVeilError::InvalidLength {
+            a: length,
+            b: length,
+        });
+    }
+    let params = read_params(&mut BufReader::new(params_bytes))?;
+
+    log("Generating membership keys...");
+    let verifier = BitVeilVerifier::new(params, CircuitKind::Membership, length)?;
+
+    let mut buf = Vec::new();
+    verifier.key_info().write(&mut buf)?;
+    Ok(copy_vec_to_uint8array(&buf))
+}
+
+// Generates the keys of a session circuit for vectors of the given length, returning the key file
+#[wasm_bindgen]
+pub fn setup_session_keys(params_bytes: &[u8], length: u32) -> Result<Uint8Array, BitVeilError> {
+    let length = length as usize;
+    if length == 0 || length > MAX_LENGTH {
+        return Err(BitVeilError::InvalidLength {
+            a: length,
+            b: length,
+        });
+    }
+    let params = read_params(&mut BufReader::new(params_bytes))?;
+
+    log("Generating session keys...");
+    let verifier = BitVeilVerifier::new(params, CircuitKind::Session, length)?;
+
+    let mut buf = Vec::new();
+    verifier.key_info().write(&mut buf)?;
+    Ok(copy_vec_to_uint8array(&buf))
+}
+
+fn bundle_to_uint8array(bundle: &ProofBundle) -> Result<Uint8Array, BitVeilError> {
+    let mut buf = Vec::new();
+    bundle.write(&mut buf)?;
+    Ok(copy_vec_to_uint8array(&buf))
+}
+
+fn read_fp(bytes: &[u8], name: &'static str) -> Result<Fp, BitVeilError> {
+    fp_from_bytes(bytes).ok_or(BitVeilError::InvalidFieldElement(name))
+}
+
+// Returns the smallest k for setup parameters that fit vectors of the given length
+#[wasm_bindgen]
+pub fn required_k(length: u32) -> u32 {
+    k_for_length(length as usize)
+}
+
+// Returns the smallest k for setup parameters that fit a batch of candidates of the given length
+#[wasm_bindgen]
+pub fn required_batch_k(length: u32, candidates: u32) -> u32 {
+    k_for_batch(length as usize, candidates as usize)
+}
+
+// Returns the smallest k for setup parameters that fit masked vectors of the given length
+#[wasm_bindgen]
+pub fn required_masked_k(length: u32) -> u32 {
+    k_for_masked(length as usize)
+}
+
+// Returns the smallest k for setup parameters that fit a rotation circuit with the given number of
+// shifts in each direction
+#[wasm_bindgen]
+pub fn required_rotation_k(length: u32, shifts: u32) -> u32 {
+    k_for_rotation(length as usize, shifts as usize)
+}
+
+// Returns the smallest k for setup parameters that fit a similarity circuit for vectors of the
+// given length
+#[wasm_bindgen]
+pub fn required_similarity_k(length: u32) -> u32 {
+    k_for_similarity(length as usize)
+}
+
+// Returns the smallest k for setup parameters that fit an inner-product circuit for integer vectors
+// of the given length
+#[wasm_bindgen]
+pub fn required_inner_product_k(length: u32) -> u32 {
+    k_for_inner_product(length as usize)
+}
+
+// Returns the smallest k for setup parameters that fit an embedding circuit for integer vectors of
+// the given length
+#[wasm_bindgen]
+pub fn required_embedding_k(length: u32) -> u32 {
+    k_for_embedding(length as usize)
+}
+
+// Returns the smallest k for setup parameters that fit a popcount circuit for vectors of the given
+// length
+#[wasm_bindgen]
+pub fn required_popcount_k(length: u32) -> u32 {
+    k_for_popcount(length as usize)
+}
+
+// Returns the smallest k for setup parameters that fit a membership circuit for vectors of the
+// given length
+#[wasm_bindgen]
+pub fn required_membership_k(length: u32) -> u32 {
+    k_for_membership(length as usize)
+}
+
+#[wasm_bindgen]
+pub fn required_session_k(length: u32) -> u32 {
+    k_for_session(length as usize)
+}
+
+// Returns the root of a registry file written by the CLI, which membership proofs are checked
+// against
+#[wasm_bindgen]
+pub fn registry_root(registry: &[u8]) -> Result<Uint8Array, BitVeilError> {
+    let registry = Registry::read(&mut BufReader::new(registry))?;
+    Ok(copy_vec_to_uint8array(&registry.root().to_repr()))
 }
 
 #[wasm_bindgen]
-pub fn proof_generate(a: &[u8], b: &[u8], params_bytes: &[u8]) -> Result<Uint8Array, JsValue> {
-    log(&format!(
-        "Generating proof for vectors of length {} and {}",
-        a.len(),
-        b.len()
-    ));
+pub fn random_salt() -> Uint8Array {
+    copy_vec_to_uint8array(&generate_salt().to_repr())
+}
+
+// Generates a random nullifier secret, which the holder of a vector keeps and commits to with it
+// in session proofs
+#[wasm_bindgen]
+pub fn random_nullifier_secret() -> Uint8Array {
+    copy_vec_to_uint8array(&generate_nullifier_secret().to_repr())
+}
+
+// Generates a random session nonce, for a verifier to challenge the prover with
+#[wasm_bindgen]
+pub fn random_nonce() -> Uint8Array {
+    copy_vec_to_uint8array(&generate_nonce().to_repr())
+}
+
+// Returns the id of a context label, which session proofs expose
+#[wasm_bindgen]
+pub fn compute_context_id(context: &str) -> Uint8Array {
+    copy_vec_to_uint8array(&context_id(context).to_repr())
+}
 
-    // Validate input lengths
-    if a.len() != 32 || b.len() != 32 {
-        error(&format!(
-            "Invalid vector length: a={}, b={} (expected 32)",
+// Computes the nullifier of a binary vector and its nullifier secret in a context, which session
+// proofs about it reveal
+#[wasm_bindgen]
+pub fn compute_nullifier(
+    bits: &[u8],
+    secret: &[u8],
+    context: &str,
+) -> Result<Uint8Array, BitVeilError> {
+    let secret = read_fp(secret, "Nullifier secret")?;
+    let bits: Vec<u64> = bits.iter().map(|x| *x as u64).collect();
+    let nullifier = derive_nullifier(&bits, secret, context_id(context))?;
+    Ok(copy_vec_to_uint8array(&nullifier.to_repr()))
+}
+
+// Computes the commitment to a binary vector together with its nullifier secret, which session
+// proofs are checked against
+#[wasm_bindgen]
+pub fn compute_session_commitment(
+    bits: &[u8],
+    salt: &[u8],
+    secret: &[u8],
+) -> Result<Uint8Array, BitVeilError> {
+    let salt = read_fp(salt, "Salt")?;
+    let secret = read_fp(secret, "Nullifier secret")?;
+    let bits: Vec<u64> = bits.iter().map(|x| *x as u64).collect();
+    let commitment = commit_session_vector(&bits, salt, secret)?;
+    Ok(copy_vec_to_uint8array(&commitment.to_repr()))
+}
+
+#[wasm_bindgen]
+pub fn compute_commitment(bits: &[u8], salt: &[u8]) -> Result<Uint8Array, BitVeilError> {
+    let salt = read_fp(salt, "Salt")?;
+    let bits: Vec<u64> = bits.iter().map(|x| *x as u64).collect();
+    let commitment = commit_vector(&bits, salt)?;
+    Ok(copy_vec_to_uint8array(&commitment.to_repr()))
+}
+
+// Computes the commitment to an integer vector with one element per byte, which inner-product and
+// embedding proofs are checked against
+#[wasm_bindgen]
+pub fn compute_integer_commitment(values: &[u8], salt: &[u8]) -> Result<Uint8Array, BitVeilError> {
+    let salt = read_fp(salt, "Salt")?;
+    let values: Vec<u64> = values.iter().map(|x| *x as u64).collect();
+    let commitment = commit_integer_vector(&values, salt)?;
+    Ok(copy_vec_to_uint8array(&commitment.to_repr()))
+}
+
+// Computes the commitment to a vector together with its mask, which masked proofs are checked
+// against
+#[wasm_bindgen]
+pub fn compute_masked_commitment(
+    bits: &[u8],
+    mask: &[u8],
+    salt: &[u8],
+) -> Result<Uint8Array, BitVeilError> {
+    let salt = read_fp(salt, "Salt")?;
+    let bits: Vec<u64> = bits.iter().map(|x| *x as u64).collect();
+    let mask: Vec<u64> = mask.iter().map(|x| *x as u64).collect();
+    let commitment = commit_masked_vector(&bits, &mask, salt)?;
+    Ok(copy_vec_to_uint8array(&commitment.to_repr()))
+}
+
+// Computes the commitment to a vector of packed bytes holding `length` bits, most significant bit
+// first, which is the same as the commitment to its bits
+#[wasm_bindgen]
+pub fn compute_packed_commitment(
+    bytes: &[u8],
+    length: u32,
+    salt: &[u8],
+) -> Result<Uint8Array, BitVeilError> {
+    let salt = read_fp(salt, "Salt")?;
+    let commitment = commit_packed(bytes, length as usize, salt)?;
+    Ok(copy_vec_to_uint8array(&commitment.to_repr()))
+}
+
+// Unpacks two vectors of packed bytes holding `length` bits each, most significant bit first
+fn read_packed_vectors(
+    a: &[u8],
+    b: &[u8],
+    length: u32,
+) -> Result<(Vec<u64>, Vec<u64>), BitVeilError> {
+    let a_vec = unpack_words(a, length as usize, "A")?;
+    let b_vec = unpack_words(b, length as usize, "B")?;
+    check_binary_vectors(&a_vec, &b_vec)?;
+    Ok((a_vec, b_vec))
+}
+
+// Checks that both vectors have the same supported length and only contain binary values
+fn read_binary_vectors(a: &[u8], b: &[u8]) -> Result<(Vec<u64>, Vec<u64>), BitVeilError> {
+    let a_vec: Vec<u64> = a.iter().map(|x| *x as u64).collect();
+    let b_vec: Vec<u64> = b.iter().map(|x| *x as u64).collect();
+
+    if let Err(e) = check_binary_vectors(&a_vec, &b_vec) {
+        error(&format!("Invalid vectors: {}", e));
+        return Err(e);
+    }
+
+    Ok((a_vec, b_vec))
+}
+
+// Reads a list of field elements given as their 32-byte representations one after another
+fn read_fps(bytes: &[u8], name: &'static str) -> Result<Vec<Fp>, BitVeilError> {
+    if !bytes.len().is_multiple_of(32) {
+        return Err(BitVeilError::InvalidFieldElement(name));
+    }
+    bytes.chunks(32).map(|chunk| read_fp(chunk, name)).collect()
+}
+
+// Splits the bits of the candidates, given one after another, into vectors as long as the probe
+fn read_candidates(probe: &[u8], candidates: &[u8]) -> Result<Vec<Vec<u64>>, BitVeilError> {
+    if probe.is_empty() || !candidates.len().is_multiple_of(probe.len()) {
+        return Err(BitVeilError::InvalidLength {
+            a: probe.len(),
+            b: candidates.len(),
+        });
+    }
+    Ok(candidates
+        .chunks(probe.len())
+        .map(|candidate| candidate.iter().map(|x| *x as u64).collect())
+        .collect())
+}
+
+// Generates proofs with the keys of one circuit, which are derived from the key file once when
+// the prover is created instead of on every proof
+#[wasm_bindgen(js_name = BitVeilProver)]
+pub struct WasmProver {
+    inner: BitVeilProver,
+}
+
+#[wasm_bindgen(js_class = BitVeilProver)]
+impl WasmProver {
+    #[wasm_bindgen(constructor)]
+    pub fn new(params_bytes: &[u8], proving_key: &[u8]) -> Result<WasmProver, BitVeilError> {
+        let params = read_params(&mut BufReader::new(params_bytes))?;
+        let info = KeyInfo::read(&mut BufReader::new(proving_key))?;
+
+        log("Loading proving key...");
+        let inner = BitVeilProver::from_key_info(params, info)?;
+        Ok(WasmProver { inner })
+    }
+
+    // Binds the proofs of the prover to a domain tag, such as an application id and protocol
+    // version, which the verifier must be given too
+    pub fn with_domain(self, domain: &str) -> Result<WasmProver, BitVeilError> {
+        Ok(WasmProver {
+            inner: self.inner.with_domain(domain)?,
+        })
+    }
+
+    pub fn prove(
+        &self,
+        a: &[u8],
+        b: &[u8],
+        salt_a: &[u8],
+        salt_b: &[u8],
+    ) -> Result<Uint8Array, BitVeilError> {
+        log(&format!(
+            "Generating proof for vectors of length {} and {}",
+            a.len(),
+            b.len()
+        ));
+
+        // Popcount keys prove the same distance with another circuit
+        match self.inner.key_info().kind {
+            CircuitKind::Popcount => self.prove_metric::<PopcountHamming>(a, b, salt_a, salt_b),
+            _ => self.prove_metric::<Hamming>(a, b, salt_a, salt_b),
+        }
+    }
+
+    // Fails early if the distance exceeds the threshold, instead of running the prover
+    pub fn prove_threshold(
+        &self,
+        a: &[u8],
+        b: &[u8],
+        salt_a: &[u8],
+        salt_b: &[u8],
+        threshold: u32,
+    ) -> Result<Uint8Array, BitVeilError> {
+        log(&format!(
+            "Generating threshold proof for vectors of length {} and {}",
             a.len(),
             b.len()
         ));
-        return Err(JsValue::from_str(&format!(
-            "Both vectors must be exactly 32 bits, got {} and {}",
+
+        let (a_vec, b_vec) = read_binary_vectors(a, b)?;
+        let salt_a = read_fp(salt_a, "Salt A")?;
+        let salt_b = read_fp(salt_b, "Salt B")?;
+
+        log("Generating ZK proof...");
+        let bundle = self
+            .inner
+            .prove_threshold(a_vec, b_vec, salt_a, salt_b, threshold as u64)?;
+
+        log("Proof generated successfully!");
+        bundle_to_uint8array(&bundle)
+    }
+
+    // Proves the smallest distance between the vectors at each shift the keys were generated for
+    pub fn prove_rotation(
+        &self,
+        a: &[u8],
+        b: &[u8],
+        salt_a: &[u8],
+        salt_b: &[u8],
+    ) -> Result<Uint8Array, BitVeilError> {
+        log(&format!(
+            "Generating rotation proof for vectors of length {} and {}",
             a.len(),
             b.len()
-        )));
+        ));
+
+        self.prove_metric::<RotatedHamming>(a, b, salt_a, salt_b)
     }
 
-    // Validate binary values
-    for (i, &bit) in a.iter().enumerate() {
-        if bit != 0 && bit != 1 {
+    // Proves the weighted distance between the vectors with the weights the keys were generated for
+    pub fn prove_weighted(
+        &self,
+        a: &[u8],
+        b: &[u8],
+        salt_a: &[u8],
+        salt_b: &[u8],
+    ) -> Result<Uint8Array, BitVeilError> {
+        log(&format!(
+            "Generating weighted proof for vectors of length {} and {}",
+            a.len(),
+            b.len()
+        ));
+
+        self.prove_metric::<WeightedHamming>(a, b, salt_a, salt_b)
+    }
+
+    // Proves the inner product of two integer vectors given with one element per byte
+    pub fn prove_inner_product(
+        &self,
+        a: &[u8],
+        b: &[u8],
+        salt_a: &[u8],
+        salt_b: &[u8],
+    ) -> Result<Uint8Array, BitVeilError> {
+        log(&format!(
+            "Generating inner-product proof for vectors of length {} and {}",
+            a.len(),
+            b.len()
+        ));
+
+        self.prove_metric::<InnerProduct>(a, b, salt_a, salt_b)
+    }
+
+    // Proves that the distance the keys were generated for between two integer vectors given with
+    // one element per byte is at most the threshold. Signed int8 elements are passed with their
+    // sign bit flipped, which shifts them by 128 and leaves the distance unchanged.
+    pub fn prove_embedding(
+        &self,
+        a: &[u8],
+        b: &[u8],
+        salt_a: &[u8],
+        salt_b: &[u8],
+        threshold: u32,
+    ) -> Result<Uint8Array, BitVeilError> {
+        log(&format!(
+            "Generating embedding proof for vectors of length {} and {}",
+            a.len(),
+            b.len()
+        ));
+
+        let a_vec: Vec<u64> = a.iter().map(|x| *x as u64).collect();
+        let b_vec: Vec<u64> = b.iter().map(|x| *x as u64).collect();
+        let salt_a = read_fp(salt_a, "Salt A")?;
+        let salt_b = read_fp(salt_b, "Salt B")?;
+
+        log("Generating ZK proof...");
+        let bundle = self
+            .inner
+            .prove_embedding(a_vec, b_vec, salt_a, salt_b, threshold as u64)?;
+
+        log("Proof generated successfully!");
+        bundle_to_uint8array(&bundle)
+    }
+
+    // Proves that the similarity the keys were generated for is at least numerator / denominator
+    pub fn prove_similarity(
+        &self,
+        a: &[u8],
+        b: &[u8],
+        salt_a: &[u8],
+        salt_b: &[u8],
+        numerator: u32,
+        denominator: u32,
+    ) -> Result<Uint8Array, BitVeilError> {
+        log(&format!(
+            "Generating similarity proof for vectors of length {} and {}",
+            a.len(),
+            b.len()
+        ));
+
+        let (a_vec, b_vec) = read_binary_vectors(a, b)?;
+        let salt_a = read_fp(salt_a, "Salt A")?;
+        let salt_b = read_fp(salt_b, "Salt B")?;
+
+        log("Generating ZK proof...");
+        let bundle = self.inner.prove_similarity(
+            a_vec,
+            b_vec,
+            salt_a,
+            salt_b,
+            numerator as u64,
+            denominator as u64,
+        )?;
+
+        log("Proof generated successfully!");
+        bundle_to_uint8array(&bundle)
+    }
+
+    // Proves that the smallest distance over the shifts is at most the threshold
+    pub fn prove_rotation_threshold(
+        &self,
+        a: &[u8],
+        b: &[u8],
+        salt_a: &[u8],
+        salt_b: &[u8],
+        threshold: u32,
+    ) -> Result<Uint8Array, BitVeilError> {
+        log(&format!(
+            "Generating rotation threshold proof for vectors of length {} and {}",
+            a.len(),
+            b.len()
+        ));
+
+        let (a_vec, b_vec) = read_binary_vectors(a, b)?;
+        let salt_a = read_fp(salt_a, "Salt A")?;
+        let salt_b = read_fp(salt_b, "Salt B")?;
+
+        log("Generating ZK proof...");
+        let bundle =
+            self.inner
+                .prove_rotation_threshold(a_vec, b_vec, salt_a, salt_b, threshold as u64)?;
+
+        log("Proof generated successfully!");
+        bundle_to_uint8array(&bundle)
+    }
+
+    // Proves the distance over the bits where both masks are 1, along with the number of such bits
+    pub fn prove_masked(
+        &self,
+        a: &[u8],
+        b: &[u8],
+        mask_a: &[u8],
+        mask_b: &[u8],
+        salt_a: &[u8],
+        salt_b: &[u8],
+    ) -> Result<Uint8Array, BitVeilError> {
+        log(&format!(
+            "Generating masked proof for vectors of length {} and {}",
+            a.len(),
+            b.len()
+        ));
+
+        let (a_vec, b_vec) = read_binary_vectors(a, b)?;
+        let (mask_a, mask_b) = read_binary_vectors(mask_a, mask_b)?;
+        let salt_a = read_fp(salt_a, "Salt A")?;
+        let salt_b = read_fp(salt_b, "Salt B")?;
+
+        log("Generating ZK proof...");
+        let bundle = self
+            .inner
+            .prove_masked(a_vec, b_vec, mask_a, mask_b, salt_a, salt_b)?;
+
+        log("Proof generated successfully!");
+        bundle_to_uint8array(&bundle)
+    }
+
+    // Proves the distance between two vectors of packed bytes, such as stored templates, which
+    // hold `length` bits each
+    pub fn prove_packed(
+        &self,
+        a: &[u8],
+        b: &[u8],
+        length: u32,
+        salt_a: &[u8],
+        salt_b: &[u8],
+    ) -> Result<Uint8Array, BitVeilError> {
+        log(&format!(
+            "Generating proof for packed vectors of {} bits",
+            length
+        ));
+
+        let (a_vec, b_vec) = read_packed_vectors(a, b, length)?;
+        let salt_a = read_fp(salt_a, "Salt A")?;
+        let salt_b = read_fp(salt_b, "Salt B")?;
+
+        log("Generating ZK proof...");
+        let bundle = self.inner.prove(a_vec, b_vec, salt_a, salt_b)?;
+
+        log("Proof generated successfully!");
+        bundle_to_uint8array(&bundle)
+    }
+
+    pub fn prove_threshold_packed(
+        &self,
+        a: &[u8],
+        b: &[u8],
+        length: u32,
+        salt_a: &[u8],
+        salt_b: &[u8],
+        threshold: u32,
+    ) -> Result<Uint8Array, BitVeilError> {
+        log(&format!(
+            "Generating threshold proof for packed vectors of {} bits",
+            length
+        ));
+
+        let (a_vec, b_vec) = read_packed_vectors(a, b, length)?;
+        let salt_a = read_fp(salt_a, "Salt A")?;
+        let salt_b = read_fp(salt_b, "Salt B")?;
+
+        log("Generating ZK proof...");
+        let bundle = self
+            .inner
+            .prove_threshold(a_vec, b_vec, salt_a, salt_b, threshold as u64)?;
+
+        log("Proof generated successfully!");
+        bundle_to_uint8array(&bundle)
+    }
+
+    // Proves the distance between two vectors in the session with the given nonce, revealing the
+    // nullifier of vector B and its secret in the given context
+    #[allow(clippy::too_many_arguments)]
+    pub fn prove_session(
+        &self,
+        a: &[u8],
+        b: &[u8],
+        salt_a: &[u8],
+        salt_b: &[u8],
+        secret: &[u8],
+        nonce: &[u8],
+        context: &str,
+    ) -> Result<Uint8Array, BitVeilError> {
+        log(&format!(
+            "Generating session proof for vectors of length {} in the context {}",
+            a.len(),
+            context
+        ));
+
+        let (a_vec, b_vec) = read_binary_vectors(a, b)?;
+        let salt_a = read_fp(salt_a, "Salt A")?;
+        let salt_b = read_fp(salt_b, "Salt B")?;
+        let secret = read_fp(secret, "Nullifier secret")?;
+        let nonce = read_fp(nonce, "Nonce")?;
+
+        log("Generating ZK proof...");
+        let bundle = self.inner.prove_session(
+            a_vec,
+            b_vec,
+            salt_a,
+            salt_b,
+            secret,
+            nonce,
+            context_id(context),
+        )?;
+
+        log("Proof generated successfully!");
+        bundle_to_uint8array(&bundle)
+    }
+
+    // Proves that the distance between the probe and a template enrolled in the registry file is at
+    // most the threshold, without revealing which template. The template is found in the registry
+    // by its commitment with the given salt.
+    pub fn prove_membership(
+        &self,
+        probe: &[u8],
+        template: &[u8],
+        salt_probe: &[u8],
+        salt_template: &[u8],
+        threshold: u32,
+        registry: &[u8],
+    ) -> Result<Uint8Array, BitVeilError> {
+        log(&format!(
+            "Generating membership proof for a probe of length {}",
+            probe.len()
+        ));
+
+        let (probe_vec, template_vec) = read_binary_vectors(probe, template)?;
+        let salt_probe = read_fp(salt_probe, "Salt of the probe")?;
+        let salt_template = read_fp(salt_template, "Salt of the template")?;
+        let registry = Registry::read(&mut BufReader::new(registry))?;
+
+        log("Generating ZK proof...");
+        let bundle = self.inner.prove_membership(
+            probe_vec,
+            template_vec,
+            salt_probe,
+            salt_template,
+            threshold as u64,
+            &registry,
+        )?;
+
+        log("Proof generated successfully!");
+        bundle_to_uint8array(&bundle)
+    }
+
+    // Proves the distance between a probe and each candidate in one proof. The candidates are
+    // given as their bits one after another, and the salts as their 32 bytes one after another.
+    pub fn prove_batch(
+        &self,
+        probe: &[u8],
+        candidates: &[u8],
+        salt_probe: &[u8],
+        salts: &[u8],
+    ) -> Result<Uint8Array, BitVeilError> {
+        let probe_vec: Vec<u64> = probe.iter().map(|x| *x as u64).collect();
+        let candidate_vecs = read_candidates(probe, candidates)?;
+        log(&format!(
+            "Generating batch proof for {} candidates of length {}",
+            candidate_vecs.len(),
+            probe.len()
+        ));
+
+        let salt_probe = read_fp(salt_probe, "Salt of the probe")?;
+        let salts = read_fps(salts, "Salt of a candidate")?;
+
+        log("Generating ZK proof...");
+        let bundle = self
+            .inner
+            .prove_batch(probe_vec, candidate_vecs, salt_probe, salts)?;
+
+        log("Proof generated successfully!");
+        bundle_to_uint8array(&bundle)
+    }
+
+    // Returns the key file the prover was created from
+    pub fn key_info(&self) -> Result<Uint8Array, BitVeilError> {
+        let mut buf = Vec::new();
+        self.inner.key_info().write(&mut buf)?;
+        Ok(copy_vec_to_uint8array(&buf))
+    }
+}
+
+impl WasmProver {
+    // Proves the metric the keys were generated for between two vectors given with one element per
+    // byte. The prover checks that the elements are valid for the metric.
+    fn prove_metric<M: DistanceMetric>(
+        &self,
+        a: &[u8],
+        b: &[u8],
+        salt_a: &[u8],
+        salt_b: &[u8],
+    ) -> Result<Uint8Array, BitVeilError> {
+        let a_vec: Vec<u64> = a.iter().map(|x| *x as u64).collect();
+        let b_vec: Vec<u64> = b.iter().map(|x| *x as u64).collect();
+        let salt_a = read_fp(salt_a, "Salt A")?;
+        let salt_b = read_fp(salt_b, "Salt B")?;
+
+        log("Generating ZK proof...");
+        let bundle = self
+            .inner
+            .prove_metric::<M>(a_vec, b_vec, salt_a, salt_b)
+            .inspect_err(|e| error(&format!("Failed to generate proof: {}", e)))?;
+
+        log("Proof generated successfully!");
+        bundle_to_uint8array(&bundle)
+    }
+}
+
+// Verifies proofs with the verifying key of one circuit, which is regenerated and checked against
+// the key file once when the verifier is created
+#[wasm_bindgen(js_name = BitVeilVerifier)]
+pub struct WasmVerifier {
+    inner: BitVeilVerifier,
+}
+
+#[wasm_bindgen(js_class = BitVeilVerifier)]
+impl WasmVerifier {
+    #[wasm_bindgen(constructor)]
+    pub fn new(params_bytes: &[u8], verifying_key: &[u8]) -> Result<WasmVerifier, BitVeilError> {
+        let params = read_params(&mut BufReader::new(params_bytes))?;
+        let info = KeyInfo::read(&mut BufReader::new(verifying_key))?;
+
+        log("Loading verifying key...");
+        let inner = BitVeilVerifier::from_key_info(params, info)?;
+        Ok(WasmVerifier { inner })
+    }
+
+    // Only accepts proofs bound to the given domain tag
+    pub fn with_domain(self, domain: &str) -> Result<WasmVerifier, BitVeilError> {
+        Ok(WasmVerifier {
+            inner: self.inner.with_domain(domain)?,
+        })
+    }
+
+    pub fn verify(
+        &self,
+        hamming_dist: u32,
+        commitment_a: &[u8],
+        commitment_b: &[u8],
+        proof_bytes: &[u8],
+    ) -> Result<bool, BitVeilError> {
+        log("Verifying proof...");
+        // Popcount proofs have the same public inputs as distance proofs
+        match self.inner.key_info().kind {
+            CircuitKind::Popcount => self.verify_metric::<PopcountHamming>(
+                hamming_dist,
+                commitment_a,
+                commitment_b,
+                proof_bytes,
+            ),
+            _ => {
+                self.verify_metric::<Hamming>(hamming_dist, commitment_a, commitment_b, proof_bytes)
+            }
+        }
+    }
+
+    pub fn verify_threshold(
+        &self,
+        threshold: u32,
+        commitment_a: &[u8],
+        commitment_b: &[u8],
+        proof_bytes: &[u8],
+    ) -> Result<bool, BitVeilError> {
+        log("Verifying threshold proof...");
+        let commitment_a = read_fp(commitment_a, "Commitment A")?;
+        let commitment_b = read_fp(commitment_b, "Commitment B")?;
+        self.verify_bundle(
+            CircuitKind::Threshold,
+            public_inputs(&[Fp::from(threshold as u64)], commitment_a, commitment_b),
+            proof_bytes,
+        )
+    }
+
+    pub fn verify_rotation(
+        &self,
+        hamming_dist: u32,
+        commitment_a: &[u8],
+        commitment_b: &[u8],
+        proof_bytes: &[u8],
+    ) -> Result<bool, BitVeilError> {
+        log("Verifying rotation proof...");
+        self.verify_metric::<RotatedHamming>(hamming_dist, commitment_a, commitment_b, proof_bytes)
+    }
+
+    pub fn verify_weighted(
+        &self,
+        weighted_dist: u32,
+        commitment_a: &[u8],
+        commitment_b: &[u8],
+        proof_bytes: &[u8],
+    ) -> Result<bool, BitVeilError> {
+        log("Verifying weighted proof...");
+        self.verify_metric::<WeightedHamming>(
+            weighted_dist,
+            commitment_a,
+            commitment_b,
+            proof_bytes,
+        )
+    }
+
+    pub fn verify_inner_product(
+        &self,
+        inner_product: u32,
+        commitment_a: &[u8],
+        commitment_b: &[u8],
+        proof_bytes: &[u8],
+    ) -> Result<bool, BitVeilError> {
+        log("Verifying inner-product proof...");
+        self.verify_metric::<InnerProduct>(inner_product, commitment_a, commitment_b, proof_bytes)
+    }
+
+    // Verifies a proof that the distance of the verifying key is at most the threshold
+    pub fn verify_embedding(
+        &self,
+        threshold: u32,
+        commitment_a: &[u8],
+        commitment_b: &[u8],
+        proof_bytes: &[u8],
+    ) -> Result<bool, BitVeilError> {
+        log("Verifying embedding proof...");
+        // Threshold proofs of the other circuits have the same public inputs
+        let kind = self.inner.key_info().kind;
+        if kind.embedding().is_none() {
             error(&format!(
-                "Invalid bit value in vector A at position {}: {}",
-                i, bit
+                "Expected an embedding verifying key, got a {} key",
+                kind
             ));
-            return Err(JsValue::from_str(&format!(
-                "Vector A contains non-binary value {} at position {}",
-                bit, i
-            )));
+            return Ok(false);
         }
+        let commitment_a = read_fp(commitment_a, "Commitment A")?;
+        let commitment_b = read_fp(commitment_b, "Commitment B")?;
+        self.verify_bundle(
+            kind,
+            public_inputs(&[Fp::from(threshold as u64)], commitment_a, commitment_b),
+            proof_bytes,
+        )
+    }
+
+    // Verifies a proof that the similarity of the verifying key is at least numerator / denominator
+    pub fn verify_similarity(
+        &self,
+        numerator: u32,
+        denominator: u32,
+        commitment_a: &[u8],
+        commitment_b: &[u8],
+        proof_bytes: &[u8],
+    ) -> Result<bool, BitVeilError> {
+        log("Verifying similarity proof...");
+        let commitment_a = read_fp(commitment_a, "Commitment A")?;
+        let commitment_b = read_fp(commitment_b, "Commitment B")?;
+        self.verify_bundle(
+            self.inner.key_info().kind,
+            similarity_public_inputs(
+                Fp::from(numerator as u64),
+                Fp::from(denominator as u64),
+                commitment_a,
+                commitment_b,
+            ),
+            proof_bytes,
+        )
+    }
+
+    pub fn verify_rotation_threshold(
+        &self,
+        threshold: u32,
+        commitment_a: &[u8],
+        commitment_b: &[u8],
+        proof_bytes: &[u8],
+    ) -> Result<bool, BitVeilError> {
+        log("Verifying rotation threshold proof...");
+        let commitment_a = read_fp(commitment_a, "Commitment A")?;
+        let commitment_b = read_fp(commitment_b, "Commitment B")?;
+        self.verify_bundle(
+            CircuitKind::RotationThreshold,
+            public_inputs(&[Fp::from(threshold as u64)], commitment_a, commitment_b),
+            proof_bytes,
+        )
+    }
+
+    // Verifies a masked proof of the distance over `valid_bits` bits, against the commitments to
+    // both vectors with their masks
+    pub fn verify_masked(
+        &self,
+        hamming_dist: u32,
+        valid_bits: u32,
+        commitment_a: &[u8],
+        commitment_b: &[u8],
+        proof_bytes: &[u8],
+    ) -> Result<bool, BitVeilError> {
+        log("Verifying masked proof...");
+        let commitment_a = read_fp(commitment_a, "Commitment A")?;
+        let commitment_b = read_fp(commitment_b, "Commitment B")?;
+        self.verify_bundle(
+            CircuitKind::Masked,
+            masked_public_inputs(
+                Fp::from(hamming_dist as u64),
+                Fp::from(valid_bits as u64),
+                commitment_a,
+                commitment_b,
+            ),
+            proof_bytes,
+        )
     }
-    for (i, &bit) in b.iter().enumerate() {
-        if bit != 0 && bit != 1 {
+
+    // Verifies a session proof against the commitments to the two vectors, the nonce the verifier
+    // issued, its context and the nullifier the prover sent with the proof, which the caller checks
+    // against the nullifiers it has already seen
+    #[allow(clippy::too_many_arguments)]
+    pub fn verify_session(
+        &self,
+        hamming_dist: u32,
+        commitment_a: &[u8],
+        commitment_b: &[u8],
+        nonce: &[u8],
+        context: &str,
+        nullifier: &[u8],
+        proof_bytes: &[u8],
+    ) -> Result<bool, BitVeilError> {
+        log("Verifying session proof...");
+        let commitment_a = read_fp(commitment_a, "Commitment A")?;
+        let commitment_b = read_fp(commitment_b, "Commitment B")?;
+        let nonce = read_fp(nonce, "Nonce")?;
+        let nullifier = read_fp(nullifier, "Nullifier")?;
+        self.verify_bundle(
+            CircuitKind::Session,
+            session_public_inputs(
+                Fp::from(hamming_dist as u64),
+                commitment_a,
+                commitment_b,
+                nonce,
+                context_id(context),
+                nullifier,
+            ),
+            proof_bytes,
+        )
+    }
+
+    // Verifies a membership proof against the commitment to the probe and the root of the registry
+    pub fn verify_membership(
+        &self,
+        threshold: u32,
+        probe_commitment: &[u8],
+        root: &[u8],
+        proof_bytes: &[u8],
+    ) -> Result<bool, BitVeilError> {
+        log("Verifying membership proof...");
+        let probe_commitment = read_fp(probe_commitment, "Commitment to the probe")?;
+        let root = read_fp(root, "Registry root")?;
+        self.verify_bundle(
+            CircuitKind::Membership,
+            membership_public_inputs(Fp::from(threshold as u64), probe_commitment, root),
+            proof_bytes,
+        )
+    }
+
+    // Verifies a batch proof of the distance to each candidate, whose commitments are given as
+    // their 32 bytes one after another
+    pub fn verify_batch(
+        &self,
+        distances: &[u32],
+        probe_commitment: &[u8],
+        candidate_commitments: &[u8],
+        proof_bytes: &[u8],
+    ) -> Result<bool, BitVeilError> {
+        log("Verifying batch proof...");
+        let probe_commitment = read_fp(probe_commitment, "Commitment to the probe")?;
+        let candidate_commitments = read_fps(candidate_commitments, "Commitment to a candidate")?;
+        let distances: Vec<Fp> = distances.iter().map(|d| Fp::from(*d as u64)).collect();
+        if distances.len() != candidate_commitments.len() {
+            error("Expected one distance per candidate commitment");
+            return Ok(false);
+        }
+        self.verify_bundle(
+            CircuitKind::Batch,
+            batch_public_inputs(probe_commitment, &distances, &candidate_commitments),
+            proof_bytes,
+        )
+    }
+}
+
+impl WasmVerifier {
+    // Verifies a proof that the metric the keys were generated for between the committed vectors
+    // has the given value
+    fn verify_metric<M: DistanceMetric>(
+        &self,
+        value: u32,
+        commitment_a: &[u8],
+        commitment_b: &[u8],
+        proof_bytes: &[u8],
+    ) -> Result<bool, BitVeilError> {
+        let commitment_a = read_fp(commitment_a, "Commitment A")?;
+        let commitment_b = read_fp(commitment_b, "Commitment B")?;
+        let metric = M::from_key_info(self.inner.key_info());
+        self.verify_bundle(
+            M::KIND,
+            metric.public_inputs(Fp::from(value as u64), commitment_a, commitment_b),
+            proof_bytes,
+        )
+    }
+
+    // Verifies a proof bundle in either format, checking that it was generated for the expected
+    // circuit and public inputs and with the verifier's key. Returns false if the proof does not
+    // verify, and an error if any of the inputs are malformed.
+    fn verify_bundle(
+        &self,
+        kind: CircuitKind,
+        expected_inputs: Vec<Fp>,
+        proof_bytes: &[u8],
+    ) -> Result<bool, BitVeilError> {
+        let bundle = ProofBundle::decode(proof_bytes)?;
+        if bundle.circuit != kind {
             error(&format!(
-                "Invalid bit value in vector B at position {}: {}",
-                i, bit
+                "Expected a {} proof, got a {} proof",
+                kind, bundle.circuit
             ));
-            return Err(JsValue::from_str(&format!(
-                "Vector B contains non-binary value {} at position {}",
-                bit, i
-            )));
+            return Ok(false);
+        }
+        if bundle.public_inputs != expected_inputs {
+            error("Proof was generated for different public inputs");
+            return Ok(false);
+        }
+        if let Err(e) = bundle.check_domain(self.inner.domain()) {
+            error(&e.to_string());
+            return Ok(false);
+        }
+
+        match self.inner.verify(&bundle) {
+            Ok(()) => Ok(true),
+            Err(e @ BitVeilError::VerificationFailed(_)) => {
+                error(&e.to_string());
+                Ok(false)
+            }
+            Err(e) => Err(e),
         }
     }
+}
 
-    let params = Params::<EqAffine>::read(&mut BufReader::new(params_bytes))
-        .map_err(|e| JsValue::from_str(&format!("Failed to read params: {}", e)))?;
+// The functions below create a prover or verifier for a single call. Callers that generate or
+// verify more than one proof should keep a BitVeilProver or BitVeilVerifier instead.
 
-    let a_vec: Vec<u64> = a.iter().map(|x| *x as u64).collect();
-    let b_vec: Vec<u64> = b.iter().map(|x| *x as u64).collect();
+#[wasm_bindgen]
+pub fn proof_generate(
+    a: &[u8],
+    b: &[u8],
+    salt_a: &[u8],
+    salt_b: &[u8],
+    params_bytes: &[u8],
+    proving_key: &[u8],
+) -> Result<Uint8Array, BitVeilError> {
+    WasmProver::new(params_bytes, proving_key)?.prove(a, b, salt_a, salt_b)
+}
+
+#[wasm_bindgen]
+pub fn proof_verify(
+    params_bytes: &[u8],
+    verifying_key: &[u8],
+    hamming_dist: u32,
+    commitment_a: &[u8],
+    commitment_b: &[u8],
+    proof_bytes: &[u8],
+) -> Result<bool, BitVeilError> {
+    WasmVerifier::new(params_bytes, verifying_key)?.verify(
+        hamming_dist,
+        commitment_a,
+        commitment_b,
+        proof_bytes,
+    )
+}
 
-    log("Calculating hamming distance...");
-    let hamming_distance = calculate_hamming_distance(a_vec.clone(), b_vec.clone());
+#[wasm_bindgen]
+pub fn proof_generate_threshold(
+    a: &[u8],
+    b: &[u8],
+    salt_a: &[u8],
+    salt_b: &[u8],
+    threshold: u32,
+    params_bytes: &[u8],
+    proving_key: &[u8],
+) -> Result<Uint8Array, BitVeilError> {
+    WasmProver::new(params_bytes, proving_key)?.prove_threshold(a, b, salt_a, salt_b, threshold)
+}
 
-    log("Generating keys...");
-    let empty_circ = empty_circuit();
-    let (pk, _vk) = generate_keys(&params, &empty_circ);
+#[wasm_bindgen]
+pub fn proof_verify_threshold(
+    params_bytes: &[u8],
+    verifying_key: &[u8],
+    threshold: u32,
+    commitment_a: &[u8],
+    commitment_b: &[u8],
+    proof_bytes: &[u8],
+) -> Result<bool, BitVeilError> {
+    WasmVerifier::new(params_bytes, verifying_key)?.verify_threshold(
+        threshold,
+        commitment_a,
+        commitment_b,
+        proof_bytes,
+    )
+}
+
+#[wasm_bindgen]
+pub fn proof_generate_rotation(
+    a: &[u8],
+    b: &[u8],
+    salt_a: &[u8],
+    salt_b: &[u8],
+    params_bytes: &[u8],
+    proving_key: &[u8],
+) -> Result<Uint8Array, BitVeilError> {
+    WasmProver::new(params_bytes, proving_key)?.prove_rotation(a, b, salt_a, salt_b)
+}
+
+#[wasm_bindgen]
+pub fn proof_verify_rotation(
+    params_bytes: &[u8],
+    verifying_key: &[u8],
+    hamming_dist: u32,
+    commitment_a: &[u8],
+    commitment_b: &[u8],
+    proof_bytes: &[u8],
+) -> Result<bool, BitVeilError> {
+    WasmVerifier::new(params_bytes, verifying_key)?.verify_rotation(
+        hamming_dist,
+        commitment_a,
+        commitment_b,
+        proof_bytes,
+    )
+}
 
-    log("Creating circuit...");
-    let hams_circ = create_circuit(a_vec, b_vec);
+#[wasm_bindgen]
+pub fn proof_generate_weighted(
+    a: &[u8],
+    b: &[u8],
+    salt_a: &[u8],
+    salt_b: &[u8],
+    params_bytes: &[u8],
+    proving_key: &[u8],
+) -> Result<Uint8Array, BitVeilError> {
+    WasmProver::new(params_bytes, proving_key)?.prove_weighted(a, b, salt_a, salt_b)
+}
 
-    log("Generating ZK proof...");
-    let proof = generate_proof(&params, &pk, hams_circ, &hamming_distance);
+#[wasm_bindgen]
+pub fn proof_verify_weighted(
+    params_bytes: &[u8],
+    verifying_key: &[u8],
+    weighted_dist: u32,
+    commitment_a: &[u8],
+    commitment_b: &[u8],
+    proof_bytes: &[u8],
+) -> Result<bool, BitVeilError> {
+    WasmVerifier::new(params_bytes, verifying_key)?.verify_weighted(
+        weighted_dist,
+        commitment_a,
+        commitment_b,
+        proof_bytes,
+    )
+}
 
-    log("Proof generated successfully!");
-    Ok(copy_vec_to_uint8array(&proof))
+#[wasm_bindgen]
+pub fn proof_generate_inner_product(
+    a: &[u8],
+    b: &[u8],
+    salt_a: &[u8],
+    salt_b: &[u8],
+    params_bytes: &[u8],
+    proving_key: &[u8],
+) -> Result<Uint8Array, BitVeilError> {
+    WasmProver::new(params_bytes, proving_key)?.prove_inner_product(a, b, salt_a, salt_b)
 }
 
 #[wasm_bindgen]
-pub fn proof_verify(params_bytes: &[u8], hamming_dist: u32, proof_bytes: &[u8]) -> bool {
-    log("Verifying proof...");
-    let params =
-        Params::<EqAffine>::read(&mut BufReader::new(params_bytes)).expect("Failed to read params");
+pub fn proof_verify_inner_product(
+    params_bytes: &[u8],
+    verifying_key: &[u8],
+    inner_product: u32,
+    commitment_a: &[u8],
+    commitment_b: &[u8],
+    proof_bytes: &[u8],
+) -> Result<bool, BitVeilError> {
+    WasmVerifier::new(params_bytes, verifying_key)?.verify_inner_product(
+        inner_product,
+        commitment_a,
+        commitment_b,
+        proof_bytes,
+    )
+}
 
-    let empty_circ = empty_circuit();
-    let vk = keygen_vk(&params, &empty_circ).expect("Failed to generate vk");
+#[wasm_bindgen]
+pub fn proof_generate_embedding(
+    a: &[u8],
+    b: &[u8],
+    salt_a: &[u8],
+    salt_b: &[u8],
+    threshold: u32,
+    params_bytes: &[u8],
+    proving_key: &[u8],
+) -> Result<Uint8Array, BitVeilError> {
+    WasmProver::new(params_bytes, proving_key)?.prove_embedding(a, b, salt_a, salt_b, threshold)
+}
 
-    let hamming_dist_fp = vec![Fp::from(hamming_dist as u64)];
+#[wasm_bindgen]
+pub fn proof_verify_embedding(
+    params_bytes: &[u8],
+    verifying_key: &[u8],
+    threshold: u32,
+    commitment_a: &[u8],
+    commitment_b: &[u8],
+    proof_bytes: &[u8],
+) -> Result<bool, BitVeilError> {
+    WasmVerifier::new(params_bytes, verifying_key)?.verify_embedding(
+        threshold,
+        commitment_a,
+        commitment_b,
+        proof_bytes,
+    )
+}
 
-    let proof_vect = proof_bytes.to_vec();
+#[allow(clippy::too_many_arguments)]
+#[wasm_bindgen]
+pub fn proof_generate_similarity(
+    a: &[u8],
+    b: &[u8],
+    salt_a: &[u8],
+    salt_b: &[u8],
+    numerator: u32,
+    denominator: u32,
+    params_bytes: &[u8],
+    proving_key: &[u8],
+) -> Result<Uint8Array, BitVeilError> {
+    WasmProver::new(params_bytes, proving_key)?.prove_similarity(
+        a,
+        b,
+        salt_a,
+        salt_b,
+        numerator,
+        denominator,
+    )
+}
 
-    let ret_val = verify(&params, &vk, &hamming_dist_fp, proof_vect);
-    match ret_val {
-        Err(_) => false,
-        _ => true,
-    }
+#[wasm_bindgen]
+pub fn proof_verify_similarity(
+    params_bytes: &[u8],
+    verifying_key: &[u8],
+    numerator: u32,
+    denominator: u32,
+    commitment_a: &[u8],
+    commitment_b: &[u8],
+    proof_bytes: &[u8],
+) -> Result<bool, BitVeilError> {
+    WasmVerifier::new(params_bytes, verifying_key)?.verify_similarity(
+        numerator,
+        denominator,
+        commitment_a,
+        commitment_b,
+        proof_bytes,
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+#[wasm_bindgen]
+pub fn proof_generate_masked(
+    a: &[u8],
+    b: &[u8],
+    mask_a: &[u8],
+    mask_b: &[u8],
+    salt_a: &[u8],
+    salt_b: &[u8],
+    params_bytes: &[u8],
+    proving_key: &[u8],
+) -> Result<Uint8Array, BitVeilError> {
+    WasmProver::new(params_bytes, proving_key)?.prove_masked(a, b, mask_a, mask_b, salt_a, salt_b)
+}
+
+#[wasm_bindgen]
+pub fn proof_verify_masked(
+    params_bytes: &[u8],
+    verifying_key: &[u8],
+    hamming_dist: u32,
+    valid_bits: u32,
+    commitment_a: &[u8],
+    commitment_b: &[u8],
+    proof_bytes: &[u8],
+) -> Result<bool, BitVeilError> {
+    WasmVerifier::new(params_bytes, verifying_key)?.verify_masked(
+        hamming_dist,
+        valid_bits,
+        commitment_a,
+        commitment_b,
+        proof_bytes,
+    )
+}
+
+#[wasm_bindgen]
+pub fn proof_generate_batch(
+    probe: &[u8],
+    candidates: &[u8],
+    salt_probe: &[u8],
+    salts: &[u8],
+    params_bytes: &[u8],
+    proving_key: &[u8],
+) -> Result<Uint8Array, BitVeilError> {
+    WasmProver::new(params_bytes, proving_key)?.prove_batch(probe, candidates, salt_probe, salts)
+}
+
+#[wasm_bindgen]
+pub fn proof_verify_batch(
+    params_bytes: &[u8],
+    verifying_key: &[u8],
+    distances: &[u32],
+    probe_commitment: &[u8],
+    candidate_commitments: &[u8],
+    proof_bytes: &[u8],
+) -> Result<bool, BitVeilError> {
+    WasmVerifier::new(params_bytes, verifying_key)?.verify_batch(
+        distances,
+        probe_commitment,
+        candidate_commitments,
+        proof_bytes,
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+#[wasm_bindgen]
+pub fn proof_generate_session(
+    a: &[u8],
+    b: &[u8],
+    salt_a: &[u8],
+    salt_b: &[u8],
+    secret: &[u8],
+    nonce: &[u8],
+    context: &str,
+    params_bytes: &[u8],
+    proving_key: &[u8],
+) -> Result<Uint8Array, BitVeilError> {
+    WasmProver::new(params_bytes, proving_key)?
+        .prove_session(a, b, salt_a, salt_b, secret, nonce, context)
+}
+
+#[allow(clippy::too_many_arguments)]
+#[wasm_bindgen]
+pub fn proof_verify_session(
+    params_bytes: &[u8],
+    verifying_key: &[u8],
+    hamming_dist: u32,
+    commitment_a: &[u8],
+    commitment_b: &[u8],
+    nonce: &[u8],
+    context: &str,
+    nullifier: &[u8],
+    proof_bytes: &[u8],
+) -> Result<bool, BitVeilError> {
+    WasmVerifier::new(params_bytes, verifying_key)?.verify_session(
+        hamming_dist,
+        commitment_a,
+        commitment_b,
+        nonce,
+        context,
+        nullifier,
+        proof_bytes,
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+#[wasm_bindgen]
+pub fn proof_generate_membership(
+    probe: &[u8],
+    template: &[u8],
+    salt_probe: &[u8],
+    salt_template: &[u8],
+    threshold: u32,
+    registry: &[u8],
+    params_bytes: &[u8],
+    proving_key: &[u8],
+) -> Result<Uint8Array, BitVeilError> {
+    WasmProver::new(params_bytes, proving_key)?.prove_membership(
+        probe,
+        template,
+        salt_probe,
+        salt_template,
+        threshold,
+        registry,
+    )
+}
+
+#[wasm_bindgen]
+pub fn proof_verify_membership(
+    params_bytes: &[u8],
+    verifying_key: &[u8],
+    threshold: u32,
+    probe_commitment: &[u8],
+    root: &[u8],
+    proof_bytes: &[u8],
+) -> Result<bool, BitVeilError> {
+    WasmVerifier::new(params_bytes, verifying_key)?.verify_membership(
+        threshold,
+        probe_commitment,
+        root,
+        proof_bytes,
+    )
+}
+
+// Converts a proof bundle in either format to JSON, for displaying its public inputs
+#[wasm_bindgen]
+pub fn proof_bundle_to_json(proof_bytes: &[u8]) -> Result<String, BitVeilError> {
+    Ok(ProofBundle::decode(proof_bytes)?.to_json())
+}
+
+// Converts a proof bundle in either format to the binary format
+#[wasm_bindgen]
+pub fn proof_bundle_to_binary(proof_bytes: &[u8]) -> Result<Uint8Array, BitVeilError> {
+    bundle_to_uint8array(&ProofBundle::decode(proof_bytes)?)
 }
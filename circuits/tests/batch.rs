@@ -0,0 +1,77 @@
+// Batch verification of proof bundles, which must report exactly the bundles that fail and never
+// accept a proof bound to another domain.
+
+use circuits::{
+    circuits::{
+        BitVeilProver, BitVeilVerifier, CircuitKind, ProofBundle, generate_setup_params,
+        k_for_length,
+    },
+    error::BitVeilError,
+};
+use halo2_proofs::pasta::Fp;
+
+const LENGTH: usize = 16;
+
+const VECTORS: [[u64; LENGTH]; 3] = [
+    [1, 0, 1, 1, 0, 0, 1, 0, 1, 1, 1, 0, 0, 1, 0, 1],
+    [1, 1, 0, 1, 0, 1, 1, 0, 0, 1, 1, 0, 1, 1, 0, 0],
+    [0, 0, 1, 1, 1, 0, 1, 0, 1, 0, 1, 1, 0, 0, 0, 1],
+];
+
+fn prove_all(prover: &BitVeilProver) -> Vec<ProofBundle> {
+    (0..VECTORS.len())
+        .map(|i| {
+            let (a, b) = (VECTORS[i], VECTORS[(i + 1) % VECTORS.len()]);
+            let salt = Fp::from(i as u64 + 1);
+            prover.prove(a.to_vec(), b.to_vec(), salt, salt).unwrap()
+        })
+        .collect()
+}
+
+fn failed_indices(result: Result<(), BitVeilError>) -> Vec<usize> {
+    match result {
+        Err(BitVeilError::BatchVerificationFailed(indices)) => indices,
+        other => panic!("expected a failed batch, got {:?}", other),
+    }
+}
+
+#[test]
+fn batch_reports_the_index_of_the_bad_proof() {
+    let params = generate_setup_params(k_for_length(LENGTH));
+    let prover = BitVeilProver::new(params.clone(), CircuitKind::Distance, LENGTH).unwrap();
+    let verifier = BitVeilVerifier::new(params, CircuitKind::Distance, LENGTH).unwrap();
+
+    let mut bundles = prove_all(&prover);
+    verifier.verify_batch(&bundles).unwrap();
+
+    // A proof of another distance than the one it claims
+    bundles[1].public_inputs[0] += Fp::one();
+    assert_eq!(failed_indices(verifier.verify_batch(&bundles)), vec![1]);
+}
+
+#[test]
+fn batch_rejects_proofs_from_another_domain() {
+    let params = generate_setup_params(k_for_length(LENGTH));
+    let prover = |domain| {
+        BitVeilProver::new(params.clone(), CircuitKind::Distance, LENGTH)
+            .unwrap()
+            .with_domain(domain)
+            .unwrap()
+    };
+    let verifier = BitVeilVerifier::new(params.clone(), CircuitKind::Distance, LENGTH)
+        .unwrap()
+        .with_domain("bitveil.example/login")
+        .unwrap();
+
+    let mut bundles = prove_all(&prover("bitveil.example/login"));
+    let other = prove_all(&prover("bitveil.example/admin"));
+    verifier.verify_batch(&bundles).unwrap();
+
+    // The bundle records the domain its proof was bound to
+    bundles[2] = other[2].clone();
+    assert_eq!(failed_indices(verifier.verify_batch(&bundles)), vec![2]);
+
+    // Relabelling the bundle does not help, as the transcript of the proof is bound to its domain
+    bundles[2].domain = "bitveil.example/login".to_string();
+    assert_eq!(failed_indices(verifier.verify_batch(&bundles)), vec![2]);
+}
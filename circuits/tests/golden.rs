@@ -0,0 +1,122 @@
+// Golden proof fixtures, generated with a seeded RNG so that every run produces the same bytes.
+// A change to a circuit, the keys, the transcript or the bundle format changes the proofs, which
+// this test reports. After an intended change, regenerate the fixtures with
+// `BITVEIL_UPDATE_FIXTURES=1 cargo test --test golden` and check them in.
+
+use circuits::circuits::{
+    BitVeilVerifier, CircuitKind, KeyInfo, ProofBundle, calculate_hamming_distance, commit_vector,
+    create_circuit, create_threshold_circuit, empty_circuit, empty_threshold_circuit,
+    generate_keys, generate_proof_with_rng, generate_setup_params, k_for_length, public_inputs,
+};
+use halo2_proofs::{pasta::Fp, plonk::Circuit};
+use rand_chacha::ChaCha20Rng;
+use rand_core::SeedableRng;
+use std::{env, fs, path::PathBuf};
+
+const SEED: u64 = 0x0B17_7E11;
+
+const A: [u64; 16] = [1, 0, 1, 1, 0, 0, 1, 0, 1, 1, 1, 0, 0, 1, 0, 1];
+const B: [u64; 16] = [1, 1, 0, 1, 0, 1, 1, 0, 0, 1, 1, 0, 1, 1, 0, 0];
+
+fn salts() -> (Fp, Fp) {
+    (Fp::from(0x5A17), Fp::from(0x5A18))
+}
+
+// Proves the circuit with the seeded RNG and compares the bundle with its fixture, or writes the
+// fixture when updating. The regenerated proof must also verify, so a fixture is never updated to
+// a broken proof.
+fn check_fixture<C: Circuit<Fp>>(
+    name: &str,
+    kind: CircuitKind,
+    empty: C,
+    circuit: C,
+    pub_input: Vec<Fp>,
+    domain: &str,
+) {
+    let params = generate_setup_params(k_for_length(A.len()));
+    let (pk, vk) = generate_keys(&params, &empty).unwrap();
+    let info = KeyInfo::new(&params, kind, A.len(), 1, 0, &[], &vk);
+
+    let rng = ChaCha20Rng::seed_from_u64(SEED);
+    let proof = generate_proof_with_rng(&params, &pk, circuit, &pub_input, domain, rng).unwrap();
+    let bundle = ProofBundle::new(&info, domain, pub_input, proof);
+    let mut bytes = Vec::new();
+    bundle.write(&mut bytes).unwrap();
+
+    BitVeilVerifier::from_key_info(params, info)
+        .unwrap()
+        .with_domain(domain)
+        .unwrap()
+        .verify(&bundle)
+        .unwrap();
+
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name);
+    if env::var_os("BITVEIL_UPDATE_FIXTURES").is_some() {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, &bytes).unwrap();
+        return;
+    }
+    let expected = fs::read(&path).unwrap_or_else(|e| panic!("failed to read {}: {}", name, e));
+    assert_eq!(
+        ProofBundle::decode(&expected).unwrap(),
+        bundle,
+        "{} no longer matches the regenerated proof",
+        name
+    );
+    assert_eq!(expected, bytes, "{} differs in its encoding", name);
+}
+
+fn distance_inputs() -> Vec<Fp> {
+    let (salt_a, salt_b) = salts();
+    public_inputs(
+        &calculate_hamming_distance(A.to_vec(), B.to_vec()).unwrap(),
+        commit_vector(&A, salt_a).unwrap(),
+        commit_vector(&B, salt_b).unwrap(),
+    )
+}
+
+#[test]
+fn distance_proof_matches_fixture() {
+    let (salt_a, salt_b) = salts();
+    check_fixture(
+        "proof_16.bin",
+        CircuitKind::Distance,
+        empty_circuit(A.len()),
+        create_circuit(A.to_vec(), B.to_vec(), salt_a, salt_b).unwrap(),
+        distance_inputs(),
+        "",
+    );
+}
+
+#[test]
+fn threshold_proof_matches_fixture() {
+    let (salt_a, salt_b) = salts();
+    let threshold = 8;
+    check_fixture(
+        "threshold_16.bin",
+        CircuitKind::Threshold,
+        empty_threshold_circuit(A.len()),
+        create_threshold_circuit(A.to_vec(), B.to_vec(), salt_a, salt_b, threshold).unwrap(),
+        public_inputs(
+            &[Fp::from(threshold)],
+            commit_vector(&A, salt_a).unwrap(),
+            commit_vector(&B, salt_b).unwrap(),
+        ),
+        "",
+    );
+}
+
+#[test]
+fn domain_proof_matches_fixture() {
+    let (salt_a, salt_b) = salts();
+    check_fixture(
+        "domain_16.bin",
+        CircuitKind::Distance,
+        empty_circuit(A.len()),
+        create_circuit(A.to_vec(), B.to_vec(), salt_a, salt_b).unwrap(),
+        distance_inputs(),
+        "bitveil.example/login",
+    );
+}